@@ -0,0 +1,109 @@
+//! Benchmark planning on repositories with many branches, with and without the reachability index.
+//! Half of the branches are merged into `master` with merge commits.
+//!
+//!     cargo bench --bench classify
+
+// Only some of the test helpers are used.
+#[allow(dead_code)]
+#[path = "../tests/fixture/mod.rs"]
+mod fixture;
+
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+
+use anyhow::Result;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use git2::{Repository, Signature};
+
+use git_trim::args::{DeleteFilter, DeleteRange};
+use git_trim::{get_trim_plan, Git, MergeStrategy, PlanParam};
+
+use fixture::{rc, test_default_param, FixtureGuard};
+
+const BRANCHES: &[usize] = &[200, 2000];
+/// Merge-bases of each branch take seconds with hundreds of branches, and minutes with thousands of them.
+const MERGE_BASE_BRANCHES: usize = 200;
+
+fn classify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_trim_plan");
+    group.sample_size(10);
+    for &branches in BRANCHES {
+        let (_guard, git) = prepare(branches).unwrap();
+        let indexed = param(true);
+        if branches <= MERGE_BASE_BRANCHES {
+            let merge_base = param(false);
+            assert_eq!(
+                get_trim_plan(&git, &merge_base).unwrap().to_delete,
+                get_trim_plan(&git, &indexed).unwrap().to_delete
+            );
+            group.bench_with_input(
+                BenchmarkId::new("merge-base", branches),
+                &merge_base,
+                |b, param| b.iter(|| get_trim_plan(&git, param).unwrap()),
+            );
+        }
+        group.bench_with_input(
+            BenchmarkId::new("reachability-index", branches),
+            &indexed,
+            |b, param| b.iter(|| get_trim_plan(&git, param).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn param(reachability_index: bool) -> PlanParam<'static> {
+    PlanParam {
+        delete: DeleteFilter::from_iter(vec![DeleteRange::MergedLocal, DeleteRange::Local]),
+        merge_strategies: vec![MergeStrategy::Ancestor, MergeStrategy::NoFastForward],
+        reachability_index,
+        ..test_default_param()
+    }
+}
+
+fn prepare(branches: usize) -> Result<(FixtureGuard, Git)> {
+    let guard = rc()
+        .append_fixture_none(
+            r#"
+            git init local
+            local <<EOF
+                git config user.name "Local Test"
+                git config user.email "local@test"
+                git commit --allow-empty -m "Initial commit"
+            EOF
+            "#,
+        )
+        .prepare("local", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    create_branches(&repo, branches)?;
+    Ok((guard, Git::try_from(repo)?))
+}
+
+/// Each branch has its own commit on top of `master`, and every other one is merged with a merge commit.
+fn create_branches(repo: &Repository, branches: usize) -> Result<()> {
+    let signature = Signature::now("Bench", "bench@test")?;
+    let mut master = repo.head()?.peel_to_commit()?;
+    let tree = master.tree()?;
+    for i in 0..branches {
+        let message = format!("Branch {}", i);
+        let oid = repo.commit(None, &signature, &signature, &message, &tree, &[&master])?;
+        let commit = repo.find_commit(oid)?;
+        repo.branch(&format!("branch-{}", i), &commit, false)?;
+        if i % 2 == 0 {
+            let message = format!("Merge branch-{}", i);
+            let oid = repo.commit(
+                None,
+                &signature,
+                &signature,
+                &message,
+                &tree,
+                &[&master, &commit],
+            )?;
+            master = repo.find_commit(oid)?;
+        }
+    }
+    repo.reference("refs/heads/master", master.id(), true, "bench")?;
+    Ok(())
+}
+
+criterion_group!(benches, classify);
+criterion_main!(benches);
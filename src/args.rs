@@ -1,14 +1,19 @@
 use std::collections::HashSet;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::hash::Hash;
 use std::iter::FromIterator;
 use std::mem::discriminant;
+use std::path::PathBuf;
 use std::process::exit;
 use std::str::FromStr;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum, ValueHint};
 use thiserror::Error;
 
+use crate::hints::HintToggle;
+use crate::merge_tracker::MergeStrategy;
+use crate::simple_glob::glob_match;
+
 #[derive(Parser, Default)]
 #[clap(
     version,
@@ -17,22 +22,78 @@ use thiserror::Error;
 `git-trim` is a missing companion to the `git fetch --prune` and a proper, safer, faster alternative to your `<bash oneliner HERE>`."
 )]
 pub struct Args {
-    /// Comma separated multiple names of branches.
-    /// All the other branches are compared with the upstream branches of those branches.
+    /// Comma separated multiple names or glob patterns (e.g. `release/*`) of branches.
+    /// All the other branches are compared with the upstream branches of those branches,
+    /// or with those branches themselves if they don't have upstreams.
     /// [default: branches that tracks `git symbolic-ref refs/remotes/*/HEAD`] [config: trim.bases]
     ///
     /// The default value is a branch that tracks `git symbolic-ref refs/remotes/*/HEAD`.
     /// They might not be reflected correctly when the HEAD branch of your remote repository is changed.
     /// You can see the changed HEAD branch name with `git remote show <remote>`
     /// and apply it to your local repository with `git remote set-head <remote> --auto`.
+    /// Without remotes, it is one of `init.defaultBranch`, `main`, and `master` that exists.
     #[clap(short, long, value_delimiter = ',', aliases=&["base"])]
     pub bases: Vec<String>,
 
+    /// Comma separated multiple commits (e.g. `v1.0`, `a1b2c3d`) to compare branches with, in addition to bases.
+    /// Branches merged into them are deleted, but they are not protected since they are not branches.
+    /// [config: trim.baseCommits]
+    #[clap(long, value_delimiter = ',')]
+    pub base_commit: Vec<String>,
+
+    /// Ask remotes for their HEAD branches with `git ls-remote --symref` when no base is found,
+    /// since `refs/remotes/<remote>/HEAD` are missing. It offers to remember them with `git remote set-head`.
+    /// It is skipped in offline mode.
+    /// [default: true] [config: trim.detectBases]
+    #[clap(long)]
+    pub detect_bases: bool,
+    #[clap(long, hide(true))]
+    pub no_detect_bases: bool,
+
     /// Comma separated multiple glob patterns (e.g. `release-*`, `feature/*`) of branches that should never be deleted.
+    /// They are matched like git matches ref globs: `*` and `**` also match `/`, and `?` and `[0-9]` are supported.
+    /// Patterns prefixed with `re:` (e.g. `re:release-\d+\.\d+`) are regular expressions
+    /// that match whole short names (e.g. `origin/release-1.0`) or refnames.
+    /// Patterns listed in `.git/trim-ignore` or `.trim-ignore`, one per line, are also protected.
     /// [config: trim.protected]
     #[clap(short, long, value_delimiter = ',')]
     pub protected: Vec<String>,
 
+    /// Never delete HEAD branches of remotes (`git symbolic-ref refs/remotes/*/HEAD`),
+    /// nor local branches that track them, even if they are not bases nor protected.
+    /// [default: true] [config: trim.protectHeadOfRemotes]
+    #[clap(long)]
+    pub protect_head_of_remotes: bool,
+    #[clap(long, hide(true))]
+    pub no_protect_head_of_remotes: bool,
+
+    /// Protect well-known deployment branches (`gh-pages`) in addition to `--protected`.
+    /// `--no-builtin-protection` to trim them like other branches.
+    /// [default: true] [config: trim.builtinProtection]
+    #[clap(long, hide(true))]
+    pub builtin_protection: bool,
+    #[clap(long)]
+    pub no_builtin_protection: bool,
+
+    /// Comma separated multiple namespaces of branch names (e.g. `users/me/`, `feature/`) to trim.
+    /// Branches outside of them are not even scanned. `/` is appended if it is missing, and a trailing `*` is ignored.
+    /// All branches are scanned if it is empty. [config: trim.includeSlashPrefixes]
+    #[clap(long, value_delimiter = ',')]
+    pub include_slash_prefix: Vec<String>,
+
+    /// Comma separated multiple namespaces (e.g. `refs/pull/*`, `refs/merge-requests/*`) of remote refs
+    /// that can be trimmed in addition to `refs/heads/*`.
+    /// Remote refs outside of these namespaces are never deleted from remotes.
+    /// [config: trim.remoteNamespaces]
+    #[clap(long, value_delimiter = ',')]
+    pub remote_namespaces: Vec<String>,
+
+    /// Comma separated multiple namespaces of remote refs (e.g. `refs/pull/*/head`) that are fetched pull requests.
+    /// Their remote tracking refs are deleted only locally by `--delete pull-refs:<remote>` when they are merged.
+    /// [default: `refs/pull/*/head,refs/merge-requests/*/head`] [config: trim.pullRefNamespaces]
+    #[clap(long, value_delimiter = ',')]
+    pub pull_ref_namespaces: Vec<String>,
+
     /// Do not update remotes
     /// [config: trim.update]
     #[clap(long)]
@@ -45,13 +106,152 @@ pub struct Args {
     #[clap(long)]
     pub update_interval: Option<u64>,
 
-    /// Do not ask confirm
+    /// Fast-forward local base branches to their upstreams after updating remotes,
+    /// so that branches are classified with the latest bases. Checked out ones are skipped.
+    /// `plan` and `check` only print what would be fast-forwarded.
+    /// [config: trim.ffBases]
+    #[clap(long)]
+    pub ff_bases: bool,
+    #[clap(long, hide(true))]
+    pub no_ff_bases: bool,
+
+    /// Seconds to wait for each remote to respond to `git ls-remote`. 0 to wait forever.
+    /// Remotes that don't respond in time are reported as unreachable,
+    /// and branches that need them to be classified are kept.
+    /// [default: 0] [config: trim.remoteTimeout]
+    #[clap(long, value_name = "SECONDS")]
+    pub remote_timeout: Option<u64>,
+
+    /// Do not contact remotes. It implies `--no-update`, and classifies branches only with local refs.
+    /// Branches that need remotes to be classified are kept,
+    /// and ones classified with possibly stale remote tracking branches are marked.
+    /// [config: trim.offline]
+    #[clap(long)]
+    pub offline: bool,
+    #[clap(long, hide(true))]
+    pub no_offline: bool,
+
+    /// Ask GitHub and GitLab which branches are protected on remotes with `gh` and `glab`,
+    /// and keep them instead of failing to delete them. It is skipped in offline mode.
+    /// [config: trim.detectRemoteProtection]
+    #[clap(long)]
+    pub detect_remote_protection: bool,
+    #[clap(long, hide(true))]
+    pub no_detect_remote_protection: bool,
+
+    /// Comma separated multiple strategies to detect merged branches. They are tried in the order.
+    /// `ancestor` detects ancestors of the base or of other merged branches.
+    /// `noff` detects branches merged with merge commits or fast-forwarded.
+    /// `rebase` detects rebased or cherry-picked branches.
+    /// `squash` detects squash merged branches without writing to the object database.
+    /// `patch-id` detects squash merged branches by comparing `git patch-id`.
+    /// `tree` detects branches whose rebased copies are fast-forwarded, by finding a commit with the same tree in the base.
+    /// [default: ancestor,noff,rebase,squash] [config: trim.detectionStrategies]
+    #[clap(long, value_delimiter = ',')]
+    pub detection_strategies: Vec<MergeStrategy>,
+
+    /// Skip detection strategies that read file contents (`rebase`, `squash`, `patch-id`).
+    /// They trigger lazy fetches of missing blobs in partial clones.
+    /// [default: true if there is a promisor remote] [config: trim.partialClone]
+    #[clap(long)]
+    pub partial_clone: bool,
+    #[clap(long, hide(true))]
+    pub no_partial_clone: bool,
+
+    /// Write a commit-graph before classification if there isn't one.
+    /// Its generation numbers speed up ancestry checks on large repositories.
+    /// [config: trim.writeCommitGraph]
+    #[clap(long)]
+    pub write_commit_graph: bool,
+    #[clap(long, hide(true))]
+    pub no_write_commit_graph: bool,
+
+    /// Mark commits reachable from bases once before classification,
+    /// so that branches merged by `noff` are told by a lookup instead of an ancestry check for each branch and base.
+    /// It speeds up repositories with thousands of branches, but it keeps every commit of the bases in memory.
+    /// [config: trim.reachabilityIndex]
+    #[clap(long)]
+    pub reachability_index: bool,
+    #[clap(long, hide(true))]
+    pub no_reachability_index: bool,
+
+    /// Reuse classifications of the last plan if refs, configs, and remote heads haven't changed since then.
+    /// `--no-cache` to classify branches again anyway.
+    /// [default: true] [config: trim.cache]
+    #[clap(long, hide(true))]
+    pub cache: bool,
+    #[clap(long)]
+    pub no_cache: bool,
+
+    /// Do not delete local branches that were checked out within the given days.
+    /// It gives a grace period to get back to merged branches. 0 to disable.
+    /// [default: 0] [config: trim.recentCheckoutDays]
+    #[clap(long)]
+    pub recent_checkout_days: Option<u64>,
+
+    /// Do not delete stray branches whose last commits are made within the given days.
+    /// A closed PR might be reopened later. 0 to disable.
+    /// [default: 0] [config: trim.strayGraceDays]
+    #[clap(long)]
+    pub stray_grace_days: Option<u64>,
+
+    /// Do not ask confirm, and delete branches even if stdin or stderr isn't a terminal, e.g. in CI or cron.
+    /// Otherwise git-trim refuses to delete branches there, since it can't ask confirm.
+    /// On Windows, only stderr is checked, so pass it when stdin is piped.
+    #[clap(short, long, conflicts_with = "confirm")]
+    pub yes: bool,
+
+    /// Do not ask confirm. It is deprecated in favor of `--yes`.
     /// [config: trim.confirm]
     #[clap(long)]
     pub no_confirm: bool,
     #[clap(long, hide(true))]
     pub confirm: bool,
 
+    /// Do not ask confirm for each diverged branch with its diverged commits.
+    /// It is asked only when confirm is enabled.
+    /// [config: trim.confirmDiverged]
+    #[clap(long)]
+    pub no_confirm_diverged: bool,
+    #[clap(long, hide(true))]
+    pub confirm_diverged: bool,
+
+    /// Ask confirm for each remote with the refspecs to push, so that deletions from a remote can be skipped.
+    /// It is asked only when confirm is enabled.
+    /// [config: trim.confirmRemotes]
+    #[clap(long)]
+    pub confirm_remotes: bool,
+    #[clap(long, hide(true))]
+    pub no_confirm_remotes: bool,
+
+    /// Read branch names from stdin, one per line (e.g. `git branch --list 'tmp/*' | git trim --stdin`),
+    /// and classify only them and their upstreams.
+    #[clap(long)]
+    pub stdin: bool,
+
+    /// Add delete ranges that the skip suggestions hint (e.g. `merged:origin`) to scan skipped branches,
+    /// and plan again with them.
+    #[clap(long)]
+    pub apply_suggestions: bool,
+
+    /// Print how long each phase of planning took, e.g. remote update, collection and classification.
+    /// The plan written by `git trim plan` includes them too.
+    #[clap(long)]
+    pub timings: bool,
+
+    /// Delete local branches even if a rebase, a merge, a bisect, etc. is in progress.
+    /// It might break the operation.
+    #[clap(long)]
+    pub force_state: bool,
+
+    /// Pick branches to delete from a list with their tip commits, instead of confirming all of them at once.
+    /// It is used only when confirm is enabled.
+    /// [config: trim.tui]
+    #[clap(long)]
+    pub tui: bool,
+    #[clap(long, hide(true))]
+    pub no_tui: bool,
+
     /// Do not detach when HEAD is about to be deleted
     /// [config: trim.detach]
     #[clap(long)]
@@ -59,11 +259,123 @@ pub struct Args {
     #[clap(long, hide(true))]
     pub detach: bool,
 
+    /// Where to go when HEAD is about to be deleted. `HEAD` detaches at the same commit,
+    /// `base` checks out the base branch that HEAD is merged into,
+    /// and other values are checked out as branches if they are local branches, or detached at otherwise.
+    /// [default: HEAD] [config: trim.detachTo]
+    #[clap(long, value_name = "REF")]
+    pub detach_to: Option<String>,
+
+    /// Check out the base branch when HEAD is about to be deleted, as `--detach-to base`,
+    /// and fast-forward it to its upstream after deleting branches.
+    /// [config: trim.switchToBaseAfterDelete]
+    #[clap(long)]
+    pub switch_to_base_after_delete: bool,
+    #[clap(long, hide(true))]
+    pub no_switch_to_base_after_delete: bool,
+
+    /// Look up push remotes of merged local branches whose remote tracking branches are already pruned,
+    /// and delete the remote branches too if they are left there.
+    /// It is still restricted by `merged-remote:<remote>` delete ranges.
+    /// [config: trim.scanPushRemote]
+    #[clap(long)]
+    pub scan_push_remote: bool,
+    #[clap(long, hide(true))]
+    pub no_scan_push_remote: bool,
+
+    /// Comma separated multiple additional sources of branches to scan.
+    /// `remote-only:<remote>` lists branches on `<remote>` with `git ls-remote`, and deletes merged ones
+    /// even if they are never fetched. Only their tip commits are fetched, without creating any refs.
+    /// It is still restricted by `merged-remote:<remote>` delete ranges.
+    /// [config: trim.scan]
+    #[clap(long, value_delimiter = ',')]
+    pub scan: Vec<ScanTarget>,
+
+    /// Use defaults for triangular workflows, where you push to your fork and PRs are merged into another remote.
+    /// Bases are looked up from the other remotes, and merged branches are deleted from your fork
+    /// that `remote.pushDefault` points to, as if `--delete merged:<fork> --scan-push-remote` is given.
+    /// Explicitly given `--bases`, `--delete`, and `--scan-push-remote` take precedence.
+    /// [default: true if `remote.pushDefault` is set and there is any other remote] [config: trim.triangular]
+    #[clap(long)]
+    pub triangular: bool,
+    #[clap(long, hide(true))]
+    pub no_triangular: bool,
+
+    /// Keep `branch.<name>.*` config entries of deleted local branches.
+    /// They are removed by default like `git branch --delete` does.
+    /// [config: trim.keepConfig]
+    #[clap(long)]
+    pub keep_config: bool,
+    #[clap(long, hide(true))]
+    pub no_keep_config: bool,
+
+    /// Fix local branches that track removed remotes.
+    /// Merged ones are deleted, and upstreams of the others are unset.
+    /// [config: trim.fixUpstreams]
+    #[clap(long)]
+    pub fix_upstreams: bool,
+    #[clap(long, hide(true))]
+    pub no_fix_upstreams: bool,
+
+    /// What to do with local branches whose upstreams are gone, but are kept.
+    /// `unset` unsets their `branch.<name>.remote` and `branch.<name>.merge`,
+    /// so that they become non-tracking local branches and are not shown as gone anymore.
+    /// [default: keep] [config: trim.fixGone]
+    #[clap(long, value_enum, value_name = "ACTION")]
+    pub fix_gone: Option<FixGone>,
+
+    /// Set upstreams of stray branches to the new names when their upstreams seem to be renamed.
+    /// They are found by new remote branches of the same remote at the same commits, and they are not deleted anyway.
+    /// [config: trim.retargetRenamed]
+    #[clap(long)]
+    pub retarget_renamed: bool,
+    #[clap(long, hide(true))]
+    pub no_retarget_renamed: bool,
+
+    /// Delete merged branches even if other stacked branches are based on them,
+    /// and set upstreams of the stacked branches to the upstreams of the deleted ones.
+    /// Otherwise, they are preserved until the stacked branches are merged.
+    /// [config: trim.retargetStacks]
+    #[clap(long)]
+    pub retarget_stacks: bool,
+    #[clap(long, hide(true))]
+    pub no_retarget_stacks: bool,
+
+    /// Delete remote tracking branches under `refs/remotes/<remote>/` of removed remotes.
+    /// `git remote update --prune` doesn't prune them.
+    /// [config: trim.pruneRemovedRemotes]
+    #[clap(long)]
+    pub prune_removed_remotes: bool,
+    #[clap(long, hide(true))]
+    pub no_prune_removed_remotes: bool,
+
+    /// Do not delete remote branches whose tip commits are signed by keys other than `--signing-keys`.
+    /// They are likely to be shared with others.
+    /// [config: trim.preserveSignedByOthers]
+    #[clap(long)]
+    pub preserve_signed_by_others: bool,
+    #[clap(long, hide(true))]
+    pub no_preserve_signed_by_others: bool,
+
+    /// Comma separated multiple keys of yours for `--preserve-signed-by-others`.
+    /// OpenPGP key IDs or fingerprints, or SSH public keys.
+    /// [default: `user.signingKey`] [config: trim.signingKeys]
+    #[clap(long, value_delimiter = ',')]
+    pub signing_keys: Vec<String>,
+
+    /// Do not delete remote branches whose tip commits are authored by others than `user.email`.
+    /// It protects your teammates' branches on shared remotes.
+    /// [config: trim.onlyMine]
+    #[clap(long)]
+    pub only_mine: bool,
+    #[clap(long, hide(true))]
+    pub no_only_mine: bool,
+
     /// Comma separated values of `<delete range>[:<remote name>]`.
-    /// Delete range is one of the `merged, merged-local, merged-remote, stray, diverged, local, remote`.
+    /// Delete range is one of the `merged, merged-local, merged-remote, stray, diverged, diverged-local, diverged-remote, local, remote, pull-refs, unrelated, duplicate-base`.
     /// `:<remote name>` is only necessary to a `<delete range>` when the range is applied to remote branches.
     /// You can use `*` as `<remote name>` to delete a range of branches from all remotes.
-    /// [default : `merged:origin`] [config: trim.delete]
+    /// [default : `merged:origin`, or `merged-local,local` without remotes] [config: trim.delete]
     ///
     /// `merged` implies `merged-local,merged-remote`.
     ///
@@ -71,29 +383,409 @@ pub struct Args {
     /// `merged-remote:<remote>` will delete merged upstream branches from `<remote>`.
     /// `stray` will delete tracking local branches, which is not merged, but the upstream is gone.
     /// `diverged:<remote>` will delete merged tracking local branches, and their upstreams from `<remote>` even if the upstreams are not merged and diverged from local ones.
+    /// `diverged-local` will delete only the local sides of diverged branches, and keep the remote sides.
+    /// `diverged-remote:<remote>` will delete only the remote sides of diverged branches from `<remote>`, and keep the local sides.
     /// `local` will delete non-tracking merged local branches.
     /// `remote:<remote>` will delete non-upstream merged remote tracking branches.
+    /// `unrelated` will delete local branches that share no history with bases (e.g. `git checkout --orphan`), even if they are not merged.
+    /// `duplicate-base` will delete merged local branches that track the upstreams of bases (e.g. `main-backup` tracking `origin/main`).
+    /// `pull-refs:<remote>` will delete merged remote tracking refs of `--pull-ref-namespaces` (e.g. fetched pull requests) only locally.
+    /// `<remote>` can be `url:<pattern>`, a glob pattern of remote URLs without schemes (e.g. `merged:url:github.com/myorg/*`).
     /// Use with caution when you are using other than `merged`. It might lose changes, and even nuke repositories.
     #[clap(short, long, value_delimiter = ',')]
     pub delete: Vec<DeleteRange>,
 
+    /// Comma separated multiple hints to turn on, or off with `no-` prefix (e.g. `no-skip-suggestions`).
+    /// Hints are `skip-suggestions`, `fix-upstreams`, `retarget-renamed`, `prune-removed-remotes`,
+    /// `remote-head-out-of-sync`, and `base-resolution`. All hints are on by default.
+    /// [config: trim.hints]
+    #[clap(long, value_delimiter = ',')]
+    pub hint: Vec<HintToggle>,
+
     /// Do not delete branches, show what branches will be deleted.
+    /// `--dry-run=commands` also prints git commands that would be executed.
+    /// `--dry-run=script` prints only those commands as a shell script.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "summary"
+    )]
+    pub dry_run: Option<DryRun>,
+
+    /// Maximum number of branches to delete at once. 0 to disable.
+    /// It asks an extra confirmation when more branches are about to be deleted,
+    /// or aborts when it doesn't ask confirm.
+    /// [default: 0] [config: trim.maxDelete]
     #[clap(long)]
-    pub dry_run: bool,
+    pub max_delete: Option<u64>,
+
+    /// Maximum number of branches in a single `git push --delete` to a remote.
+    /// Too many branches at once may exceed the command line length limit of the OS,
+    /// or time out pre-receive hooks of the server. 0 to disable.
+    /// Local branches are deleted without running `git`, so it only splits
+    /// the `git branch --delete` commands that `--dry-run=commands` and `--dry-run=script` print.
+    /// [default: 500] [config: trim.deleteChunkSize]
+    #[clap(long)]
+    pub delete_chunk_size: Option<u64>,
+
+    /// Maximum number of remote branches to delete per minute from each remote.
+    /// Pushes wait between chunks not to trip abuse detection of the server on huge cleanups. 0 for no limit.
+    /// [default: 0] [config: trim.deleteRateLimit]
+    #[clap(long, value_name = "PER_MINUTE")]
+    pub delete_rate_limit: Option<u64>,
+
+    /// Run `git maintenance run --task=gc` after deleting more than the given number of branches.
+    /// It falls back to `git gc --auto` if `git maintenance` is not available. 0 to disable.
+    /// [default: 0] [config: trim.gcAfter]
+    #[clap(long)]
+    pub gc_after: Option<u64>,
+
+    /// How much to print about branches before deleting them.
+    /// `short` prints only the number of branches that will remain instead of listing them.
+    /// `none` prints nothing, but branches to delete are still listed if it asks to confirm them.
+    /// [default: full] [config: trim.summary]
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    pub summary: Option<SummaryFormat>,
+
+    /// The order of branches to delete. `age` lists ones with the oldest tip commits first,
+    /// and `remote` groups them by their remotes.
+    /// [default: name] [config: trim.sort]
+    #[clap(long, value_enum, value_name = "KEY")]
+    pub sort: Option<SortKey>,
+
+    /// Delete at most the given number of branches in the order of `--sort`.
+    /// Branches of the same name (e.g. a local branch and its upstream) count as one.
+    /// Others are left for later runs. 0 to disable.
+    /// [default: 0] [config: trim.limit]
+    #[clap(long)]
+    pub limit: Option<u64>,
+
+    /// Show the date, the author, and the subject of the tip commit of each branch to delete.
+    /// [config: trim.details]
+    #[clap(long)]
+    pub details: bool,
+    #[clap(long, hide(true))]
+    pub no_details: bool,
+
+    /// Explain why each branch is going to be deleted or kept, as compared with each base.
+    #[clap(long)]
+    pub explain_all: bool,
+
+    /// The number of threads to classify branches and to contact remotes. 0 to use all cores.
+    /// [default: 0] [config: trim.jobs]
+    #[clap(long, short = 'j')]
+    pub jobs: Option<u64>,
+
+    /// A name of a config profile. Values of `trim.profile.<name>.*` config take precedence over `trim.*`,
+    /// e.g. `trim.profile.<name>.delete` over `trim.delete`.
+    #[clap(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Override a `trim.*` config for this run, e.g. `--config trim.updateInterval=0`. It can be given multiple times.
+    /// They take precedence over the config profile, but not over the flags for them.
+    #[clap(long, value_name = "KEY=VALUE")]
+    pub config: Vec<String>,
+
+    /// Run as if git-trim was started in `<PATH>` instead of the current working directory.
+    #[clap(short = 'C', value_name = "PATH", value_hint = ValueHint::DirPath)]
+    pub directory: Option<PathBuf>,
+
+    /// The path to the repository (the `.git` directory), like `git --git-dir`.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
+    pub git_dir: Option<PathBuf>,
+
+    /// The path to the working tree, like `git --work-tree`.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
+    pub work_tree: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Trim branches. It is the same as running without a subcommand.
+    Trim,
+    /// Explain how a branch is classified, and why it is deleted or kept.
+    Why {
+        /// A name of a local branch or a remote tracking branch (e.g. `feature`, `origin/feature`)
+        branch: String,
+    },
+    /// Write the trim plan as JSON without deleting anything, to review or edit it before `apply`.
+    Plan {
+        /// A file to write the plan. It is printed to stdout if not given.
+        #[clap(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+        /// `github-actions` prints annotations of branches to delete instead,
+        /// and writes a summary table to `--output` or `$GITHUB_STEP_SUMMARY`.
+        /// `markdown` writes the table only, e.g. to post it as an issue comment.
+        #[clap(long, value_enum, value_name = "FORMAT", default_value = "json")]
+        format: PlanFormat,
+    },
+    /// Delete branches in the plan written by `plan`.
+    /// It fails if any of them has changed after it is planned.
+    Apply {
+        /// A plan file written by `plan`
+        #[clap(value_hint = ValueHint::FilePath)]
+        plan: PathBuf,
+    },
+    /// Count branches that can be trimmed without deleting anything, e.g. to nudge a team in CI.
+    /// It fails if they are more than `--max-stale`.
+    Check {
+        /// The number of branches that can be left untrimmed
+        #[clap(long, value_name = "N", default_value = "0")]
+        max_stale: u64,
+        /// Print each branch as a GitHub Actions warning annotation (`::warning::`)
+        #[clap(long)]
+        annotate: bool,
+    },
+    /// Restore local branches deleted by the last run.
+    Undo {
+        /// Restore remote branches too, by pushing their last known commits
+        #[clap(long)]
+        remote: bool,
+    },
+    /// Print how many branches are deleted recently, and how much disk space can be reclaimed.
+    Stats {
+        /// Aggregate runs within these days
+        #[clap(long, default_value = "90")]
+        days: u64,
+    },
+    /// Print a shell completion script.
+    /// e.g. `source <(git trim completions bash)`
+    Completions {
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PlanFormat {
+    Json,
+    GithubActions,
+    Markdown,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DryRun {
+    Summary,
+    Commands,
+    Script,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SummaryFormat {
+    Short,
+    Full,
+    None,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FixGone {
+    Keep,
+    Unset,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SortKey {
+    Age,
+    Name,
+    Remote,
 }
 
 impl Args {
+    pub fn dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
     pub fn update(&self) -> Option<bool> {
         exclusive_bool(("update", self.update), ("no-update", self.no_update))
     }
 
+    pub fn ff_bases(&self) -> Option<bool> {
+        exclusive_bool(
+            ("ff-bases", self.ff_bases),
+            ("no-ff-bases", self.no_ff_bases),
+        )
+    }
+
+    pub fn offline(&self) -> Option<bool> {
+        exclusive_bool(("offline", self.offline), ("no-offline", self.no_offline))
+    }
+
+    pub fn detect_bases(&self) -> Option<bool> {
+        exclusive_bool(
+            ("detect-bases", self.detect_bases),
+            ("no-detect-bases", self.no_detect_bases),
+        )
+    }
+
+    pub fn protect_head_of_remotes(&self) -> Option<bool> {
+        exclusive_bool(
+            ("protect-head-of-remotes", self.protect_head_of_remotes),
+            (
+                "no-protect-head-of-remotes",
+                self.no_protect_head_of_remotes,
+            ),
+        )
+    }
+
+    pub fn builtin_protection(&self) -> Option<bool> {
+        exclusive_bool(
+            ("builtin-protection", self.builtin_protection),
+            ("no-builtin-protection", self.no_builtin_protection),
+        )
+    }
+
+    pub fn detect_remote_protection(&self) -> Option<bool> {
+        exclusive_bool(
+            ("detect-remote-protection", self.detect_remote_protection),
+            (
+                "no-detect-remote-protection",
+                self.no_detect_remote_protection,
+            ),
+        )
+    }
+
     pub fn confirm(&self) -> Option<bool> {
+        if self.yes {
+            return Some(false);
+        }
         exclusive_bool(("confirm", self.confirm), ("no-confirm", self.no_confirm))
     }
 
+    pub fn tui(&self) -> Option<bool> {
+        exclusive_bool(("tui", self.tui), ("no-tui", self.no_tui))
+    }
+
+    pub fn confirm_diverged(&self) -> Option<bool> {
+        exclusive_bool(
+            ("confirm-diverged", self.confirm_diverged),
+            ("no-confirm-diverged", self.no_confirm_diverged),
+        )
+    }
+
+    pub fn confirm_remotes(&self) -> Option<bool> {
+        exclusive_bool(
+            ("confirm-remotes", self.confirm_remotes),
+            ("no-confirm-remotes", self.no_confirm_remotes),
+        )
+    }
+
     pub fn detach(&self) -> Option<bool> {
         exclusive_bool(("detach", self.detach), ("no-detach", self.no_detach))
     }
+
+    pub fn switch_to_base_after_delete(&self) -> Option<bool> {
+        exclusive_bool(
+            (
+                "switch-to-base-after-delete",
+                self.switch_to_base_after_delete,
+            ),
+            (
+                "no-switch-to-base-after-delete",
+                self.no_switch_to_base_after_delete,
+            ),
+        )
+    }
+
+    pub fn scan_push_remote(&self) -> Option<bool> {
+        exclusive_bool(
+            ("scan-push-remote", self.scan_push_remote),
+            ("no-scan-push-remote", self.no_scan_push_remote),
+        )
+    }
+
+    pub fn triangular(&self) -> Option<bool> {
+        exclusive_bool(
+            ("triangular", self.triangular),
+            ("no-triangular", self.no_triangular),
+        )
+    }
+
+    pub fn retarget_stacks(&self) -> Option<bool> {
+        exclusive_bool(
+            ("retarget-stacks", self.retarget_stacks),
+            ("no-retarget-stacks", self.no_retarget_stacks),
+        )
+    }
+
+    pub fn retarget_renamed(&self) -> Option<bool> {
+        exclusive_bool(
+            ("retarget-renamed", self.retarget_renamed),
+            ("no-retarget-renamed", self.no_retarget_renamed),
+        )
+    }
+
+    pub fn partial_clone(&self) -> Option<bool> {
+        exclusive_bool(
+            ("partial-clone", self.partial_clone),
+            ("no-partial-clone", self.no_partial_clone),
+        )
+    }
+
+    pub fn write_commit_graph(&self) -> Option<bool> {
+        exclusive_bool(
+            ("write-commit-graph", self.write_commit_graph),
+            ("no-write-commit-graph", self.no_write_commit_graph),
+        )
+    }
+
+    pub fn cache(&self) -> Option<bool> {
+        exclusive_bool(("cache", self.cache), ("no-cache", self.no_cache))
+    }
+
+    pub fn reachability_index(&self) -> Option<bool> {
+        exclusive_bool(
+            ("reachability-index", self.reachability_index),
+            ("no-reachability-index", self.no_reachability_index),
+        )
+    }
+
+    pub fn keep_config(&self) -> Option<bool> {
+        exclusive_bool(
+            ("keep-config", self.keep_config),
+            ("no-keep-config", self.no_keep_config),
+        )
+    }
+
+    pub fn prune_removed_remotes(&self) -> Option<bool> {
+        exclusive_bool(
+            ("prune-removed-remotes", self.prune_removed_remotes),
+            ("no-prune-removed-remotes", self.no_prune_removed_remotes),
+        )
+    }
+
+    pub fn fix_upstreams(&self) -> Option<bool> {
+        exclusive_bool(
+            ("fix-upstreams", self.fix_upstreams),
+            ("no-fix-upstreams", self.no_fix_upstreams),
+        )
+    }
+
+    pub fn only_mine(&self) -> Option<bool> {
+        exclusive_bool(
+            ("only-mine", self.only_mine),
+            ("no-only-mine", self.no_only_mine),
+        )
+    }
+
+    pub fn details(&self) -> Option<bool> {
+        exclusive_bool(("details", self.details), ("no-details", self.no_details))
+    }
+
+    pub fn preserve_signed_by_others(&self) -> Option<bool> {
+        exclusive_bool(
+            ("preserve-signed-by-others", self.preserve_signed_by_others),
+            (
+                "no-preserve-signed-by-others",
+                self.no_preserve_signed_by_others,
+            ),
+        )
+    }
 }
 
 fn exclusive_bool(
@@ -121,6 +813,8 @@ fn exclusive_bool(
 pub enum Scope {
     All,
     Scoped(String),
+    /// A glob pattern of remote URLs without schemes, written as `url:<pattern>` (e.g. `url:github.com/myorg/*`)
+    UrlPattern(String),
 }
 
 impl FromStr for Scope {
@@ -132,11 +826,55 @@ impl FromStr for Scope {
                 message: "Scope is empty".to_owned(),
             }),
             "*" => Ok(Scope::All),
-            scope => Ok(Scope::Scoped(scope.to_owned())),
+            scope => match scope.strip_prefix("url:") {
+                Some("") => Err(ScopeParseError {
+                    message: "URL pattern is empty".to_owned(),
+                }),
+                Some(pattern) => Ok(Scope::UrlPattern(pattern.to_owned())),
+                // Remote names may contain `/`, e.g. `team/origin`
+                None => Ok(Scope::Scoped(scope.to_owned())),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::All => write!(f, "*"),
+            Scope::Scoped(scope) => write!(f, "{}", scope),
+            Scope::UrlPattern(pattern) => write!(f, "url:{}", pattern),
+        }
+    }
+}
+
+impl Scope {
+    /// `url` is the URL of the `remote`. It is required to match `UrlPattern`.
+    pub fn covers(&self, remote: &str, url: Option<&str>) -> bool {
+        match self {
+            Scope::All => true,
+            Scope::Scoped(specific) => specific == remote,
+            Scope::UrlPattern(pattern) => {
+                url.map_or(false, |url| glob_match(pattern, &strip_url_scheme(url)))
+            }
         }
     }
 }
 
+/// `https://github.com/myorg/repo.git`, `ssh://git@github.com/myorg/repo.git`,
+/// and `git@github.com:myorg/repo.git` are all `github.com/myorg/repo.git`.
+pub(crate) fn strip_url_scheme(url: &str) -> String {
+    if let Some((_, rest)) = url.split_once("://") {
+        let rest = rest.split_once('@').map_or(rest, |(_, host)| host);
+        rest.to_owned()
+    } else if let Some((host, path)) = url.split_once(':') {
+        let host = host.split_once('@').map_or(host, |(_, host)| host);
+        format!("{}/{}", host, path)
+    } else {
+        url.to_owned()
+    }
+}
+
 #[derive(Error, Debug)]
 #[error("{message}")]
 pub struct ScopeParseError {
@@ -150,8 +888,13 @@ pub enum DeleteRange {
     MergedRemote(Scope),
     Stray,
     Diverged(Scope),
+    DivergedLocal,
+    DivergedRemote(Scope),
     Local,
     Remote(Scope),
+    PullRefs(Scope),
+    Unrelated,
+    DuplicateBase,
 }
 
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
@@ -160,8 +903,13 @@ pub enum DeleteUnit {
     MergedRemote(Scope),
     Stray,
     Diverged(Scope),
+    DivergedLocal,
+    DivergedRemote(Scope),
     MergedNonTrackingLocal,
     MergedNonUpstreamRemoteTracking(Scope),
+    MergedPullRef(Scope),
+    Unrelated,
+    DuplicateOfBase,
 }
 
 impl FromStr for DeleteRange {
@@ -173,15 +921,39 @@ impl FromStr for DeleteRange {
             ["merged", remote] => Ok(DeleteRange::Merged(remote.parse()?)),
             ["stray"] => Ok(DeleteRange::Stray),
             ["diverged", remote] => Ok(DeleteRange::Diverged(remote.parse()?)),
+            ["diverged-local"] => Ok(DeleteRange::DivergedLocal),
+            ["diverged-remote", remote] => Ok(DeleteRange::DivergedRemote(remote.parse()?)),
             ["merged-local"] => Ok(DeleteRange::MergedLocal),
             ["merged-remote", remote] => Ok(DeleteRange::MergedRemote(remote.parse()?)),
             ["local"] => Ok(DeleteRange::Local),
             ["remote", remote] => Ok(DeleteRange::Remote(remote.parse()?)),
+            ["pull-refs", remote] => Ok(DeleteRange::PullRefs(remote.parse()?)),
+            ["unrelated"] => Ok(DeleteRange::Unrelated),
+            ["duplicate-base"] => Ok(DeleteRange::DuplicateBase),
             _ => Err(DeleteParseError::InvalidDeleteRangeFormat(arg.to_owned())),
         }
     }
 }
 
+impl fmt::Display for DeleteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeleteRange::Merged(scope) => write!(f, "merged:{}", scope),
+            DeleteRange::MergedLocal => write!(f, "merged-local"),
+            DeleteRange::MergedRemote(scope) => write!(f, "merged-remote:{}", scope),
+            DeleteRange::Stray => write!(f, "stray"),
+            DeleteRange::Diverged(scope) => write!(f, "diverged:{}", scope),
+            DeleteRange::DivergedLocal => write!(f, "diverged-local"),
+            DeleteRange::DivergedRemote(scope) => write!(f, "diverged-remote:{}", scope),
+            DeleteRange::Local => write!(f, "local"),
+            DeleteRange::Remote(scope) => write!(f, "remote:{}", scope),
+            DeleteRange::PullRefs(scope) => write!(f, "pull-refs:{}", scope),
+            DeleteRange::Unrelated => write!(f, "unrelated"),
+            DeleteRange::DuplicateBase => write!(f, "duplicate-base"),
+        }
+    }
+}
+
 impl DeleteRange {
     fn to_delete_units(&self) -> Vec<DeleteUnit> {
         match self {
@@ -193,19 +965,26 @@ impl DeleteRange {
             DeleteRange::MergedRemote(scope) => vec![DeleteUnit::MergedRemote(scope.clone())],
             DeleteRange::Stray => vec![DeleteUnit::Stray],
             DeleteRange::Diverged(scope) => vec![DeleteUnit::Diverged(scope.clone())],
+            DeleteRange::DivergedLocal => vec![DeleteUnit::DivergedLocal],
+            DeleteRange::DivergedRemote(scope) => vec![DeleteUnit::DivergedRemote(scope.clone())],
             DeleteRange::Local => vec![DeleteUnit::MergedNonTrackingLocal],
             DeleteRange::Remote(scope) => {
                 vec![DeleteUnit::MergedNonUpstreamRemoteTracking(scope.clone())]
             }
+            DeleteRange::PullRefs(scope) => vec![DeleteUnit::MergedPullRef(scope.clone())],
+            DeleteRange::Unrelated => vec![DeleteUnit::Unrelated],
+            DeleteRange::DuplicateBase => vec![DeleteUnit::DuplicateOfBase],
         }
     }
 
     pub fn merged_origin() -> Vec<Self> {
+        Self::merged_local_and_remote("origin")
+    }
+
+    /// `merged:<remote>`
+    pub fn merged_local_and_remote(remote: &str) -> Vec<Self> {
         use DeleteRange::*;
-        vec![
-            MergedLocal,
-            MergedRemote(Scope::Scoped("origin".to_string())),
-        ]
+        vec![MergedLocal, MergedRemote(Scope::Scoped(remote.to_string()))]
     }
 }
 
@@ -217,6 +996,32 @@ pub enum DeleteParseError {
     ScopeParseError(#[from] ScopeParseError),
 }
 
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ScanTarget {
+    /// Branches on the remote that don't have remote tracking branches.
+    RemoteOnly(String),
+}
+
+impl FromStr for ScanTarget {
+    type Err = ScanTargetParseError;
+
+    fn from_str(arg: &str) -> Result<ScanTarget, Self::Err> {
+        match arg
+            .split_once(':')
+            .map(|(kind, remote)| (kind.trim(), remote.trim()))
+        {
+            Some(("remote-only", remote)) if !remote.is_empty() => {
+                Ok(ScanTarget::RemoteOnly(remote.to_owned()))
+            }
+            _ => Err(ScanTargetParseError(arg.to_owned())),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid scan target format `{0}`")]
+pub struct ScanTargetParseError(String);
+
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 pub struct DeleteFilter(HashSet<DeleteUnit>);
 
@@ -229,78 +1034,122 @@ impl DeleteFilter {
                     | DeleteUnit::MergedRemote(_)
                     | DeleteUnit::Stray
                     | DeleteUnit::Diverged(_)
+                    | DeleteUnit::DivergedLocal
+                    | DeleteUnit::DivergedRemote(_)
+                    | DeleteUnit::Unrelated
+                    | DeleteUnit::DuplicateOfBase
             )
         })
     }
 
+    /// Whether tracking branches of `remote` can be deleted whatever they are classified as,
+    /// i.e. merged local, stray, merged remote, or either side of diverged.
+    pub fn scan_tracking_remote(&self, remote: &str, url: Option<&str>) -> bool {
+        self.delete_merged_local()
+            || self.delete_stray()
+            || self.delete_merged_remote(remote, url)
+            || self.delete_diverged(remote, url)
+            || self.delete_diverged_local()
+            || self.delete_diverged_remote(remote, url)
+            || self.delete_unrelated()
+            || self.delete_duplicate_base()
+    }
+
     pub fn scan_non_tracking_local(&self) -> bool {
-        self.0.contains(&DeleteUnit::MergedNonTrackingLocal)
+        self.0.contains(&DeleteUnit::MergedNonTrackingLocal) || self.delete_unrelated()
     }
 
-    pub fn scan_non_upstream_remote(&self, remote: &str) -> bool {
-        for unit in self.0.iter() {
-            match unit {
-                DeleteUnit::MergedNonUpstreamRemoteTracking(Scope::All) => return true,
-                DeleteUnit::MergedNonUpstreamRemoteTracking(Scope::Scoped(specific))
-                    if specific == remote =>
-                {
-                    return true
-                }
-                _ => {}
-            }
-        }
-        false
+    pub fn scan_pull_refs(&self) -> bool {
+        self.0
+            .iter()
+            .any(|unit| matches!(unit, DeleteUnit::MergedPullRef(_)))
+    }
+
+    pub fn scan_non_upstream_remote(&self, remote: &str, url: Option<&str>) -> bool {
+        self.0.iter().any(|unit| {
+            matches!(unit, DeleteUnit::MergedNonUpstreamRemoteTracking(scope) if scope.covers(remote, url))
+        })
     }
 
     pub fn delete_merged_local(&self) -> bool {
         self.0.contains(&DeleteUnit::MergedLocal)
     }
 
-    pub fn delete_merged_remote(&self, remote: &str) -> bool {
-        for unit in self.0.iter() {
-            match unit {
-                DeleteUnit::MergedRemote(Scope::All) => return true,
-                DeleteUnit::MergedRemote(Scope::Scoped(specific)) if specific == remote => {
-                    return true
-                }
-                _ => {}
-            }
-        }
-        false
+    pub fn delete_merged_remote(&self, remote: &str, url: Option<&str>) -> bool {
+        self.0.iter().any(
+            |unit| matches!(unit, DeleteUnit::MergedRemote(scope) if scope.covers(remote, url)),
+        )
     }
 
     pub fn delete_stray(&self) -> bool {
         self.0.contains(&DeleteUnit::Stray)
     }
 
-    pub fn delete_diverged(&self, remote: &str) -> bool {
-        for unit in self.0.iter() {
-            match unit {
-                DeleteUnit::Diverged(Scope::All) => return true,
-                DeleteUnit::Diverged(Scope::Scoped(specific)) if specific == remote => return true,
-                _ => {}
-            }
-        }
-        false
+    pub fn delete_unrelated(&self) -> bool {
+        self.0.contains(&DeleteUnit::Unrelated)
+    }
+
+    pub fn delete_duplicate_base(&self) -> bool {
+        self.0.contains(&DeleteUnit::DuplicateOfBase)
+    }
+
+    pub fn delete_diverged(&self, remote: &str, url: Option<&str>) -> bool {
+        self.0
+            .iter()
+            .any(|unit| matches!(unit, DeleteUnit::Diverged(scope) if scope.covers(remote, url)))
+    }
+
+    pub fn delete_diverged_local(&self) -> bool {
+        self.0.contains(&DeleteUnit::DivergedLocal)
+    }
+
+    pub fn delete_diverged_remote(&self, remote: &str, url: Option<&str>) -> bool {
+        self.0.iter().any(
+            |unit| matches!(unit, DeleteUnit::DivergedRemote(scope) if scope.covers(remote, url)),
+        )
+    }
+
+    /// The filter with `ranges` added.
+    pub fn extended(&self, ranges: impl IntoIterator<Item = DeleteRange>) -> Self {
+        self.0
+            .iter()
+            .cloned()
+            .chain(ranges.into_iter().flat_map(|range| range.to_delete_units()))
+            .collect()
     }
 
     pub fn delete_merged_non_tracking_local(&self) -> bool {
         self.0.contains(&DeleteUnit::MergedNonTrackingLocal)
     }
 
-    pub fn delete_merged_non_upstream_remote_tracking(&self, remote: &str) -> bool {
-        for filter in self.0.iter() {
-            match filter {
-                DeleteUnit::MergedNonUpstreamRemoteTracking(Scope::All) => return true,
-                DeleteUnit::MergedNonUpstreamRemoteTracking(Scope::Scoped(specific))
-                    if specific == remote =>
-                {
-                    return true
-                }
-                _ => {}
+    pub fn delete_merged_non_upstream_remote_tracking(
+        &self,
+        remote: &str,
+        url: Option<&str>,
+    ) -> bool {
+        self.0.iter().any(|unit| {
+            matches!(unit, DeleteUnit::MergedNonUpstreamRemoteTracking(scope) if scope.covers(remote, url))
+        })
+    }
+
+    /// Whether `remote` is named in any range, e.g. `merged:<remote>`, rather than covered by `*` or a URL pattern.
+    pub fn names_remote(&self, remote: &str) -> bool {
+        self.0.iter().any(|unit| match unit {
+            DeleteUnit::MergedRemote(scope)
+            | DeleteUnit::Diverged(scope)
+            | DeleteUnit::DivergedRemote(scope)
+            | DeleteUnit::MergedNonUpstreamRemoteTracking(scope)
+            | DeleteUnit::MergedPullRef(scope) => {
+                matches!(scope, Scope::Scoped(named) if named == remote)
             }
-        }
-        false
+            _ => false,
+        })
+    }
+
+    pub fn delete_merged_pull_refs(&self, remote: &str, url: Option<&str>) -> bool {
+        self.0.iter().any(
+            |unit| matches!(unit, DeleteUnit::MergedPullRef(scope) if scope.covers(remote, url)),
+        )
     }
 }
 
@@ -315,10 +1164,19 @@ impl FromIterator<DeleteUnit> for DeleteFilter {
         let mut result = HashSet::new();
         for unit in iter.into_iter() {
             match unit {
-                MergedLocal | Stray | MergedNonTrackingLocal => {
+                MergedLocal
+                | Stray
+                | DivergedLocal
+                | MergedNonTrackingLocal
+                | Unrelated
+                | DuplicateOfBase => {
                     result.insert(unit.clone());
                 }
-                MergedRemote(All) | Diverged(All) | MergedNonUpstreamRemoteTracking(All) => {
+                MergedRemote(All)
+                | Diverged(All)
+                | DivergedRemote(All)
+                | MergedNonUpstreamRemoteTracking(All)
+                | MergedPullRef(All) => {
                     result.retain(|x| discriminant(x) != discriminant(&unit));
                     result.insert(unit.clone());
                 }
@@ -332,11 +1190,21 @@ impl FromIterator<DeleteUnit> for DeleteFilter {
                         result.insert(unit.clone());
                     }
                 }
+                DivergedRemote(_) => {
+                    if !result.contains(&DivergedRemote(All)) {
+                        result.insert(unit.clone());
+                    }
+                }
                 MergedNonUpstreamRemoteTracking(_) => {
                     if !result.contains(&MergedNonUpstreamRemoteTracking(All)) {
                         result.insert(unit.clone());
                     }
                 }
+                MergedPullRef(_) => {
+                    if !result.contains(&MergedPullRef(All)) {
+                        result.insert(unit.clone());
+                    }
+                }
             }
         }
 
@@ -0,0 +1,213 @@
+//! Backends of the repository operations that the merge detection runs for each branch.
+//! `git2` is the default. An experimental pure-Rust `gix` (gitoxide) backend is built with the `gix` feature.
+//!
+//! Backends open the repository by its path by themselves, so they can be shared across the classification threads.
+//! Both of them run `git` for the operations that neither of the libraries implements.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use git2::{ErrorClass, ErrorCode, Oid, Repository};
+use log::*;
+
+use crate::subprocess;
+use crate::util::common_dir;
+
+pub trait RepoBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Peel a commit id, a refname, or any other revision to the commit id.
+    fn peel_to_commit_id(&self, name: &str) -> Result<Oid>;
+
+    /// `None` if they don't share any history.
+    fn merge_base(&self, one: Oid, two: Oid) -> Result<Option<Oid>>;
+
+    /// Commits at the boundary of a shallow clone. Empty if the repository isn't shallow.
+    fn shallow_commits(&self) -> Result<Vec<Oid>>;
+
+    /// Whether all commits of `commit` are applied to `base`, e.g. rebase merged or cherry-picked.
+    /// `git rev-list --cherry-pick --right-only --no-merges -n1 <base>...<commit>`
+    fn is_merged_by_rev_list(&self, base: &str, commit: &str) -> Result<bool>;
+}
+
+/// Use the gix backend if it is built, and it can open the repository.
+/// Otherwise (e.g. unsupported extensions), use the git2 backend.
+pub fn open(repo: &Repository) -> Result<Arc<dyn RepoBackend>> {
+    #[cfg(feature = "gix")]
+    match GixBackend::open(repo) {
+        Ok(backend) => {
+            debug!("Use gix backend");
+            return Ok(Arc::new(backend));
+        }
+        Err(err) => warn!(
+            "Use git2 backend since gix failed to open the repository: {:#}",
+            err
+        ),
+    }
+    debug!("Use git2 backend");
+    Ok(Arc::new(Git2Backend::open(repo)))
+}
+
+fn run_rev_list(workdir: Option<&Path>, base: &str, commit: &str) -> Result<bool> {
+    let workdir = workdir.context("Bare repository is not supported")?;
+    subprocess::is_merged_by_rev_list(workdir, base, commit)
+}
+
+pub struct Git2Backend {
+    path: PathBuf,
+    workdir: Option<PathBuf>,
+    shallow_file: PathBuf,
+    /// `Repository` can't be shared across threads. Each call takes one from here, or opens a new one.
+    repos: Mutex<Vec<Repository>>,
+}
+
+impl Git2Backend {
+    pub fn open(repo: &Repository) -> Self {
+        Self {
+            path: repo.path().to_owned(),
+            workdir: repo.workdir().map(Path::to_owned),
+            shallow_file: common_dir(repo).join("shallow"),
+            repos: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn with_repo<T>(&self, f: impl FnOnce(&Repository) -> Result<T>) -> Result<T> {
+        let repo = self.repos.lock().unwrap().pop();
+        let repo = match repo {
+            Some(repo) => repo,
+            None => Repository::open(&self.path)?,
+        };
+        let result = f(&repo);
+        self.repos.lock().unwrap().push(repo);
+        result
+    }
+}
+
+impl RepoBackend for Git2Backend {
+    fn name(&self) -> &'static str {
+        "git2"
+    }
+
+    fn peel_to_commit_id(&self, name: &str) -> Result<Oid> {
+        self.with_repo(|repo| {
+            // Short hex names parse too, but they might be refnames.
+            if let Ok(oid) = Oid::from_str(name) {
+                if let Ok(commit) = repo.find_commit(oid) {
+                    return Ok(commit.id());
+                }
+            }
+            Ok(repo.revparse_single(name)?.peel_to_commit()?.id())
+        })
+    }
+
+    fn merge_base(&self, one: Oid, two: Oid) -> Result<Option<Oid>> {
+        self.with_repo(|repo| match repo.merge_base(one, two) {
+            Ok(merge_base) => Ok(Some(merge_base)),
+            Err(err) if err.class() == ErrorClass::Merge && err.code() == ErrorCode::NotFound => {
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        })
+    }
+
+    fn shallow_commits(&self) -> Result<Vec<Oid>> {
+        let shallow = match std::fs::read_to_string(&self.shallow_file) {
+            Ok(shallow) => shallow,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut result = Vec::new();
+        for line in shallow
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            result.push(Oid::from_str(line)?);
+        }
+        Ok(result)
+    }
+
+    fn is_merged_by_rev_list(&self, base: &str, commit: &str) -> Result<bool> {
+        run_rev_list(self.workdir.as_deref(), base, commit)
+    }
+}
+
+#[cfg(feature = "gix")]
+pub use gix_backend::GixBackend;
+
+#[cfg(feature = "gix")]
+mod gix_backend {
+    use std::path::{Path, PathBuf};
+
+    use anyhow::Result;
+    use git2::{Oid, Repository};
+
+    use super::{run_rev_list, RepoBackend};
+
+    pub struct GixBackend {
+        repo: gix::ThreadSafeRepository,
+        workdir: Option<PathBuf>,
+    }
+
+    impl GixBackend {
+        pub fn open(repo: &Repository) -> Result<Self> {
+            Ok(Self {
+                repo: gix::ThreadSafeRepository::open(repo.path())?,
+                workdir: repo.workdir().map(Path::to_owned),
+            })
+        }
+    }
+
+    fn to_git2(id: &gix::oid) -> Result<Oid> {
+        Ok(Oid::from_bytes(id.as_bytes())?)
+    }
+
+    fn to_gix(oid: Oid) -> Result<gix::ObjectId> {
+        Ok(gix::ObjectId::try_from(oid.as_bytes())?)
+    }
+
+    impl RepoBackend for GixBackend {
+        fn name(&self) -> &'static str {
+            "gix"
+        }
+
+        fn peel_to_commit_id(&self, name: &str) -> Result<Oid> {
+            let repo = self.repo.to_thread_local();
+            // Short hex names parse too, but they might be refnames.
+            if let Ok(id) = gix::ObjectId::from_hex(name.as_bytes()) {
+                if let Ok(object) = repo.find_object(id) {
+                    if let Ok(commit) = object.peel_to_commit() {
+                        return to_git2(&commit.id);
+                    }
+                }
+            }
+            let commit = repo.rev_parse_single(name)?.object()?.peel_to_commit()?;
+            to_git2(&commit.id)
+        }
+
+        fn merge_base(&self, one: Oid, two: Oid) -> Result<Option<Oid>> {
+            let repo = self.repo.to_thread_local();
+            match repo.merge_base(to_gix(one)?, to_gix(two)?) {
+                Ok(merge_base) => Ok(Some(to_git2(&merge_base)?)),
+                Err(gix::repository::merge_base::Error::NotFound { .. }) => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        }
+
+        fn shallow_commits(&self) -> Result<Vec<Oid>> {
+            let repo = self.repo.to_thread_local();
+            let mut result = Vec::new();
+            if let Some(commits) = repo.shallow_commits()? {
+                for id in commits.iter() {
+                    result.push(to_git2(id)?);
+                }
+            }
+            Ok(result)
+        }
+
+        fn is_merged_by_rev_list(&self, base: &str, commit: &str) -> Result<bool> {
+            run_rev_list(self.workdir.as_deref(), base, commit)
+        }
+    }
+}
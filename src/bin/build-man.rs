@@ -65,5 +65,23 @@ fn main() {
         }
     }
 
+    let mut subcommands = Section::new("SUBCOMMANDS");
+    let mut any_subcommands = false;
+    for subcommand in command.get_subcommands() {
+        let mut usage = format!("{} {}", command.get_name(), subcommand.get_name());
+        for arg in subcommand.get_positionals() {
+            usage.push_str(&format!(" <{}>", arg.get_id().as_str()));
+        }
+        let about = subcommand
+            .get_about()
+            .map(|about| about.to_string())
+            .unwrap_or_default();
+        subcommands = subcommands.paragraph(&format!("{}: {}", usage, about));
+        any_subcommands = true;
+    }
+    if any_subcommands {
+        page = page.custom(subcommands);
+    }
+
     println!("{}", page.render());
 }
@@ -1,8 +1,8 @@
 use std::convert::TryFrom;
 
 use anyhow::{Context, Result};
-use git2::{Branch, Config, Direction, Reference, Repository};
-use log::*;
+use git2::{Branch, BranchType, Config, Direction, Reference, Repository};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::config;
@@ -12,7 +12,7 @@ pub trait Refname {
     fn refname(&self) -> &str;
 }
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Clone)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Clone)]
 pub struct LocalBranch {
     pub refname: String,
 }
@@ -83,7 +83,19 @@ impl<'repo> TryFrom<&git2::Reference<'repo>> for LocalBranch {
     }
 }
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Clone)]
+/// Branches whose refnames are not valid UTF-8 are skipped. They are listed by `core::get_non_utf8_refnames`.
+pub(crate) fn utf8_branches(repo: &Repository, kind: BranchType) -> Result<Vec<Branch<'_>>> {
+    let mut result = Vec::new();
+    for branch in repo.branches(Some(kind))? {
+        let (branch, _) = branch?;
+        if branch.get().name().is_some() {
+            result.push(branch);
+        }
+    }
+    Ok(result)
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Clone)]
 pub struct RemoteTrackingBranch {
     pub refname: String,
 }
@@ -124,10 +136,14 @@ impl RemoteTrackingBranch {
         Ok(RemoteTrackingBranchStatus::None)
     }
 
+    /// When refspecs of many remotes fetch into this, it is disambiguated in order by
+    /// `branch.<name>.remote` of local branches tracking this, `branch.<name>.pushRemote`,
+    /// and `checkout.defaultRemote`.
     pub fn to_remote_branch(
         &self,
         repo: &Repository,
     ) -> std::result::Result<RemoteBranch, RemoteBranchError> {
+        let mut candidates = Vec::new();
         for remote_name in repo.remotes()?.iter() {
             let remote_name = remote_name.context("non-utf8 remote name")?;
             let remote = repo.find_remote(remote_name)?;
@@ -137,13 +153,71 @@ impl RemoteTrackingBranch {
                 Direction::Fetch,
                 ExpansionSide::Left,
             )? {
-                return Ok(RemoteBranch {
+                candidates.push(RemoteBranch {
                     remote: remote.name().context("non-utf8 remote name")?.to_string(),
                     refname: expanded,
                 });
             }
         }
-        Err(RemoteBranchError::RemoteNotFound)
+        match candidates.len() {
+            0 => Err(RemoteBranchError::RemoteNotFound),
+            1 => Ok(candidates.remove(0)),
+            _ => self.disambiguate(repo, candidates),
+        }
+    }
+
+    fn disambiguate(
+        &self,
+        repo: &Repository,
+        candidates: Vec<RemoteBranch>,
+    ) -> std::result::Result<RemoteBranch, RemoteBranchError> {
+        let config = repo.config()?.snapshot()?;
+        let find = |remote_name: &str| {
+            candidates
+                .iter()
+                .find(|candidate| candidate.remote == remote_name)
+                .cloned()
+        };
+
+        for branch in utf8_branches(repo, BranchType::Local)? {
+            let local = LocalBranch::try_from(&branch)?;
+            if let RemoteTrackingBranchStatus::Exists(upstream) =
+                local.fetch_upstream(repo, &config)?
+            {
+                if upstream == *self {
+                    if let Some(remote_name) = config::get_remote_name(&config, &local)? {
+                        if let Some(found) = find(&remote_name) {
+                            return Ok(found);
+                        }
+                    }
+                }
+            }
+        }
+        for candidate in &candidates {
+            if let Some(short_name) = candidate.refname.strip_prefix("refs/heads/") {
+                let key = format!("branch.{}.pushRemote", short_name);
+                if config::get::<String>(&config, &key).read()?.as_deref()
+                    == Some(&candidate.remote)
+                {
+                    return Ok(candidate.clone());
+                }
+            }
+        }
+        if let Some(default_remote) =
+            config::get::<String>(&config, "checkout.defaultRemote").read()?
+        {
+            if let Some(found) = find(&default_remote) {
+                return Ok(found);
+            }
+        }
+
+        Err(RemoteBranchError::AmbiguousRemotes {
+            refname: self.refname.clone(),
+            remotes: candidates
+                .into_iter()
+                .map(|candidate| candidate.remote)
+                .collect(),
+        })
     }
 }
 
@@ -175,13 +249,40 @@ impl<'repo> TryFrom<&git2::Reference<'repo>> for RemoteTrackingBranch {
     }
 }
 
+/// A ref that other branches are compared with to tell whether they are merged.
+/// It is usually an upstream of a base branch, but it can be any ref (e.g. a tag), or a commit id.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Clone)]
+pub struct BaseRef {
+    pub refname: String,
+}
+
+impl BaseRef {
+    pub fn new(refname: &str) -> BaseRef {
+        BaseRef {
+            refname: refname.to_string(),
+        }
+    }
+}
+
+impl From<&RemoteTrackingBranch> for BaseRef {
+    fn from(remote: &RemoteTrackingBranch) -> Self {
+        BaseRef::new(&remote.refname)
+    }
+}
+
+impl Refname for BaseRef {
+    fn refname(&self) -> &str {
+        &self.refname
+    }
+}
+
 pub enum RemoteTrackingBranchStatus {
     Exists(RemoteTrackingBranch),
     Gone(String),
     None,
 }
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Hash, Debug)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Clone, Hash, Debug)]
 pub struct RemoteBranch {
     pub remote: String,
     pub refname: String,
@@ -193,6 +294,20 @@ impl std::fmt::Display for RemoteBranch {
     }
 }
 
+/// A branch on a remote that doesn't have a remote tracking branch, e.g. it is never fetched.
+/// It is inspected by its tip commit, which is fetched without creating any refs.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Clone, Hash, Debug)]
+pub struct RemoteOnlyBranch {
+    pub remote: RemoteBranch,
+    pub commit: String,
+}
+
+impl Refname for RemoteOnlyBranch {
+    fn refname(&self) -> &str {
+        &self.commit
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RemoteBranchError {
     #[error("anyhow error")]
@@ -201,4 +316,12 @@ pub enum RemoteBranchError {
     GitError(#[from] git2::Error),
     #[error("remote with matching refspec not found")]
     RemoteNotFound,
+    #[error(
+        "{refname} is fetched from many remotes: {}. Set `checkout.defaultRemote` to choose one",
+        remotes.join(", ")
+    )]
+    AmbiguousRemotes {
+        refname: String,
+        remotes: Vec<String>,
+    },
 }
@@ -0,0 +1,183 @@
+//! A minimal reader of commit-graph files to get generation numbers of commits.
+//! https://git-scm.com/docs/gitformat-commit-graph
+//!
+//! If a commit is an ancestor of another, its generation number is strictly smaller than the other's.
+//! So we can tell that a commit is not an ancestor of another without walking the history.
+
+use std::path::Path;
+
+use anyhow::Result;
+use git2::{Oid, Repository};
+use log::*;
+
+use crate::util::common_dir;
+
+pub struct CommitGraph {
+    files: Vec<CommitGraphFile>,
+}
+
+struct CommitGraphFile {
+    data: Vec<u8>,
+    hash_len: usize,
+    fanout: usize,
+    oid_lookup: usize,
+    commit_data: usize,
+}
+
+impl CommitGraph {
+    /// Read `objects/info/commit-graph`, or split commit-graphs in `objects/info/commit-graphs`.
+    /// Returns `None` if there is none.
+    pub fn open(repo: &Repository) -> Result<Option<CommitGraph>> {
+        let info = common_dir(repo).join("objects").join("info");
+        let mut paths = Vec::new();
+        let single = info.join("commit-graph");
+        if single.is_file() {
+            paths.push(single);
+        }
+        let chain_dir = info.join("commit-graphs");
+        match std::fs::read_to_string(chain_dir.join("commit-graph-chain")) {
+            Ok(chain) => {
+                for hash in chain.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                    paths.push(chain_dir.join(format!("graph-{}.graph", hash)));
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        let mut files = Vec::new();
+        for path in paths {
+            match CommitGraphFile::read(&path)? {
+                Some(file) => files.push(file),
+                None => debug!("Ignore malformed commit-graph: {}", path.display()),
+            }
+        }
+        if files.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(CommitGraph { files }))
+    }
+
+    pub fn generation(&self, oid: Oid) -> Option<u32> {
+        self.files.iter().find_map(|file| file.generation(oid))
+    }
+
+    /// Returns `false` if `ancestor` is surely not an ancestor of `descendant`.
+    /// `true` doesn't mean that it is, but that it needs a history walk to tell.
+    pub fn may_be_ancestor(&self, ancestor: Oid, descendant: Oid) -> bool {
+        if ancestor == descendant {
+            return true;
+        }
+        match (self.generation(ancestor), self.generation(descendant)) {
+            (Some(ancestor), Some(descendant)) => ancestor < descendant,
+            _ => true,
+        }
+    }
+}
+
+impl CommitGraphFile {
+    fn read(path: &Path) -> Result<Option<CommitGraphFile>> {
+        let data = std::fs::read(path)?;
+        Ok(Self::parse(data))
+    }
+
+    /// Files can be corrupted or truncated, so every range is checked here before lookups use it.
+    fn parse(data: Vec<u8>) -> Option<CommitGraphFile> {
+        if data.get(0..4)? != b"CGPH" || *data.get(4)? != 1 {
+            return None;
+        }
+        let hash_len = match *data.get(5)? {
+            1 => 20,
+            2 => 32,
+            _ => return None,
+        };
+        let chunk_count = *data.get(6)? as usize;
+
+        let (mut fanout, mut oid_lookup, mut commit_data) = (None, None, None);
+        for i in 0..chunk_count {
+            let entry = data.get(8 + i * 12..8 + (i + 1) * 12)?;
+            let offset = usize::try_from(read_u64(&entry[4..12])).ok()?;
+            match &entry[0..4] {
+                b"OIDF" => fanout = Some(offset),
+                b"OIDL" => oid_lookup = Some(offset),
+                b"CDAT" => commit_data = Some(offset),
+                _ => {}
+            }
+        }
+
+        let file = CommitGraphFile {
+            hash_len,
+            fanout: fanout?,
+            oid_lookup: oid_lookup?,
+            commit_data: commit_data?,
+            data,
+        };
+        file.data
+            .get(file.fanout..file.fanout.checked_add(256 * 4)?)?;
+        // Counts are cumulative, so binary searches in `position` stay in the lookup table.
+        let mut count = 0;
+        for first_byte in 0..256 {
+            let cumulative = file.fanout(first_byte)?;
+            if cumulative < count {
+                return None;
+            }
+            count = cumulative;
+        }
+        let oid_lookup_end = count.checked_mul(hash_len)?.checked_add(file.oid_lookup)?;
+        file.data.get(file.oid_lookup..oid_lookup_end)?;
+        let commit_data_end = count
+            .checked_mul(hash_len + 16)?
+            .checked_add(file.commit_data)?;
+        file.data.get(file.commit_data..commit_data_end)?;
+        Some(file)
+    }
+
+    fn fanout(&self, first_byte: usize) -> Option<usize> {
+        let offset = self.fanout + first_byte * 4;
+        Some(read_u32(self.data.get(offset..offset + 4)?) as usize)
+    }
+
+    fn position(&self, oid: Oid) -> Option<usize> {
+        let oid = oid.as_bytes();
+        if oid.len() != self.hash_len {
+            return None;
+        }
+        let first_byte = oid[0] as usize;
+        let mut low = if first_byte == 0 {
+            0
+        } else {
+            self.fanout(first_byte - 1)?
+        };
+        let mut high = self.fanout(first_byte)?;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let offset = self.oid_lookup + mid * self.hash_len;
+            match self.data.get(offset..offset + self.hash_len)?.cmp(oid) {
+                std::cmp::Ordering::Equal => return Some(mid),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+
+    /// The topological level. It is in the upper 30 bits after the tree and the parents.
+    fn generation(&self, oid: Oid) -> Option<u32> {
+        let position = self.position(oid)?;
+        let offset = self.commit_data + position * (self.hash_len + 16) + self.hash_len + 8;
+        let generation = read_u32(self.data.get(offset..offset + 4)?) >> 2;
+        // Written by old versions of git that don't compute generation numbers.
+        if generation == 0 {
+            return None;
+        }
+        Some(generation)
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    (read_u32(&bytes[0..4]) as u64) << 32 | read_u32(&bytes[4..8]) as u64
+}
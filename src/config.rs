@@ -8,21 +8,73 @@ use anyhow::{Context, Result};
 use git2::{BranchType, Config as GitConfig, Error, ErrorClass, ErrorCode, Remote, Repository};
 use log::*;
 
-use crate::args::{Args, DeleteFilter, DeleteRange};
-use crate::branch::{LocalBranch, RemoteTrackingBranchStatus};
+use crate::args::{Args, DeleteFilter, DeleteRange, FixGone, ScanTarget, SortKey, SummaryFormat};
+use crate::branch::{utf8_branches, LocalBranch, RemoteTrackingBranchStatus};
+use crate::core::ProtectPattern;
+use crate::error::InvalidConfig;
+use crate::hints::HintFilter;
+use crate::merge_tracker::MergeStrategy;
+use crate::util::common_dir;
+use crate::DeletePacing;
 use std::collections::HashSet;
 
 type GitResult<T> = std::result::Result<T, git2::Error>;
 
 #[derive(Debug)]
 pub struct Config {
+    pub triangular: ConfigValue<bool>,
     pub bases: ConfigValue<HashSet<String>>,
+    pub base_commits: ConfigValue<Vec<String>>,
+    pub detect_bases: ConfigValue<bool>,
+    /// User's patterns, and builtin ones unless `builtin_protection` is off.
     pub protected: ConfigValue<Vec<String>>,
+    pub builtin_protection: ConfigValue<bool>,
+    pub protect_head_of_remotes: ConfigValue<bool>,
+    pub include_slash_prefixes: ConfigValue<Vec<String>>,
+    pub scan: ConfigValue<Vec<ScanTarget>>,
+    pub hints: ConfigValue<HintFilter>,
+    pub remote_namespaces: ConfigValue<Vec<String>>,
+    pub pull_ref_namespaces: ConfigValue<Vec<String>>,
     pub update: ConfigValue<bool>,
     pub update_interval: ConfigValue<u64>,
+    pub ff_bases: ConfigValue<bool>,
+    pub remote_timeout: ConfigValue<u64>,
+    pub offline: ConfigValue<bool>,
+    pub detect_remote_protection: ConfigValue<bool>,
+    pub recent_checkout_days: ConfigValue<u64>,
+    pub stray_grace_days: ConfigValue<u64>,
+    pub detection_strategies: ConfigValue<Vec<MergeStrategy>>,
+    pub partial_clone: ConfigValue<bool>,
+    pub write_commit_graph: ConfigValue<bool>,
+    pub reachability_index: ConfigValue<bool>,
+    pub cache: ConfigValue<bool>,
     pub confirm: ConfigValue<bool>,
+    pub confirm_diverged: ConfigValue<bool>,
+    pub confirm_remotes: ConfigValue<bool>,
+    pub tui: ConfigValue<bool>,
     pub detach: ConfigValue<bool>,
+    pub detach_to: ConfigValue<String>,
+    pub switch_to_base_after_delete: ConfigValue<bool>,
+    pub scan_push_remote: ConfigValue<bool>,
+    pub keep_config: ConfigValue<bool>,
+    pub fix_upstreams: ConfigValue<bool>,
+    pub fix_gone: ConfigValue<FixGone>,
+    pub retarget_renamed: ConfigValue<bool>,
+    pub retarget_stacks: ConfigValue<bool>,
+    pub prune_removed_remotes: ConfigValue<bool>,
+    pub preserve_signed_by_others: ConfigValue<bool>,
+    pub signing_keys: ConfigValue<Vec<String>>,
+    pub only_mine: ConfigValue<bool>,
     pub delete: ConfigValue<DeleteFilter>,
+    pub max_delete: ConfigValue<u64>,
+    pub delete_chunk_size: ConfigValue<u64>,
+    pub delete_rate_limit: ConfigValue<u64>,
+    pub gc_after: ConfigValue<u64>,
+    pub summary: ConfigValue<SummaryFormat>,
+    pub sort: ConfigValue<SortKey>,
+    pub limit: ConfigValue<u64>,
+    pub jobs: ConfigValue<u64>,
+    pub details: ConfigValue<bool>,
 }
 
 impl Config {
@@ -35,56 +87,530 @@ impl Config {
             }
         }
 
+        let overrides = ConfigOverrides::parse(&args.config)?;
+        let profile = args.profile.as_deref();
+        if let Some(profile) = profile {
+            if !profile_exists(config, profile)? {
+                return Err(anyhow::anyhow!(
+                    "Config profile `{}` doesn't exist. Set `trim.profile.{}.*` configs first.",
+                    profile,
+                    profile
+                ));
+            }
+        }
+
+        let push_default = get::<String>(config, "remote.pushDefault")
+            .read()?
+            .map(ConfigValue::unwrap);
+        let triangular = get(config, "trim.triangular")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.triangular())
+            .with_default(is_triangular(repo, push_default.as_deref())?)
+            .read()?
+            .expect("has default");
+        let fork = if *triangular {
+            Some(push_default.as_deref().unwrap_or("origin"))
+        } else {
+            None
+        };
+
+        // Local only repositories are trimmed with local branches only.
+        let local_only = repo.remotes()?.is_empty();
+        let default_bases = if local_only {
+            get_local_default_branch(repo, config)?
+                .into_iter()
+                .collect()
+        } else {
+            get_branches_tracks_remote_heads(repo, config, fork)?
+        };
         let bases = get_comma_separated_multi(config, "trim.bases")
+            .with_profile(profile)
+            .with_overrides(&overrides)
             .with_explicit(non_empty(args.bases.clone()))
-            .with_default(get_branches_tracks_remote_heads(repo, config)?)
+            .with_default(default_bases)
             .parses_and_collect::<HashSet<String>>()?;
+        let base_commits = get_comma_separated_multi(config, "trim.baseCommits")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.base_commit.clone()))
+            .parses_and_collect::<Vec<String>>()?;
+        let detect_bases = get(config, "trim.detectBases")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.detect_bases())
+            .with_default(true)
+            .read()?
+            .expect("has default");
+        let builtin_protection = get(config, "trim.builtinProtection")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.builtin_protection())
+            .with_default(true)
+            .read()?
+            .expect("has default");
         let protected = get_comma_separated_multi(config, "trim.protected")
+            .with_profile(profile)
+            .with_overrides(&overrides)
             .with_explicit(non_empty(args.protected.clone()))
             .parses_and_collect::<Vec<String>>()?;
+        let protected = if *builtin_protection {
+            protected.map(with_builtin_protected)
+        } else {
+            protected
+        };
+        // Fail early on invalid regular expressions.
+        for pattern in protected.iter() {
+            ProtectPattern::parse(pattern)?;
+        }
+        let protect_head_of_remotes = get(config, "trim.protectHeadOfRemotes")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.protect_head_of_remotes())
+            .with_default(true)
+            .read()?
+            .expect("has default");
+        let include_slash_prefixes = get_comma_separated_multi(config, "trim.includeSlashPrefixes")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.include_slash_prefix.clone()))
+            .parses_and_collect::<Vec<String>>()?;
+        let scan = get_comma_separated_multi(config, "trim.scan")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.scan.clone()))
+            .parses_and_collect::<Vec<ScanTarget>>()?;
+        let hints = get_comma_separated_multi(config, "trim.hints")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.hint.clone()))
+            .parses_and_collect::<HintFilter>()?;
+        let remote_namespaces = get_comma_separated_multi(config, "trim.remoteNamespaces")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.remote_namespaces.clone()))
+            .parses_and_collect::<Vec<String>>()?;
+        let pull_ref_namespaces = get_comma_separated_multi(config, "trim.pullRefNamespaces")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.pull_ref_namespaces.clone()))
+            .with_default(vec![
+                "refs/pull/*/head".to_owned(),
+                "refs/merge-requests/*/head".to_owned(),
+            ])
+            .parses_and_collect::<Vec<String>>()?;
         let update = get(config, "trim.update")
+            .with_profile(profile)
+            .with_overrides(&overrides)
             .with_explicit(args.update())
-            .with_default(true)
+            .with_default(!local_only)
             .read()?
             .expect("has default");
         let update_interval = get(config, "trim.updateInterval")
+            .with_profile(profile)
+            .with_overrides(&overrides)
             .with_explicit(args.update_interval)
             .with_default(5)
             .read()?
             .expect("has default");
+        let ff_bases = get(config, "trim.ffBases")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.ff_bases())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let remote_timeout = get(config, "trim.remoteTimeout")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.remote_timeout)
+            .with_default(0)
+            .read()?
+            .expect("has default");
+        let offline = get(config, "trim.offline")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.offline())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let detect_remote_protection = get(config, "trim.detectRemoteProtection")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.detect_remote_protection())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let detection_strategies = get_comma_separated_multi(config, "trim.detectionStrategies")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.detection_strategies.clone()))
+            .with_default(MergeStrategy::defaults())
+            .parses_and_collect::<Vec<MergeStrategy>>()?;
+        // `trim.mergeDetection` is a deprecated name of `trim.detectionStrategies`.
+        let detection_strategies = if detection_strategies.is_implicit() {
+            get_comma_separated_multi(config, "trim.mergeDetection")
+                .with_profile(profile)
+                .with_overrides(&overrides)
+                .with_default(MergeStrategy::defaults())
+                .parses_and_collect::<Vec<MergeStrategy>>()?
+        } else {
+            detection_strategies
+        };
+        let partial_clone = get(config, "trim.partialClone")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.partial_clone())
+            .with_default(has_promisor_remote(config)?)
+            .read()?
+            .expect("has default");
+        let write_commit_graph = get(config, "trim.writeCommitGraph")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.write_commit_graph())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let reachability_index = get(config, "trim.reachabilityIndex")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.reachability_index())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let cache = get(config, "trim.cache")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.cache())
+            .with_default(true)
+            .read()?
+            .expect("has default");
+        let recent_checkout_days = get(config, "trim.recentCheckoutDays")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.recent_checkout_days)
+            .with_default(0)
+            .read()?
+            .expect("has default");
+        let stray_grace_days = get(config, "trim.strayGraceDays")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.stray_grace_days)
+            .with_default(0)
+            .read()?
+            .expect("has default");
         let confirm = get(config, "trim.confirm")
+            .with_profile(profile)
+            .with_overrides(&overrides)
             .with_explicit(args.confirm())
             .with_default(true)
             .read()?
             .expect("has default");
+        let confirm_diverged = get(config, "trim.confirmDiverged")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.confirm_diverged())
+            .with_default(true)
+            .read()?
+            .expect("has default");
+        let confirm_remotes = get(config, "trim.confirmRemotes")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.confirm_remotes())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let tui = get(config, "trim.tui")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.tui())
+            .with_default(false)
+            .read()?
+            .expect("has default");
         let detach = get(config, "trim.detach")
+            .with_profile(profile)
+            .with_overrides(&overrides)
             .with_explicit(args.detach())
             .with_default(true)
             .read()?
             .expect("has default");
+        let detach_to = get(config, "trim.detachTo")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.detach_to.clone())
+            .with_default("HEAD".to_owned())
+            .read()?
+            .expect("has default");
+        let switch_to_base_after_delete = get(config, "trim.switchToBaseAfterDelete")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.switch_to_base_after_delete())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let scan_push_remote = get(config, "trim.scanPushRemote")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.scan_push_remote())
+            .with_default(*triangular)
+            .read()?
+            .expect("has default");
+        let keep_config = get(config, "trim.keepConfig")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.keep_config())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let fix_upstreams = get(config, "trim.fixUpstreams")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.fix_upstreams())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let fix_gone = get(config, "trim.fixGone")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.fix_gone)
+            .with_default(FixGone::Keep)
+            .read()?
+            .expect("has default");
+        let retarget_renamed = get(config, "trim.retargetRenamed")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.retarget_renamed())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let retarget_stacks = get(config, "trim.retargetStacks")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.retarget_stacks())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let prune_removed_remotes = get(config, "trim.pruneRemovedRemotes")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.prune_removed_remotes())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let preserve_signed_by_others = get(config, "trim.preserveSignedByOthers")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.preserve_signed_by_others())
+            .with_default(false)
+            .read()?
+            .expect("has default");
+        let signing_keys = get_comma_separated_multi(config, "trim.signingKeys")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(non_empty(args.signing_keys.clone()))
+            .with_default(get_user_signing_key(config)?)
+            .parses_and_collect::<Vec<String>>()?;
+        let only_mine = get(config, "trim.onlyMine")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.only_mine())
+            .with_default(false)
+            .read()?
+            .expect("has default");
         let delete = get_comma_separated_multi(config, "trim.delete")
+            .with_profile(profile)
+            .with_overrides(&overrides)
             .with_explicit(non_empty(args.delete.clone()))
-            .with_default(DeleteRange::merged_origin())
+            .with_default(match fork {
+                _ if local_only => vec![DeleteRange::MergedLocal, DeleteRange::Local],
+                Some(fork) => DeleteRange::merged_local_and_remote(fork),
+                None => DeleteRange::merged_origin(),
+            })
             .parses_and_collect::<DeleteFilter>()?;
+        let max_delete = get(config, "trim.maxDelete")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.max_delete)
+            .with_default(0)
+            .read()?
+            .expect("has default");
+        let delete_chunk_size = get(config, "trim.deleteChunkSize")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.delete_chunk_size)
+            .with_default(500)
+            .read()?
+            .expect("has default");
+        let delete_rate_limit = get(config, "trim.deleteRateLimit")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.delete_rate_limit)
+            .with_default(0)
+            .read()?
+            .expect("has default");
+        let gc_after = get(config, "trim.gcAfter")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.gc_after)
+            .with_default(0)
+            .read()?
+            .expect("has default");
+        let summary = get(config, "trim.summary")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.summary)
+            .with_default(SummaryFormat::Full)
+            .read()?
+            .expect("has default");
+        let sort = get(config, "trim.sort")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.sort)
+            .with_default(SortKey::Name)
+            .read()?
+            .expect("has default");
+        let limit = get(config, "trim.limit")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.limit)
+            .with_default(0)
+            .read()?
+            .expect("has default");
+        let jobs = get(config, "trim.jobs")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.jobs)
+            .with_default(0)
+            .read()?
+            .expect("has default");
+        let details = get(config, "trim.details")
+            .with_profile(profile)
+            .with_overrides(&overrides)
+            .with_explicit(args.details())
+            .with_default(false)
+            .read()?
+            .expect("has default");
 
         Ok(Config {
+            triangular,
             bases,
+            base_commits,
+            detect_bases,
             protected,
+            builtin_protection,
+            protect_head_of_remotes,
+            include_slash_prefixes,
+            scan,
+            hints,
+            remote_namespaces,
+            pull_ref_namespaces,
             update,
             update_interval,
+            ff_bases,
+            remote_timeout,
+            offline,
+            detect_remote_protection,
+            recent_checkout_days,
+            stray_grace_days,
+            detection_strategies,
+            partial_clone,
+            write_commit_graph,
+            reachability_index,
+            cache,
             confirm,
+            confirm_diverged,
+            confirm_remotes,
+            tui,
             detach,
+            detach_to,
+            switch_to_base_after_delete,
+            scan_push_remote,
+            keep_config,
+            fix_upstreams,
+            fix_gone,
+            retarget_renamed,
+            retarget_stacks,
+            prune_removed_remotes,
+            preserve_signed_by_others,
+            signing_keys,
+            only_mine,
             delete,
+            max_delete,
+            delete_chunk_size,
+            delete_rate_limit,
+            gc_after,
+            summary,
+            sort,
+            limit,
+            jobs,
+            details,
         })
     }
+
+    /// Detection strategies to run. Ones that read blobs are skipped for partial clones.
+    pub fn merge_strategies(&self) -> Vec<MergeStrategy> {
+        self.detection_strategies
+            .iter()
+            .filter(|strategy| !*self.partial_clone || !strategy.reads_blobs())
+            .copied()
+            .collect()
+    }
+
+    pub fn delete_pacing(&self) -> DeletePacing {
+        DeletePacing {
+            chunk_size: *self.delete_chunk_size,
+            rate_limit: *self.delete_rate_limit,
+        }
+    }
 }
 
-fn get_branches_tracks_remote_heads(repo: &Repository, config: &GitConfig) -> Result<Vec<String>> {
-    let mut local_bases = Vec::new();
-    let mut all_bases = Vec::new();
+/// `git clone --filter` sets `remote.<name>.promisor` or `extensions.partialClone`.
+fn has_promisor_remote(config: &GitConfig) -> Result<bool> {
+    if get::<String>(config, "extensions.partialClone")
+        .read()?
+        .is_some()
+    {
+        return Ok(true);
+    }
+    let mut entries = config.entries(Some(r"^remote\..*\.promisor$"))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        if let Some(name) = entry.name() {
+            if config.get_bool(name).unwrap_or(false) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// You push to `remote.pushDefault`, and there is another remote to fetch merged branches from.
+fn is_triangular(repo: &Repository, push_default: Option<&str>) -> Result<bool> {
+    let push_default = match push_default {
+        Some(push_default) => push_default,
+        None => return Ok(false),
+    };
+    Ok(repo
+        .remotes()?
+        .iter()
+        .flatten()
+        .any(|remote| remote != push_default))
+}
 
+/// HEADs of `excluded_remote` are ignored. It is your fork in triangular workflows.
+fn get_branches_tracks_remote_heads(
+    repo: &Repository,
+    config: &GitConfig,
+    excluded_remote: Option<&str>,
+) -> Result<Vec<String>> {
+    let mut remote_heads = Vec::new();
     for reference in repo.references_glob("refs/remotes/*/HEAD")? {
         let reference = reference?;
+        if let Some(excluded_remote) = excluded_remote {
+            if reference.name() == Some(&format!("refs/remotes/{}/HEAD", excluded_remote)) {
+                continue;
+            }
+        }
         // git symbolic-ref refs/remotes/*/HEAD
         let resolved = match reference.resolve() {
             Ok(resolved) => resolved,
@@ -97,18 +623,42 @@ fn get_branches_tracks_remote_heads(repo: &Repository, config: &GitConfig) -> Re
             }
         };
         let refname = resolved.name().context("non utf-8 reference name")?;
-        all_bases.push(refname.to_owned());
+        remote_heads.push(refname.to_owned());
+    }
+    get_bases_tracking(repo, config, &remote_heads)
+}
 
-        for branch in repo.branches(Some(BranchType::Local))? {
-            let (branch, _) = branch?;
-            let branch = LocalBranch::try_from(&branch)?;
+/// `init.defaultBranch`, `main`, or `master`, whichever exists first.
+fn get_local_default_branch(repo: &Repository, config: &GitConfig) -> Result<Option<String>> {
+    let init_default = get::<String>(config, "init.defaultBranch")
+        .read()?
+        .map(ConfigValue::unwrap);
+    let candidates = init_default
+        .iter()
+        .map(String::as_str)
+        .chain(["main", "master"]);
+    for candidate in candidates {
+        if repo.find_branch(candidate, BranchType::Local).is_ok() {
+            return Ok(Some(candidate.to_owned()));
+        }
+    }
+    Ok(None)
+}
 
-            if let RemoteTrackingBranchStatus::Exists(upstream) =
-                branch.fetch_upstream(repo, config)?
-            {
-                if upstream.refname == refname {
-                    local_bases.push(branch.short_name().to_owned());
-                }
+/// Local branches that track any of `remote_heads`, or `remote_heads` themselves if there aren't any.
+pub fn get_bases_tracking(
+    repo: &Repository,
+    config: &GitConfig,
+    remote_heads: &[String],
+) -> Result<Vec<String>> {
+    let mut local_bases = Vec::new();
+    let all_bases = remote_heads.to_vec();
+    for branch in utf8_branches(repo, BranchType::Local)? {
+        let branch = LocalBranch::try_from(&branch)?;
+
+        if let RemoteTrackingBranchStatus::Exists(upstream) = branch.fetch_upstream(repo, config)? {
+            if remote_heads.contains(&upstream.refname) {
+                local_bases.push(branch.short_name().to_owned());
             }
         }
     }
@@ -120,6 +670,42 @@ fn get_branches_tracks_remote_heads(repo: &Repository, config: &GitConfig) -> Re
     }
 }
 
+/// `user.signingKey` can be a path to an SSH public key file.
+fn get_user_signing_key(config: &GitConfig) -> Result<Vec<String>> {
+    let key = match get::<String>(config, "user.signingKey").read()? {
+        Some(key) => key.unwrap(),
+        None => return Ok(Vec::new()),
+    };
+    let path = match (key.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(stripped), Some(home)) => std::path::Path::new(&home).join(stripped),
+        _ => std::path::PathBuf::from(&key),
+    };
+    if !key.starts_with("key::") && path.is_file() {
+        let content =
+            std::fs::read_to_string(&path).with_context(|| format!("user.signingKey={}", key))?;
+        return Ok(content
+            .lines()
+            .next()
+            .map(str::to_owned)
+            .into_iter()
+            .collect());
+    }
+    Ok(vec![key])
+}
+
+/// Well-known deployment branches that are protected unless `trim.builtinProtection` is off.
+/// `*/` matches remote tracking branches, e.g. `refs/remotes/origin/gh-pages`.
+pub const BUILTIN_PROTECTED: &[&str] = &["gh-pages", "*/gh-pages"];
+
+fn with_builtin_protected(mut patterns: Vec<String>) -> Vec<String> {
+    for builtin in BUILTIN_PROTECTED {
+        if !patterns.iter().any(|pattern| pattern == builtin) {
+            patterns.push((*builtin).to_owned());
+        }
+    }
+    patterns
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum ConfigValue<T> {
     Explicit(T),
@@ -134,6 +720,14 @@ impl<T> ConfigValue<T> {
         }
     }
 
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> ConfigValue<U> {
+        match self {
+            ConfigValue::Explicit(x) => ConfigValue::Explicit(f(x)),
+            ConfigValue::GitConfig(x) => ConfigValue::GitConfig(f(x)),
+            ConfigValue::Implicit(x) => ConfigValue::Implicit(f(x)),
+        }
+    }
+
     pub fn is_implicit(&self) -> bool {
         match self {
             ConfigValue::Explicit(_) => false,
@@ -156,6 +750,8 @@ impl<T> Deref for ConfigValue<T> {
 pub struct ConfigBuilder<'a, T> {
     config: &'a GitConfig,
     key: &'a str,
+    profile: Option<&'a str>,
+    overrides: Option<&'a ConfigOverrides>,
     explicit: Option<T>,
     default: Option<T>,
     comma_separated: bool,
@@ -165,6 +761,8 @@ pub fn get<'a, T>(config: &'a GitConfig, key: &'a str) -> ConfigBuilder<'a, T> {
     ConfigBuilder {
         config,
         key,
+        profile: None,
+        overrides: None,
         explicit: None,
         default: None,
         comma_separated: false,
@@ -178,6 +776,8 @@ pub fn get_comma_separated_multi<'a, T>(
     ConfigBuilder {
         config,
         key,
+        profile: None,
+        overrides: None,
         explicit: None,
         default: None,
         comma_separated: true,
@@ -202,6 +802,87 @@ impl<'a, T> ConfigBuilder<'a, T> {
             ..self
         }
     }
+
+    /// Read `trim.profile.<profile>.<name>` before `trim.<name>`.
+    pub fn with_profile(self, profile: Option<&'a str>) -> ConfigBuilder<'a, T> {
+        ConfigBuilder { profile, ..self }
+    }
+
+    /// Read `--config <key>=<value>` before the profile and git configs.
+    pub fn with_overrides(self, overrides: &'a ConfigOverrides) -> ConfigBuilder<'a, T> {
+        ConfigBuilder {
+            overrides: Some(overrides),
+            ..self
+        }
+    }
+
+    fn overridden(&self) -> Vec<&'a str> {
+        self.overrides
+            .map(|overrides| overrides.values(self.key))
+            .unwrap_or_default()
+    }
+
+    fn profile_key(&self) -> Option<String> {
+        let profile = self.profile?;
+        let name = self.key.strip_prefix("trim.")?;
+        Some(profile_key(profile, name))
+    }
+}
+
+/// Config values given by `--config <key>=<value>`.
+/// They take precedence over `trim.profile.<name>.*` and `trim.*` configs, but not over dedicated flags.
+#[derive(Debug, Default)]
+pub struct ConfigOverrides {
+    /// Normalized keys and their values in the given order.
+    entries: Vec<(String, String)>,
+}
+
+impl ConfigOverrides {
+    pub fn parse(overrides: &[String]) -> Result<Self> {
+        let mut entries = Vec::new();
+        for entry in overrides {
+            // `git -c <key>` without a value sets it to true.
+            let (key, value) = entry.split_once('=').unwrap_or((entry, "true"));
+            let key = normalize_key(key);
+            if key.strip_prefix("trim.").map_or(true, str::is_empty) {
+                return Err(anyhow::anyhow!(
+                    "`--config {}` is not a `trim.*` config",
+                    entry
+                ));
+            }
+            entries.push((key, value.to_owned()));
+        }
+        Ok(ConfigOverrides { entries })
+    }
+
+    fn values(&self, key: &str) -> Vec<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .filter(|(overridden, _)| *overridden == key)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+}
+
+fn profile_key(profile: &str, name: &str) -> String {
+    format!("trim.profile.{}.{}", profile, name)
+}
+
+fn profile_exists(config: &GitConfig, profile: &str) -> Result<bool> {
+    // Section and variable names are normalized to lowercase, but the profile name is not.
+    let prefix = format!("trim.profile.{}.", profile);
+    let mut entries = config.entries(None)?;
+    while let Some(entry) = entries.next() {
+        if let Some(name) = entry?.name() {
+            if let Some(variable) = name.strip_prefix(&prefix) {
+                if !variable.contains('.') {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
 }
 
 impl<T> ConfigBuilder<'_, T>
@@ -212,6 +893,18 @@ where
         if let Some(value) = self.explicit {
             return Ok(Some(ConfigValue::Explicit(value)));
         }
+        let overridden = self.overridden();
+        if !overridden.is_empty() {
+            let value = T::parse_config_values(self.key, &overridden)?;
+            return Ok(Some(ConfigValue::Explicit(value)));
+        }
+        if let Some(profile_key) = self.profile_key() {
+            match T::get_config_value(self.config, &profile_key) {
+                Ok(value) => return Ok(Some(ConfigValue::GitConfig(value))),
+                Err(err) if config_not_exist(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
         match T::get_config_value(self.config, self.key) {
             Ok(value) => Ok(Some(ConfigValue::GitConfig(value))),
             Err(err) if config_not_exist(&err) => {
@@ -238,35 +931,45 @@ impl<T> ConfigBuilder<'_, T> {
             return Ok(ConfigValue::Explicit(value.into_iter().collect()));
         }
 
-        let result = match Vec::<String>::get_config_value(self.config, self.key) {
-            Ok(entries) if !entries.is_empty() => {
-                let mut result = Vec::new();
-                if self.comma_separated {
-                    for entry in entries {
-                        for item in entry.split(',') {
-                            if !item.is_empty() {
-                                let value = <T::Item>::from_str(item)?;
-                                result.push(value);
-                            }
+        let overridden = self.overridden();
+        let mut entries = Vec::new();
+        if overridden.is_empty() {
+            if let Some(profile_key) = self.profile_key() {
+                entries = Vec::<String>::get_config_value(self.config, &profile_key)?;
+            }
+            if entries.is_empty() {
+                entries = Vec::<String>::get_config_value(self.config, self.key)?;
+            }
+        } else {
+            entries = overridden.iter().map(|value| (*value).to_owned()).collect();
+        }
+        let result = if !entries.is_empty() {
+            let mut result = Vec::new();
+            if self.comma_separated {
+                for entry in entries {
+                    for item in entry.split(',') {
+                        if !item.is_empty() {
+                            let value = <T::Item>::from_str(item)?;
+                            result.push(value);
                         }
                     }
-                } else {
-                    for entry in entries {
-                        let value = <T::Item>::from_str(&entry)?;
-                        result.push(value);
-                    }
                 }
+            } else {
+                for entry in entries {
+                    let value = <T::Item>::from_str(&entry)?;
+                    result.push(value);
+                }
+            }
 
+            if overridden.is_empty() {
                 ConfigValue::GitConfig(result.into_iter().collect())
+            } else {
+                ConfigValue::Explicit(result.into_iter().collect())
             }
-            Ok(_) => {
-                if let Some(default) = self.default {
-                    ConfigValue::Implicit(default.into_iter().collect())
-                } else {
-                    ConfigValue::Implicit(U::default())
-                }
-            }
-            Err(err) => return Err(err.into()),
+        } else if let Some(default) = self.default {
+            ConfigValue::Implicit(default.into_iter().collect())
+        } else {
+            ConfigValue::Implicit(U::default())
         };
         Ok(result)
     }
@@ -276,18 +979,32 @@ pub trait ConfigValues {
     fn get_config_value(config: &GitConfig, key: &str) -> Result<Self, git2::Error>
     where
         Self: Sized;
+
+    /// Parse values given by `--config`. The last one wins unless the config has multiple values.
+    fn parse_config_values(key: &str, values: &[&str]) -> Result<Self, git2::Error>
+    where
+        Self: Sized;
+}
+
+fn last_value<'a>(values: &[&'a str]) -> &'a str {
+    values.last().copied().unwrap_or_default()
 }
 
 impl ConfigValues for String {
     fn get_config_value(config: &GitConfig, key: &str) -> Result<Self, git2::Error> {
         config.get_string(key)
     }
+
+    fn parse_config_values(_key: &str, values: &[&str]) -> Result<Self, git2::Error> {
+        Ok(last_value(values).to_owned())
+    }
 }
 
 impl ConfigValues for Vec<String> {
     fn get_config_value(config: &GitConfig, key: &str) -> Result<Self, git2::Error> {
         let mut result = Vec::new();
-        let mut entries = config.entries(Some(key))?;
+        // Entries are matched against normalized names, while `get_*` normalizes given keys.
+        let mut entries = config.entries(Some(&normalize_key(key)))?;
         while let Some(entry) = entries.next() {
             let entry = entry?;
             if let Some(value) = entry.value() {
@@ -301,12 +1018,20 @@ impl ConfigValues for Vec<String> {
         }
         Ok(result)
     }
+
+    fn parse_config_values(_key: &str, values: &[&str]) -> Result<Self, git2::Error> {
+        Ok(values.iter().map(|value| (*value).to_owned()).collect())
+    }
 }
 
 impl ConfigValues for bool {
     fn get_config_value(config: &GitConfig, key: &str) -> Result<Self, git2::Error> {
         config.get_bool(key)
     }
+
+    fn parse_config_values(_key: &str, values: &[&str]) -> Result<Self, git2::Error> {
+        GitConfig::parse_bool(last_value(values))
+    }
 }
 
 impl ConfigValues for u64 {
@@ -317,6 +1042,65 @@ impl ConfigValues for u64 {
         }
         panic!("`git config {}` cannot be negative value", key);
     }
+
+    fn parse_config_values(key: &str, values: &[&str]) -> Result<Self, git2::Error> {
+        let value = GitConfig::parse_i64(last_value(values))?;
+        u64::try_from(value).map_err(|_| {
+            git2::Error::from_str(&format!("`--config {}` cannot be negative value", key))
+        })
+    }
+}
+
+fn parse_value_enum<T: clap::ValueEnum>(key: &str, value: &str) -> Result<T, git2::Error> {
+    T::from_str(value, true).map_err(|_| {
+        git2::Error::from_str(&format!(
+            "`git config {}` has invalid value `{}`",
+            key, value
+        ))
+    })
+}
+
+impl ConfigValues for SummaryFormat {
+    fn get_config_value(config: &GitConfig, key: &str) -> Result<Self, git2::Error> {
+        parse_value_enum(key, &config.get_string(key)?)
+    }
+
+    fn parse_config_values(key: &str, values: &[&str]) -> Result<Self, git2::Error> {
+        parse_value_enum(key, last_value(values))
+    }
+}
+
+impl ConfigValues for FixGone {
+    fn get_config_value(config: &GitConfig, key: &str) -> Result<Self, git2::Error> {
+        parse_value_enum(key, &config.get_string(key)?)
+    }
+
+    fn parse_config_values(key: &str, values: &[&str]) -> Result<Self, git2::Error> {
+        parse_value_enum(key, last_value(values))
+    }
+}
+
+impl ConfigValues for SortKey {
+    fn get_config_value(config: &GitConfig, key: &str) -> Result<Self, git2::Error> {
+        parse_value_enum(key, &config.get_string(key)?)
+    }
+
+    fn parse_config_values(key: &str, values: &[&str]) -> Result<Self, git2::Error> {
+        parse_value_enum(key, last_value(values))
+    }
+}
+
+/// Section names and variable names are case-insensitive, but subsection names are not.
+fn normalize_key(key: &str) -> String {
+    match (key.find('.'), key.rfind('.')) {
+        (Some(first), Some(last)) => format!(
+            "{}{}{}",
+            key[..first].to_lowercase(),
+            &key[first..last],
+            key[last..].to_lowercase()
+        ),
+        _ => key.to_lowercase(),
+    }
 }
 
 fn config_not_exist(err: &git2::Error) -> bool {
@@ -336,6 +1120,27 @@ pub fn get_push_remote(config: &GitConfig, branch: &LocalBranch) -> Result<Strin
     Ok(get_remote_name(config, branch)?.unwrap_or_else(|| "origin".to_owned()))
 }
 
+/// Get the remote refname that `git push` pushes the branch to, according to `push.default`.
+/// It is `None` when `git push` doesn't push the branch without an explicit refspec.
+pub fn get_push_refname(config: &GitConfig, branch: &LocalBranch) -> Result<Option<String>> {
+    let push_default = get::<String>(config, "push.default")
+        .with_default("simple".to_owned())
+        .read()?
+        .expect("has default");
+    match push_default.as_str() {
+        "nothing" => Ok(None),
+        // `upstream` refuses to push when the branch doesn't have an upstream.
+        "upstream" | "tracking" => get_merge(config, branch),
+        // `matching` pushes to the ref with the same name if it exists on the remote.
+        "simple" | "current" | "matching" => Ok(Some(branch.refname.clone())),
+        _ => Err(InvalidConfig {
+            key: "push.default".to_owned(),
+            value: push_default.to_string(),
+        }
+        .into()),
+    }
+}
+
 pub fn get_remote_name(config: &GitConfig, branch: &LocalBranch) -> Result<Option<String>> {
     let key = format!("branch.{}.remote", branch.short_name());
     match config.get_string(&key) {
@@ -347,7 +1152,9 @@ pub fn get_remote_name(config: &GitConfig, branch: &LocalBranch) -> Result<Optio
 
 pub fn get_remote<'a>(repo: &'a Repository, remote_name: &str) -> Result<Option<Remote<'a>>> {
     fn error_is_missing_remote(err: &Error) -> bool {
-        err.class() == ErrorClass::Config && err.code() == ErrorCode::InvalidSpec
+        // An invalid remote name is a URL or a path of direct fetched branches.
+        err.class() == ErrorClass::Config
+            && matches!(err.code(), ErrorCode::InvalidSpec | ErrorCode::NotFound)
     }
 
     match repo.find_remote(remote_name) {
@@ -357,6 +1164,81 @@ pub fn get_remote<'a>(repo: &'a Repository, remote_name: &str) -> Result<Option<
     }
 }
 
+fn get_remote_bool(config: &GitConfig, remote_name: &str, key: &str) -> Result<bool> {
+    match config.get_bool(&format!("remote.{}.{}", remote_name, key)) {
+        Ok(value) => Ok(value),
+        Err(err) if config_not_exist(&err) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Remotes that `git fetch --all` skips: `remote.<name>.skipFetchAll`.
+/// `remote.<name>.skipDefaultUpdate` is also respected as `git remote update` does.
+pub fn get_remotes_to_update(repo: &Repository, config: &GitConfig) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    for remote_name in repo.remotes()?.iter().flatten() {
+        if get_remote_bool(config, remote_name, "skipFetchAll")?
+            || get_remote_bool(config, remote_name, "skipDefaultUpdate")?
+        {
+            continue;
+        }
+        result.push(remote_name.to_owned());
+    }
+    Ok(result)
+}
+
+/// `git remote add --mirror=push` sets `remote.<name>.mirror`.
+/// Refs under `refs/remotes/<name>/` are not what git pushes to the remote.
+pub fn is_mirror_remote(config: &GitConfig, remote_name: &str) -> Result<bool> {
+    get_remote_bool(config, remote_name, "mirror")
+}
+
+/// `trim.libgit2LsRemote` lists remote refs with libgit2 instead of `git ls-remote`,
+/// for when `git` is built with a TLS library that can't reach the remote.
+pub fn use_libgit2_ls_remote(config: &GitConfig) -> Result<bool> {
+    match config.get_bool("trim.libgit2LsRemote") {
+        Ok(value) => Ok(value),
+        Err(err) if config_not_exist(&err) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Get the URL of the remote. The remote of a direct fetch branch is already a URL.
+pub fn get_remote_url(repo: &Repository, remote_name: &str) -> Result<Option<String>> {
+    match get_remote(repo, remote_name)? {
+        Some(remote) => Ok(remote.url().map(str::to_owned)),
+        None => Ok(Some(remote_name.to_owned())),
+    }
+}
+
+/// Patterns of branches to protect, one per line, in `$GIT_DIR/trim-ignore`
+/// and in `.trim-ignore` at the top of the worktree, which can be committed.
+/// Blank lines and lines starting with `#` are ignored, as in `.gitignore`.
+pub fn read_trim_ignore(repo: &Repository) -> Result<Vec<String>> {
+    let mut paths = vec![common_dir(repo).join("trim-ignore")];
+    if let Some(workdir) = repo.workdir() {
+        paths.push(workdir.join(".trim-ignore"));
+    }
+    let mut result = Vec::new();
+    for path in paths {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        result.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_owned),
+        );
+    }
+    Ok(result)
+}
+
 pub fn get_merge(config: &GitConfig, branch: &LocalBranch) -> Result<Option<String>> {
     let key = format!("branch.{}.merge", branch.short_name());
     match config.get_string(&key) {
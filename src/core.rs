@@ -1,19 +1,31 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
 use crossbeam_channel::unbounded;
-use git2::{BranchType, Config, Repository};
+use git2::{BranchType, Config, Direction, ErrorCode, Repository};
 use log::*;
 use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
-use crate::args::DeleteFilter;
+use crate::args::{DeleteFilter, DeleteRange, Scope, SortKey};
 use crate::branch::{
-    LocalBranch, Refname, RemoteBranch, RemoteTrackingBranch, RemoteTrackingBranchStatus,
+    utf8_branches, BaseRef, LocalBranch, Refname, RemoteBranch, RemoteBranchError,
+    RemoteOnlyBranch, RemoteTrackingBranch, RemoteTrackingBranchStatus,
 };
-use crate::merge_tracker::MergeTracker;
-use crate::subprocess::{self, get_worktrees, RemoteHead};
+use crate::error::InvalidConfig;
+use crate::hosting::Hosting;
+use crate::merge_tracker::{MergeMetrics, MergeState, MergeTracker};
+use crate::report::Classification;
+use crate::signature::Signer;
+use crate::simple_glob::{expand, glob_match, refspec_pairs};
+use crate::stack::Stacks;
+use crate::subprocess::{self, get_worktrees, is_timeout, RemoteHead};
+use crate::timings::Timings;
 use crate::util::ForceSendSync;
 use crate::{config, BaseSpec, Git};
 
@@ -21,6 +33,88 @@ pub struct TrimPlan {
     pub skipped: HashMap<String, SkipSuggestion>,
     pub to_delete: HashSet<ClassifiedBranch>,
     pub preserved: Vec<Preserved>,
+    pub classifications: Vec<ClassificationResponse>,
+    /// Local branches that track removed remotes, and are not going to be deleted.
+    pub broken_upstreams: Vec<BrokenUpstream>,
+    /// Remote tracking branches of removed remotes.
+    pub orphan_namespaces: Vec<OrphanNamespace>,
+    /// Stray local branches whose upstreams seem to be renamed. They are not going to be deleted.
+    pub renamed_upstreams: Vec<RenamedUpstream>,
+    /// Local branches whose upstreams are gone, and are not going to be deleted. Renamed ones are not included.
+    pub gone_upstreams: Vec<LocalBranch>,
+    /// Commits of branches to delete and their remote tracking branches by refnames, when it is planned.
+    /// Branches that are moved after that are skipped.
+    /// Remote branches without remote tracking branches are keyed by `<remote>:<refname>`.
+    pub tips: HashMap<String, String>,
+    /// Refnames that are not valid UTF-8, lossily converted. They are skipped, not aborting the run.
+    pub non_utf8_refnames: Vec<String>,
+    /// Remotes that didn't respond in time, or weren't contacted in offline mode.
+    /// Branches that need them to be classified are kept.
+    pub unreachable_remotes: Vec<String>,
+    /// Remotes weren't contacted, so remote tracking branches might be stale.
+    pub offline: bool,
+    /// How many branches are classified without the detection strategies.
+    pub merge_metrics: MergeMetrics,
+    /// Time spent in each phase of planning.
+    pub timings: Timings,
+    /// Classifications are reused from the last plan, since nothing has changed since then.
+    pub cached: bool,
+    /// Bases that are not found. The others are used.
+    pub unresolved_bases: Vec<UnresolvedBase>,
+}
+
+/// A local branch whose `branch.<name>.remote` is a remote that doesn't exist anymore.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BrokenUpstream {
+    pub local: LocalBranch,
+    pub remote: String,
+}
+
+/// A base that doesn't resolve to any ref. It is ignored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnresolvedBase {
+    pub base: String,
+    /// Refnames or patterns that are tried in order.
+    pub candidates: Vec<String>,
+}
+
+/// A local branch whose upstream is gone, but another branch of the same remote has the same tip.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RenamedUpstream {
+    pub local: LocalBranch,
+    /// The remote tracking branch of the new name
+    pub upstream: RemoteTrackingBranch,
+    pub remote_branch: RemoteBranch,
+}
+
+/// `refs/remotes/<remote>/*` of a remote that doesn't exist anymore.
+/// `git remote update --prune` never prunes them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OrphanNamespace {
+    pub remote: String,
+    pub refnames: Vec<String>,
+}
+
+/// The remote branch of `branch.<name>.remote` and `branch.<name>.merge`.
+pub(crate) fn upstream_remote_branch(
+    config: &Config,
+    local: &LocalBranch,
+) -> Result<Option<RemoteBranch>> {
+    match (
+        config::get_remote_name(config, local)?,
+        config::get_merge(config, local)?,
+    ) {
+        (Some(remote), Some(refname)) => Ok(Some(RemoteBranch { remote, refname })),
+        _ => Ok(None),
+    }
+}
+
+/// A local branch and its remote branch that are both going to be deleted.
+/// They are deleted together or neither of them is, so that a half of it isn't left alone.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BranchPair {
+    pub local: LocalBranch,
+    pub remote: RemoteBranch,
 }
 
 pub struct Preserved {
@@ -29,6 +123,61 @@ pub struct Preserved {
     pub base: bool,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum BranchRole {
+    Local,
+    Remote,
+}
+
+/// A side of a classified branch, so that it can be inspected without matching `ClassifiedBranch`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PlannedBranch {
+    /// A local branch or a remote tracking branch.
+    /// It is a branch on `remote` if there is no remote tracking branch, e.g. a direct fetch branch.
+    pub refname: String,
+    /// The remote of `refname` if it is a branch on the remote.
+    pub remote: Option<String>,
+    pub role: BranchRole,
+    pub classification: Classification,
+    /// Why it is kept. It is going to be deleted if it is `None`.
+    pub preserved: Option<String>,
+}
+
+impl PlannedBranch {
+    fn sides(branch: &ClassifiedBranch, preserved: Option<&str>) -> Vec<PlannedBranch> {
+        let classification = Classification::from(branch);
+        let planned = |refname: &str, remote: Option<&str>, role| PlannedBranch {
+            refname: refname.to_owned(),
+            remote: remote.map(str::to_owned),
+            role,
+            classification,
+            preserved: preserved.map(str::to_owned),
+        };
+        let mut result = Vec::new();
+        if let Some(local) = branch.local() {
+            result.push(planned(&local.refname, None, BranchRole::Local));
+        }
+        let remote = match branch {
+            ClassifiedBranch::MergedDirectFetch { remote, .. }
+            | ClassifiedBranch::DivergedDirectFetch { remote, .. }
+            | ClassifiedBranch::DivergedRemote { remote, .. }
+            | ClassifiedBranch::MergedPushRemote { remote, .. }
+            | ClassifiedBranch::MergedRemoteOnly(RemoteOnlyBranch { remote, .. }) => Some(remote),
+            _ => None,
+        };
+        if let Some(upstream) = branch.upstream() {
+            result.push(planned(&upstream.refname, None, BranchRole::Remote));
+        } else if let Some(remote) = remote {
+            result.push(planned(
+                &remote.refname,
+                Some(&remote.remote),
+                BranchRole::Remote,
+            ));
+        }
+        result
+    }
+}
+
 impl TrimPlan {
     pub fn locals_to_delete(&self) -> Vec<&LocalBranch> {
         let mut result = Vec::new();
@@ -49,9 +198,220 @@ impl TrimPlan {
         }
         Ok(result)
     }
+
+    /// Remote tracking refs of pull requests to delete locally.
+    pub fn pull_refs_to_delete(&self) -> Vec<&RemoteTrackingBranch> {
+        let mut result = Vec::new();
+        for branch in &self.to_delete {
+            if let ClassifiedBranch::MergedPullRef {
+                remote_tracking, ..
+            } = branch
+            {
+                result.push(remote_tracking);
+            }
+        }
+        result
+    }
+
+    /// Local branches to delete, paired with their remote branches to delete.
+    /// A merged local branch and its merged upstream are classified separately, but they are paired.
+    pub fn pairs_to_delete(&self, repo: &Repository, config: &Config) -> Result<Vec<BranchPair>> {
+        let remotes: HashSet<_> = self.remotes_to_delete(repo)?.into_iter().collect();
+        let mut result = Vec::new();
+        for branch in &self.to_delete {
+            let local = match branch.local() {
+                Some(local) => local,
+                None => continue,
+            };
+            let remote = match branch.remote(repo)? {
+                Some(remote) => remote,
+                None => match upstream_remote_branch(config, local)? {
+                    Some(remote) => remote,
+                    None => continue,
+                },
+            };
+            if remotes.contains(&remote) {
+                result.push(BranchPair {
+                    local: local.clone(),
+                    remote,
+                });
+            }
+        }
+        result.sort_by(|a, b| a.local.cmp(&b.local));
+        Ok(result)
+    }
+
+    /// Branches to delete in the order of `key`. Ties are broken by names.
+    pub fn sorted_to_delete(
+        &self,
+        repo: &Repository,
+        key: SortKey,
+    ) -> Result<Vec<&ClassifiedBranch>> {
+        let mut keyed = Vec::new();
+        for branch in &self.to_delete {
+            let (remote, name) = get_remote_and_name(repo, branch)?;
+            let sort_key = match key {
+                SortKey::Age => (get_tip_time(repo, branch)?, String::new(), name),
+                SortKey::Name => (0, String::new(), name),
+                SortKey::Remote => (0, remote, name),
+            };
+            keyed.push((sort_key, branch));
+        }
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(keyed.into_iter().map(|(_, branch)| branch).collect())
+    }
 }
 
 impl TrimPlan {
+    /// Merged local branches might be left on their push remotes even if their upstreams are gone.
+    /// For example, when `git fetch --prune` already pruned the remote tracking branches.
+    pub(crate) fn classify_push_remotes(
+        &mut self,
+        git: &Git,
+        filter: &DeleteFilter,
+        remote_timeout: Duration,
+        progress: &ProgressFn,
+    ) -> Result<()> {
+        let mut candidates = Vec::new();
+        for branch in &self.to_delete {
+            let local = if let ClassifiedBranch::MergedLocal(local) = branch {
+                local
+            } else {
+                continue;
+            };
+            if let RemoteTrackingBranchStatus::Exists(_) =
+                local.fetch_upstream(&git.repo, &git.config)?
+            {
+                continue;
+            }
+
+            let remote = config::get_push_remote(&git.config, local)?;
+            let url = match config::get_remote(&git.repo, &remote)? {
+                Some(remote) => remote.url().map(str::to_owned),
+                None => continue,
+            };
+            if !filter.delete_merged_remote(&remote, url.as_deref()) {
+                continue;
+            }
+            let refname = match config::get_push_refname(&git.config, local)? {
+                Some(refname) => refname,
+                None => continue,
+            };
+            let remote = RemoteBranch { remote, refname };
+            if let RemoteTrackingBranchStatus::Exists(_) =
+                RemoteTrackingBranch::from_remote_branch(&git.repo, &remote)?
+            {
+                continue;
+            }
+            candidates.push((local.clone(), remote));
+        }
+
+        if candidates.is_empty() {
+            return Ok(());
+        }
+        let remotes: Vec<_> = candidates
+            .iter()
+            .map(|(_, remote)| remote.clone())
+            .collect();
+        let remote_heads = get_remote_heads(git, &remotes, remote_timeout, progress)?;
+        self.add_unreachable_remotes(&remote_heads.unreachable);
+        for (local, remote) in candidates {
+            let commit = git
+                .repo
+                .find_reference(&local.refname)?
+                .peel_to_commit()?
+                .id()
+                .to_string();
+            let left_on_remote = remote_heads.heads.iter().any(|head| {
+                head.remote == remote.remote
+                    && head.refname == remote.refname
+                    && head.commit == commit
+            });
+            if left_on_remote {
+                trace!("push remote: {:?} -> {}", local, remote);
+                self.to_delete
+                    .remove(&ClassifiedBranch::MergedLocal(local.clone()));
+                self.to_delete
+                    .insert(ClassifiedBranch::MergedPushRemote { local, remote });
+            }
+        }
+        Ok(())
+    }
+
+    /// Each side of branches to delete and preserved ones.
+    /// Skipped branches are not included, since they are not classified.
+    pub fn iter_classified(&self) -> impl Iterator<Item = PlannedBranch> + '_ {
+        let to_delete = self.to_delete.iter().map(|branch| (branch, None));
+        let preserved = self
+            .preserved
+            .iter()
+            .map(|preserved| (&preserved.branch, Some(preserved.reason.as_str())));
+        to_delete
+            .chain(preserved)
+            .flat_map(|(branch, reason)| PlannedBranch::sides(branch, reason))
+    }
+
+    /// Delete ranges to add to scan all the skipped branches, sorted and deduplicated.
+    pub fn suggested_delete_ranges(&self) -> Vec<DeleteRange> {
+        let mut ranges: Vec<_> = self
+            .skipped
+            .values()
+            .map(SkipSuggestion::delete_range)
+            .collect();
+        ranges.sort_by_key(|range| range.to_string());
+        ranges.dedup();
+        ranges
+    }
+
+    /// Whether the branch is classified with remote tracking branches that might be stale.
+    pub fn possibly_stale(&self, branch: &ClassifiedBranch) -> bool {
+        self.offline && branch.by_remote_tracking()
+    }
+
+    pub(crate) fn add_unreachable_remotes(&mut self, remotes: &[String]) {
+        for remote in remotes {
+            if !self.unreachable_remotes.contains(remote) {
+                self.unreachable_remotes.push(remote.clone());
+            }
+        }
+    }
+
+    /// Merged local branches that track upstreams of bases, but are not bases themselves.
+    pub(crate) fn classify_duplicates_of_base(
+        &mut self,
+        repo: &Repository,
+        config: &Config,
+        base_specs: &[BaseSpec],
+    ) -> Result<()> {
+        let mut duplicates = Vec::new();
+        for branch in &self.to_delete {
+            let local = match branch {
+                ClassifiedBranch::MergedLocal(local) => local,
+                _ => continue,
+            };
+            if base_specs.iter().any(|spec| spec.is_local(local)) {
+                continue;
+            }
+            if let RemoteTrackingBranchStatus::Exists(upstream) =
+                local.fetch_upstream(repo, config)?
+            {
+                if base_specs
+                    .iter()
+                    .any(|spec| spec.covers_remote(upstream.refname()))
+                {
+                    duplicates.push((branch.clone(), local.clone(), upstream));
+                }
+            }
+        }
+
+        for (branch, local, base) in duplicates {
+            self.to_delete.remove(&branch);
+            self.to_delete
+                .insert(ClassifiedBranch::DuplicateOfBase { local, base });
+        }
+        Ok(())
+    }
+
     pub(crate) fn preserve_bases(
         &mut self,
         repo: &Repository,
@@ -97,7 +457,15 @@ impl TrimPlan {
                 | ClassifiedBranch::Stray(local)
                 | ClassifiedBranch::MergedDirectFetch { local, .. }
                 | ClassifiedBranch::DivergedDirectFetch { local, .. }
-                | ClassifiedBranch::MergedNonTrackingLocal(local) => {
+                | ClassifiedBranch::DivergedLocal { local, .. }
+                | ClassifiedBranch::DivergedRemote {
+                    local,
+                    upstream: None,
+                    ..
+                }
+                | ClassifiedBranch::MergedPushRemote { local, .. }
+                | ClassifiedBranch::MergedNonTrackingLocal(local)
+                | ClassifiedBranch::Unrelated(local) => {
                     if let Some(reason) = local_is_or_tracks_base(repo, config, base_specs, local)?
                     {
                         preserve.push(Preserved {
@@ -109,7 +477,15 @@ impl TrimPlan {
                     }
                 }
                 ClassifiedBranch::MergedRemoteTracking(upstream)
-                | ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream) => {
+                | ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream)
+                | ClassifiedBranch::DivergedRemote {
+                    upstream: Some(upstream),
+                    ..
+                }
+                | ClassifiedBranch::MergedPullRef {
+                    remote_tracking: upstream,
+                    ..
+                } => {
                     if base_specs
                         .iter()
                         .any(|spec| spec.covers_remote(upstream.refname()))
@@ -122,6 +498,21 @@ impl TrimPlan {
                         continue;
                     }
                 }
+                ClassifiedBranch::MergedRemoteOnly(remote_only) => {
+                    if base_specs
+                        .iter()
+                        .any(|spec| spec.covers_branch_of_remote(repo, &remote_only.remote))
+                    {
+                        preserve.push(Preserved {
+                            branch: branch.clone(),
+                            reason: "base".to_owned(),
+                            base: true,
+                        });
+                        continue;
+                    }
+                }
+                // It tracks a base by definition, but it is not a base itself.
+                ClassifiedBranch::DuplicateOfBase { .. } => {}
                 ClassifiedBranch::DivergedRemoteTracking { local, upstream } => {
                     if let Some(reason) = local_is_or_tracks_base(repo, config, base_specs, local)?
                     {
@@ -154,36 +545,166 @@ impl TrimPlan {
         Ok(())
     }
 
-    pub fn preserve_protected(
+    pub fn preserve_protected(&mut self, preserved_patterns: &[&str]) -> Result<()> {
+        let preserved_patterns = preserved_patterns
+            .iter()
+            .map(|pattern| ProtectPattern::parse(pattern))
+            .collect::<Result<Vec<_>>>()?;
+        let preserved_patterns = preserved_patterns.as_slice();
+        let mut preserve = Vec::new();
+        for branch in &self.to_delete {
+            let pattern = match &branch {
+                ClassifiedBranch::MergedLocal(local)
+                | ClassifiedBranch::Stray(local)
+                | ClassifiedBranch::MergedDirectFetch { local, .. }
+                | ClassifiedBranch::DivergedDirectFetch { local, .. }
+                | ClassifiedBranch::DivergedLocal { local, .. }
+                | ClassifiedBranch::DivergedRemote {
+                    local,
+                    upstream: None,
+                    ..
+                }
+                | ClassifiedBranch::MergedPushRemote { local, .. }
+                | ClassifiedBranch::MergedNonTrackingLocal(local)
+                | ClassifiedBranch::Unrelated(local)
+                | ClassifiedBranch::DuplicateOfBase { local, .. } => {
+                    get_protect_pattern(preserved_patterns, local)
+                }
+                ClassifiedBranch::MergedRemoteTracking(upstream)
+                | ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream)
+                | ClassifiedBranch::DivergedRemote {
+                    upstream: Some(upstream),
+                    ..
+                }
+                | ClassifiedBranch::MergedPullRef {
+                    remote_tracking: upstream,
+                    ..
+                } => get_protect_pattern(preserved_patterns, upstream),
+                ClassifiedBranch::DivergedRemoteTracking { local, upstream } => {
+                    get_protect_pattern(preserved_patterns, local)
+                        .or(get_protect_pattern(preserved_patterns, upstream))
+                }
+                ClassifiedBranch::MergedRemoteOnly(remote_only) => {
+                    get_remote_only_protect_pattern(preserved_patterns, &remote_only.remote)
+                }
+            };
+
+            if let Some(pattern) = pattern {
+                preserve.push(Preserved {
+                    branch: branch.clone(),
+                    reason: format!("protected by a pattern `{}`", pattern),
+                    base: false,
+                });
+            }
+        }
+
+        for preserved in &preserve {
+            self.to_delete.remove(&preserved.branch);
+        }
+        self.preserved.extend(preserve);
+
+        Ok(())
+    }
+
+    /// Preserve HEAD branches of remotes, and local branches that track them,
+    /// so that aggressive delete ranges like `remote:*` don't delete e.g. `origin/main`.
+    pub(crate) fn preserve_remote_heads(
         &mut self,
         repo: &Repository,
-        preserved_patterns: &[&str],
+        config: &Config,
     ) -> Result<()> {
+        let mut remote_heads = HashSet::new();
+        for reference in repo.references_glob("refs/remotes/*/HEAD")? {
+            // git symbolic-ref refs/remotes/*/HEAD
+            if let Ok(resolved) = reference?.resolve() {
+                if let Some(refname) = resolved.name() {
+                    remote_heads.insert(refname.to_owned());
+                }
+            }
+        }
+        if remote_heads.is_empty() {
+            return Ok(());
+        }
+
         let mut preserve = Vec::new();
         for branch in &self.to_delete {
-            let pattern =
-                match &branch {
-                    ClassifiedBranch::MergedLocal(local)
-                    | ClassifiedBranch::Stray(local)
-                    | ClassifiedBranch::MergedDirectFetch { local, .. }
-                    | ClassifiedBranch::DivergedDirectFetch { local, .. }
-                    | ClassifiedBranch::MergedNonTrackingLocal(local) => {
-                        get_protect_pattern(repo, preserved_patterns, local)?
-                    }
-                    ClassifiedBranch::MergedRemoteTracking(upstream)
-                    | ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream) => {
-                        get_protect_pattern(repo, preserved_patterns, upstream)?
+            // The HEAD of the remote is the base that it duplicates, which is not going to be deleted.
+            if let ClassifiedBranch::DuplicateOfBase { .. } = branch {
+                continue;
+            }
+            let remote_tracking = match (branch.upstream(), branch.remote(repo)?, branch.local()) {
+                (Some(upstream), _, _) => Some(upstream.refname.clone()),
+                (None, Some(remote), _) => {
+                    match RemoteTrackingBranch::from_remote_branch(repo, &remote)? {
+                        RemoteTrackingBranchStatus::Exists(remote_tracking) => {
+                            Some(remote_tracking.refname)
+                        }
+                        _ => None,
                     }
-                    ClassifiedBranch::DivergedRemoteTracking { local, upstream } => {
-                        get_protect_pattern(repo, preserved_patterns, local)?
-                            .or(get_protect_pattern(repo, preserved_patterns, upstream)?)
+                }
+                (None, None, Some(local)) => match local.fetch_upstream(repo, config)? {
+                    RemoteTrackingBranchStatus::Exists(upstream) => Some(upstream.refname),
+                    RemoteTrackingBranchStatus::Gone(upstream) => Some(upstream),
+                    _ => None,
+                },
+                (None, None, None) => None,
+            };
+            if let Some(remote_tracking) = remote_tracking {
+                if remote_heads.contains(&remote_tracking) {
+                    preserve.push(Preserved {
+                        branch: branch.clone(),
+                        reason: format!("HEAD of remote (`{}`)", remote_tracking),
+                        base: false,
+                    });
+                }
+            }
+        }
+
+        for preserved in &preserve {
+            self.to_delete.remove(&preserved.branch);
+        }
+        self.preserved.extend(preserve);
+
+        Ok(())
+    }
+
+    /// Remotes reject to delete protected branches, so ask their hosting services beforehand.
+    /// Remotes that are not on GitHub or GitLab, or whose CLIs fail, are not checked.
+    pub fn preserve_protected_on_remotes(&mut self, repo: &Repository) -> Result<()> {
+        let mut protected_per_remote: HashMap<String, Vec<String>> = HashMap::new();
+        let mut preserve = Vec::new();
+        for branch in &self.to_delete {
+            let remote = if let Some(remote) = branch.remote(repo)? {
+                remote
+            } else {
+                continue;
+            };
+            if !protected_per_remote.contains_key(&remote.remote) {
+                let url = config::get_remote_url(repo, &remote.remote)?;
+                let hosting = url.as_deref().and_then(Hosting::from_url);
+                let protected = match hosting.map(|hosting| hosting.protected_branches()) {
+                    Some(Ok(protected)) => protected,
+                    Some(Err(err)) => {
+                        warn!(
+                            "Failed to get protected branches of {}: {:#}",
+                            remote.remote, err
+                        );
+                        Vec::new()
                     }
+                    None => Vec::new(),
                 };
-
-            if let Some(pattern) = pattern {
+                debug!("protected on {}: {:?}", remote.remote, protected);
+                protected_per_remote.insert(remote.remote.clone(), protected);
+            }
+            let short_name = remote.refname.trim_start_matches("refs/heads/");
+            let protected = &protected_per_remote[&remote.remote];
+            if protected
+                .iter()
+                .any(|pattern| glob_match(pattern, short_name))
+            {
                 preserve.push(Preserved {
                     branch: branch.clone(),
-                    reason: format!("protected by a pattern `{}`", pattern),
+                    reason: "protected on remote".to_owned(),
                     base: false,
                 });
             }
@@ -198,8 +719,12 @@ impl TrimPlan {
     }
 
     /// `hub-cli` can checkout pull request branch. However they are stored in `refs/pulls/`.
-    /// This prevents to remove them.
-    pub fn preserve_non_heads_remotes(&mut self, repo: &Repository) -> Result<()> {
+    /// This prevents to remove them unless their namespaces are given in `namespaces`.
+    pub fn preserve_non_heads_remotes(
+        &mut self,
+        repo: &Repository,
+        namespaces: &[&str],
+    ) -> Result<()> {
         let mut preserve = Vec::new();
 
         for branch in &self.to_delete {
@@ -209,7 +734,11 @@ impl TrimPlan {
                 continue;
             };
 
-            if !remote.refname.starts_with("refs/heads/") {
+            let in_namespace = remote.refname.starts_with("refs/heads/")
+                || namespaces
+                    .iter()
+                    .any(|namespace| in_remote_namespace(namespace, &remote.refname));
+            if !in_namespace {
                 trace!("filter-out: remote ref {}", remote);
                 preserve.push(Preserved {
                     branch: branch.clone(),
@@ -239,7 +768,7 @@ impl TrimPlan {
             if let Some(path) = worktrees.get(local) {
                 preserve.push(Preserved {
                     branch: branch.clone(),
-                    reason: format!("worktree at {}", path),
+                    reason: format!("worktree at {}", path.display()),
                     base: false,
                 });
             }
@@ -253,12 +782,309 @@ impl TrimPlan {
         Ok(())
     }
 
+    /// Classifications that concluded `branch` per base. Their messages explain why.
+    pub fn explanations<'a>(
+        &'a self,
+        branch: &'a ClassifiedBranch,
+    ) -> impl Iterator<Item = &'a ClassificationResponse> {
+        self.classifications
+            .iter()
+            .filter(move |classification| classification.result.contains(branch))
+    }
+
+    /// Preserve stray and diverged branches when they are concluded as not merged
+    /// only because the shallow history doesn't reach where they might be merged.
+    pub fn preserve_shallow_unknowns(&mut self) {
+        let mut preserve = Vec::new();
+        for classification in &self.classifications {
+            if !classification
+                .merge_states
+                .iter()
+                .any(|state| state.unknown)
+            {
+                continue;
+            }
+            for branch in &classification.result {
+                match branch {
+                    ClassifiedBranch::Stray(_)
+                    | ClassifiedBranch::DivergedRemoteTracking { .. }
+                    | ClassifiedBranch::DivergedDirectFetch { .. } => {}
+                    _ => continue,
+                }
+                if self.to_delete.remove(branch) {
+                    preserve.push(Preserved {
+                        branch: branch.clone(),
+                        reason: "unknown (shallow history)".to_owned(),
+                        base: false,
+                    });
+                }
+            }
+        }
+        self.preserved.extend(preserve);
+    }
+
+    /// Preserve remote branches whose tip commits are signed by keys other than `my_keys`.
+    /// They are likely to be shared with others.
+    pub fn preserve_signed_by_others(&mut self, repo: &Repository, my_keys: &[&str]) -> Result<()> {
+        self.preserve_remote_tips_where(repo, |commit| {
+            let signature = match repo.extract_signature(&commit.id(), None) {
+                Ok((signature, _)) => signature,
+                Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            let signer = Signer::parse(signature.as_str().unwrap_or_default());
+            if my_keys.iter().any(|key| signer.is_signed_by(key)) {
+                return Ok(None);
+            }
+            Ok(Some("the tip is signed by someone else".to_owned()))
+        })
+    }
+
+    /// Preserve remote branches whose tip commits are authored by others than `my_email`.
+    pub fn preserve_authored_by_others(&mut self, repo: &Repository, my_email: &str) -> Result<()> {
+        self.preserve_remote_tips_where(repo, |commit| {
+            let author = commit.author();
+            let email = author.email().unwrap_or_default();
+            if email.eq_ignore_ascii_case(my_email) {
+                return Ok(None);
+            }
+            Ok(Some(format!("the tip is authored by {}", email)))
+        })
+    }
+
+    /// Preserve branches to delete from remotes if `reason` gives a reason to preserve their tip commits.
+    /// They are also preserved if their tips on the remotes are unknown.
+    fn preserve_remote_tips_where(
+        &mut self,
+        repo: &Repository,
+        mut reason: impl FnMut(&git2::Commit) -> Result<Option<String>>,
+    ) -> Result<()> {
+        let mut preserve = Vec::new();
+        for branch in &self.to_delete {
+            if branch.remote(repo)?.is_none() {
+                continue;
+            }
+            let reason = match get_remote_tip(repo, branch)? {
+                Some(tip) => reason(&repo.find_commit(tip)?)?,
+                None => Some("the tip on the remote is unknown".to_owned()),
+            };
+            if let Some(reason) = reason {
+                preserve.push(Preserved {
+                    branch: branch.clone(),
+                    reason,
+                    base: false,
+                });
+            }
+        }
+
+        for preserved in &preserve {
+            self.to_delete.remove(&preserved.branch);
+        }
+        self.preserved.extend(preserve);
+
+        Ok(())
+    }
+
+    /// Preserve local branches that are checked out within `days` according to the HEAD reflog.
+    pub fn preserve_recently_checked_out(&mut self, repo: &Repository, days: u64) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let since = now - (days * 24 * 60 * 60) as i64;
+
+        let mut checked_out = HashSet::new();
+        let reflog = repo.reflog("HEAD")?;
+        for entry in reflog.iter() {
+            if entry.committer().when().seconds() < since {
+                continue;
+            }
+            let message = if let Some(message) = entry.message() {
+                message
+            } else {
+                continue;
+            };
+            // checkout: moving from <from> to <to>
+            if let Some(moving) = message.strip_prefix("checkout: moving from ") {
+                if let Some((from, to)) = moving.split_once(" to ") {
+                    checked_out.insert(format!("refs/heads/{}", from));
+                    checked_out.insert(format!("refs/heads/{}", to));
+                }
+            }
+        }
+        trace!("recently checked out: {:?}", checked_out);
+
+        let mut preserve = Vec::new();
+        for branch in &self.to_delete {
+            let local = if let Some(local) = branch.local() {
+                local
+            } else {
+                continue;
+            };
+            if checked_out.contains(&local.refname) {
+                preserve.push(Preserved {
+                    branch: branch.clone(),
+                    reason: format!("checked out within {} days", days),
+                    base: false,
+                });
+            }
+        }
+
+        for preserved in &preserve {
+            self.to_delete.remove(&preserved.branch);
+        }
+        self.preserved.extend(preserve);
+
+        Ok(())
+    }
+
+    /// Preserve stray branches whose upstreams are likely renamed on the remote rather than deleted.
+    /// The new name is the only remote tracking branch of the same remote that is at the same commit
+    /// and has never been at other commits. It is recorded in `renamed_upstreams` to be retargeted.
+    pub fn preserve_renamed_upstreams(&mut self, repo: &Repository, config: &Config) -> Result<()> {
+        let strays: Vec<_> = self
+            .to_delete
+            .iter()
+            .filter_map(|branch| match branch {
+                ClassifiedBranch::Stray(local) => Some(local.clone()),
+                _ => None,
+            })
+            .collect();
+        if strays.is_empty() {
+            return Ok(());
+        }
+
+        let mut remote_tips: HashMap<git2::Oid, Vec<(RemoteTrackingBranch, RemoteBranch)>> =
+            HashMap::new();
+        for branch in utf8_branches(repo, BranchType::Remote)? {
+            // Skip `refs/remotes/*/HEAD`
+            if branch.get().symbolic_target_bytes().is_some() {
+                continue;
+            }
+            let upstream = RemoteTrackingBranch::try_from(&branch)?;
+            let remote_branch = match upstream.to_remote_branch(repo) {
+                Ok(remote_branch) => remote_branch,
+                Err(RemoteBranchError::RemoteNotFound) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let tip = branch.get().peel_to_commit()?.id();
+            // A renamed branch is new to us. The other one has been at other commits,
+            // e.g. another branch that the stray branch is fast-forward merged into.
+            let reflog = repo.reflog(&upstream.refname)?;
+            if reflog.iter().any(|entry| entry.id_new() != tip) {
+                continue;
+            }
+            remote_tips
+                .entry(tip)
+                .or_default()
+                .push((upstream, remote_branch));
+        }
+
+        for local in strays {
+            let remote_name = match config::get_remote_name(config, &local)? {
+                Some(remote_name) => remote_name,
+                None => continue,
+            };
+            let tip = repo.find_reference(&local.refname)?.peel_to_commit()?.id();
+            let candidates: Vec<_> = remote_tips
+                .get(&tip)
+                .into_iter()
+                .flatten()
+                .filter(|(_, remote_branch)| remote_branch.remote == remote_name)
+                .collect();
+            // It is ambiguous when there are many.
+            let (upstream, remote_branch) = match candidates.as_slice() {
+                [candidate] => (*candidate).clone(),
+                _ => continue,
+            };
+
+            let branch = ClassifiedBranch::Stray(local.clone());
+            self.to_delete.remove(&branch);
+            self.preserved.push(Preserved {
+                branch,
+                reason: format!(
+                    "upstream renamed to {}",
+                    upstream.refname.trim_start_matches("refs/remotes/")
+                ),
+                base: false,
+            });
+            self.renamed_upstreams.push(RenamedUpstream {
+                local,
+                upstream,
+                remote_branch,
+            });
+        }
+        Ok(())
+    }
+
+    /// Preserve stray branches whose tip commits are made within `days`.
+    pub fn preserve_recent_strays(&mut self, repo: &Repository, days: u64) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let since = now - (days * 24 * 60 * 60) as i64;
+
+        let mut preserve = Vec::new();
+        for branch in &self.to_delete {
+            let local = if let ClassifiedBranch::Stray(local) = branch {
+                local
+            } else {
+                continue;
+            };
+            let commit = repo.find_reference(&local.refname)?.peel_to_commit()?;
+            if commit.committer().when().seconds() >= since {
+                preserve.push(Preserved {
+                    branch: branch.clone(),
+                    reason: "stray but recent".to_owned(),
+                    base: false,
+                });
+            }
+        }
+
+        for preserved in &preserve {
+            self.to_delete.remove(&preserved.branch);
+        }
+        self.preserved.extend(preserve);
+
+        Ok(())
+    }
+
+    /// Deleting a branch in the middle of a stack breaks upstreams of the branches stacked on it.
+    /// A branch is preserved while any branch stacked on it is not going to be deleted.
+    pub fn preserve_stack_bases(&mut self, stacks: &Stacks) {
+        loop {
+            let locals_to_delete: HashSet<_> = self.locals_to_delete().into_iter().collect();
+            let preserve: Vec<_> = self
+                .to_delete
+                .iter()
+                .filter(|branch| match branch.local() {
+                    Some(local) => stacks
+                        .children(local)
+                        .any(|child| !locals_to_delete.contains(child)),
+                    None => false,
+                })
+                .map(|branch| Preserved {
+                    branch: branch.clone(),
+                    reason: "base of unmerged stack".to_owned(),
+                    base: false,
+                })
+                .collect();
+            if preserve.is_empty() {
+                return;
+            }
+
+            for preserved in &preserve {
+                self.to_delete.remove(&preserved.branch);
+            }
+            self.preserved.extend(preserve);
+        }
+    }
+
     pub fn apply_delete_range_filter(
         &mut self,
         repo: &Repository,
         filter: &DeleteFilter,
     ) -> Result<()> {
+        let url = |remote: &str| config::get_remote_url(repo, remote);
         let mut preserve = Vec::new();
+        // Diverged branches of which only one side is in the delete range:
+        // `(branch, side to delete, side to keep, range that was not given)`
+        let mut halves = Vec::new();
 
         for branch in &self.to_delete {
             let range = match branch {
@@ -276,33 +1102,123 @@ impl TrimPlan {
                         None
                     }
                 }
+                ClassifiedBranch::Unrelated(_) => {
+                    if !filter.delete_unrelated() {
+                        Some("unrelated".to_owned())
+                    } else {
+                        None
+                    }
+                }
+                ClassifiedBranch::DuplicateOfBase { .. } => {
+                    if !filter.delete_duplicate_base() {
+                        Some("duplicate-base".to_owned())
+                    } else {
+                        None
+                    }
+                }
                 ClassifiedBranch::MergedRemoteTracking(upstream) => {
                     let remote = upstream.to_remote_branch(repo)?;
-                    if !filter.delete_merged_remote(&remote.remote) {
+                    if !filter.delete_merged_remote(&remote.remote, url(&remote.remote)?.as_deref())
+                    {
                         Some(format!("merged-remote:{}", &remote.remote))
                     } else {
                         None
                     }
                 }
-                ClassifiedBranch::DivergedRemoteTracking { upstream, .. } => {
+                ClassifiedBranch::DivergedRemoteTracking { local, upstream } => {
                     let remote = upstream.to_remote_branch(repo)?;
-                    if !filter.delete_diverged(&remote.remote) {
-                        Some(format!("diverged:{}", &remote.remote))
-                    } else {
+                    let url = url(&remote.remote)?;
+                    let delete_local = filter.delete_diverged_local();
+                    let delete_remote =
+                        filter.delete_diverged_remote(&remote.remote, url.as_deref());
+                    if filter.delete_diverged(&remote.remote, url.as_deref())
+                        || delete_local && delete_remote
+                    {
+                        None
+                    } else if delete_local || delete_remote {
+                        let local_side = ClassifiedBranch::DivergedLocal {
+                            local: local.clone(),
+                            remote: remote.clone(),
+                        };
+                        let remote_side = ClassifiedBranch::DivergedRemote {
+                            local: local.clone(),
+                            remote: remote.clone(),
+                            upstream: Some(upstream.clone()),
+                        };
+                        halves.push(if delete_local {
+                            let range = format!("diverged-remote:{}", remote.remote);
+                            (branch.clone(), local_side, remote_side, range)
+                        } else {
+                            (
+                                branch.clone(),
+                                remote_side,
+                                local_side,
+                                "diverged-local".to_owned(),
+                            )
+                        });
                         None
+                    } else {
+                        Some(format!("diverged:{}", &remote.remote))
                     }
                 }
 
-                ClassifiedBranch::MergedDirectFetch { remote, .. } => {
-                    if !filter.delete_merged_remote(&remote.remote) {
+                ClassifiedBranch::MergedDirectFetch { remote, .. }
+                | ClassifiedBranch::MergedPushRemote { remote, .. }
+                | ClassifiedBranch::MergedRemoteOnly(RemoteOnlyBranch { remote, .. }) => {
+                    if !filter.delete_merged_remote(&remote.remote, url(&remote.remote)?.as_deref())
+                    {
                         Some(format!("merged-remote:{}", &remote.remote))
                     } else {
                         None
                     }
                 }
-                ClassifiedBranch::DivergedDirectFetch { remote, .. } => {
-                    if !filter.delete_diverged(&remote.remote) {
+                ClassifiedBranch::DivergedDirectFetch { local, remote } => {
+                    let url = url(&remote.remote)?;
+                    let delete_local = filter.delete_diverged_local();
+                    let delete_remote =
+                        filter.delete_diverged_remote(&remote.remote, url.as_deref());
+                    if filter.delete_diverged(&remote.remote, url.as_deref())
+                        || delete_local && delete_remote
+                    {
+                        None
+                    } else if delete_local || delete_remote {
+                        let local_side = ClassifiedBranch::DivergedLocal {
+                            local: local.clone(),
+                            remote: remote.clone(),
+                        };
+                        let remote_side = ClassifiedBranch::DivergedRemote {
+                            local: local.clone(),
+                            remote: remote.clone(),
+                            upstream: None,
+                        };
+                        halves.push(if delete_local {
+                            let range = format!("diverged-remote:{}", remote.remote);
+                            (branch.clone(), local_side, remote_side, range)
+                        } else {
+                            (
+                                branch.clone(),
+                                remote_side,
+                                local_side,
+                                "diverged-local".to_owned(),
+                            )
+                        });
+                        None
+                    } else {
                         Some(format!("diverged:{}", &remote.remote))
+                    }
+                }
+                ClassifiedBranch::DivergedLocal { .. } => {
+                    if !filter.delete_diverged_local() {
+                        Some("diverged-local".to_owned())
+                    } else {
+                        None
+                    }
+                }
+                ClassifiedBranch::DivergedRemote { remote, .. } => {
+                    if !filter
+                        .delete_diverged_remote(&remote.remote, url(&remote.remote)?.as_deref())
+                    {
+                        Some(format!("diverged-remote:{}", &remote.remote))
                     } else {
                         None
                     }
@@ -317,12 +1233,23 @@ impl TrimPlan {
                 }
                 ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream) => {
                     let remote = upstream.to_remote_branch(repo)?;
-                    if !filter.delete_merged_non_upstream_remote_tracking(&remote.remote) {
+                    if !filter.delete_merged_non_upstream_remote_tracking(
+                        &remote.remote,
+                        url(&remote.remote)?.as_deref(),
+                    ) {
                         Some(format!("remote:{}", &remote.remote))
                     } else {
                         None
                     }
                 }
+                ClassifiedBranch::MergedPullRef { pull, .. } => {
+                    if !filter.delete_merged_pull_refs(&pull.remote, url(&pull.remote)?.as_deref())
+                    {
+                        Some(format!("pull-refs:{}", &pull.remote))
+                    } else {
+                        None
+                    }
+                }
             };
 
             trace!("Delete range result: {:?} => {:?}", branch, range);
@@ -341,6 +1268,24 @@ impl TrimPlan {
         }
         self.preserved.extend(preserve);
 
+        for (branch, delete, keep, range) in halves {
+            self.to_delete.remove(&branch);
+            // So that the halves are explained as the whole was.
+            for classification in &mut self.classifications {
+                if let Some(index) = classification.result.iter().position(|b| b == &branch) {
+                    classification.result.remove(index);
+                    classification.result.push(delete.clone());
+                    classification.result.push(keep.clone());
+                }
+            }
+            self.to_delete.insert(delete);
+            self.preserved.push(Preserved {
+                branch: keep,
+                reason: format!("delete range `{}` was not given", range),
+                base: false,
+            });
+        }
+
         Ok(())
     }
 
@@ -371,6 +1316,91 @@ impl TrimPlan {
         Ok(())
     }
 
+    /// Preserve branches after the first `limit` ones in the order of `key`.
+    /// Branches of the same name, e.g. a local branch and its upstream, count as one.
+    pub fn preserve_over_limit(
+        &mut self,
+        repo: &Repository,
+        key: SortKey,
+        limit: usize,
+    ) -> Result<()> {
+        let mut names = HashSet::new();
+        let mut preserve = Vec::new();
+        for branch in self.sorted_to_delete(repo, key)? {
+            let (_, name) = get_remote_and_name(repo, branch)?;
+            if names.contains(&name) {
+                continue;
+            }
+            if names.len() < limit {
+                names.insert(name);
+                continue;
+            }
+            preserve.push(Preserved {
+                branch: branch.clone(),
+                reason: "over the limit".to_owned(),
+                base: false,
+            });
+        }
+
+        for preserved in &preserve {
+            self.to_delete.remove(&preserved.branch);
+        }
+        self.preserved.extend(preserve);
+
+        Ok(())
+    }
+
+    /// Preserve `branches` that are not at the `selected` indices, e.g. ones toggled off in `--tui`.
+    pub fn preserve_unselected(&mut self, branches: &[ClassifiedBranch], selected: &[usize]) {
+        for (index, branch) in branches.iter().enumerate() {
+            if selected.contains(&index) || !self.to_delete.remove(branch) {
+                continue;
+            }
+            self.preserved.push(Preserved {
+                branch: branch.clone(),
+                reason: "kept interactively".to_owned(),
+                base: false,
+            });
+        }
+    }
+
+    pub fn record_tips(&mut self, repo: &Repository) -> Result<()> {
+        let mut tips = HashMap::new();
+        for branch in &self.to_delete {
+            let refnames = branch
+                .local()
+                .map(|local| &local.refname)
+                .into_iter()
+                .chain(branch.upstream().map(|upstream| &upstream.refname));
+            for refname in refnames {
+                let commit = repo.find_reference(refname)?.peel_to_commit()?;
+                tips.insert(refname.clone(), commit.id().to_string());
+            }
+            if let ClassifiedBranch::MergedRemoteOnly(remote_only) = branch {
+                let RemoteBranch { remote, refname } = &remote_only.remote;
+                tips.insert(
+                    format!("{}:{}", remote, refname),
+                    remote_only.commit.clone(),
+                );
+            }
+        }
+        self.tips = tips;
+        Ok(())
+    }
+
+    /// Bases that local branches to delete are merged into.
+    pub fn local_bases(&self) -> HashMap<LocalBranch, BaseRef> {
+        let mut result = HashMap::new();
+        for classification in &self.classifications {
+            for branch in &classification.result {
+                if let Some(local) = branch.local() {
+                    result.insert(local.clone(), classification.base.clone());
+                }
+            }
+        }
+        result
+    }
+
     pub fn get_preserved_local(&self, target: &LocalBranch) -> Option<&Preserved> {
         self.preserved
             .iter()
@@ -384,7 +1414,7 @@ impl TrimPlan {
     }
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum SkipSuggestion {
     Tracking,
     TrackingRemote(String),
@@ -392,43 +1422,195 @@ pub enum SkipSuggestion {
     NonUpstream(String),
 }
 
-impl SkipSuggestion {
-    pub const KIND_TRACKING: i32 = 1;
-    pub const KIND_NON_TRACKING: i32 = 2;
-    pub const KIND_NON_UPSTREAM: i32 = 3;
+impl SkipSuggestion {
+    pub const KIND_TRACKING: i32 = 1;
+    pub const KIND_NON_TRACKING: i32 = 2;
+    pub const KIND_NON_UPSTREAM: i32 = 3;
+
+    pub fn kind(&self) -> i32 {
+        match self {
+            SkipSuggestion::Tracking => Self::KIND_TRACKING,
+            SkipSuggestion::TrackingRemote(_) => Self::KIND_TRACKING,
+            SkipSuggestion::NonTracking => Self::KIND_NON_TRACKING,
+            SkipSuggestion::NonUpstream(_) => Self::KIND_NON_UPSTREAM,
+        }
+    }
+
+    /// A delete range to add to scan the skipped branch.
+    pub fn delete_range(&self) -> DeleteRange {
+        match self {
+            SkipSuggestion::Tracking => DeleteRange::MergedLocal,
+            SkipSuggestion::TrackingRemote(remote) => {
+                DeleteRange::Merged(Scope::Scoped(remote.clone()))
+            }
+            SkipSuggestion::NonTracking => DeleteRange::Local,
+            SkipSuggestion::NonUpstream(remote) => {
+                DeleteRange::Remote(Scope::Scoped(remote.clone()))
+            }
+        }
+    }
+}
+
+/// A remote name (empty for local only branches) and a branch name without `refs/heads/`.
+fn get_remote_and_name(repo: &Repository, branch: &ClassifiedBranch) -> Result<(String, String)> {
+    if let ClassifiedBranch::MergedPullRef { pull, .. } = branch {
+        return Ok((pull.remote.clone(), pull.refname.clone()));
+    }
+    match (branch.local(), branch.remote(repo)?) {
+        (Some(local), remote) => Ok((
+            remote.map(|remote| remote.remote).unwrap_or_default(),
+            local.short_name().to_owned(),
+        )),
+        (None, Some(remote)) => {
+            let name = remote.refname.trim_start_matches("refs/heads/").to_owned();
+            Ok((remote.remote, name))
+        }
+        (None, None) => unreachable!("a branch to delete is either local or remote"),
+    }
+}
+
+/// Seconds since the UNIX epoch of the tip commit. The local one is used if there are both.
+fn get_tip_time(repo: &Repository, branch: &ClassifiedBranch) -> Result<i64> {
+    let commit = match branch.local() {
+        Some(local) => repo.find_reference(&local.refname)?.peel_to_commit()?,
+        None => match get_remote_tip(repo, branch)? {
+            Some(tip) => repo.find_commit(tip)?,
+            // Direct fetched and push remote branches always have local branches.
+            None => return Ok(0),
+        },
+    };
+    let seconds = commit.committer().when().seconds();
+    Ok(seconds)
+}
+
+/// The tip commit of the remote branch, if it is known locally.
+fn get_remote_tip(repo: &Repository, branch: &ClassifiedBranch) -> Result<Option<git2::Oid>> {
+    let peel = |refname: &str| -> Result<Option<git2::Oid>> {
+        Ok(Some(repo.find_reference(refname)?.peel_to_commit()?.id()))
+    };
+    match branch {
+        ClassifiedBranch::MergedRemoteOnly(remote_only) => {
+            return Ok(Some(git2::Oid::from_str(&remote_only.commit)?));
+        }
+        // They are classified so only when the remote branch points to the local tip.
+        ClassifiedBranch::MergedDirectFetch { local, .. }
+        | ClassifiedBranch::MergedPushRemote { local, .. } => return peel(&local.refname),
+        _ => {}
+    }
+    if let Some(upstream) = branch.upstream() {
+        return peel(&upstream.refname);
+    }
+    let remote = match branch.remote(repo)? {
+        Some(remote) => remote,
+        None => return Ok(None),
+    };
+    // e.g. a diverged direct fetch branch that happens to be fetched to a remote tracking branch
+    match RemoteTrackingBranch::from_remote_branch(repo, &remote)? {
+        RemoteTrackingBranchStatus::Exists(remote_tracking) => peel(&remote_tracking.refname),
+        _ => Ok(None),
+    }
+}
+
+/// A protected pattern. It is a ref glob, or a regular expression if it is prefixed with `re:`.
+pub(crate) enum ProtectPattern<'a> {
+    Glob(&'a str),
+    /// It matches whole short names (e.g. `feature`, `origin/feature`) or refnames.
+    Regex {
+        pattern: &'a str,
+        regex: Regex,
+    },
+}
+
+impl<'a> ProtectPattern<'a> {
+    pub(crate) fn parse(pattern: &'a str) -> Result<Self> {
+        let source = match pattern.strip_prefix("re:") {
+            Some(source) => source,
+            None => return Ok(ProtectPattern::Glob(pattern)),
+        };
+        let regex = Regex::new(&format!("^(?:{})$", source)).with_context(|| InvalidConfig {
+            key: "trim.protected".to_owned(),
+            value: pattern.to_owned(),
+        })?;
+        Ok(ProtectPattern::Regex { pattern, regex })
+    }
 
-    pub fn kind(&self) -> i32 {
+    fn as_str(&self) -> &'a str {
         match self {
-            SkipSuggestion::Tracking => Self::KIND_TRACKING,
-            SkipSuggestion::TrackingRemote(_) => Self::KIND_TRACKING,
-            SkipSuggestion::NonTracking => Self::KIND_NON_TRACKING,
-            SkipSuggestion::NonUpstream(_) => Self::KIND_NON_UPSTREAM,
+            ProtectPattern::Glob(pattern) | ProtectPattern::Regex { pattern, .. } => pattern,
         }
     }
 }
 
 fn get_protect_pattern<'a, B: Refname>(
-    repo: &Repository,
-    protected_patterns: &[&'a str],
+    protected_patterns: &[ProtectPattern<'a>],
     branch: &B,
-) -> Result<Option<&'a str>> {
+) -> Option<&'a str> {
     let prefixes = &["", "refs/remotes/", "refs/heads/"];
     let target_refname = branch.refname();
     for protected_pattern in protected_patterns {
-        for prefix in prefixes {
-            for reference in repo.references_glob(&format!("{}{}", prefix, protected_pattern))? {
-                let reference = reference?;
-                let refname = reference.name().context("non utf-8 refname")?;
-                if target_refname == refname {
-                    return Ok(Some(protected_pattern));
+        let protected_pattern = match protected_pattern {
+            ProtectPattern::Glob(pattern) => *pattern,
+            ProtectPattern::Regex { pattern, regex } => {
+                let short_name = prefixes[1..]
+                    .iter()
+                    .find_map(|prefix| target_refname.strip_prefix(prefix))
+                    .unwrap_or(target_refname);
+                if regex.is_match(target_refname) || regex.is_match(short_name) {
+                    return Some(pattern);
                 }
+                continue;
             }
+        };
+        if prefixes
+            .iter()
+            .any(|prefix| glob_match(&format!("{}{}", prefix, protected_pattern), target_refname))
+        {
+            return Some(protected_pattern);
         }
     }
-    Ok(None)
+    None
 }
 
-#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+/// Remote only branches don't have refs to match with patterns.
+/// Match them as if they are fetched into `refs/remotes/<remote>/*`.
+fn get_remote_only_protect_pattern<'a>(
+    protected_patterns: &[ProtectPattern<'a>],
+    remote: &RemoteBranch,
+) -> Option<&'a str> {
+    let short_name = remote
+        .refname
+        .strip_prefix("refs/heads/")
+        .unwrap_or(&remote.refname);
+    let remote_tracking = format!("{}/{}", remote.remote, short_name);
+    let candidates = [
+        remote.refname.as_str(),
+        short_name,
+        &remote_tracking,
+        &format!("refs/remotes/{}", remote_tracking),
+    ];
+    protected_patterns
+        .iter()
+        .find(|pattern| {
+            candidates.iter().any(|candidate| match pattern {
+                ProtectPattern::Glob(glob) => glob_match(glob, candidate),
+                ProtectPattern::Regex { regex, .. } => regex.is_match(candidate),
+            })
+        })
+        .map(ProtectPattern::as_str)
+}
+
+/// `refs/pull/*`, `refs/pull/` and `refs/pull` are all the same namespace.
+fn in_remote_namespace(namespace: &str, refname: &str) -> bool {
+    let prefix = namespace.trim_end_matches('*').trim_end_matches('/');
+    if prefix.is_empty() {
+        return false;
+    }
+    refname
+        .strip_prefix(prefix)
+        .map_or(false, |rest| rest.starts_with('/'))
+}
+
+#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug, Clone)]
 pub enum ClassifiedBranch {
     MergedLocal(LocalBranch),
     Stray(LocalBranch),
@@ -446,9 +1628,49 @@ pub enum ClassifiedBranch {
         local: LocalBranch,
         remote: RemoteBranch,
     },
+    /// The local side of a diverged branch. The remote side is kept by the delete range.
+    DivergedLocal {
+        local: LocalBranch,
+        remote: RemoteBranch,
+    },
+    /// The remote side of a diverged branch. The local side is kept by the delete range.
+    /// `upstream` is `None` for a direct fetch branch.
+    DivergedRemote {
+        local: LocalBranch,
+        remote: RemoteBranch,
+        upstream: Option<RemoteTrackingBranch>,
+    },
+
+    /// A merged local branch whose remote tracking branch is gone,
+    /// but it is still left on the push remote.
+    MergedPushRemote {
+        local: LocalBranch,
+        remote: RemoteBranch,
+    },
 
     MergedNonTrackingLocal(LocalBranch),
     MergedNonUpstreamRemoteTracking(RemoteTrackingBranch),
+
+    /// A merged branch on a remote that is scanned by `--scan remote-only:<remote>`.
+    MergedRemoteOnly(RemoteOnlyBranch),
+
+    /// A merged remote tracking ref of a fetched pull request, e.g. `refs/remotes/origin/pr/1` of `refs/pull/1/head`.
+    /// It is deleted only locally, since the remote ref is read-only.
+    MergedPullRef {
+        remote_tracking: RemoteTrackingBranch,
+        pull: RemoteBranch,
+    },
+
+    /// A local branch that shares no history with bases, e.g. created by `git checkout --orphan`.
+    /// It is not merged, so it is kept unless `--delete unrelated` is given.
+    Unrelated(LocalBranch),
+
+    /// A merged local branch that tracks the upstream of a base, e.g. `main-backup` tracking `origin/main`.
+    /// It is a leftover of a rename or a backup, and kept unless `--delete duplicate-base` is given.
+    DuplicateOfBase {
+        local: LocalBranch,
+        base: RemoteTrackingBranch,
+    },
 }
 
 impl ClassifiedBranch {
@@ -459,7 +1681,11 @@ impl ClassifiedBranch {
             | ClassifiedBranch::DivergedRemoteTracking { local, .. }
             | ClassifiedBranch::MergedDirectFetch { local, .. }
             | ClassifiedBranch::DivergedDirectFetch { local, .. }
-            | ClassifiedBranch::MergedNonTrackingLocal(local) => Some(local),
+            | ClassifiedBranch::DivergedLocal { local, .. }
+            | ClassifiedBranch::MergedPushRemote { local, .. }
+            | ClassifiedBranch::MergedNonTrackingLocal(local)
+            | ClassifiedBranch::Unrelated(local)
+            | ClassifiedBranch::DuplicateOfBase { local, .. } => Some(local),
             _ => None,
         }
     }
@@ -468,11 +1694,22 @@ impl ClassifiedBranch {
         match self {
             ClassifiedBranch::MergedRemoteTracking(upstream)
             | ClassifiedBranch::DivergedRemoteTracking { upstream, .. }
-            | ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream) => Some(upstream),
+            | ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream)
+            | ClassifiedBranch::MergedPullRef {
+                remote_tracking: upstream,
+                ..
+            } => Some(upstream),
+            ClassifiedBranch::DivergedRemote { upstream, .. } => upstream.as_ref(),
             _ => None,
         }
     }
 
+    /// Whether it is classified with its remote tracking branch, or with the absence of it.
+    pub fn by_remote_tracking(&self) -> bool {
+        matches!(self, ClassifiedBranch::Stray(_)) || self.upstream().is_some()
+    }
+
+    /// The remote branch to delete from the remote. Pull refs are not deleted from remotes.
     pub fn remote(&self, repo: &Repository) -> Result<Option<RemoteBranch>> {
         match self {
             ClassifiedBranch::MergedRemoteTracking(upstream)
@@ -482,22 +1719,37 @@ impl ClassifiedBranch {
                 Ok(Some(remote))
             }
             ClassifiedBranch::MergedDirectFetch { remote, .. }
-            | ClassifiedBranch::DivergedDirectFetch { remote, .. } => Ok(Some(remote.clone())),
+            | ClassifiedBranch::DivergedDirectFetch { remote, .. }
+            | ClassifiedBranch::DivergedRemote { remote, .. }
+            | ClassifiedBranch::MergedPushRemote { remote, .. }
+            | ClassifiedBranch::MergedRemoteOnly(RemoteOnlyBranch { remote, .. }) => {
+                Ok(Some(remote.clone()))
+            }
             _ => Ok(None),
         }
     }
 
+    /// The tip commit of the remote branch, if it is known locally.
+    pub fn remote_tip(&self, repo: &Repository) -> Result<Option<git2::Oid>> {
+        get_remote_tip(repo, self)
+    }
+
     pub fn message_local(&self) -> String {
         match self {
-            ClassifiedBranch::MergedLocal(_) | ClassifiedBranch::MergedDirectFetch { .. } => {
-                "merged".to_owned()
-            }
+            ClassifiedBranch::MergedLocal(_)
+            | ClassifiedBranch::MergedDirectFetch { .. }
+            | ClassifiedBranch::MergedPushRemote { .. } => "merged".to_owned(),
             ClassifiedBranch::MergedNonTrackingLocal(_) => "merged non-tracking".to_owned(),
             ClassifiedBranch::Stray(_) => "stray".to_owned(),
+            ClassifiedBranch::Unrelated(_) => "unrelated history".to_owned(),
+            ClassifiedBranch::DuplicateOfBase { base, .. } => {
+                format!("duplicate of base {}", base.refname)
+            }
             ClassifiedBranch::DivergedRemoteTracking {
                 upstream: remote, ..
             } => format!("diverged with {}", remote.refname),
-            ClassifiedBranch::DivergedDirectFetch { remote, .. } => {
+            ClassifiedBranch::DivergedDirectFetch { remote, .. }
+            | ClassifiedBranch::DivergedLocal { remote, .. } => {
                 format!("diverged with {}", remote)
             }
             _ => "If you see this message, report this as a bug".to_owned(),
@@ -507,14 +1759,18 @@ impl ClassifiedBranch {
     pub fn message_remote(&self) -> String {
         match self {
             ClassifiedBranch::MergedRemoteTracking(_)
-            | ClassifiedBranch::MergedDirectFetch { .. } => "merged".to_owned(),
+            | ClassifiedBranch::MergedDirectFetch { .. }
+            | ClassifiedBranch::MergedPushRemote { .. } => "merged".to_owned(),
             ClassifiedBranch::MergedNonUpstreamRemoteTracking(_) => {
                 "merged non-upstream".to_owned()
             }
+            ClassifiedBranch::MergedRemoteOnly(_) => "merged remote-only".to_owned(),
+            ClassifiedBranch::MergedPullRef { pull, .. } => format!("merged pull ref {}", pull),
             ClassifiedBranch::DivergedRemoteTracking { local, .. } => {
                 format!("diverged with {}", local.refname)
             }
-            ClassifiedBranch::DivergedDirectFetch { local, .. } => {
+            ClassifiedBranch::DivergedDirectFetch { local, .. }
+            | ClassifiedBranch::DivergedRemote { local, .. } => {
                 format!("diverged with {}", local.short_name())
             }
             _ => "If you see this message, report this as a bug".to_owned(),
@@ -526,6 +1782,8 @@ pub struct Classifier<'a> {
     git: &'a Git,
     merge_tracker: &'a MergeTracker,
     tasks: Vec<Box<dyn FnOnce() -> Result<ClassificationResponseWithId> + Send + Sync + 'a>>,
+    /// Queued requests formatted with `Debug`, to tell whether they are the same as the last plan.
+    requests: Vec<String>,
 }
 
 impl<'a> Classifier<'a> {
@@ -534,12 +1792,14 @@ impl<'a> Classifier<'a> {
             git,
             merge_tracker,
             tasks: Vec::new(),
+            requests: Vec::new(),
         }
     }
 
     pub fn queue_request<R: ClassificationRequest + Send + Sync + Debug + 'a>(&mut self, req: R) {
         let id = self.tasks.len();
         trace!("Enqueue #{}: {:#?}", id, req);
+        self.requests.push(format!("{:?}", req));
         let git = ForceSendSync::new(self.git);
         let merge_tracker = self.merge_tracker;
         self.tasks.push(Box::new(move || {
@@ -559,6 +1819,7 @@ impl<'a> Classifier<'a> {
     ) {
         let id = self.tasks.len();
         trace!("Enqueue #{}: {:#?}", id, req);
+        self.requests.push(format!("{:?}", req));
         let git = ForceSendSync::new(self.git);
         let merge_tracker = self.merge_tracker;
         self.tasks.push(Box::new(move || {
@@ -568,16 +1829,25 @@ impl<'a> Classifier<'a> {
         }));
     }
 
-    pub fn classify(self) -> Result<Vec<ClassificationResponse>> {
+    pub fn requests(&self) -> &[String] {
+        &self.requests
+    }
+
+    pub fn classify(self, progress: &ProgressFn) -> Result<Vec<ClassificationResponse>> {
         info!("Classify {} requests", self.tasks.len());
         let tasks = self.tasks;
-        let receiver = rayon::scope(move |scope| {
+        let total = tasks.len();
+        let done = AtomicUsize::new(0);
+        let receiver = rayon::scope(|scope| {
             let (sender, receiver) = unbounded();
             for tasks in tasks {
                 let sender = sender.clone();
+                let done = &done;
                 scope.spawn(move |_| {
                     let result = tasks();
                     sender.send(result).unwrap();
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress(Progress::Classify { done, total });
                 })
             }
             receiver
@@ -600,13 +1870,21 @@ struct ClassificationResponseWithId {
     response: ClassificationResponse,
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ClassificationResponse {
-    #[allow(dead_code)] // used in `Debug`
-    message: &'static str,
+    /// The base that the branch is found to be merged into among all bases,
+    /// or the first base if it is not merged.
+    pub base: BaseRef,
+    pub message: String,
+    pub merge_states: Vec<MergeState<String>>,
     pub result: Vec<ClassifiedBranch>,
 }
 
+/// The base that a branch is found to be merged into, or the first one if it is not known.
+fn concluded_base(bases: &[BaseRef], merged_into: Option<&BaseRef>) -> BaseRef {
+    merged_into.unwrap_or(&bases[0]).clone()
+}
+
 pub trait ClassificationRequest {
     fn classify(
         &self,
@@ -626,7 +1904,7 @@ pub trait ClassificationRequestWithContext<C> {
 
 #[derive(Debug)]
 pub struct TrackingBranchClassificationRequest<'a> {
-    pub base: &'a RemoteTrackingBranch,
+    pub bases: &'a [BaseRef],
     pub local: &'a LocalBranch,
     pub upstream: Option<&'a RemoteTrackingBranch>,
 }
@@ -637,53 +1915,72 @@ impl ClassificationRequest for TrackingBranchClassificationRequest<'_> {
         git: ForceSendSync<&Git>,
         merge_tracker: &MergeTracker,
     ) -> Result<ClassificationResponse> {
-        let local = merge_tracker.check_and_track(&git.repo, &self.base.refname, self.local)?;
-        let upstream = if let Some(upstream) = self.upstream {
-            merge_tracker.check_and_track(&git.repo, &self.base.refname, upstream)?
+        let (merged_into, local) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.local)?;
+        let (upstream_merged_into, upstream) = if let Some(upstream) = self.upstream {
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, upstream)?
         } else {
-            let result = if local.merged {
-                ClassificationResponse {
-                    message: "local is merged but remote is gone",
-                    result: vec![ClassifiedBranch::MergedLocal(local.branch)],
-                }
+            let merge_states = vec![local.to_refname_state()];
+            let (message, result) = if local.merged {
+                (
+                    "local is merged but remote is gone",
+                    vec![ClassifiedBranch::MergedLocal(local.branch)],
+                )
+            } else if merge_tracker.is_unrelated(self.bases, &local.commit)? {
+                (
+                    "local shares no history with bases",
+                    vec![ClassifiedBranch::Unrelated(local.branch)],
+                )
             } else {
-                ClassificationResponse {
-                    message: "local is stray but remote is gone",
-                    result: vec![ClassifiedBranch::Stray(local.branch)],
-                }
+                (
+                    "local is stray but remote is gone",
+                    vec![ClassifiedBranch::Stray(local.branch)],
+                )
             };
-            return Ok(result);
+            return Ok(ClassificationResponse {
+                base: concluded_base(self.bases, merged_into),
+                message: message.to_owned(),
+                merge_states,
+                result,
+            });
         };
 
-        let result = match (local.merged, upstream.merged) {
-            (true, true) => ClassificationResponse {
-                message: "local & upstream are merged",
-                result: vec![
+        let merge_states = vec![local.to_refname_state(), upstream.to_refname_state()];
+        let (message, result) = match (local.merged, upstream.merged) {
+            (true, true) => (
+                "local & upstream are merged",
+                vec![
                     ClassifiedBranch::MergedLocal(local.branch),
                     ClassifiedBranch::MergedRemoteTracking(upstream.branch),
                 ],
-            },
-            (true, false) => ClassificationResponse {
-                message: "local is merged but diverged with upstream",
-                result: vec![ClassifiedBranch::DivergedRemoteTracking {
+            ),
+            (true, false) => (
+                "local is merged but diverged with upstream",
+                vec![ClassifiedBranch::DivergedRemoteTracking {
                     local: local.branch,
                     upstream: upstream.branch,
                 }],
-            },
-            (false, true) => ClassificationResponse {
-                message: "upstream is merged, but the local strays",
-                result: vec![
+            ),
+            (false, true) => (
+                "upstream is merged, but the local strays",
+                vec![
                     ClassifiedBranch::Stray(local.branch),
                     ClassifiedBranch::MergedRemoteTracking(upstream.branch),
                 ],
-            },
-            (false, false) => ClassificationResponse {
-                message: "local & upstream are not merged yet",
-                result: vec![],
-            },
+            ),
+            (false, false) if merge_tracker.is_unrelated(self.bases, &local.commit)? => (
+                "local shares no history with bases",
+                vec![ClassifiedBranch::Unrelated(local.branch)],
+            ),
+            (false, false) => ("local & upstream are not merged yet", vec![]),
         };
 
-        Ok(result)
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into.or(upstream_merged_into)),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
     }
 }
 
@@ -692,7 +1989,7 @@ impl ClassificationRequest for TrackingBranchClassificationRequest<'_> {
 /// However we can try manual classification without `remote.{remote}` entry.
 #[derive(Debug)]
 pub struct DirectFetchClassificationRequest<'a> {
-    pub base: &'a RemoteTrackingBranch,
+    pub bases: &'a [BaseRef],
     pub local: &'a LocalBranch,
     pub remote: &'a RemoteBranch,
 }
@@ -706,48 +2003,52 @@ impl<'a> ClassificationRequestWithContext<&'a [RemoteHead]>
         merge_tracker: &MergeTracker,
         remote_heads: &[RemoteHead],
     ) -> Result<ClassificationResponse> {
-        let local = merge_tracker.check_and_track(&git.repo, &self.base.refname, self.local)?;
+        let (merged_into, local) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.local)?;
         let remote_head = remote_heads
             .iter()
             .find(|h| h.remote == self.remote.remote && h.refname == self.remote.refname)
             .map(|h| &h.commit);
 
-        let result = match (local.merged, remote_head) {
-            (true, Some(head)) if head == &local.commit => ClassificationResponse {
-                message: "local & remote are merged",
-                result: vec![ClassifiedBranch::MergedDirectFetch {
+        let merge_states = vec![local.to_refname_state()];
+        let (message, result) = match (local.merged, remote_head) {
+            (true, Some(head)) if head == &local.commit => (
+                "local & remote are merged",
+                vec![ClassifiedBranch::MergedDirectFetch {
                     local: local.branch,
                     remote: self.remote.clone(),
                 }],
-            },
-            (true, Some(_)) => ClassificationResponse {
-                message: "local is merged, but diverged with upstream",
-                result: vec![ClassifiedBranch::DivergedDirectFetch {
+            ),
+            (true, Some(_)) => (
+                "local is merged, but diverged with upstream",
+                vec![ClassifiedBranch::DivergedDirectFetch {
                     local: local.branch,
                     remote: self.remote.clone(),
                 }],
-            },
-            (true, None) => ClassificationResponse {
-                message: "local is merged and its upstream is gone",
-                result: vec![ClassifiedBranch::MergedLocal(local.branch)],
-            },
-            (false, None) => ClassificationResponse {
-                message: "local is not merged but the remote is gone somehow",
-                result: vec![ClassifiedBranch::Stray(local.branch)],
-            },
-            (false, _) => ClassificationResponse {
-                message: "local is not merged yet",
-                result: vec![],
-            },
+            ),
+            (true, None) => (
+                "local is merged and its upstream is gone",
+                vec![ClassifiedBranch::MergedLocal(local.branch)],
+            ),
+            (false, None) => (
+                "local is not merged but the remote is gone somehow",
+                vec![ClassifiedBranch::Stray(local.branch)],
+            ),
+            (false, _) => ("local is not merged yet", vec![]),
         };
 
-        Ok(result)
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct NonTrackingBranchClassificationRequest<'a> {
-    pub base: &'a RemoteTrackingBranch,
+    pub bases: &'a [BaseRef],
     pub local: &'a LocalBranch,
 }
 
@@ -757,25 +2058,98 @@ impl ClassificationRequest for NonTrackingBranchClassificationRequest<'_> {
         git: ForceSendSync<&Git>,
         merge_tracker: &MergeTracker,
     ) -> Result<ClassificationResponse> {
-        let local = merge_tracker.check_and_track(&git.repo, &self.base.refname, self.local)?;
-        let result = if local.merged {
-            ClassificationResponse {
-                message: "non-tracking local is merged",
-                result: vec![ClassifiedBranch::MergedNonTrackingLocal(local.branch)],
-            }
+        let (merged_into, local) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.local)?;
+        let merge_states = vec![local.to_refname_state()];
+        let (message, result) = if local.merged {
+            (
+                "non-tracking local is merged",
+                vec![ClassifiedBranch::MergedNonTrackingLocal(local.branch)],
+            )
+        } else if merge_tracker.is_unrelated(self.bases, &local.commit)? {
+            (
+                "non-tracking local shares no history with bases",
+                vec![ClassifiedBranch::Unrelated(local.branch)],
+            )
         } else {
-            ClassificationResponse {
-                message: "non-tracking local is not merged",
-                result: vec![],
-            }
+            ("non-tracking local is not merged", vec![])
         };
-        Ok(result)
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct StackedBranchClassificationRequest<'a> {
+    pub bases: &'a [BaseRef],
+    pub local: &'a LocalBranch,
+}
+
+impl ClassificationRequest for StackedBranchClassificationRequest<'_> {
+    fn classify(
+        &self,
+        git: ForceSendSync<&Git>,
+        merge_tracker: &MergeTracker,
+    ) -> Result<ClassificationResponse> {
+        let (merged_into, local) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.local)?;
+        let merge_states = vec![local.to_refname_state()];
+        let (message, result) = if local.merged {
+            (
+                "stacked local is merged",
+                vec![ClassifiedBranch::MergedLocal(local.branch)],
+            )
+        } else {
+            ("stacked local is not merged", vec![])
+        };
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BrokenUpstreamClassificationRequest<'a> {
+    pub bases: &'a [BaseRef],
+    pub local: &'a LocalBranch,
+}
+
+impl ClassificationRequest for BrokenUpstreamClassificationRequest<'_> {
+    fn classify(
+        &self,
+        git: ForceSendSync<&Git>,
+        merge_tracker: &MergeTracker,
+    ) -> Result<ClassificationResponse> {
+        let (merged_into, local) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.local)?;
+        let merge_states = vec![local.to_refname_state()];
+        let (message, result) = if local.merged {
+            (
+                "local tracking a removed remote is merged",
+                vec![ClassifiedBranch::MergedLocal(local.branch)],
+            )
+        } else {
+            ("local tracking a removed remote is not merged", vec![])
+        };
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct NonUpstreamBranchClassificationRequest<'a> {
-    pub base: &'a RemoteTrackingBranch,
+    pub bases: &'a [BaseRef],
     pub remote: &'a RemoteTrackingBranch,
 }
 
@@ -785,30 +2159,242 @@ impl ClassificationRequest for NonUpstreamBranchClassificationRequest<'_> {
         git: ForceSendSync<&Git>,
         merge_tracker: &MergeTracker,
     ) -> Result<ClassificationResponse> {
-        let remote = merge_tracker.check_and_track(&git.repo, &self.base.refname, self.remote)?;
-        let result = if remote.merged {
-            ClassificationResponse {
-                message: "non-upstream local is merged",
-                result: vec![ClassifiedBranch::MergedNonUpstreamRemoteTracking(
+        let (merged_into, remote) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.remote)?;
+        let merge_states = vec![remote.to_refname_state()];
+        let (message, result) = if remote.merged {
+            (
+                "non-upstream local is merged",
+                vec![ClassifiedBranch::MergedNonUpstreamRemoteTracking(
                     remote.branch,
                 )],
-            }
+            )
         } else {
-            ClassificationResponse {
-                message: "non-upstream local is not merged",
-                result: vec![],
-            }
+            ("non-upstream local is not merged", vec![])
         };
-        Ok(result)
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PullRefClassificationRequest<'a> {
+    pub bases: &'a [BaseRef],
+    pub remote_tracking: &'a RemoteTrackingBranch,
+    pub pull: &'a RemoteBranch,
+}
+
+impl ClassificationRequest for PullRefClassificationRequest<'_> {
+    fn classify(
+        &self,
+        git: ForceSendSync<&Git>,
+        merge_tracker: &MergeTracker,
+    ) -> Result<ClassificationResponse> {
+        let (merged_into, remote_tracking) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.remote_tracking)?;
+        let merge_states = vec![remote_tracking.to_refname_state()];
+        let (message, result) = if remote_tracking.merged {
+            (
+                "pull ref is merged",
+                vec![ClassifiedBranch::MergedPullRef {
+                    remote_tracking: remote_tracking.branch,
+                    pull: self.pull.clone(),
+                }],
+            )
+        } else {
+            ("pull ref is not merged", vec![])
+        };
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoteOnlyClassificationRequest<'a> {
+    pub bases: &'a [BaseRef],
+    pub remote_only: &'a RemoteOnlyBranch,
+}
+
+impl ClassificationRequest for RemoteOnlyClassificationRequest<'_> {
+    fn classify(
+        &self,
+        git: ForceSendSync<&Git>,
+        merge_tracker: &MergeTracker,
+    ) -> Result<ClassificationResponse> {
+        let (merged_into, remote_only) =
+            merge_tracker.check_and_track_bases(&git.repo, self.bases, self.remote_only)?;
+        let merge_states = vec![remote_only.to_refname_state()];
+        let (message, result) = if remote_only.merged {
+            (
+                "remote-only branch is merged",
+                vec![ClassifiedBranch::MergedRemoteOnly(remote_only.branch)],
+            )
+        } else {
+            ("remote-only branch is not merged", vec![])
+        };
+        Ok(ClassificationResponse {
+            base: concluded_base(self.bases, merged_into),
+            message: message.to_owned(),
+            merge_states,
+            result,
+        })
+    }
+}
+
+/// Get branches on `remote` that don't have remote tracking branches, with `git ls-remote`.
+/// Their tip commits are fetched if they are missing.
+/// It fails with `SubprocessError::Timeout` if the remote doesn't respond in `remote_timeout`.
+pub fn get_remote_only_branches(
+    git: &Git,
+    remote: &str,
+    scope: &BranchScope,
+    remote_timeout: Duration,
+) -> Result<Vec<RemoteOnlyBranch>> {
+    let mut result = Vec::new();
+    for head in subprocess::ls_remote_heads(&git.repo, remote, remote_timeout)? {
+        if !scope.contains(&head.refname) {
+            continue;
+        }
+        let remote_branch = RemoteBranch {
+            remote: remote.to_owned(),
+            refname: head.refname,
+        };
+        if let RemoteTrackingBranchStatus::Exists(_) =
+            RemoteTrackingBranch::from_remote_branch(&git.repo, &remote_branch)?
+        {
+            continue;
+        }
+        result.push(RemoteOnlyBranch {
+            remote: remote_branch,
+            commit: head.commit,
+        });
+    }
+
+    let has_commit = |branch: &RemoteOnlyBranch| {
+        git2::Oid::from_str(&branch.commit).map_or(false, |oid| git.repo.find_commit(oid).is_ok())
+    };
+    let missing: Vec<_> = result.iter().filter(|branch| !has_commit(branch)).collect();
+    if !missing.is_empty() {
+        let commits: Vec<_> = missing
+            .iter()
+            .map(|branch| branch.commit.as_str())
+            .collect();
+        if let Err(err) = subprocess::fetch_objects(&git.repo, remote, &commits) {
+            // Some remotes don't allow to fetch commits that are not tips of refs, e.g. the moved ones.
+            debug!("Fetch branches instead of commits: {:#}", err);
+            let refnames: Vec<_> = missing
+                .iter()
+                .map(|branch| branch.remote.refname.as_str())
+                .collect();
+            subprocess::fetch_objects(&git.repo, remote, &refnames)?;
+        }
+    }
+    result.retain(|branch| {
+        // It might be moved after `git ls-remote`.
+        let fetched = has_commit(branch);
+        if !fetched {
+            warn!("{} is moved while it is fetched", branch.remote);
+        }
+        fetched
+    });
+    Ok(result)
+}
+
+/// Branches whose names are in one of the namespaces. Every branch is in the scope if there are none.
+/// Branches are scoped before they are inspected further, so that it is cheap to trim a few namespaces of
+/// a repository with thousands of branches.
+#[derive(Debug, Clone, Default)]
+pub struct BranchScope {
+    prefixes: Vec<String>,
+    /// Longer ones first, so that `refs/remotes/<remote>/` is stripped correctly
+    /// even if a remote name is a prefix of another.
+    remotes: Vec<String>,
+}
+
+impl BranchScope {
+    pub fn new(repo: &Repository, prefixes: &[&str]) -> Result<Self> {
+        let prefixes = prefixes
+            .iter()
+            .map(|prefix| {
+                let prefix = prefix.trim_end_matches('*');
+                if prefix.is_empty() || prefix.ends_with('/') {
+                    prefix.to_owned()
+                } else {
+                    format!("{}/", prefix)
+                }
+            })
+            .collect();
+        let mut remotes: Vec<String> = repo
+            .remotes()?
+            .iter()
+            .flatten()
+            .map(str::to_owned)
+            .collect();
+        remotes.sort_by_key(|remote| std::cmp::Reverse(remote.len()));
+        Ok(BranchScope { prefixes, remotes })
+    }
+
+    /// Everything is in the scope.
+    pub fn all() -> Self {
+        BranchScope::default()
     }
+
+    pub fn contains(&self, refname: &str) -> bool {
+        if self.prefixes.is_empty() {
+            return true;
+        }
+        let name = if let Some(name) = refname.strip_prefix("refs/heads/") {
+            Some(name)
+        } else if let Some(rest) = refname.strip_prefix("refs/remotes/") {
+            self.remotes
+                .iter()
+                .find_map(|remote| rest.strip_prefix(remote.as_str())?.strip_prefix('/'))
+                .or_else(|| rest.split_once('/').map(|(_, name)| name))
+        } else {
+            None
+        };
+        match name {
+            Some(name) => self.prefixes.iter().any(|prefix| name.starts_with(prefix)),
+            None => false,
+        }
+    }
+}
+
+/// Local and remote tracking branches that are skipped since their refnames are not valid UTF-8.
+pub fn get_non_utf8_refnames(repo: &Repository) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let name = reference.name_bytes();
+        if std::str::from_utf8(name).is_ok() {
+            continue;
+        }
+        if name.starts_with(b"refs/heads/") || name.starts_with(b"refs/remotes/") {
+            result.push(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    result.sort();
+    Ok(result)
 }
 
 pub fn get_tracking_branches(
     git: &Git,
+    scope: &BranchScope,
 ) -> Result<Vec<(LocalBranch, Option<RemoteTrackingBranch>)>> {
     let mut result = Vec::new();
-    for branch in git.repo.branches(Some(BranchType::Local))? {
-        let local = LocalBranch::try_from(&branch?.0)?;
+    for branch in utf8_branches(&git.repo, BranchType::Local)? {
+        let local = LocalBranch::try_from(&branch)?;
+        if !scope.contains(&local.refname) {
+            continue;
+        }
 
         match local.fetch_upstream(&git.repo, &git.config)? {
             RemoteTrackingBranchStatus::Exists(upstream) => {
@@ -825,10 +2411,16 @@ pub fn get_tracking_branches(
 }
 
 /// Get `hub-cli` style direct fetched branches
-pub fn get_direct_fetch_branches(git: &Git) -> Result<Vec<(LocalBranch, RemoteBranch)>> {
+pub fn get_direct_fetch_branches(
+    git: &Git,
+    scope: &BranchScope,
+) -> Result<Vec<(LocalBranch, RemoteBranch)>> {
     let mut result = Vec::new();
-    for branch in git.repo.branches(Some(BranchType::Local))? {
-        let local = LocalBranch::try_from(&branch?.0)?;
+    for branch in utf8_branches(&git.repo, BranchType::Local)? {
+        let local = LocalBranch::try_from(&branch)?;
+        if !scope.contains(&local.refname) {
+            continue;
+        }
 
         let remote = if let Some(remote) = config::get_remote_name(&git.config, &local)? {
             remote
@@ -836,7 +2428,11 @@ pub fn get_direct_fetch_branches(git: &Git) -> Result<Vec<(LocalBranch, RemoteBr
             continue;
         };
 
-        if config::get_remote(&git.repo, &remote)?.is_some() {
+        // Stacked branches track other local branches.
+        if remote == "."
+            || config::get_remote(&git.repo, &remote)?.is_some()
+            || is_removed_remote(&git.repo, &remote)?
+        {
             continue;
         }
 
@@ -856,11 +2452,52 @@ pub fn get_direct_fetch_branches(git: &Git) -> Result<Vec<(LocalBranch, RemoteBr
     Ok(result)
 }
 
+/// Get local branches that track remotes that are removed.
+pub fn get_broken_upstream_branches(git: &Git, scope: &BranchScope) -> Result<Vec<BrokenUpstream>> {
+    let mut result = Vec::new();
+    for branch in utf8_branches(&git.repo, BranchType::Local)? {
+        let local = LocalBranch::try_from(&branch)?;
+        if !scope.contains(&local.refname) {
+            continue;
+        }
+
+        let remote = if let Some(remote) = config::get_remote_name(&git.config, &local)? {
+            remote
+        } else {
+            continue;
+        };
+
+        if is_removed_remote(&git.repo, &remote)? {
+            result.push(BrokenUpstream { local, remote });
+        }
+    }
+
+    Ok(result)
+}
+
+/// `branch.<name>.remote` can be a URL or a path of a direct fetched branch.
+/// A name that is neither a remote, nor looks like a URL or a path is a leftover of a removed remote.
+fn is_removed_remote(repo: &Repository, remote: &str) -> Result<bool> {
+    if remote == "." || config::get_remote(repo, remote)?.is_some() {
+        return Ok(false);
+    }
+    if remote.contains(['/', '\\', ':']) {
+        return Ok(false);
+    }
+    let is_path = repo
+        .workdir()
+        .map_or(false, |workdir| workdir.join(remote).exists());
+    Ok(!is_path)
+}
+
 /// Get local branches that doesn't track any branch.
-pub fn get_non_tracking_local_branches(git: &Git) -> Result<Vec<LocalBranch>> {
+pub fn get_non_tracking_local_branches(git: &Git, scope: &BranchScope) -> Result<Vec<LocalBranch>> {
     let mut result = Vec::new();
-    for branch in git.repo.branches(Some(BranchType::Local))? {
-        let branch = LocalBranch::try_from(&branch?.0)?;
+    for branch in utf8_branches(&git.repo, BranchType::Local)? {
+        let branch = LocalBranch::try_from(&branch)?;
+        if !scope.contains(&branch.refname) {
+            continue;
+        }
 
         if config::get_remote_name(&git.config, &branch)?.is_some() {
             continue;
@@ -873,10 +2510,14 @@ pub fn get_non_tracking_local_branches(git: &Git) -> Result<Vec<LocalBranch>> {
 }
 
 /// Get remote tracking branches that doesn't tracked by any branch.
-pub fn get_non_upstream_remote_tracking_branches(git: &Git) -> Result<Vec<RemoteTrackingBranch>> {
+pub fn get_non_upstream_remote_tracking_branches(
+    git: &Git,
+    scope: &BranchScope,
+) -> Result<Vec<RemoteTrackingBranch>> {
     let mut upstreams = HashSet::new();
 
-    let tracking_branches = get_tracking_branches(git)?;
+    // Out of scope local branches may track remote tracking branches in the scope.
+    let tracking_branches = get_tracking_branches(git, &BranchScope::all())?;
     for (_local, upstream) in tracking_branches {
         if let Some(upstream) = upstream {
             upstreams.insert(upstream);
@@ -884,17 +2525,23 @@ pub fn get_non_upstream_remote_tracking_branches(git: &Git) -> Result<Vec<Remote
     }
 
     let mut result = Vec::new();
-    for branch in git.repo.branches(Some(BranchType::Remote))? {
-        let (branch, _) = branch?;
+    for branch in utf8_branches(&git.repo, BranchType::Remote)? {
         if branch.get().symbolic_target_bytes().is_some() {
             continue;
         }
 
         let branch = RemoteTrackingBranch::try_from(&branch)?;
+        if !scope.contains(&branch.refname) {
+            continue;
+        }
 
         if upstreams.contains(&branch) {
             continue;
         }
+        if let Err(RemoteBranchError::RemoteNotFound) = branch.to_remote_branch(&git.repo) {
+            // They are collected by `get_orphan_namespaces`.
+            continue;
+        }
 
         result.push(branch);
     }
@@ -902,24 +2549,193 @@ pub fn get_non_upstream_remote_tracking_branches(git: &Git) -> Result<Vec<Remote
     Ok(result)
 }
 
-pub fn get_remote_heads(git: &Git, branches: &[RemoteBranch]) -> Result<Vec<RemoteHead>> {
+/// Remote tracking refs of remote refs in `namespaces`, fetched by custom refspecs of remotes,
+/// e.g. `refs/remotes/origin/pr/1` of `refs/pull/1/head` by `+refs/pull/*/head:refs/remotes/origin/pr/*`.
+pub fn get_pull_refs(
+    git: &Git,
+    scope: &BranchScope,
+    namespaces: &[&str],
+) -> Result<Vec<(RemoteTrackingBranch, RemoteBranch)>> {
+    let mut result = Vec::new();
+    let mut found = HashSet::new();
+    for remote_name in git.repo.remotes()?.iter() {
+        let remote_name = remote_name.context("non-utf8 remote name")?;
+        let remote = git.repo.find_remote(remote_name)?;
+        for (src, dst) in refspec_pairs(&remote, Direction::Fetch)? {
+            if !dst.starts_with("refs/remotes/") {
+                continue;
+            }
+            for reference in git.repo.references_glob(&dst)? {
+                let reference = reference?;
+                let refname = match reference.name() {
+                    Some(refname) if reference.symbolic_target_bytes().is_none() => refname,
+                    _ => continue,
+                };
+                if !scope.contains(refname) || found.contains(refname) {
+                    continue;
+                }
+                let pull = match expand(&dst, &src, refname) {
+                    Some(pull) => pull,
+                    None => continue,
+                };
+                if namespaces
+                    .iter()
+                    .any(|namespace| glob_match(namespace, &pull))
+                {
+                    found.insert(refname.to_owned());
+                    result.push((
+                        RemoteTrackingBranch::new(refname),
+                        RemoteBranch {
+                            remote: remote_name.to_owned(),
+                            refname: pull,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Remote tracking branches that no remote fetches into, grouped by the removed remotes.
+/// Remote names may contain `/`, so each ref is matched against the longest remote name.
+pub fn get_orphan_namespaces(git: &Git) -> Result<Vec<OrphanNamespace>> {
+    let remotes: Vec<String> = git
+        .repo
+        .remotes()?
+        .iter()
+        .flatten()
+        .map(str::to_owned)
+        .collect();
+    // Local branches might still track removed remotes, which tell their names.
+    let mut removed_remotes = Vec::new();
+    for branch in utf8_branches(&git.repo, BranchType::Local)? {
+        let local = LocalBranch::try_from(&branch)?;
+        if let Some(remote) = config::get_remote_name(&git.config, &local)? {
+            if remote != "." && !remotes.contains(&remote) && !removed_remotes.contains(&remote) {
+                removed_remotes.push(remote);
+            }
+        }
+    }
+
+    let mut namespaces: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for reference in git.repo.references_glob("refs/remotes/*")? {
+        let reference = reference?;
+        let refname = match reference.name() {
+            Some(refname) => refname,
+            None => continue,
+        };
+        let name = match refname.strip_prefix("refs/remotes/") {
+            Some(name) => name,
+            None => continue,
+        };
+        // Refs under an existing remote are its own, even if the name of a removed remote is a prefix of them.
+        if longest_remote_prefix(&remotes, name).is_some() {
+            continue;
+        }
+        // A remote with custom refspecs might fetch into other namespaces.
+        let remote_tracking = RemoteTrackingBranch::new(refname);
+        match remote_tracking.to_remote_branch(&git.repo) {
+            Err(RemoteBranchError::RemoteNotFound) => {}
+            _ => continue,
+        }
+        let remote = match longest_remote_prefix(&removed_remotes, name) {
+            Some(remote) => remote,
+            None => match name.split_once('/') {
+                Some((remote, _)) => remote,
+                None => continue,
+            },
+        };
+        namespaces
+            .entry(remote.to_owned())
+            .or_default()
+            .push(refname.to_owned());
+    }
+
+    Ok(namespaces
+        .into_iter()
+        .map(|(remote, refnames)| OrphanNamespace { remote, refnames })
+        .collect())
+}
+
+/// The longest name of `remotes` that `name` (e.g. `foo/bar/feature`) is in the namespace of.
+fn longest_remote_prefix<'a>(remotes: &'a [String], name: &str) -> Option<&'a str> {
+    remotes
+        .iter()
+        .filter(|remote| {
+            name.strip_prefix(remote.as_str())
+                .map_or(false, |rest| rest.starts_with('/'))
+        })
+        .max_by_key(|remote| remote.len())
+        .map(String::as_str)
+}
+
+/// Heads of remotes, and remotes that didn't respond in time.
+#[derive(Debug, Default)]
+pub struct RemoteHeads {
+    pub heads: Vec<RemoteHead>,
+    pub unreachable: Vec<String>,
+}
+
+pub fn get_remote_heads(
+    git: &Git,
+    branches: &[RemoteBranch],
+    remote_timeout: Duration,
+    progress: &ProgressFn,
+) -> Result<RemoteHeads> {
     let mut remote_urls = Vec::new();
 
     for branch in branches {
-        remote_urls.push(&branch.remote);
+        if !remote_urls.contains(&&branch.remote) {
+            remote_urls.push(&branch.remote);
+        }
     }
 
-    Ok(remote_urls
+    let total = remote_urls.len();
+    let done = AtomicUsize::new(0);
+    let results = remote_urls
         .into_par_iter()
         .map({
             let git = ForceSendSync::new(git);
+            let done = &done;
             move |remote_url| {
-                subprocess::ls_remote_heads(&git.repo, remote_url)
-                    .with_context(|| format!("remote_url={}", remote_url))
+                let result = subprocess::ls_remote_heads(&git.repo, remote_url, remote_timeout);
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                progress(Progress::ScanRemote {
+                    remote: remote_url,
+                    done,
+                    total,
+                });
+                (remote_url, result)
             }
         })
-        .collect::<Result<Vec<Vec<RemoteHead>>, _>>()?
-        .into_iter()
-        .flatten()
-        .collect::<Vec<RemoteHead>>())
+        .collect::<Vec<_>>();
+
+    let mut result = RemoteHeads::default();
+    for (remote_url, heads) in results {
+        match heads {
+            Ok(heads) => result.heads.extend(heads),
+            Err(err) if is_timeout(&err) => {
+                warn!("{:#}", err);
+                result.unreachable.push(remote_url.clone());
+            }
+            Err(err) => return Err(err.context(format!("remote_url={}", remote_url))),
+        }
+    }
+    Ok(result)
 }
+
+/// Progress of long running steps while planning.
+#[derive(Debug, Clone, Copy)]
+pub enum Progress<'a> {
+    /// `git ls-remote` to a remote is done.
+    ScanRemote {
+        remote: &'a str,
+        done: usize,
+        total: usize,
+    },
+    /// A classification request is done.
+    Classify { done: usize, total: usize },
+}
+
+pub type ProgressFn<'a> = dyn Fn(Progress) + Send + Sync + 'a;
@@ -0,0 +1,100 @@
+//! Errors of the public API, so that library consumers can match on their kinds.
+//! Internally, errors are `anyhow::Error`s with contexts, and they are classified into `Error`
+//! where they leave the API.
+
+use git2::ErrorClass;
+use thiserror::Error;
+
+use crate::branch::RemoteBranch;
+use crate::subprocess::{AuthenticationFailure, PushRejection, SubprocessError};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Git configs are invalid, e.g. a config file failed to parse, or `push.default` is unknown.
+    #[error("Invalid config: {0:#}")]
+    Config(anyhow::Error),
+    /// None of the bases resolves to a branch or a commit. `tried` are the refnames or patterns tried for them.
+    #[error("No base branch is found among: {} (tried {})", .bases.join(", "), .tried.join(", "))]
+    BaseResolution {
+        bases: Vec<String>,
+        tried: Vec<String>,
+    },
+    /// A git operation on a branch failed.
+    #[error("{error:#}")]
+    Branch {
+        refname: String,
+        error: anyhow::Error,
+    },
+    /// The remote rejected to delete a branch.
+    #[error(transparent)]
+    PushRejected(#[from] PushRejection),
+    /// The remote refused the credentials, or they couldn't be read.
+    #[error(transparent)]
+    Authentication(#[from] AuthenticationFailure),
+    /// A git subprocess failed or timed out. `command` is the command line, and `code` is its exit code.
+    #[error("{error:#}")]
+    Subprocess {
+        command: String,
+        code: Option<i32>,
+        error: anyhow::Error,
+    },
+    /// A local branch is kept, since its remote branch of the same pair is failed to delete.
+    #[error("kept, since {}:{} is failed to delete", .remote.remote, .remote.refname)]
+    PartiallyFailed { remote: RemoteBranch },
+    /// Other errors, e.g. from libgit2 or the file system.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// A config value that git-trim doesn't understand.
+#[derive(Error, Debug)]
+#[error("Unknown value for {key}: {value}")]
+pub struct InvalidConfig {
+    pub key: String,
+    pub value: String,
+}
+
+impl Error {
+    pub(crate) fn branch(refname: &str, error: anyhow::Error) -> Self {
+        Error::Branch {
+            refname: refname.to_owned(),
+            error,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<PushRejection>() {
+            Ok(rejection) => return Error::PushRejected(rejection),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<AuthenticationFailure>() {
+            Ok(failure) => return Error::Authentication(failure),
+            Err(error) => error,
+        };
+        if let Some(subprocess) = error.downcast_ref::<SubprocessError>() {
+            let command = format!("git {}", subprocess.args().join(" "));
+            let code = subprocess.code();
+            return Error::Subprocess {
+                command,
+                code,
+                error,
+            };
+        }
+        let invalid_config = error.downcast_ref::<InvalidConfig>().is_some()
+            || matches!(
+                error.downcast_ref::<git2::Error>(),
+                Some(err) if err.class() == ErrorClass::Config
+            );
+        if invalid_config {
+            Error::Config(error)
+        } else {
+            Error::Other(error)
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Advice that is given along with the output, e.g. flags to scan skipped branches.
+//! Each kind of hint has an id, so that it can be turned off with `--hint no-<id>` or `trim.hints`,
+//! and tools reading the JSON plan get them as data rather than prose.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::core::{SkipSuggestion, TrimPlan};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum HintId {
+    /// Flags to scan skipped branches
+    SkipSuggestions,
+    /// `--fix-upstreams` for branches tracking removed remotes
+    FixUpstreams,
+    /// `--fix-gone=unset` for kept branches whose upstreams are gone
+    FixGone,
+    /// `--retarget-renamed` for branches whose upstreams seem to be renamed
+    RetargetRenamed,
+    /// `--prune-removed-remotes` for remote tracking branches of removed remotes
+    PruneRemovedRemotes,
+    /// Commands to sync `refs/remotes/*/HEAD` that are deduced as bases
+    RemoteHeadOutOfSync,
+    /// Ways to set bases when none is found
+    BaseResolution,
+}
+
+impl HintId {
+    pub const ALL: &'static [HintId] = &[
+        HintId::SkipSuggestions,
+        HintId::FixUpstreams,
+        HintId::FixGone,
+        HintId::RetargetRenamed,
+        HintId::PruneRemovedRemotes,
+        HintId::RemoteHeadOutOfSync,
+        HintId::BaseResolution,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HintId::SkipSuggestions => "skip-suggestions",
+            HintId::FixUpstreams => "fix-upstreams",
+            HintId::FixGone => "fix-gone",
+            HintId::RetargetRenamed => "retarget-renamed",
+            HintId::PruneRemovedRemotes => "prune-removed-remotes",
+            HintId::RemoteHeadOutOfSync => "remote-head-out-of-sync",
+            HintId::BaseResolution => "base-resolution",
+        }
+    }
+}
+
+impl fmt::Display for HintId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HintId {
+    type Err = HintParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HintId::ALL
+            .iter()
+            .copied()
+            .find(|id| id.as_str() == s.trim())
+            .ok_or_else(|| HintParseError(s.to_owned()))
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown hint `{0}`")]
+pub struct HintParseError(String);
+
+/// `<id>` turns a hint on, and `no-<id>` turns it off.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HintToggle {
+    pub id: HintId,
+    pub enabled: bool,
+}
+
+impl FromStr for HintToggle {
+    type Err = HintParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().strip_prefix("no-") {
+            Some(id) => Ok(HintToggle {
+                id: id.parse()?,
+                enabled: false,
+            }),
+            None => Ok(HintToggle {
+                id: s.parse()?,
+                enabled: true,
+            }),
+        }
+    }
+}
+
+/// All hints are shown unless they are turned off. Later toggles take precedence.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct HintFilter {
+    disabled: HashSet<HintId>,
+}
+
+impl HintFilter {
+    pub fn enabled(&self, id: HintId) -> bool {
+        !self.disabled.contains(&id)
+    }
+}
+
+impl FromIterator<HintToggle> for HintFilter {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = HintToggle>,
+    {
+        let mut disabled = HashSet::new();
+        for toggle in iter {
+            if toggle.enabled {
+                disabled.remove(&toggle.id);
+            } else {
+                disabled.insert(toggle.id);
+            }
+        }
+        HintFilter { disabled }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Hint {
+    pub id: HintId,
+    pub message: String,
+    /// Things to try. Each of them is an alternative.
+    pub suggestions: Vec<String>,
+}
+
+impl Hint {
+    pub fn new(id: HintId, message: impl Into<String>) -> Self {
+        Hint {
+            id,
+            message: message.into(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestions.push(suggestion.into());
+        self
+    }
+}
+
+/// General ways to set bases manually.
+pub const SET_BASES_MANUALLY: &[&str] = &[
+    "`git config trim.bases develop,master` for a repository.",
+    "`git config --global trim.bases develop,master` to set globally.",
+    "`git trim --bases develop,master` to set temporarily.",
+];
+
+pub fn skip_suggestions(plan: &TrimPlan) -> Option<Hint> {
+    if plan.skipped.is_empty() {
+        return None;
+    }
+    let mut hint = Hint::new(
+        HintId::SkipSuggestions,
+        "Some branches are skipped. Consider following to scan them:",
+    );
+
+    let tracking = plan
+        .skipped
+        .values()
+        .any(|suggest| suggest == &SkipSuggestion::Tracking);
+    let tracking_remotes: Vec<_> = plan
+        .skipped
+        .values()
+        .filter_map(|suggest| match suggest {
+            SkipSuggestion::TrackingRemote(remote) => Some(remote),
+            _ => None,
+        })
+        .collect();
+    if let [single] = tracking_remotes.as_slice() {
+        hint = hint.with_suggestion(format!(
+            "*{}: Add `--delete 'merged:{}'` flag.",
+            SkipSuggestion::KIND_TRACKING,
+            single
+        ));
+    } else if tracking_remotes.len() > 1 {
+        hint = hint.with_suggestion(format!(
+            "*{}: Add `--delete 'merged:*'` flag.",
+            SkipSuggestion::KIND_TRACKING,
+        ));
+    } else if tracking {
+        hint = hint.with_suggestion(format!(
+            "*{}: Add `--delete 'merged-local'` flag.",
+            SkipSuggestion::KIND_TRACKING,
+        ));
+    }
+
+    let non_tracking = plan
+        .skipped
+        .values()
+        .any(|suggest| suggest == &SkipSuggestion::NonTracking);
+    if non_tracking {
+        hint = hint.with_suggestion(format!(
+            "*{}: Set an upstream to make it a tracking branch or add `--delete 'local'` flag.",
+            SkipSuggestion::KIND_NON_TRACKING,
+        ));
+    }
+
+    let non_upstream_remotes: Vec<_> = plan
+        .skipped
+        .values()
+        .filter_map(|suggest| match suggest {
+            SkipSuggestion::NonUpstream(remote) => Some(remote),
+            _ => None,
+        })
+        .collect();
+    if let [single] = non_upstream_remotes.as_slice() {
+        hint = hint.with_suggestion(format!(
+            "*{}: Make it upstream of a tracking branch or add `--delete 'remote:{}'` flag.",
+            SkipSuggestion::KIND_NON_UPSTREAM,
+            single
+        ));
+    } else if non_upstream_remotes.len() > 1 {
+        hint = hint.with_suggestion(format!(
+            "*{}: Make it upstream of a tracking branch or add `--delete 'remote:*'` flag.",
+            SkipSuggestion::KIND_NON_UPSTREAM,
+        ));
+    }
+    Some(hint)
+}
+
+pub fn fix_upstreams(plan: &TrimPlan) -> Option<Hint> {
+    if plan.broken_upstreams.is_empty() {
+        return None;
+    }
+    Some(Hint::new(
+        HintId::FixUpstreams,
+        "Add `--fix-upstreams` flag to delete merged ones, and unset upstreams of the others.",
+    ))
+}
+
+pub fn fix_gone(plan: &TrimPlan) -> Option<Hint> {
+    if plan.gone_upstreams.is_empty() {
+        return None;
+    }
+    Some(Hint::new(
+        HintId::FixGone,
+        "Add `--fix-gone=unset` to unset upstreams of kept branches whose upstreams are gone.",
+    ))
+}
+
+pub fn retarget_renamed(plan: &TrimPlan) -> Option<Hint> {
+    if plan.renamed_upstreams.is_empty() {
+        return None;
+    }
+    Some(Hint::new(
+        HintId::RetargetRenamed,
+        "Add `--retarget-renamed` flag to set their upstreams to the new names.",
+    ))
+}
+
+pub fn prune_removed_remotes(plan: &TrimPlan) -> Option<Hint> {
+    if plan.orphan_namespaces.is_empty() {
+        return None;
+    }
+    Some(Hint::new(
+        HintId::PruneRemovedRemotes,
+        "Add `--prune-removed-remotes` flag to delete them.",
+    ))
+}
@@ -0,0 +1,109 @@
+//! Queries to hosting services about remote repositories.
+//! They go through the CLIs of the services (`gh` and `glab`), so that their hosts and credentials
+//! are configured in one place, and git-trim doesn't need an HTTP client.
+
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use log::*;
+
+use crate::args::strip_url_scheme;
+use crate::subprocess;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Hosting {
+    /// `repo` is `<owner>/<name>`.
+    GitHub { host: String, repo: String },
+    /// `project` is `<namespace>/<name>`, where the namespace can be nested.
+    GitLab { host: String, project: String },
+}
+
+impl Hosting {
+    /// Hosts whose names contain `github` or `gitlab` are recognized, e.g. `github.example.com`.
+    pub fn from_url(url: &str) -> Option<Hosting> {
+        let stripped = strip_url_scheme(url);
+        let (host, path) = stripped.split_once('/')?;
+        // `host:port`
+        let host = host.split(':').next().unwrap_or(host).to_owned();
+        let path = path.trim_end_matches('/');
+        let path = path.strip_suffix(".git").unwrap_or(path).to_owned();
+        if path.split('/').count() < 2 {
+            return None;
+        }
+        if host.contains("github") {
+            Some(Hosting::GitHub { host, repo: path })
+        } else if host.contains("gitlab") {
+            Some(Hosting::GitLab {
+                host,
+                project: path,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Names of protected branches. They may be glob patterns, e.g. `release/*`.
+    pub fn protected_branches(&self) -> Result<Vec<String>> {
+        match self {
+            Hosting::GitHub { host, repo } => {
+                let endpoint = format!("repos/{}/branches?protected=true", repo);
+                let output = run(
+                    "gh",
+                    &[
+                        "api",
+                        "--paginate",
+                        "--hostname",
+                        host,
+                        &endpoint,
+                        "--jq",
+                        ".[].name",
+                    ],
+                )?;
+                Ok(output.lines().map(str::to_owned).collect())
+            }
+            Hosting::GitLab { host, project } => {
+                let endpoint = format!(
+                    "projects/{}/protected_branches",
+                    project.replace('/', "%2F")
+                );
+                let output = run(
+                    "glab",
+                    &["api", "--paginate", "--hostname", host, &endpoint],
+                )?;
+                parse_gitlab_protected_branches(&output)
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct GitLabProtectedBranch {
+    name: String,
+}
+
+/// Pages are printed one after another as JSON arrays.
+fn parse_gitlab_protected_branches(output: &str) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    for page in serde_json::Deserializer::from_str(output).into_iter::<Vec<GitLabProtectedBranch>>()
+    {
+        result.extend(page?.into_iter().map(|branch| branch.name));
+    }
+    Ok(result)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    trace!("> {} {}", program, args.join(" "));
+    let output = subprocess::output(
+        Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .with_context(|| format!("Failed to run `{}`", program))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("`{} {}` failed: {}", program, args.join(" "), stderr.trim());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
@@ -1,34 +1,75 @@
 pub mod args;
+pub mod backend;
 mod branch;
+mod commit_graph;
 pub mod config;
 mod core;
+mod error;
+pub mod hints;
+mod hosting;
+mod ls_remote;
 mod merge_tracker;
+mod plan_cache;
+mod reachability;
+pub mod report;
+mod signature;
 mod simple_glob;
+mod stack;
 mod subprocess;
+mod timings;
+pub mod trim_log;
 mod util;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use git2::{Config as GitConfig, Error as GitError, ErrorCode, Repository};
+use git2::{
+    BranchType, Config as GitConfig, ConfigLevel, Direction, Error as GitError, ErrorCode,
+    Reference, Repository, RepositoryState,
+};
 use log::*;
+use rayon::prelude::*;
+use thiserror::Error;
 
-use crate::args::DeleteFilter;
+use crate::args::{DeleteFilter, ScanTarget, SortKey};
 use crate::branch::RemoteTrackingBranchStatus;
 pub use crate::branch::{
-    LocalBranch, Refname, RemoteBranch, RemoteBranchError, RemoteTrackingBranch,
+    BaseRef, LocalBranch, Refname, RemoteBranch, RemoteBranchError, RemoteOnlyBranch,
+    RemoteTrackingBranch,
 };
+use crate::commit_graph::CommitGraph;
 use crate::core::{
-    get_direct_fetch_branches, get_non_tracking_local_branches,
-    get_non_upstream_remote_tracking_branches, get_remote_heads, get_tracking_branches, Classifier,
-    DirectFetchClassificationRequest, NonTrackingBranchClassificationRequest,
-    NonUpstreamBranchClassificationRequest, TrackingBranchClassificationRequest,
+    get_broken_upstream_branches, get_direct_fetch_branches, get_non_tracking_local_branches,
+    get_non_upstream_remote_tracking_branches, get_non_utf8_refnames, get_orphan_namespaces,
+    get_pull_refs, get_remote_heads, get_remote_only_branches, get_tracking_branches, BranchScope,
+    BrokenUpstreamClassificationRequest, Classifier, DirectFetchClassificationRequest,
+    NonTrackingBranchClassificationRequest, NonUpstreamBranchClassificationRequest,
+    PullRefClassificationRequest, RemoteHeads, RemoteOnlyClassificationRequest,
+    StackedBranchClassificationRequest, TrackingBranchClassificationRequest,
+};
+pub use crate::core::{
+    BranchPair, BranchRole, BrokenUpstream, ClassificationResponse, ClassifiedBranch,
+    OrphanNamespace, PlannedBranch, Preserved, Progress, ProgressFn, RenamedUpstream,
+    SkipSuggestion, TrimPlan, UnresolvedBase,
 };
-pub use crate::core::{ClassifiedBranch, SkipSuggestion, TrimPlan};
+pub use crate::error::{Error, InvalidConfig};
 use crate::merge_tracker::MergeTracker;
-pub use crate::subprocess::{ls_remote_head, remote_update, RemoteHead};
-pub use crate::util::ForceSendSync;
+pub use crate::merge_tracker::{MergeMetrics, MergeState, MergeStrategy, MergeStrategyParseError};
+pub use crate::signature::Signer;
+use crate::simple_glob::{expand_refspec, ExpansionSide};
+use crate::stack::Stacks;
+use crate::subprocess::is_timeout;
+pub use crate::subprocess::{
+    fast_forward_to_upstream, kill_running, log_oneline, ls_remote_head, remote_update,
+    AuthenticationFailure, CheckoutTarget, PushDeleteOutput, PushRejection, RejectionKind,
+    RemoteHead, SubprocessError,
+};
+use crate::timings::Stopwatch;
+pub use crate::timings::Timings;
+use crate::trim_log::{DeletedLocal, DeletedRemote, TrimLogRecord};
+pub use crate::util::{common_dir, format_date, shell_quote, ForceSendSync};
 
 pub struct Git {
     pub repo: Repository,
@@ -46,95 +87,309 @@ impl TryFrom<Repository> for Git {
 
 pub struct PlanParam<'a> {
     pub bases: Vec<&'a str>,
+    /// Commits to compare branches with, like bases. They can be any revisions.
+    pub base_commits: Vec<&'a str>,
+    /// Patterns in `.git/trim-ignore` and `.trim-ignore` are protected in addition to them.
     pub protected_patterns: Vec<&'a str>,
+    /// Preserve HEAD branches of remotes and local branches that track them, regardless of the patterns.
+    pub protect_head_of_remotes: bool,
+    /// Namespaces of branch names to scan. Everything is scanned if it is empty.
+    pub include_slash_prefixes: Vec<&'a str>,
+    pub remote_namespaces: Vec<&'a str>,
+    /// Namespaces of remote refs of fetched pull requests, for `--delete pull-refs:<remote>`.
+    pub pull_ref_namespaces: Vec<&'a str>,
     pub delete: DeleteFilter,
     pub detach: bool,
+    pub scan_push_remote: bool,
+    /// Additional sources of branches to scan.
+    pub scan: Vec<ScanTarget>,
+    /// Preserve local branches checked out within these days. 0 to disable.
+    pub recent_checkout_days: u64,
+    pub stray_grace_days: u64,
+    /// Strategies to detect merged branches.
+    pub merge_strategies: Vec<MergeStrategy>,
+    /// Index commits reachable from bases before classification, to tell merged branches by lookups.
+    pub reachability_index: bool,
+    /// Reuse classifications of the last plan if nothing they depend on has changed.
+    pub cache: bool,
+    /// Delete merged local branches that track removed remotes.
+    pub fix_upstreams: bool,
+    /// Preserve remote branches whose tip commits are signed by keys other than `signing_keys`.
+    pub preserve_signed_by_others: bool,
+    pub signing_keys: Vec<&'a str>,
+    /// Preserve remote branches whose tip commits are not authored by `user.email`.
+    pub only_mine: bool,
+    /// The order of branches to delete, which decides ones to delete within `limit`.
+    pub sort: SortKey,
+    /// Maximum number of branches to delete. 0 to disable.
+    pub limit: u64,
+    /// Delete merged branches that other stacked branches are based on.
+    /// Upstreams of the stacked branches are retargeted with `retarget_stacked_upstreams`.
+    pub retarget_stacks: bool,
+    /// Seconds to wait for each remote to respond to `git ls-remote`. 0 to wait forever.
+    /// Remotes that don't respond in time are reported as `TrimPlan::unreachable_remotes`.
+    pub remote_timeout: u64,
+    /// Do not contact remotes. Remotes that are needed are reported as `TrimPlan::unreachable_remotes`,
+    /// and `TrimPlan::possibly_stale` tells branches that are classified with remote tracking branches.
+    /// `scan_push_remote` is ignored.
+    pub offline: bool,
+    /// Preserve branches that are protected on their GitHub or GitLab remotes.
+    /// It runs `gh` or `glab`, and it is skipped when `offline` is set.
+    pub detect_remote_protection: bool,
+}
+
+pub fn get_trim_plan(git: &Git, param: &PlanParam) -> Result<TrimPlan, Error> {
+    get_trim_plan_with_progress(git, param, &|_| {})
+}
+
+/// Same as `get_trim_plan`, but reports progress of long running steps to `progress`.
+/// `progress` can be called from multiple threads.
+pub fn get_trim_plan_with_progress(
+    git: &Git,
+    param: &PlanParam,
+    progress: &ProgressFn,
+) -> Result<TrimPlan, Error> {
+    Ok(get_filtered_trim_plan(git, param, |_| true, progress)?)
+}
+
+/// Get a trim plan that only classifies the branch `refname` and its upstream.
+/// It is useful to explain how a specific branch is classified.
+pub fn get_trim_plan_of_branch(
+    git: &Git,
+    param: &PlanParam,
+    refname: &str,
+) -> Result<TrimPlan, Error> {
+    get_trim_plan_of_branches(git, param, &[refname])
+}
+
+/// Get a trim plan that only classifies the branches `refnames` and their upstreams.
+pub fn get_trim_plan_of_branches(
+    git: &Git,
+    param: &PlanParam,
+    refnames: &[&str],
+) -> Result<TrimPlan, Error> {
+    let mut targets: HashSet<String> = refnames
+        .iter()
+        .map(|refname| (*refname).to_owned())
+        .collect();
+    for refname in refnames {
+        if !refname.starts_with("refs/heads/") {
+            continue;
+        }
+        if let RemoteTrackingBranchStatus::Exists(upstream) =
+            LocalBranch::new(refname).fetch_upstream(&git.repo, &git.config)?
+        {
+            targets.insert(upstream.refname);
+        }
+    }
+    Ok(get_filtered_trim_plan(
+        git,
+        param,
+        |target| targets.contains(target),
+        &|_| {},
+    )?)
 }
 
-pub fn get_trim_plan(git: &Git, param: &PlanParam) -> Result<TrimPlan> {
-    let bases = resolve_bases(&git.repo, &git.config, &param.bases)?;
+fn get_filtered_trim_plan(
+    git: &Git,
+    param: &PlanParam,
+    filter: impl Fn(&str) -> bool,
+    progress: &ProgressFn,
+) -> Result<TrimPlan> {
+    let mut stopwatch = Stopwatch::start();
+    let (bases, unresolved_bases) =
+        resolve_bases(&git.repo, &git.config, &param.bases, &param.base_commits)?;
+    if bases.is_empty() {
+        let bases = param.bases.iter().chain(&param.base_commits);
+        return Err(Error::BaseResolution {
+            bases: bases.map(|base| (*base).to_owned()).collect(),
+            tried: unresolved_bases
+                .into_iter()
+                .flat_map(|unresolved| unresolved.candidates)
+                .collect(),
+        }
+        .into());
+    }
+    if !unresolved_bases.is_empty() {
+        warn!("Bases are not found: {:?}", unresolved_bases);
+    }
     let base_upstreams: Vec<_> = bases
         .iter()
         .map(|b| match b {
-            BaseSpec::Local { upstream, .. } => upstream.clone(),
-            BaseSpec::Remote { remote, .. } => remote.clone(),
+            BaseSpec::Local { upstream, .. } => BaseRef::from(upstream),
+            BaseSpec::Remote { remote, .. } => BaseRef::from(remote),
+            BaseSpec::PureLocal { local, .. } => BaseRef::new(&local.refname),
+            BaseSpec::Commit { base, .. } => base.clone(),
         })
         .collect();
     trace!("bases: {:#?}", bases);
 
-    let tracking_branches = get_tracking_branches(git)?;
+    let scope = BranchScope::new(&git.repo, &param.include_slash_prefixes)?;
+    let mut tracking_branches = get_tracking_branches(git, &scope)?;
+    tracking_branches.retain(|(local, upstream)| {
+        filter(&local.refname) || matches!(upstream, Some(upstream) if filter(&upstream.refname))
+    });
     debug!("tracking_branches: {:#?}", tracking_branches);
 
-    let direct_fetch_branches = get_direct_fetch_branches(git)?;
+    let mut direct_fetch_branches = get_direct_fetch_branches(git, &scope)?;
+    direct_fetch_branches.retain(|(local, _)| filter(&local.refname));
     debug!("direct_fetch_branches: {:#?}", direct_fetch_branches);
 
-    let non_tracking_branches = get_non_tracking_local_branches(git)?;
+    let mut broken_upstreams = get_broken_upstream_branches(git, &scope)?;
+    broken_upstreams.retain(|broken| filter(&broken.local.refname));
+    debug!("broken_upstreams: {:#?}", broken_upstreams);
+
+    let stacks = Stacks::read(&git.repo, &git.config)?;
+    let mut stacked_branches: Vec<_> = stacks.stacked().cloned().collect();
+    stacked_branches.retain(|local| scope.contains(&local.refname) && filter(&local.refname));
+    debug!("stacked_branches: {:#?}", stacked_branches);
+
+    let mut non_tracking_branches = get_non_tracking_local_branches(git, &scope)?;
+    non_tracking_branches.retain(|local| filter(&local.refname));
     debug!("non_tracking_branches: {:#?}", non_tracking_branches);
 
-    let non_upstream_branches = get_non_upstream_remote_tracking_branches(git)?;
+    let mirror_remotes = get_untargeted_mirror_remotes(git, &param.delete)?;
+    let mut non_upstream_branches = get_non_upstream_remote_tracking_branches(git, &scope)?;
+    non_upstream_branches.retain(|remote| filter(&remote.refname));
+    for remote_tracking in std::mem::take(&mut non_upstream_branches) {
+        let remote = remote_tracking.to_remote_branch(&git.repo)?.remote;
+        if !mirror_remotes.contains(&remote) {
+            non_upstream_branches.push(remote_tracking);
+        }
+    }
+
+    // Pull refs are not upstreams of any local branches. They are classified by themselves.
+    let mut pull_refs = Vec::new();
+    if param.delete.scan_pull_refs() {
+        let non_upstreams: HashSet<_> = non_upstream_branches.iter().cloned().collect();
+        for (remote_tracking, pull) in get_pull_refs(git, &scope, &param.pull_ref_namespaces)? {
+            let url = config::get_remote_url(&git.repo, &pull.remote)?;
+            if non_upstreams.contains(&remote_tracking)
+                && param
+                    .delete
+                    .delete_merged_pull_refs(&pull.remote, url.as_deref())
+            {
+                pull_refs.push((remote_tracking, pull));
+            }
+        }
+        let pulls: HashSet<_> = pull_refs
+            .iter()
+            .map(|(remote_tracking, _)| remote_tracking)
+            .collect();
+        non_upstream_branches.retain(|remote_tracking| !pulls.contains(remote_tracking));
+    }
     debug!("non_upstream_branches: {:#?}", non_upstream_branches);
+    debug!("pull_refs: {:#?}", pull_refs);
+
+    let remote_timeout = Duration::from_secs(param.remote_timeout);
+    let mut unreachable_remotes = Vec::new();
+    let mut remote_only_branches = Vec::new();
+    for ScanTarget::RemoteOnly(remote) in &param.scan {
+        if param.offline {
+            unreachable_remotes.push(remote.clone());
+            continue;
+        }
+        match get_remote_only_branches(git, remote, &scope, remote_timeout) {
+            Ok(branches) => remote_only_branches.extend(branches),
+            Err(err) if is_timeout(&err) => {
+                warn!("{:#}", err);
+                unreachable_remotes.push(remote.clone());
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    debug!("remote_only_branches: {:#?}", remote_only_branches);
+
+    // Ones out of the delete range are also kept.
+    let gone_upstreams: Vec<_> = tracking_branches
+        .iter()
+        .filter(|(_, upstream)| upstream.is_none())
+        .map(|(local, _)| local.clone())
+        .collect();
+
+    let mut skipped = HashMap::new();
+    skip_out_of_delete_range(
+        git,
+        &param.delete,
+        &mut tracking_branches,
+        &mut direct_fetch_branches,
+        &mut stacked_branches,
+        &mut skipped,
+    )?;
+    stopwatch.lap("collection");
 
-    let remote_heads = if param.delete.scan_tracking() {
+    let remote_heads = if direct_fetch_branches.is_empty() {
+        RemoteHeads::default()
+    } else if param.offline {
+        let mut unreachable: Vec<_> = direct_fetch_branches
+            .iter()
+            .map(|(_, r)| r.remote.clone())
+            .collect();
+        unreachable.sort();
+        unreachable.dedup();
+        RemoteHeads {
+            heads: Vec::new(),
+            unreachable,
+        }
+    } else {
         let remotes: Vec<_> = direct_fetch_branches
             .iter()
             .map(|(_, r)| r.clone())
             .collect();
-        get_remote_heads(git, &remotes)?
-    } else {
-        Vec::new()
+        get_remote_heads(git, &remotes, remote_timeout, progress)?
     };
     debug!("remote_heads: {:#?}", remote_heads);
+    // They can't be told from ones whose remote branches are gone.
+    direct_fetch_branches.retain(|(_, remote)| !remote_heads.unreachable.contains(&remote.remote));
+    unreachable_remotes.extend(remote_heads.unreachable.iter().cloned());
+    stopwatch.lap("remote heads");
 
-    let merge_tracker = MergeTracker::with_base_upstreams(&git.repo, &git.config, &base_upstreams)?;
+    let mut merge_tracker = MergeTracker::with_base_upstreams(
+        &git.repo,
+        &git.config,
+        &base_upstreams,
+        &param.merge_strategies,
+    )?;
+    if param.reachability_index {
+        merge_tracker = merge_tracker.with_reachability_index(&git.repo, &base_upstreams)?;
+    }
+    stopwatch.lap("merge tracker");
     let mut classifier = Classifier::new(git, &merge_tracker);
-    let mut skipped = HashMap::new();
 
+    // Each branch is classified once against all bases, not once per base.
     info!("Enqueue classification requests");
-    if param.delete.scan_tracking() {
-        for (local, upstream) in &tracking_branches {
-            for base in &base_upstreams {
-                classifier.queue_request(TrackingBranchClassificationRequest {
-                    base,
-                    local,
-                    upstream: upstream.as_ref(),
-                });
-            }
-        }
+    for (local, upstream) in &tracking_branches {
+        classifier.queue_request(TrackingBranchClassificationRequest {
+            bases: &base_upstreams,
+            local,
+            upstream: upstream.as_ref(),
+        });
+    }
 
-        for (local, remote) in &direct_fetch_branches {
-            for base in &base_upstreams {
-                classifier.queue_request_with_context(
-                    DirectFetchClassificationRequest {
-                        base,
-                        local,
-                        remote,
-                    },
-                    &remote_heads,
-                );
-            }
-        }
-    } else {
-        for (local, upstream) in &tracking_branches {
-            if let Some(upstream) = upstream {
-                let remote = upstream.to_remote_branch(&git.repo)?.remote;
-                let suggestion = SkipSuggestion::TrackingRemote(remote);
-                skipped.insert(local.refname.clone(), suggestion.clone());
-                skipped.insert(upstream.refname.clone(), suggestion.clone());
-            } else {
-                skipped.insert(local.refname.clone(), SkipSuggestion::Tracking);
-            }
-        }
+    for (local, remote) in &direct_fetch_branches {
+        classifier.queue_request_with_context(
+            DirectFetchClassificationRequest {
+                bases: &base_upstreams,
+                local,
+                remote,
+            },
+            &remote_heads.heads,
+        );
+    }
 
-        for (local, _) in &direct_fetch_branches {
-            skipped.insert(local.refname.clone(), SkipSuggestion::Tracking);
-        }
+    for local in &stacked_branches {
+        classifier.queue_request(StackedBranchClassificationRequest {
+            bases: &base_upstreams,
+            local,
+        });
     }
 
     if param.delete.scan_non_tracking_local() {
-        for base in &base_upstreams {
-            for local in &non_tracking_branches {
-                classifier.queue_request(NonTrackingBranchClassificationRequest { base, local });
-            }
+        for local in &non_tracking_branches {
+            classifier.queue_request(NonTrackingBranchClassificationRequest {
+                bases: &base_upstreams,
+                local,
+            });
         }
     } else {
         for local in &non_tracking_branches {
@@ -142,44 +397,197 @@ pub fn get_trim_plan(git: &Git, param: &PlanParam) -> Result<TrimPlan> {
         }
     }
 
-    for base in &base_upstreams {
-        for remote_tracking in &non_upstream_branches {
-            let remote = remote_tracking.to_remote_branch(&git.repo)?;
-            if param.delete.scan_non_upstream_remote(&remote.remote) {
-                classifier.queue_request(NonUpstreamBranchClassificationRequest {
-                    base,
-                    remote: remote_tracking,
-                });
-            } else {
-                let remote = remote_tracking.to_remote_branch(&git.repo)?.remote;
-                skipped.insert(
-                    remote_tracking.refname.clone(),
-                    SkipSuggestion::NonUpstream(remote),
-                );
-            }
+    if param.fix_upstreams {
+        for broken in &broken_upstreams {
+            classifier.queue_request(BrokenUpstreamClassificationRequest {
+                bases: &base_upstreams,
+                local: &broken.local,
+            });
         }
     }
 
-    let classifications = classifier.classify()?;
+    for remote_tracking in &non_upstream_branches {
+        let remote = remote_tracking.to_remote_branch(&git.repo)?;
+        let url = config::get_remote_url(&git.repo, &remote.remote)?;
+        if param
+            .delete
+            .scan_non_upstream_remote(&remote.remote, url.as_deref())
+        {
+            classifier.queue_request(NonUpstreamBranchClassificationRequest {
+                bases: &base_upstreams,
+                remote: remote_tracking,
+            });
+        } else {
+            skipped.insert(
+                remote_tracking.refname.clone(),
+                SkipSuggestion::NonUpstream(remote.remote),
+            );
+        }
+    }
+
+    for (remote_tracking, pull) in &pull_refs {
+        classifier.queue_request(PullRefClassificationRequest {
+            bases: &base_upstreams,
+            remote_tracking,
+            pull,
+        });
+    }
+
+    for remote_only in &remote_only_branches {
+        classifier.queue_request(RemoteOnlyClassificationRequest {
+            bases: &base_upstreams,
+            remote_only,
+        });
+    }
+
+    let fingerprint = if param.cache {
+        let mut inputs = vec![
+            format!("{:?}", param.merge_strategies),
+            format!("{:?}", remote_heads),
+        ];
+        inputs.extend(classifier.requests().iter().cloned());
+        Some(plan_cache::fingerprint(git, &inputs)?)
+    } else {
+        None
+    };
+    let cached = match &fingerprint {
+        Some(fingerprint) => plan_cache::read(&git.repo, fingerprint)?,
+        None => None,
+    };
+    let cache_hit = cached.is_some();
+    let classifications = match cached {
+        Some(classifications) => {
+            info!("Nothing has changed since the last plan. Reuse its classifications");
+            // It borrows the branches.
+            drop(classifier);
+            classifications
+        }
+        None => {
+            let classifications = classifier.classify(progress)?;
+            if let Some(fingerprint) = &fingerprint {
+                if let Err(err) = plan_cache::write(&git.repo, fingerprint, &classifications) {
+                    warn!("Failed to write the plan cache: {:#}", err);
+                }
+            }
+            classifications
+        }
+    };
+    let merge_metrics = merge_tracker.metrics();
+    info!(
+        "{} branches are found in the merged set, and {} are checked with the strategies",
+        merge_metrics.precomputed, merge_metrics.checked
+    );
 
     let mut result = TrimPlan {
         skipped,
         to_delete: HashSet::new(),
         preserved: Vec::new(),
+        classifications: Vec::new(),
+        broken_upstreams: Vec::new(),
+        orphan_namespaces: Vec::new(),
+        renamed_upstreams: Vec::new(),
+        gone_upstreams: Vec::new(),
+        tips: HashMap::new(),
+        non_utf8_refnames: Vec::new(),
+        unreachable_remotes,
+        offline: param.offline,
+        merge_metrics,
+        timings: Timings::default(),
+        cached: cache_hit,
+        unresolved_bases,
     };
     for classification in classifications {
-        result.to_delete.extend(classification.result);
+        result
+            .to_delete
+            .extend(classification.result.iter().cloned());
+        result.classifications.push(classification);
     }
 
+    if param.scan_push_remote && !param.offline {
+        result.classify_push_remotes(git, &param.delete, remote_timeout, progress)?;
+    }
+    stopwatch.lap("classification");
+
+    if git.repo.is_shallow() {
+        warn!("The repository is shallow. Some branches may not be classified correctly.");
+        result.preserve_shallow_unknowns();
+    }
+    result.classify_duplicates_of_base(&git.repo, &git.config, &bases)?;
     result.preserve_bases(&git.repo, &git.config, &bases)?;
-    result.preserve_protected(&git.repo, &param.protected_patterns)?;
-    result.preserve_non_heads_remotes(&git.repo)?;
+    let trim_ignore = config::read_trim_ignore(&git.repo)?;
+    let protected_patterns: Vec<_> = param
+        .protected_patterns
+        .iter()
+        .copied()
+        .chain(trim_ignore.iter().map(String::as_str))
+        .collect();
+    result.preserve_protected(&protected_patterns)?;
+    if param.protect_head_of_remotes {
+        result.preserve_remote_heads(&git.repo, &git.config)?;
+    }
+    if param.detect_remote_protection && !param.offline {
+        result.preserve_protected_on_remotes(&git.repo)?;
+    }
+    result.preserve_non_heads_remotes(&git.repo, &param.remote_namespaces)?;
     result.preserve_worktree(&git.repo)?;
+    if param.preserve_signed_by_others {
+        result.preserve_signed_by_others(&git.repo, &param.signing_keys)?;
+    }
+    if param.only_mine {
+        let email = config::get::<String>(&git.config, "user.email")
+            .read()?
+            .context("`user.email` is required to find your branches")?;
+        result.preserve_authored_by_others(&git.repo, &email)?;
+    }
+    if param.recent_checkout_days > 0 {
+        result.preserve_recently_checked_out(&git.repo, param.recent_checkout_days)?;
+    }
+    result.preserve_renamed_upstreams(&git.repo, &git.config)?;
+    if param.stray_grace_days > 0 {
+        result.preserve_recent_strays(&git.repo, param.stray_grace_days)?;
+    }
     result.apply_delete_range_filter(&git.repo, &param.delete)?;
+    if !param.retarget_stacks {
+        result.preserve_stack_bases(&stacks);
+    }
 
     if !param.detach {
         result.adjust_not_to_detach(&git.repo)?;
     }
+    if param.limit > 0 {
+        result.preserve_over_limit(&git.repo, param.sort, param.limit as usize)?;
+    }
+    result.record_tips(&git.repo)?;
+
+    let locals_to_delete: HashSet<_> = result.locals_to_delete().into_iter().cloned().collect();
+    broken_upstreams.retain(|broken| !locals_to_delete.contains(&broken.local));
+    result.broken_upstreams = broken_upstreams;
+    result.gone_upstreams = gone_upstreams
+        .into_iter()
+        .filter(|local| {
+            !locals_to_delete.contains(local)
+                && !result
+                    .renamed_upstreams
+                    .iter()
+                    .any(|renamed| &renamed.local == local)
+        })
+        .collect();
+    result.orphan_namespaces = get_orphan_namespaces(git)?
+        .into_iter()
+        .map(|mut namespace| {
+            namespace
+                .refnames
+                .retain(|refname| filter(refname) && scope.contains(refname));
+            namespace
+        })
+        .filter(|namespace| !namespace.refnames.is_empty())
+        .collect();
+    result.non_utf8_refnames = get_non_utf8_refnames(&git.repo)?
+        .into_iter()
+        .filter(|refname| scope.contains(refname))
+        .collect();
+    stopwatch.lap("filter passes");
+    result.timings = stopwatch.finish();
 
     Ok(result)
 }
@@ -196,11 +604,26 @@ pub(crate) enum BaseSpec<'a> {
         pattern: &'a str,
         remote: RemoteTrackingBranch,
     },
+    /// A local branch without an upstream. Branches are compared with the local branch itself.
+    PureLocal {
+        #[allow(dead_code)] // used in `Debug`
+        pattern: &'a str,
+        local: LocalBranch,
+    },
+    /// Other refs like tags, or commits given by `--base-commit`.
+    Commit {
+        #[allow(dead_code)] // used in `Debug`
+        pattern: &'a str,
+        base: BaseRef,
+    },
 }
 
 impl BaseSpec<'_> {
     fn is_local(&self, branch: &LocalBranch) -> bool {
-        matches!(self, BaseSpec::Local { local, .. } if local == branch)
+        matches!(
+            self,
+            BaseSpec::Local { local, .. } | BaseSpec::PureLocal { local, .. } if local == branch
+        )
     }
 
     fn covers_remote(&self, refname: &str) -> bool {
@@ -211,6 +634,16 @@ impl BaseSpec<'_> {
         }
     }
 
+    /// A branch of any remote that has the same name as the base.
+    fn covers_branch_of_remote(&self, repo: &Repository, remote_branch: &RemoteBranch) -> bool {
+        let upstream = match self {
+            BaseSpec::Local { upstream, .. } => upstream,
+            BaseSpec::Remote { remote, .. } => remote,
+            BaseSpec::PureLocal { .. } | BaseSpec::Commit { .. } => return false,
+        };
+        matches!(upstream.to_remote_branch(repo), Ok(base) if base.refname == remote_branch.refname)
+    }
+
     fn remote_pattern(&self, refname: &str) -> Option<&str> {
         match self {
             BaseSpec::Remote { pattern, remote } if remote.refname() == refname => Some(pattern),
@@ -219,88 +652,1448 @@ impl BaseSpec<'_> {
     }
 }
 
+/// Mirror remotes (`remote.<name>.mirror`) are not scanned for remote tracking branches,
+/// unless they are named in the delete range, e.g. `remote:<remote>`.
+fn get_untargeted_mirror_remotes(git: &Git, delete: &DeleteFilter) -> Result<HashSet<String>> {
+    let mut result = HashSet::new();
+    for remote in git.repo.remotes()?.iter().flatten() {
+        if config::is_mirror_remote(&git.config, remote)? && !delete.names_remote(remote) {
+            result.insert(remote.to_owned());
+        }
+    }
+    Ok(result)
+}
+
+/// Skip tracking branches that can't be deleted in the delete range whatever they are classified as,
+/// before classifying them, which is the most expensive step.
+fn skip_out_of_delete_range(
+    git: &Git,
+    delete: &DeleteFilter,
+    tracking_branches: &mut Vec<(LocalBranch, Option<RemoteTrackingBranch>)>,
+    direct_fetch_branches: &mut Vec<(LocalBranch, RemoteBranch)>,
+    stacked_branches: &mut Vec<LocalBranch>,
+    skipped: &mut HashMap<String, SkipSuggestion>,
+) -> Result<()> {
+    let mut retained = Vec::new();
+    for (local, upstream) in tracking_branches.drain(..) {
+        match &upstream {
+            Some(upstream) => {
+                let remote = upstream.to_remote_branch(&git.repo)?.remote;
+                let url = config::get_remote_url(&git.repo, &remote)?;
+                if !delete.scan_tracking_remote(&remote, url.as_deref()) {
+                    let suggestion = SkipSuggestion::TrackingRemote(remote);
+                    skipped.insert(local.refname.clone(), suggestion.clone());
+                    skipped.insert(upstream.refname.clone(), suggestion);
+                    continue;
+                }
+            }
+            // It is classified either as merged local or stray.
+            None => {
+                if !delete.delete_merged_local() && !delete.delete_stray() {
+                    skipped.insert(local.refname.clone(), SkipSuggestion::Tracking);
+                    continue;
+                }
+            }
+        }
+        retained.push((local, upstream));
+    }
+    *tracking_branches = retained;
+
+    let mut retained = Vec::new();
+    for (local, remote) in direct_fetch_branches.drain(..) {
+        let url = config::get_remote_url(&git.repo, &remote.remote)?;
+        if delete.scan_tracking_remote(&remote.remote, url.as_deref()) {
+            retained.push((local, remote));
+        } else {
+            skipped.insert(local.refname.clone(), SkipSuggestion::Tracking);
+        }
+    }
+    *direct_fetch_branches = retained;
+
+    // They are classified only as merged local.
+    if !delete.delete_merged_local() {
+        for local in stacked_branches.drain(..) {
+            skipped.insert(local.refname, SkipSuggestion::Tracking);
+        }
+    }
+    Ok(())
+}
+
+/// Resolve bases to refs. Bases that are not found are returned with the candidates that are tried.
 pub(crate) fn resolve_bases<'a>(
     repo: &Repository,
     config: &GitConfig,
     bases: &[&'a str],
-) -> Result<Vec<BaseSpec<'a>>> {
-    let mut result = Vec::new();
+    base_commits: &[&'a str],
+) -> Result<(Vec<BaseSpec<'a>>, Vec<UnresolvedBase>)> {
+    let mut result: Vec<BaseSpec> = Vec::new();
+    let mut unresolved = Vec::new();
     for base in bases {
-        let reference = match repo.resolve_reference_from_short_name(base) {
-            Ok(reference) => reference,
-            Err(err) if err.code() == ErrorCode::NotFound => continue,
-            Err(err) => return Err(err.into()),
+        let references = if is_glob(base) {
+            let references = expand_base_glob(repo, base)?;
+            if references.is_empty() {
+                unresolved.push(UnresolvedBase {
+                    base: (*base).to_owned(),
+                    candidates: BASE_GLOB_PREFIXES
+                        .iter()
+                        .map(|prefix| format!("{}{}", prefix, base))
+                        .collect(),
+                });
+            }
+            references
+        } else {
+            match repo.resolve_reference_from_short_name(base) {
+                Ok(reference) => vec![reference],
+                Err(err) if err.code() == ErrorCode::NotFound => {
+                    let mapped = map_remote_branch(repo, base)?;
+                    let found = mapped
+                        .iter()
+                        .find(|refname| repo.find_reference(refname).is_ok());
+                    match found {
+                        Some(refname) => result.push(BaseSpec::Remote {
+                            pattern: base,
+                            remote: RemoteTrackingBranch::new(refname),
+                        }),
+                        None => unresolved.push(UnresolvedBase {
+                            base: (*base).to_owned(),
+                            candidates: short_name_candidates(base)
+                                .into_iter()
+                                .chain(mapped)
+                                .collect(),
+                        }),
+                    }
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            }
         };
 
-        if reference.is_branch() {
-            let local = LocalBranch::try_from(&reference)?;
-            if let RemoteTrackingBranchStatus::Exists(upstream) =
-                local.fetch_upstream(repo, config)?
-            {
-                result.push(BaseSpec::Local {
+        for reference in references {
+            // A glob might match refs that are not valid UTF-8.
+            let refname = match reference.name() {
+                Some(refname) => refname,
+                None => continue,
+            };
+            if reference.is_branch() {
+                let local = LocalBranch::try_from(&reference)?;
+                match local.fetch_upstream(repo, config)? {
+                    RemoteTrackingBranchStatus::Exists(upstream) => result.push(BaseSpec::Local {
+                        pattern: base,
+                        local,
+                        upstream,
+                    }),
+                    RemoteTrackingBranchStatus::None => result.push(BaseSpec::PureLocal {
+                        pattern: base,
+                        local,
+                    }),
+                    // Its upstream is gone. It is not clear what to compare with.
+                    RemoteTrackingBranchStatus::Gone(_) => {}
+                }
+            } else if reference.is_remote() {
+                let remote = RemoteTrackingBranch::try_from(&reference)?;
+                // A glob might match both a local branch and its upstream.
+                if result
+                    .iter()
+                    .any(|spec| spec.covers_remote(remote.refname()))
+                {
+                    continue;
+                }
+                result.push(BaseSpec::Remote {
                     pattern: base,
-                    local,
-                    upstream,
+                    remote,
+                })
+            } else {
+                reference
+                    .peel_to_commit()
+                    .with_context(|| format!("base `{}` is not a commit", refname))?;
+                result.push(BaseSpec::Commit {
+                    pattern: base,
+                    base: BaseRef::new(refname),
                 })
             }
-        } else {
-            let remote = RemoteTrackingBranch::try_from(&reference)?;
-            result.push(BaseSpec::Remote {
-                pattern: base,
-                remote,
-            })
         }
     }
 
+    for base_commit in base_commits {
+        let commit = repo
+            .revparse_single(base_commit)
+            .and_then(|object| object.peel_to_commit())
+            .with_context(|| format!("base commit `{}` is not found", base_commit))?;
+        result.push(BaseSpec::Commit {
+            pattern: base_commit,
+            base: BaseRef::new(&commit.id().to_string()),
+        });
+    }
+
+    Ok((result, unresolved))
+}
+
+/// Refnames that git tries for a short name, in order. See `git help revisions`.
+fn short_name_candidates(name: &str) -> Vec<String> {
+    vec![
+        name.to_owned(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+        format!("refs/remotes/{}/HEAD", name),
+    ]
+}
+
+/// Map `<remote>/<branch>` through the fetch refspecs of `<remote>`,
+/// for remotes that fetch into other namespaces than `refs/remotes/<remote>/`.
+fn map_remote_branch(repo: &Repository, name: &str) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    for remote_name in repo.remotes()?.iter().flatten() {
+        let branch = match name
+            .strip_prefix(remote_name)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            Some(branch) if !branch.is_empty() => branch,
+            _ => continue,
+        };
+        let remote = repo.find_remote(remote_name)?;
+        if let Some(refname) = expand_refspec(
+            &remote,
+            &format!("refs/heads/{}", branch),
+            Direction::Fetch,
+            ExpansionSide::Right,
+        )? {
+            result.push(refname);
+        }
+    }
+    Ok(result)
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+const BASE_GLOB_PREFIXES: &[&str] = &["refs/heads/", "refs/remotes/", "refs/remotes/*/"];
+
+/// Expand a glob pattern to local branches first, and then remote tracking branches.
+/// `release/*` matches `refs/heads/release/*` and `refs/remotes/*/release/*`.
+fn expand_base_glob<'repo>(
+    repo: &'repo Repository,
+    pattern: &str,
+) -> Result<Vec<Reference<'repo>>> {
+    let mut result: Vec<Reference> = Vec::new();
+    for prefix in BASE_GLOB_PREFIXES {
+        for reference in repo.references_glob(&format!("{}{}", prefix, pattern))? {
+            let reference = reference?;
+            // Skip `refs/remotes/*/HEAD`
+            if reference.symbolic_target_bytes().is_some() {
+                continue;
+            }
+            if result
+                .iter()
+                .any(|r| r.name_bytes() == reference.name_bytes())
+            {
+                continue;
+            }
+            result.push(reference);
+        }
+    }
     Ok(result)
 }
 
-pub fn delete_local_branches(
+/// Tip commits of branches, to estimate the disk space that is reclaimed after they are deleted.
+pub fn get_branch_tips(
+    repo: &Repository,
+    locals: &[&LocalBranch],
+    remotes: &[RemoteBranch],
+) -> Result<Vec<String>> {
+    let mut refnames: Vec<String> = locals.iter().map(|local| local.refname.clone()).collect();
+    for remote in remotes {
+        if let RemoteTrackingBranchStatus::Exists(remote_tracking) =
+            RemoteTrackingBranch::from_remote_branch(repo, remote)?
+        {
+            refnames.push(remote_tracking.refname);
+        }
+    }
+    let mut result = Vec::new();
+    for refname in refnames {
+        if let Ok(reference) = repo.find_reference(&refname) {
+            result.push(reference.peel_to_commit()?.id().to_string());
+        }
+    }
+    Ok(result)
+}
+
+/// Record how much disk space the deletion of this run can reclaim in its trim log record.
+/// `tips` are from `get_branch_tips` before the deletion.
+pub fn record_reclaimable_bytes(repo: &Repository, tips: &[String]) -> Result<()> {
+    let reclaimable_bytes = if tips.is_empty() {
+        Some(0)
+    } else {
+        match subprocess::disk_usage(repo, tips) {
+            Ok(bytes) => Some(bytes),
+            Err(err) => {
+                debug!("Failed to estimate the reclaimable disk space: {:#}", err);
+                None
+            }
+        }
+    };
+    trim_log::set_reclaimable_bytes(repo, reclaimable_bytes)
+}
+
+/// Collect garbage if more than `threshold` branches are deleted. `threshold` 0 to disable.
+/// Returns sizes of objects before and after it, if it ran.
+pub fn gc_after_deletion(
+    repo: &Repository,
+    num_deleted: usize,
+    threshold: u64,
+    dry_run: bool,
+) -> Result<Option<(u64, u64)>> {
+    if threshold == 0 || num_deleted as u64 <= threshold {
+        return Ok(None);
+    }
+    let before = subprocess::count_objects_size(repo)?;
+    subprocess::gc(repo, dry_run)?;
+    let after = subprocess::count_objects_size(repo)?;
+    Ok(Some((before, after)))
+}
+
+/// Write a commit-graph to speed up ancestry checks, if there isn't one.
+pub fn write_commit_graph_if_missing(repo: &Repository, dry_run: bool) -> Result<()> {
+    if CommitGraph::open(repo)?.is_some() {
+        return Ok(());
+    }
+    subprocess::commit_graph_write(repo, dry_run)
+}
+
+/// Ask remotes without `refs/remotes/<remote>/HEAD` which branches their HEADs point to,
+/// like `git ls-remote --symref <remote> HEAD` does.
+/// Remotes that don't answer, or whose HEAD branches are not fetched, are skipped with warnings.
+pub fn detect_remote_heads(
+    git: &Git,
+    timeout: Duration,
+) -> Result<Vec<(RemoteBranch, RemoteTrackingBranch)>> {
+    let mut result = Vec::new();
+    for remote in git.repo.remotes()?.iter().flatten() {
+        if git
+            .repo
+            .find_reference(&format!("refs/remotes/{}/HEAD", remote))
+            .is_ok()
+        {
+            continue;
+        }
+        let head = match subprocess::ls_remote_head(&git.repo, remote, timeout) {
+            Ok(head) => head,
+            Err(err) => {
+                warn!("Failed to detect HEAD of {}: {:#}", remote, err);
+                continue;
+            }
+        };
+        let remote_branch = RemoteBranch {
+            remote: remote.to_owned(),
+            refname: head.refname,
+        };
+        match RemoteTrackingBranch::from_remote_branch(&git.repo, &remote_branch)? {
+            RemoteTrackingBranchStatus::Exists(remote_tracking) => {
+                result.push((remote_branch, remote_tracking))
+            }
+            _ => warn!(
+                "HEAD of {} is {}, but it is not fetched",
+                remote, remote_branch.refname
+            ),
+        }
+    }
+    Ok(result)
+}
+
+/// Point `refs/remotes/<remote>/HEAD` to `remote_tracking`, like `git remote set-head` does.
+pub fn set_remote_head(
+    repo: &Repository,
+    remote: &str,
+    remote_tracking: &RemoteTrackingBranch,
+    dry_run: bool,
+) -> Result<()> {
+    let refname = format!("refs/remotes/{}/HEAD", remote);
+    if dry_run {
+        info!("> set {} to {} (dry-run)", refname, remote_tracking.refname);
+        return Ok(());
+    }
+    info!("> set {} to {}", refname, remote_tracking.refname);
+    repo.reference_symbolic(
+        &refname,
+        &remote_tracking.refname,
+        true,
+        "git-trim: set-head",
+    )?;
+    Ok(())
+}
+
+/// Commands to delete remote tracking branches of removed remotes, in chunks of `chunk_size`.
+pub fn get_orphan_namespace_delete_commands(
+    namespaces: &[OrphanNamespace],
+    chunk_size: u64,
+) -> Vec<Vec<String>> {
+    let orphans = namespaces
+        .iter()
+        .flat_map(|namespace| &namespace.refnames)
+        .map(|refname| RemoteTrackingBranch::new(refname))
+        .collect::<Vec<_>>();
+    let orphans = orphans.iter().collect::<Vec<_>>();
+    chunks(&orphans, chunk_size)
+        .map(subprocess::branch_delete_remotes_args)
+        .collect()
+}
+
+/// Delete all remote tracking branches of removed remotes.
+pub fn delete_orphan_namespaces(
+    repo: &Repository,
+    namespaces: &[OrphanNamespace],
+    dry_run: bool,
+) -> Result<(), Error> {
+    for namespace in namespaces {
+        for refname in &namespace.refnames {
+            if dry_run {
+                info!("> delete {} (dry-run)", refname);
+                continue;
+            }
+            info!("> delete {}", refname);
+            let deleted = match repo.find_reference(refname) {
+                Ok(mut reference) => reference.delete(),
+                Err(err) if err.code() == ErrorCode::NotFound => Ok(()),
+                Err(err) => Err(err),
+            };
+            deleted.map_err(|err| Error::branch(refname, err.into()))?;
+        }
+        if dry_run {
+            println!(
+                "Delete {} refs of removed remote {} (dry run).",
+                namespace.refnames.len(),
+                namespace.remote
+            );
+        } else {
+            println!(
+                "Deleted {} refs of removed remote {}.",
+                namespace.refnames.len(),
+                namespace.remote
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Delete remote tracking refs of merged pull requests locally, like `git branch --delete --remotes` does.
+/// They are read-only on remotes.
+pub fn delete_pull_refs(
+    repo: &Repository,
+    pull_refs: &[&RemoteTrackingBranch],
+    dry_run: bool,
+) -> Result<(), Error> {
+    for remote_tracking in pull_refs {
+        let refname = &remote_tracking.refname;
+        let name = refname.trim_start_matches("refs/remotes/");
+        if dry_run {
+            info!("> delete {} (dry-run)", refname);
+            println!("Delete remote-tracking branch {} (dry run).", name);
+            continue;
+        }
+        info!("> delete {}", refname);
+        let deleted = match repo.find_reference(refname) {
+            Ok(mut reference) => reference
+                .peel_to_commit()
+                .and_then(|commit| commit.as_object().short_id())
+                .and_then(|short_id| {
+                    reference.delete()?;
+                    Ok(Some(short_id))
+                }),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err),
+        };
+        let deleted = deleted.map_err(|err| Error::branch(refname, err.into()))?;
+        if let Some(short_id) = deleted {
+            println!(
+                "Deleted remote-tracking branch {} (was {}).",
+                name,
+                short_id.as_str().unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Unset upstreams of local branches like `git branch --unset-upstream` does.
+pub fn unset_upstreams(repo: &Repository, branches: &[&LocalBranch], dry_run: bool) -> Result<()> {
+    for branch in branches {
+        let branch_name = branch.short_name();
+        if dry_run {
+            info!("> git branch --unset-upstream {} (dry-run)", branch_name);
+            println!("Unset upstream of {} (dry run).", branch_name);
+        } else {
+            info!("> git branch --unset-upstream {}", branch_name);
+            repo.find_branch(branch_name, BranchType::Local)?
+                .set_upstream(None)?;
+            println!("Unset upstream of {}.", branch_name);
+        }
+    }
+    Ok(())
+}
+
+/// Point upstreams of local branches to the new names of renamed upstreams.
+pub fn retarget_renamed_upstreams(
+    repo: &Repository,
+    renamed: &[RenamedUpstream],
+    dry_run: bool,
+) -> Result<()> {
+    for renamed in renamed {
+        let branch_name = renamed.local.short_name();
+        let key = format!("branch.{}.merge", branch_name);
+        let upstream = renamed.upstream.refname.trim_start_matches("refs/remotes/");
+        if dry_run {
+            info!(
+                "> git config {} {} (dry-run)",
+                key, renamed.remote_branch.refname
+            );
+            println!("Set upstream of {} to {} (dry run).", branch_name, upstream);
+        } else {
+            info!("> git config {} {}", key, renamed.remote_branch.refname);
+            repo.config()?
+                .set_str(&key, &renamed.remote_branch.refname)?;
+            println!("Set upstream of {} to {}.", branch_name, upstream);
+        }
+    }
+    Ok(())
+}
+
+/// Fast-forward local `bases` to their upstreams, so that branches are classified with the latest bases.
+/// Only refs are updated, so branches checked out in any worktree are skipped.
+/// Ones that have diverged from their upstreams are left as they are.
+pub fn fast_forward_bases(
     repo: &Repository,
+    config: &GitConfig,
+    bases: &[&str],
+    dry_run: bool,
+) -> Result<()> {
+    let worktrees = subprocess::get_worktrees(repo)?;
+    for base in bases {
+        let branch = match repo.find_branch(base, BranchType::Local) {
+            Ok(branch) => branch,
+            Err(_) => continue,
+        };
+        let local = LocalBranch::try_from(&branch)?;
+        if branch.is_head() || worktrees.contains_key(&local) {
+            debug!("Skip to fast-forward checked out base {}", local.refname);
+            continue;
+        }
+        let upstream = match local.fetch_upstream(repo, config)? {
+            RemoteTrackingBranchStatus::Exists(upstream) => upstream,
+            _ => continue,
+        };
+        let local_oid = repo.refname_to_id(&local.refname)?;
+        let upstream_oid = repo.refname_to_id(&upstream.refname)?;
+        if local_oid == upstream_oid || !repo.graph_descendant_of(upstream_oid, local_oid)? {
+            continue;
+        }
+        let message = format!(
+            "Fast-forward {} to {}",
+            local.short_name(),
+            upstream.refname.trim_start_matches("refs/remotes/")
+        );
+        if dry_run {
+            info!("> update-ref {} {} (dry-run)", local.refname, upstream_oid);
+            println!("{} (dry run).", message);
+        } else {
+            info!("> update-ref {} {}", local.refname, upstream_oid);
+            repo.reference_matching(
+                &local.refname,
+                upstream_oid,
+                true,
+                local_oid,
+                "git-trim: fast-forward",
+            )?;
+            println!("{}.", message);
+        }
+    }
+    Ok(())
+}
+
+/// Set upstreams of branches stacked on `branches`, which are going to be deleted,
+/// to the bases that their nearest ancestors to be deleted are merged into.
+/// `bases` are from `TrimPlan::local_bases`.
+/// Upstreams of ones whose bases are unknown or not branches are unset.
+pub fn retarget_stacked_upstreams(
+    repo: &Repository,
+    bases: &HashMap<LocalBranch, BaseRef>,
     branches: &[&LocalBranch],
     dry_run: bool,
 ) -> Result<()> {
+    let stacks = Stacks::read(repo, &repo.config()?.snapshot()?)?;
+    let deleting: HashSet<&LocalBranch> = branches.iter().copied().collect();
+    for parent in branches {
+        for child in stacks.children(parent) {
+            if deleting.contains(child) {
+                continue;
+            }
+            let mut ancestor = *parent;
+            while let Some(next) = stacks.parent(ancestor) {
+                if !deleting.contains(next) || next == child {
+                    break;
+                }
+                ancestor = next;
+            }
+            let base = bases.get(ancestor);
+            let target = match base {
+                Some(base) if base.refname.starts_with("refs/heads/") => {
+                    Some((".".to_owned(), base.refname.clone()))
+                }
+                Some(base) if base.refname.starts_with("refs/remotes/") => {
+                    let remote = RemoteTrackingBranch::new(&base.refname).to_remote_branch(repo)?;
+                    Some((remote.remote, remote.refname))
+                }
+                _ => None,
+            };
+            retarget_upstream(repo, child, target, dry_run)?;
+        }
+    }
+    Ok(())
+}
+
+fn retarget_upstream(
+    repo: &Repository,
+    branch: &LocalBranch,
+    target: Option<(String, String)>,
+    dry_run: bool,
+) -> Result<()> {
+    let branch_name = branch.short_name();
+    let (remote, merge) = match target {
+        Some(target) => target,
+        None => return unset_upstreams(repo, &[branch], dry_run),
+    };
+    let upstream = if remote == "." {
+        merge.trim_start_matches("refs/heads/").to_owned()
+    } else {
+        format!("{}/{}", remote, merge.trim_start_matches("refs/heads/"))
+    };
+    if dry_run {
+        info!(
+            "> git config branch.{0}.remote {1} && git config branch.{0}.merge {2} (dry-run)",
+            branch_name, remote, merge
+        );
+        println!("Set upstream of {} to {} (dry run).", branch_name, upstream);
+    } else {
+        info!(
+            "> git config branch.{0}.remote {1} && git config branch.{0}.merge {2}",
+            branch_name, remote, merge
+        );
+        let mut config = repo.config()?;
+        config.set_str(&format!("branch.{}.remote", branch_name), &remote)?;
+        config.set_str(&format!("branch.{}.merge", branch_name), &merge)?;
+        println!("Set upstream of {} to {}.", branch_name, upstream);
+    }
+    Ok(())
+}
+
+/// A branch that is moved after it is planned to be deleted. It is skipped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MovedBranch {
+    pub refname: String,
+    pub planned: String,
+    /// `None` if it is gone.
+    pub current: Option<String>,
+}
+
+/// Exclude branches whose tips are moved from `tips` of the plan, e.g. new commits are made or fetched
+/// while it is waiting for the confirmation. Remote branches are checked with their remote tracking branches.
+/// Branches that are not in `tips` are kept as is.
+pub fn skip_moved_branches<'a>(
+    repo: &Repository,
+    tips: &HashMap<String, String>,
+    locals: &[&'a LocalBranch],
+    remotes: &[RemoteBranch],
+) -> Result<(Vec<&'a LocalBranch>, Vec<RemoteBranch>, Vec<MovedBranch>)> {
+    let mut moved = Vec::new();
+    let mut check = |refname: &str| -> Result<bool> {
+        let planned = match tips.get(refname) {
+            Some(planned) => planned,
+            None => return Ok(true),
+        };
+        let current = match repo.find_reference(refname) {
+            Ok(reference) => Some(reference.peel_to_commit()?.id().to_string()),
+            Err(err) if err.code() == ErrorCode::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        if current.as_ref() == Some(planned) {
+            return Ok(true);
+        }
+        moved.push(MovedBranch {
+            refname: refname.to_owned(),
+            planned: planned.clone(),
+            current,
+        });
+        Ok(false)
+    };
+
+    let mut remaining_locals = Vec::new();
+    for local in locals {
+        if check(&local.refname)? {
+            remaining_locals.push(*local);
+        }
+    }
+    let mut remaining_remotes = Vec::new();
+    for remote in remotes {
+        let unmoved = match RemoteTrackingBranch::from_remote_branch(repo, remote)? {
+            RemoteTrackingBranchStatus::Exists(remote_tracking) => check(&remote_tracking.refname)?,
+            _ => true,
+        };
+        if unmoved {
+            remaining_remotes.push(remote.clone());
+        }
+    }
+    Ok((remaining_locals, remaining_remotes, moved))
+}
+
+/// Exclude the other halves of pairs whose halves are excluded,
+/// e.g. one of them is moved after it is planned, or deletions from its remote are declined.
+/// The pairs are returned.
+pub fn keep_pairs_together<'a>(
+    pairs: &[BranchPair],
+    locals: &[&'a LocalBranch],
+    remotes: &[RemoteBranch],
+) -> (Vec<&'a LocalBranch>, Vec<RemoteBranch>, Vec<BranchPair>) {
+    let mut split = Vec::new();
+    for pair in pairs {
+        let local = locals.contains(&&pair.local);
+        let remote = remotes.contains(&pair.remote);
+        if local != remote {
+            split.push(pair.clone());
+        }
+    }
+    let remaining_locals = locals
+        .iter()
+        .filter(|local| !split.iter().any(|pair| pair.local == ***local))
+        .copied()
+        .collect();
+    let remaining_remotes = remotes
+        .iter()
+        .filter(|remote| !split.iter().any(|pair| pair.remote == **remote))
+        .cloned()
+        .collect();
+    (remaining_locals, remaining_remotes, split)
+}
+
+/// Exclude local branches whose remote branches are failed to delete,
+/// and return them as failures, so that they aren't left as stray branches.
+pub fn skip_pairs_of_failed_remotes<'a>(
+    pairs: &[BranchPair],
+    locals: &[&'a LocalBranch],
+    remote_failures: &[RemoteDeletionFailure],
+) -> (Vec<&'a LocalBranch>, Vec<LocalDeletionFailure>) {
+    let mut remaining = Vec::new();
+    let mut failures = Vec::new();
+    for local in locals {
+        let failed = pairs.iter().find(|pair| {
+            pair.local == **local
+                && remote_failures
+                    .iter()
+                    .any(|failure| failure.branches.contains(&pair.remote))
+        });
+        match failed {
+            Some(pair) => failures.push(LocalDeletionFailure {
+                branch: (*local).clone(),
+                error: Error::PartiallyFailed {
+                    remote: pair.remote.clone(),
+                },
+            }),
+            None => remaining.push(*local),
+        }
+    }
+    (remaining, failures)
+}
+
+#[derive(Error, Debug)]
+#[error("{operation} is in progress. Deleting or detaching branches may break it. Finish or abort it first, or use `--force-state`.")]
+pub struct OperationInProgress {
+    pub operation: &'static str,
+}
+
+/// Local branches shouldn't be deleted during a rebase, a merge, a bisect, etc.
+/// They might refer to the branches, or HEAD might be detached.
+pub fn check_no_operation_in_progress(repo: &Repository) -> Result<(), OperationInProgress> {
+    let operation = match repo.state() {
+        RepositoryState::Clean => return Ok(()),
+        RepositoryState::Merge => "A merge",
+        RepositoryState::Revert | RepositoryState::RevertSequence => "A revert",
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => "A cherry-pick",
+        RepositoryState::Bisect => "A bisect",
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => "A rebase",
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => "`git am`",
+    };
+    Err(OperationInProgress { operation })
+}
+
+/// A failure to delete a local branch.
+/// It doesn't prevent deletions of other branches.
+pub struct LocalDeletionFailure {
+    pub branch: LocalBranch,
+    /// `Error::Branch` for the branch,
+    /// or `Error::PartiallyFailed` if it is kept since its remote branch is failed to delete.
+    pub error: Error,
+}
+
+/// Delete local branches in-process like `git branch --delete --force` does.
+/// Failures are collected and returned instead of aborting deletions of other branches.
+/// `branch.<name>.*` config entries of deleted branches are removed unless `keep_config` is set.
+/// Deleted branches are recorded in the trim log to be restored with `git trim undo`.
+/// HEAD is detached at the same commit if it is about to be deleted.
+pub fn delete_local_branches(
+    repo: &Repository,
+    branches: &[&LocalBranch],
+    keep_config: bool,
+    dry_run: bool,
+) -> Result<Vec<LocalDeletionFailure>, Error> {
+    delete_local_branches_detaching_to(repo, branches, keep_config, None, dry_run)
+}
+
+/// `delete_local_branches` that checks out `detach_to` instead if HEAD is about to be deleted.
+/// It is switched to if it is a local branch, or HEAD is detached at it otherwise.
+pub fn delete_local_branches_detaching_to(
+    repo: &Repository,
+    branches: &[&LocalBranch],
+    keep_config: bool,
+    detach_to: Option<&str>,
+    dry_run: bool,
+) -> Result<Vec<LocalDeletionFailure>, Error> {
+    Ok(delete_local_branches_impl(
+        repo,
+        branches,
+        keep_config,
+        detach_to,
+        dry_run,
+    )?)
+}
+
+fn delete_local_branches_impl(
+    repo: &Repository,
+    branches: &[&LocalBranch],
+    keep_config: bool,
+    detach_to: Option<&str>,
+    dry_run: bool,
+) -> Result<Vec<LocalDeletionFailure>> {
     if branches.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
-    let detach_to = if repo.head_detached()? {
-        None
+    if let Some(checkout) = get_checkout_target(repo, branches, detach_to)? {
+        subprocess::checkout(repo, &checkout, dry_run)?;
+    }
+    let stacks = Stacks::read(repo, &repo.config()?.snapshot()?)?;
+    let mut deleted = Vec::new();
+    let mut failures = Vec::new();
+    for branch in stacks.children_first(branches) {
+        match delete_local_branch(repo, branch, keep_config, dry_run) {
+            Ok(Some(local)) => deleted.push(local),
+            Ok(None) => {}
+            Err(error) => failures.push(LocalDeletionFailure {
+                branch: (*branch).clone(),
+                error: Error::branch(&branch.refname, error),
+            }),
+        }
+    }
+    if !deleted.is_empty() {
+        trim_log::append(repo, &TrimLogRecord::now(deleted, Vec::new())?)?;
+    }
+    Ok(failures)
+}
+
+fn delete_local_branch(
+    repo: &Repository,
+    branch: &LocalBranch,
+    keep_config: bool,
+    dry_run: bool,
+) -> Result<Option<DeletedLocal>> {
+    let reference = repo.find_reference(&branch.refname)?;
+    assert!(reference.is_branch());
+    let branch_name = reference
+        .shorthand()
+        .context("non utf-8 branch name")?
+        .to_owned();
+    let commit = reference.peel_to_commit()?;
+    let short_id = commit.as_object().short_id()?;
+    let short_id = short_id.as_str().context("non utf-8 short id")?;
+
+    if dry_run {
+        info!("> delete {} (dry-run)", branch.refname);
+        println!("Delete branch {} (dry run).", branch_name);
+        Ok(None)
     } else {
-        let head = repo.head()?;
-        let head_refname = head.name().context("non-utf8 head ref name")?;
-        if branches.iter().any(|branch| branch.refname == head_refname) {
-            Some(head)
+        info!("> delete {}", branch.refname);
+        // libgit2 removes the config section of the branch along with it.
+        let config_entries = get_branch_config_entries(repo, &branch_name)?;
+        let result = git2::Branch::wrap(reference)
+            .delete()
+            .with_context(|| format!("branch={}", branch_name));
+        let deleted = result.is_ok() || repo.find_reference(&branch.refname).is_err();
+        if !deleted || keep_config {
+            // It might have been removed even if the deletion failed.
+            restore_branch_config_entries(repo, &branch_name, &config_entries)?;
         } else {
-            None
+            remove_branch_config_entries(repo, &branch_name)?;
         }
-    };
+        result?;
+        println!("Deleted branch {} (was {}).", branch_name, short_id);
+        Ok(Some(DeletedLocal {
+            refname: branch.refname.clone(),
+            oid: commit.id().to_string(),
+            config: config_entries,
+        }))
+    }
+}
+
+/// Restore a local branch deleted by git-trim, along with its config entries.
+/// It is fine if it is already restored.
+pub fn restore_local_branch(
+    repo: &Repository,
+    deleted: &DeletedLocal,
+    dry_run: bool,
+) -> Result<()> {
+    let oid = git2::Oid::from_str(&deleted.oid)?;
+    let branch_name = deleted
+        .refname
+        .strip_prefix("refs/heads/")
+        .context("not a local branch")?;
+    match repo.refname_to_id(&deleted.refname) {
+        Ok(existing) if existing == oid => return Ok(()),
+        Ok(existing) => {
+            return Err(anyhow::anyhow!(
+                "branch `{}` already exists at {}",
+                branch_name,
+                existing
+            ))
+        }
+        Err(err) if err.code() == ErrorCode::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    repo.find_commit(oid)
+        .with_context(|| format!("the commit of `{}` is gone", branch_name))?;
+
+    if dry_run {
+        info!("> restore {} (dry-run)", deleted.refname);
+        return Ok(());
+    }
+    info!("> restore {}", deleted.refname);
+    repo.reference(&deleted.refname, oid, false, "git-trim: undo")?;
+    restore_branch_config_entries(repo, branch_name, &deleted.config)?;
+    Ok(())
+}
+
+/// Get `branch.<name>.*` entries from the repository local config.
+fn get_branch_config_entries(
+    repo: &Repository,
+    branch_name: &str,
+) -> Result<Vec<(String, String)>> {
+    let config = repo.config()?.open_level(ConfigLevel::Local)?;
+    let mut result = Vec::new();
+    let mut entries = config.entries(None)?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        if is_branch_config_key(name, branch_name) {
+            result.push((name.to_owned(), value.to_owned()));
+        }
+    }
+    Ok(result)
+}
+
+/// `<name>` is a subsection, so it is case-sensitive and may contain dots.
+fn is_branch_config_key(key: &str, branch_name: &str) -> bool {
+    match key
+        .strip_prefix("branch.")
+        .and_then(|rest| rest.strip_prefix(branch_name))
+        .and_then(|rest| rest.strip_prefix('.'))
+    {
+        Some(variable) => !variable.contains('.'),
+        None => false,
+    }
+}
 
-    if let Some(head) = detach_to {
-        subprocess::checkout(repo, head, dry_run)?;
+fn remove_branch_config_entries(repo: &Repository, branch_name: &str) -> Result<()> {
+    let mut config = repo.config()?.open_level(ConfigLevel::Local)?;
+    for (name, _) in get_branch_config_entries(repo, branch_name)? {
+        match config.remove_multivar(&name, ".*") {
+            Ok(()) => {}
+            Err(err) if err.code() == ErrorCode::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
     }
-    subprocess::branch_delete(repo, branches, dry_run)?;
+    Ok(())
+}
 
+fn restore_branch_config_entries(
+    repo: &Repository,
+    branch_name: &str,
+    entries: &[(String, String)],
+) -> Result<()> {
+    let existing = get_branch_config_entries(repo, branch_name)?;
+    let mut config = repo.config()?.open_level(ConfigLevel::Local)?;
+    for (name, value) in entries {
+        if existing.contains(&(name.clone(), value.clone())) {
+            continue;
+        }
+        // A regex that matches nothing appends a value instead of replacing existing ones.
+        config.set_multivar(name, "$^", value)?;
+    }
     Ok(())
 }
 
+fn chunks<T>(items: &[T], chunk_size: u64) -> std::slice::Chunks<'_, T> {
+    let chunk_size = match usize::try_from(chunk_size) {
+        Ok(0) | Err(_) => items.len().max(1),
+        Ok(chunk_size) => chunk_size,
+    };
+    items.chunks(chunk_size)
+}
+
+fn get_head_to_detach<'repo>(
+    repo: &'repo Repository,
+    branches: &[&LocalBranch],
+) -> Result<Option<Reference<'repo>>> {
+    if repo.head_detached()? {
+        return Ok(None);
+    }
+    let head = repo.head()?;
+    let head_refname = head.name().context("non-utf8 head ref name")?;
+    if branches.iter().any(|branch| branch.refname == head_refname) {
+        Ok(Some(head))
+    } else {
+        Ok(None)
+    }
+}
+
+/// What to check out when HEAD is about to be deleted.
+/// HEAD is detached at the same commit without `detach_to`, or if `detach_to` is also about to be deleted.
+fn get_checkout_target(
+    repo: &Repository,
+    branches: &[&LocalBranch],
+    detach_to: Option<&str>,
+) -> Result<Option<CheckoutTarget>> {
+    let head = match get_head_to_detach(repo, branches)? {
+        Some(head) => head,
+        None => return Ok(None),
+    };
+    // `git checkout refs/heads/<branch>` detaches at the branch.
+    let head = CheckoutTarget {
+        target: head.name().context("non-utf8 head ref name")?.to_owned(),
+        detach: false,
+    };
+    let detach_to = match detach_to {
+        Some(detach_to) => detach_to,
+        None => return Ok(Some(head)),
+    };
+    if let Ok(reference) = repo.resolve_reference_from_short_name(detach_to) {
+        if reference.is_branch() {
+            let refname = reference.name().context("non-utf8 ref name")?;
+            if branches.iter().any(|branch| branch.refname == refname) {
+                eprintln!(
+                    "{} is also about to be deleted. Detach at HEAD instead.",
+                    detach_to
+                );
+                return Ok(Some(head));
+            }
+            let short_name = reference.shorthand().context("non-utf8 branch name")?;
+            return Ok(Some(CheckoutTarget {
+                target: short_name.to_owned(),
+                detach: false,
+            }));
+        }
+    }
+    repo.revparse_single(detach_to)
+        .with_context(|| format!("detach-to={}", detach_to))?;
+    Ok(Some(CheckoutTarget {
+        target: detach_to.to_owned(),
+        detach: true,
+    }))
+}
+
+/// A failure to delete branches on a remote.
+/// It doesn't prevent deletions of other branches.
+pub struct RemoteDeletionFailure {
+    pub remote: String,
+    pub branches: Vec<RemoteBranch>,
+    /// Either `Error::PushRejected` for a branch which the remote rejected to delete,
+    /// or other errors (e.g. `Error::Authentication`, `Error::Subprocess`)
+    /// for which all branches on the remote are failed.
+    pub error: Error,
+}
+
+/// How pushes to delete remote branches are paced, not to trip abuse detection or hook timeouts of servers.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DeletePacing {
+    /// Maximum number of branches in a single `git push --delete`. 0 to push all branches of a remote at once.
+    pub chunk_size: u64,
+    /// Maximum number of branches deleted per minute from each remote. 0 for no limit.
+    pub rate_limit: u64,
+}
+
+impl DeletePacing {
+    /// A chunk is not larger than the rate limit, so that a single push doesn't exceed it.
+    fn push_chunk_size(&self) -> u64 {
+        match (self.chunk_size, self.rate_limit) {
+            (0, rate_limit) => rate_limit,
+            (chunk_size, 0) => chunk_size,
+            (chunk_size, rate_limit) => chunk_size.min(rate_limit),
+        }
+    }
+}
+
+/// Waits between pushes to a remote, not to delete more than the rate limit per minute.
+struct Pacer {
+    interval: Option<Duration>,
+    next: Option<Instant>,
+}
+
+impl Pacer {
+    fn new(rate_limit: u64) -> Self {
+        Pacer {
+            interval: (rate_limit > 0).then(|| Duration::from_secs_f64(60.0 / rate_limit as f64)),
+            next: None,
+        }
+    }
+
+    fn wait(&mut self, remote_name: &str, deletions: usize) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        if let Some(wait) = self
+            .next
+            .and_then(|next| next.checked_duration_since(Instant::now()))
+        {
+            println!(
+                "Wait {:.0?} to delete more branches from {} (trim.deleteRateLimit)",
+                wait, remote_name
+            );
+            std::thread::sleep(wait);
+        }
+        self.next = Some(Instant::now() + interval * deletions as u32);
+    }
+}
+
+/// Delete remote branches concurrently per remote.
+/// Failures are collected and returned instead of aborting deletions of other branches.
+/// A push that failed as a whole (e.g. a flaky connection) is retried once.
+/// Credentials are read from credential helpers and ssh-agent while pushing concurrently,
+/// and pushes that failed to authenticate are retried one by one, prompting on the terminal.
+/// If a remote tracking branch is in `tips`, the remote rejects to delete the branch
+/// unless it still points to the commit, so that new commits pushed from elsewhere are not lost.
 pub fn delete_remote_branches(
     repo: &Repository,
     remote_branches: &[RemoteBranch],
+    tips: &HashMap<String, String>,
+    dry_run: bool,
+) -> Vec<RemoteDeletionFailure> {
+    delete_remote_branches_paced(
+        repo,
+        remote_branches,
+        tips,
+        dry_run,
+        &DeletePacing::default(),
+    )
+}
+
+/// Same as `delete_remote_branches`, but branches of each remote are pushed in chunks,
+/// waiting between them not to exceed the rate limit of `pacing`.
+pub fn delete_remote_branches_paced(
+    repo: &Repository,
+    remote_branches: &[RemoteBranch],
+    tips: &HashMap<String, String>,
+    dry_run: bool,
+    pacing: &DeletePacing,
+) -> Vec<RemoteDeletionFailure> {
+    let leases = get_leases(repo, remote_branches, tips);
+    let last_known_oids = get_last_known_oids(repo, remote_branches, &leases);
+    let per_remote: Vec<_> = group_per_remote(remote_branches).into_iter().collect();
+    let failures: Vec<_> = per_remote
+        .into_par_iter()
+        .flat_map_iter({
+            let repo = ForceSendSync::new(repo);
+            let leases = &leases;
+            move |(remote_name, remote_refnames)| {
+                delete_from_remote_paced(
+                    &repo,
+                    remote_name,
+                    &remote_refnames,
+                    leases,
+                    dry_run,
+                    false,
+                    pacing,
+                )
+            }
+        })
+        .collect();
+
+    // Prompts of concurrent pushes would be interleaved.
+    let mut result = Vec::new();
+    for failure in failures {
+        if !matches!(failure.error, Error::Authentication(_)) {
+            result.push(failure);
+            continue;
+        }
+        warn!("{}", failure.error);
+        let remote_refnames: Vec<_> = failure.branches.iter().collect();
+        result.extend(delete_from_remote_paced(
+            repo,
+            &failure.remote,
+            &remote_refnames,
+            &leases,
+            dry_run,
+            true,
+            pacing,
+        ));
+    }
+
+    if !dry_run {
+        let deleted: Vec<_> = last_known_oids
+            .into_iter()
+            .filter(|(branch, _)| {
+                !result
+                    .iter()
+                    .any(|failure| failure.branches.contains(branch))
+            })
+            .map(|(branch, oid)| DeletedRemote {
+                remote: branch.remote,
+                refname: branch.refname,
+                oid,
+            })
+            .collect();
+        if !deleted.is_empty() {
+            if let Err(err) = TrimLogRecord::now(Vec::new(), deleted)
+                .and_then(|record| trim_log::append(repo, &record))
+            {
+                warn!("Failed to record deleted remote branches: {:#}", err);
+            }
+        }
+    }
+    result
+}
+
+/// Commits that remote branches pointed to, to restore them later.
+/// Branches without leases fall back to their remote tracking branches, and are skipped if they don't have either.
+fn get_last_known_oids(
+    repo: &Repository,
+    remote_branches: &[RemoteBranch],
+    leases: &HashMap<(String, String), String>,
+) -> Vec<(RemoteBranch, String)> {
+    let mut result = Vec::new();
+    for remote_branch in remote_branches {
+        let key = (remote_branch.remote.clone(), remote_branch.refname.clone());
+        let oid = match leases.get(&key) {
+            Some(oid) => Some(oid.clone()),
+            None => match RemoteTrackingBranch::from_remote_branch(repo, remote_branch) {
+                Ok(RemoteTrackingBranchStatus::Exists(remote_tracking)) => repo
+                    .refname_to_id(&remote_tracking.refname)
+                    .ok()
+                    .map(|oid| oid.to_string()),
+                _ => None,
+            },
+        };
+        match oid {
+            Some(oid) => result.push((remote_branch.clone(), oid)),
+            None => warn!(
+                "Can't tell the commit of {}, it can't be restored by undo",
+                remote_branch
+            ),
+        }
+    }
+    result
+}
+
+/// Restore a remote branch deleted by git-trim, by pushing its last known commit.
+/// The remote is left untouched if the branch is recreated.
+pub fn restore_remote_branch(
+    repo: &Repository,
+    deleted: &DeletedRemote,
     dry_run: bool,
 ) -> Result<()> {
-    if remote_branches.is_empty() {
-        return Ok(());
+    let oid = git2::Oid::from_str(&deleted.oid)?;
+    repo.find_commit(oid)
+        .with_context(|| format!("the commit of `{}` is gone", deleted.refname))?;
+    subprocess::push_restore(
+        repo,
+        &deleted.remote,
+        &deleted.oid,
+        &deleted.refname,
+        dry_run,
+    )
+}
+
+/// Push chunks of branches one by one. After a chunk fails to authenticate, the rest are not pushed,
+/// and they are added to the failure to be retried together.
+fn delete_from_remote_paced(
+    repo: &Repository,
+    remote_name: &str,
+    remote_refnames: &[&RemoteBranch],
+    leases: &HashMap<(String, String), String>,
+    dry_run: bool,
+    prompt: bool,
+    pacing: &DeletePacing,
+) -> Vec<RemoteDeletionFailure> {
+    let mut pacer = Pacer::new(if dry_run { 0 } else { pacing.rate_limit });
+    let mut failures = Vec::new();
+    let mut chunks = chunks(remote_refnames, pacing.push_chunk_size());
+    while let Some(chunk) = chunks.next() {
+        pacer.wait(remote_name, chunk.len());
+        let mut chunk_failures =
+            delete_from_remote(repo, remote_name, chunk, leases, dry_run, prompt);
+        let unauthenticated = chunk_failures
+            .iter_mut()
+            .find(|failure| matches!(failure.error, Error::Authentication(_)));
+        if let Some(failure) = unauthenticated {
+            failure
+                .branches
+                .extend(chunks.flatten().map(|branch| (*branch).clone()));
+            failures.extend(chunk_failures);
+            break;
+        }
+        failures.extend(chunk_failures);
+    }
+    failures
+}
+
+fn delete_from_remote(
+    repo: &Repository,
+    remote_name: &str,
+    remote_refnames: &[&RemoteBranch],
+    leases: &HashMap<(String, String), String>,
+    dry_run: bool,
+    prompt: bool,
+) -> Vec<RemoteDeletionFailure> {
+    let push_delete =
+        || subprocess::push_delete(repo, remote_name, remote_refnames, leases, dry_run, prompt);
+    let result = push_delete().or_else(|err| {
+        if err.is::<AuthenticationFailure>() {
+            return Err(err);
+        }
+        warn!("Retry to delete branches from {}: {:#}", remote_name, err);
+        push_delete()
+    });
+    match result {
+        Ok(output) => {
+            print_push_delete_output(remote_name, &output, dry_run);
+            output
+                .rejections
+                .into_iter()
+                .map(|rejection| RemoteDeletionFailure {
+                    remote: remote_name.to_owned(),
+                    branches: vec![RemoteBranch {
+                        remote: remote_name.to_owned(),
+                        refname: rejection.refname.clone(),
+                    }],
+                    error: rejection.into(),
+                })
+                .collect()
+        }
+        Err(error) => vec![RemoteDeletionFailure {
+            remote: remote_name.to_owned(),
+            branches: remote_refnames
+                .iter()
+                .map(|branch| (*branch).clone())
+                .collect(),
+            error: error.into(),
+        }],
+    }
+}
+
+/// Show what the remote responded at once, so that outputs of remotes are not interleaved.
+/// On a dry run, it is a preview of the deletion, e.g. `[deleted]` or `[rejected] (stale info)`.
+fn print_push_delete_output(remote_name: &str, output: &PushDeleteOutput, dry_run: bool) {
+    let mut lines = Vec::new();
+    if dry_run {
+        for (refname, summary) in &output.summaries {
+            lines.push(format!("  {}: {}", refname, summary));
+        }
+    }
+    for message in &output.remote_messages {
+        lines.push(format!("  remote: {}", message));
+    }
+    if lines.is_empty() {
+        return;
     }
-    let mut per_remote = HashMap::new();
+    if dry_run {
+        println!(
+            "Response from {} (dry run):\n{}",
+            remote_name,
+            lines.join("\n")
+        );
+    } else {
+        println!("Response from {}:\n{}", remote_name, lines.join("\n"));
+    }
+}
+
+/// Expected commits of remote branches by `(remote, refname)`.
+fn get_leases(
+    repo: &Repository,
+    remote_branches: &[RemoteBranch],
+    tips: &HashMap<String, String>,
+) -> HashMap<(String, String), String> {
+    let mut result = HashMap::new();
+    for remote_branch in remote_branches {
+        let key = match RemoteTrackingBranch::from_remote_branch(repo, remote_branch) {
+            Ok(RemoteTrackingBranchStatus::Exists(remote_tracking)) => remote_tracking.refname,
+            _ => format!("{}:{}", remote_branch.remote, remote_branch.refname),
+        };
+        if let Some(tip) = tips.get(&key) {
+            result.insert(
+                (remote_branch.remote.clone(), remote_branch.refname.clone()),
+                tip.clone(),
+            );
+        }
+    }
+    result
+}
+
+fn group_per_remote(remote_branches: &[RemoteBranch]) -> BTreeMap<&str, Vec<&RemoteBranch>> {
+    let mut per_remote = BTreeMap::new();
     for remote_branch in remote_branches {
         let entry = per_remote
-            .entry(&remote_branch.remote)
+            .entry(remote_branch.remote.as_str())
             .or_insert_with(Vec::new);
         entry.push(remote_branch);
     }
-    for (remote_name, remote_refnames) in per_remote.iter() {
-        subprocess::push_delete(repo, remote_name, remote_refnames, dry_run)?;
+    per_remote
+}
+
+/// Get git commands equivalent to what `delete_remote_branches` and `delete_local_branches` do in order.
+/// Each command is a list of arguments to `git`.
+/// `delete_local_branches` deletes them all at once in-process, but they are split in chunks of `chunk_size` here
+/// not to exceed the command line length limit when they are run.
+pub fn get_delete_commands(
+    repo: &Repository,
+    local_branches: &[&LocalBranch],
+    remote_branches: &[RemoteBranch],
+    detach_to: Option<&str>,
+    chunk_size: u64,
+) -> Result<Vec<Vec<String>>> {
+    let mut commands = Vec::new();
+    for (remote_name, remote_refnames) in group_per_remote(remote_branches) {
+        for chunk in chunks(&remote_refnames, chunk_size) {
+            commands.push(subprocess::push_delete_args(remote_name, chunk));
+        }
     }
-    Ok(())
+    if !local_branches.is_empty() {
+        if let Some(checkout) = get_checkout_target(repo, local_branches, detach_to)? {
+            commands.push(subprocess::checkout_args(&checkout));
+        }
+        for chunk in chunks(local_branches, chunk_size) {
+            commands.push(subprocess::branch_delete_args(repo, chunk)?);
+        }
+    }
+    Ok(commands)
+}
+
+/// Commands to delete remote tracking refs of pull requests locally, in chunks of `chunk_size`.
+pub fn get_pull_ref_delete_commands(
+    pull_refs: &[&RemoteTrackingBranch],
+    chunk_size: u64,
+) -> Vec<Vec<String>> {
+    chunks(pull_refs, chunk_size)
+        .map(subprocess::branch_delete_remotes_args)
+        .collect()
 }
@@ -0,0 +1,79 @@
+//! `git ls-remote` with libgit2 when `trim.libgit2LsRemote` is set, so that it doesn't depend on `git` and the TLS library that it is built with.
+//! Callers fall back to `git ls-remote` when this fails, e.g. when the remote needs an interactive password prompt.
+
+use anyhow::{Context, Result};
+use git2::{Cred, CredentialType, Direction, ErrorCode, RemoteCallbacks, Repository};
+
+use crate::subprocess::RemoteHead;
+
+struct ListedRef {
+    refname: String,
+    commit: String,
+    symref_target: Option<String>,
+}
+
+/// `remote_name` can be a name of a remote or a URL.
+fn list(repo: &Repository, remote_name: &str) -> Result<Vec<ListedRef>> {
+    let mut remote = match repo.find_remote(remote_name) {
+        Ok(remote) => remote,
+        Err(err) if matches!(err.code(), ErrorCode::NotFound | ErrorCode::InvalidSpec) => {
+            repo.remote_anonymous(remote_name)?
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let config = repo.config()?;
+    let mut tried = CredentialType::empty();
+    let mut callbacks = RemoteCallbacks::new();
+    // libgit2 calls this again and again until it succeeds. Try each type of credentials only once.
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) && !tried.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && !tried.contains(CredentialType::USER_PASS_PLAINTEXT)
+        {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            return Cred::credential_helper(&config, url, username);
+        }
+        Err(git2::Error::from_str("no more credentials to try"))
+    });
+
+    let connection = remote.connect_auth(Direction::Fetch, Some(callbacks), None)?;
+    let mut result = Vec::new();
+    for head in connection.list()? {
+        result.push(ListedRef {
+            refname: head.name().to_owned(),
+            commit: head.oid().to_string(),
+            symref_target: head.symref_target().map(str::to_owned),
+        });
+    }
+    Ok(result)
+}
+
+pub fn ls_remote_heads(repo: &Repository, remote_name: &str) -> Result<Vec<RemoteHead>> {
+    Ok(list(repo, remote_name)?
+        .into_iter()
+        .filter(|listed| listed.refname.starts_with("refs/heads/"))
+        .map(|listed| RemoteHead {
+            remote: remote_name.to_owned(),
+            refname: listed.refname,
+            commit: listed.commit,
+        })
+        .collect())
+}
+
+pub fn ls_remote_head(repo: &Repository, remote_name: &str) -> Result<RemoteHead> {
+    let head = list(repo, remote_name)?
+        .into_iter()
+        .find(|listed| listed.refname == "HEAD")
+        .with_context(|| format!("HEAD not found on {}", remote_name))?;
+    Ok(RemoteHead {
+        remote: remote_name.to_owned(),
+        refname: head
+            .symref_target
+            .with_context(|| format!("HEAD is not a symbolic ref on {}", remote_name))?,
+        commit: head.commit,
+    })
+}
@@ -1,27 +1,54 @@
 mod remote_head_change_checker;
+mod tui;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
 use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use console::Term;
 use dialoguer::Confirm;
-use git2::{BranchType, Repository};
+use git2::{BranchType, ErrorCode, Oid, Repository};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::*;
 
-use git_trim::args::Args;
+use git_trim::args::{Args, Command, DryRun, FixGone, PlanFormat, SortKey, SummaryFormat};
 use git_trim::config::{self, get, Config, ConfigValue};
+use git_trim::hints::{self, Hint, HintFilter, HintId};
+use git_trim::report::{escape_workflow_data, escape_workflow_property, TimingReport, TrimReport};
+use git_trim::trim_log::{self, TrimLogRecord, TrimStats};
 use git_trim::{
-    delete_local_branches, delete_remote_branches, get_trim_plan, ls_remote_head, remote_update,
-    ClassifiedBranch, ForceSendSync, Git, LocalBranch, PlanParam, RemoteHead, RemoteTrackingBranch,
-    SkipSuggestion, TrimPlan,
+    check_no_operation_in_progress, common_dir, delete_local_branches_detaching_to,
+    delete_orphan_namespaces, delete_pull_refs, delete_remote_branches_paced, detect_remote_heads,
+    fast_forward_bases, fast_forward_to_upstream, format_date, gc_after_deletion, get_branch_tips,
+    get_delete_commands, get_orphan_namespace_delete_commands, get_pull_ref_delete_commands,
+    get_trim_plan_of_branch, get_trim_plan_of_branches, get_trim_plan_with_progress,
+    keep_pairs_together, kill_running, log_oneline, ls_remote_head, record_reclaimable_bytes,
+    remote_update, restore_local_branch, restore_remote_branch, retarget_renamed_upstreams,
+    retarget_stacked_upstreams, set_remote_head, shell_quote, skip_moved_branches,
+    skip_pairs_of_failed_remotes, unset_upstreams, write_commit_graph_if_missing, BaseRef,
+    BranchPair, ClassifiedBranch, Error, ForceSendSync, Git, LocalBranch, LocalDeletionFailure,
+    MovedBranch, PlanParam, Preserved, Progress, RejectionKind, RemoteBranch, RemoteBranchError,
+    RemoteDeletionFailure, RemoteHead, RemoteOnlyBranch, RemoteTrackingBranch, SubprocessError,
+    Timings, TrimPlan,
 };
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
     env_logger::init();
+    // Children are interrupted along with it, but reap them not to leave them behind.
+    // Prompts hide the cursor while they are shown. Restore it.
+    ctrlc::set_handler(|| {
+        kill_running();
+        let _ = Term::stderr().show_cursor();
+        std::process::exit(130);
+    })?;
     if let Some(version) = option_env!("VERGEN_GIT_DESCRIBE") {
         info!("VERSION: {version}");
     } else {
@@ -32,48 +59,300 @@ fn main() -> Result<()> {
     }
     info!("TARGET_TRIPLE: {}", env!("VERGEN_CARGO_TARGET_TRIPLE"));
 
-    let git = Git::try_from(Repository::open_from_env()?)?;
-
-    if git.repo.remotes()?.is_empty() {
-        return Err(anyhow::anyhow!("git-trim requires at least one remote"));
+    if let Some(Command::Completions { shell }) = &args.command {
+        clap_complete::generate(
+            *shell,
+            &mut Args::command(),
+            "git-trim",
+            &mut std::io::stdout(),
+        );
+        return Ok(());
     }
 
-    let config = Config::read(&git.repo, &git.config, &args)?;
+    let git = Git::try_from(open_repository(&args)?)?;
+
+    let mut config = Config::read(&git.repo, &git.config, &args)?;
     info!("config: {:?}", config);
+    if *config.jobs > 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(*config.jobs as usize)
+            .build_global()?;
+    }
+    if args.no_confirm {
+        eprintln!("`--no-confirm` is deprecated. Use `--yes` instead.");
+    }
+    // Prompts can't be answered without terminals. Deletions are refused then, unless `--yes` is given.
+    let unattended = *config.confirm && !is_interactive();
+    if unattended {
+        config.confirm = ConfigValue::Implicit(false);
+    }
+    if config.bases.is_empty()
+        && matches!(config.bases, ConfigValue::Implicit(_))
+        && *config.detect_bases
+        && !*config.offline
+    {
+        config.bases = ConfigValue::Implicit(detect_bases(&git, &config, args.dry_run())?);
+    }
     if config.bases.is_empty() {
-        return error_no_bases(&git.repo, &config.bases);
+        return error_no_bases(&git.repo, &config.bases, &config.hints);
+    }
+
+    if *config.partial_clone {
+        let skipped: Vec<_> = config
+            .detection_strategies
+            .iter()
+            .filter(|strategy| strategy.reads_blobs())
+            .map(|strategy| strategy.to_string())
+            .collect();
+        if !skipped.is_empty() {
+            warn!(
+                "Skip detection strategies {} to avoid fetching missing blobs of the partial clone",
+                skipped.join(", ")
+            );
+        }
+    }
+
+    match &args.command {
+        Some(Command::Why { branch }) => return explain(&git, &config, branch),
+        Some(Command::Apply { plan }) => {
+            return apply(
+                &git,
+                &config,
+                plan,
+                args.dry_run(),
+                args.force_state,
+                unattended,
+            )
+        }
+        Some(Command::Undo { remote }) => return undo(&git, *remote, args.dry_run()),
+        Some(Command::Stats { days }) => return stats(&git, *days),
+        _ => {}
     }
 
+    // The script should be the only thing printed to stdout so that it can be piped to a shell.
+    // So is the plan when it is not written to a file.
+    let script = args.dry_run == Some(DryRun::Script)
+        || matches!(args.command, Some(Command::Plan { output: None, .. }));
+
     let mut checker = None;
-    if *config.update {
-        if should_update(&git, *config.update_interval, config.update)? {
-            checker = Some(remote_head_change_checker::RemoteHeadChangeChecker::spawn()?);
-            remote_update(&git.repo, args.dry_run)?;
-            println!();
-        } else {
+    let mut update_elapsed = None;
+    if *config.update && !*config.offline {
+        if should_update(&git, *config.update_interval, &config.update)? {
+            checker = Some(remote_head_change_checker::RemoteHeadChangeChecker::spawn(
+                &git.repo,
+                Duration::from_secs(*config.remote_timeout),
+            )?);
+            let started = Instant::now();
+            remote_update(&git.repo, args.dry_run())?;
+            update_elapsed = Some(started.elapsed());
+            if !script {
+                println!();
+            }
+        } else if !script {
             println!("Repository is updated recently. Skip to update it")
         }
     }
 
-    let plan = get_trim_plan(
-        &git,
-        &PlanParam {
-            bases: config.bases.iter().map(String::as_str).collect(),
-            protected_patterns: config.protected.iter().map(String::as_str).collect(),
-            delete: config.delete.clone(),
-            detach: *config.detach,
-        },
-    )?;
+    // Only a real trim updates refs. `plan` and `check` print what it would fast-forward.
+    // It prints what are fast-forwarded, which is not a part of the script.
+    if *config.ff_bases && !script {
+        let bases: Vec<_> = config.bases.iter().map(String::as_str).collect();
+        let dry_run = args.dry_run()
+            || matches!(
+                args.command,
+                Some(Command::Plan { .. } | Command::Check { .. })
+            );
+        fast_forward_bases(&git.repo, &git.config, &bases, dry_run)?;
+    }
+
+    if *config.write_commit_graph {
+        write_commit_graph_if_missing(&git.repo, args.dry_run())?;
+    }
 
-    print_summary(&plan, &git.repo)?;
+    let progress_bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template("{msg} [{bar:40}] {pos}/{len}")?.progress_chars("=> "),
+    );
+    let stdin_refnames = if args.stdin {
+        Some(read_branches_from_stdin(&git.repo)?)
+    } else {
+        None
+    };
+    let get_plan = |param: &PlanParam| -> Result<TrimPlan> {
+        progress_bar.reset();
+        let plan = match &stdin_refnames {
+            Some(refnames) => {
+                let refnames: Vec<_> = refnames.iter().map(String::as_str).collect();
+                get_trim_plan_of_branches(&git, param, &refnames)?
+            }
+            None => get_trim_plan_with_progress(&git, param, &|progress| {
+                report_progress(&progress_bar, progress)
+            })?,
+        };
+        progress_bar.finish_and_clear();
+        Ok(plan)
+    };
+    let mut plan = get_plan(&plan_param(&config))?;
+    if plan.cached && !script {
+        println!("Nothing has changed since the last run. Reuse its classifications (`--no-cache` to classify again)");
+    }
+
+    if args.apply_suggestions && !plan.skipped.is_empty() {
+        let ranges = plan.suggested_delete_ranges();
+        if !script {
+            let ranges: Vec<_> = ranges.iter().map(ToString::to_string).collect();
+            println!("Apply suggestions: --delete {}", ranges.join(","));
+        }
+        let mut param = plan_param(&config);
+        param.delete = param.delete.extended(ranges);
+        plan = get_plan(&param)?;
+    }
+
+    if let Some(elapsed) = update_elapsed {
+        plan.timings.prepend("remote update", elapsed);
+    }
+    if args.timings {
+        // Not to stdout, since it may be a script or a plan to pipe.
+        print_timings(&plan.timings);
+    }
+
+    if let Some(Command::Plan { output, format }) = &args.command {
+        return write_plan(
+            &git,
+            &config,
+            &plan,
+            output.as_deref(),
+            *format,
+            args.timings,
+        );
+    }
+    if let Some(Command::Check {
+        max_stale,
+        annotate,
+    }) = &args.command
+    {
+        return check(&git, &plan, *max_stale, *annotate);
+    }
+
+    if !args.dry_run() && !args.force_state && !plan.locals_to_delete().is_empty() {
+        check_no_operation_in_progress(&git.repo)?;
+    }
+    if !args.dry_run() && *config.confirm && *config.confirm_diverged {
+        confirm_diverged(&git, &mut plan)?;
+    }
+    if !args.dry_run()
+        && *config.confirm
+        && *config.tui
+        && !tui::select_branches(&git.repo, &mut plan, *config.sort)?
+    {
+        println!("Cancelled");
+        return Ok(());
+    }
 
     let locals = plan.locals_to_delete();
     let remotes = plan.remotes_to_delete(&git.repo)?;
-    let any_branches_to_remove = !(locals.is_empty() && remotes.is_empty());
+    let pull_refs = plan.pull_refs_to_delete();
+    let orphans_to_prune = *config.prune_removed_remotes && !plan.orphan_namespaces.is_empty();
+    let orphan_namespaces: &[_] = if orphans_to_prune {
+        &plan.orphan_namespaces
+    } else {
+        &[]
+    };
+    let any_branches_to_remove =
+        !(locals.is_empty() && remotes.is_empty() && pull_refs.is_empty()) || orphans_to_prune;
+    // Branch configs are rewritten only after they are confirmed along with the branches.
+    let any_upstreams_to_fix = (*config.fix_upstreams && !plan.broken_upstreams.is_empty())
+        || (*config.fix_gone == FixGone::Unset && !plan.gone_upstreams.is_empty())
+        || (*config.retarget_renamed && !plan.renamed_upstreams.is_empty());
+
+    let detach_to = resolve_detach_to(&git, &config, &plan.local_bases())?;
+    if script {
+        let mut commands = get_delete_commands(
+            &git.repo,
+            &locals,
+            &remotes,
+            detach_to.as_deref(),
+            *config.delete_chunk_size,
+        )?;
+        commands.extend(get_pull_ref_delete_commands(
+            &pull_refs,
+            *config.delete_chunk_size,
+        ));
+        commands.extend(get_orphan_namespace_delete_commands(
+            orphan_namespaces,
+            *config.delete_chunk_size,
+        ));
+        println!("#!/bin/sh");
+        println!("set -e");
+        for command in &commands {
+            println!("{}", format_command(command));
+        }
+        return Ok(());
+    }
+
+    let confirming = !args.dry_run() && *config.confirm && !*config.tui;
+    print_summary(
+        &plan,
+        &git.repo,
+        *config.summary,
+        *config.sort,
+        *config.details,
+        confirming,
+        &config.hints,
+    )?;
+    print_broken_upstreams(&plan, *config.fix_upstreams, &config.hints);
+    print_orphan_namespaces(&plan, *config.prune_removed_remotes, &config.hints);
+    print_gone_upstreams(&plan, *config.fix_gone, &config.hints);
+    print_renamed_upstreams(&plan, *config.retarget_renamed, &config.hints);
+    print_unreachable_remotes(&plan);
+    print_unresolved_bases(&plan);
+    if args.explain_all {
+        print_explanations(&plan, &git.repo)?;
+    }
+
+    if !check_max_delete(
+        &config,
+        locals.len()
+            + remotes.len()
+            + pull_refs.len()
+            + orphan_namespaces
+                .iter()
+                .map(|namespace| namespace.refnames.len())
+                .sum::<usize>(),
+        args.dry_run(),
+    )? {
+        println!("Cancelled");
+        return Ok(());
+    }
+
+    if args.dry_run == Some(DryRun::Commands) && any_branches_to_remove {
+        println!("Commands to execute:");
+        let commands = get_delete_commands(
+            &git.repo,
+            &locals,
+            &remotes,
+            detach_to.as_deref(),
+            *config.delete_chunk_size,
+        )?;
+        let pull_ref_commands = get_pull_ref_delete_commands(&pull_refs, *config.delete_chunk_size);
+        let orphan_commands =
+            get_orphan_namespace_delete_commands(orphan_namespaces, *config.delete_chunk_size);
+        for command in commands
+            .iter()
+            .chain(&pull_ref_commands)
+            .chain(&orphan_commands)
+        {
+            println!("    {}", format_command(command));
+        }
+        println!();
+    }
 
-    if !args.dry_run
+    if !args.dry_run() && unattended && (any_branches_to_remove || any_upstreams_to_fix) {
+        return Err(refuse_unattended());
+    }
+    // The list of `--tui` is already confirmed, but upstreams to fix are not listed there.
+    if !args.dry_run()
         && *config.confirm
-        && any_branches_to_remove
+        && ((any_branches_to_remove && !*config.tui) || any_upstreams_to_fix)
         && !Confirm::new()
             .with_prompt("Confirm?")
             .default(false)
@@ -83,33 +362,877 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    delete_remote_branches(&git.repo, remotes.as_slice(), args.dry_run)?;
-    delete_local_branches(&git.repo, &locals, args.dry_run)?;
+    let (locals, mut remotes, moved) =
+        skip_moved_branches(&git.repo, &plan.tips, &locals, &remotes)?;
+    print_moved_branches(&moved);
+    if !args.dry_run() && *config.confirm && *config.confirm_remotes {
+        confirm_remotes(&git, &mut remotes)?;
+    }
+    let pairs = plan.pairs_to_delete(&git.repo, &git.config)?;
+    let (locals, remotes, split) = keep_pairs_together(&pairs, &locals, &remotes);
+    print_split_pairs(&split);
+    let tips = if args.dry_run() {
+        Vec::new()
+    } else {
+        get_branch_tips(&git.repo, &locals, &remotes)?
+    };
+    let remote_failures = delete_remote_branches_paced(
+        &git.repo,
+        remotes.as_slice(),
+        &plan.tips,
+        args.dry_run(),
+        &config.delete_pacing(),
+    );
+    let (locals_to_delete, mut local_failures) =
+        skip_pairs_of_failed_remotes(&pairs, &locals, &remote_failures);
+    if *config.retarget_stacks {
+        retarget_stacked_upstreams(
+            &git.repo,
+            &plan.local_bases(),
+            &locals_to_delete,
+            args.dry_run(),
+        )?;
+    }
+    local_failures.extend(delete_local_branches_detaching_to(
+        &git.repo,
+        &locals_to_delete,
+        *config.keep_config,
+        detach_to.as_deref(),
+        args.dry_run(),
+    )?);
+    if *config.switch_to_base_after_delete {
+        fast_forward_switched_base(&git, &locals_to_delete, args.dry_run());
+    }
+    if !args.dry_run() {
+        record_reclaimable_bytes(&git.repo, &tips)?;
+    }
+    let num_deleted = locals.len() + remotes.len() - num_failed(&remote_failures, &local_failures);
+    collect_garbage(&git, &config, num_deleted, args.dry_run())?;
+    if *config.fix_upstreams {
+        let broken: Vec<_> = plan.broken_upstreams.iter().map(|b| &b.local).collect();
+        unset_upstreams(&git.repo, &broken, args.dry_run())?;
+    }
+    if *config.fix_gone == FixGone::Unset {
+        let gone: Vec<_> = plan.gone_upstreams.iter().collect();
+        unset_upstreams(&git.repo, &gone, args.dry_run())?;
+    }
+    if *config.retarget_renamed {
+        retarget_renamed_upstreams(&git.repo, &plan.renamed_upstreams, args.dry_run())?;
+    }
+    if !pull_refs.is_empty() {
+        delete_pull_refs(&git.repo, &pull_refs, args.dry_run())?;
+    }
+    if orphans_to_prune {
+        delete_orphan_namespaces(&git.repo, &plan.orphan_namespaces, args.dry_run())?;
+    }
 
-    prompt_survey_on_push_upstream(&git)?;
+    if !args.dry_run() && is_interactive() {
+        if let Err(err) = prompt_survey_on_push_upstream(&git) {
+            warn!("Failed to remember that the survey is shown: {:#}", err);
+        }
+    }
+    print_non_utf8_refnames(&plan);
 
     if let Some(checker) = checker.take() {
-        checker.check_and_notify(&git.repo)?;
+        if let Some(hint) = checker.check(&git.repo)? {
+            eprint_hint(&hint, &config.hints);
+        }
+    }
+
+    check_failures(&remote_failures, &local_failures)
+}
+
+/// `-C`, `--git-dir`, and `--work-tree` are applied like git does.
+/// The paths are exported to `git` subprocesses, as git exports them to its subcommands.
+fn open_repository(args: &Args) -> Result<Repository> {
+    if let Some(directory) = &args.directory {
+        std::env::set_current_dir(directory)
+            .with_context(|| format!("Cannot change to {}", directory.display()))?;
+    }
+    if let Some(git_dir) = &args.git_dir {
+        std::env::set_var("GIT_DIR", std::env::current_dir()?.join(git_dir));
+    }
+    if let Some(work_tree) = &args.work_tree {
+        std::env::set_var("GIT_WORK_TREE", std::env::current_dir()?.join(work_tree));
+    }
+    Ok(Repository::open_from_env()?)
+}
+
+fn write_plan(
+    git: &Git,
+    config: &Config,
+    plan: &TrimPlan,
+    output: Option<&Path>,
+    format: PlanFormat,
+    timings: bool,
+) -> Result<()> {
+    let mut report = TrimReport::new(&git.repo, plan)?;
+    report.hints = plan_hints(plan, config);
+    if timings {
+        report.timings = plan.timings.phases.iter().map(TimingReport::from).collect();
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    match format {
+        PlanFormat::Json => {}
+        PlanFormat::GithubActions => return write_github_actions_report(&report, output, now),
+        PlanFormat::Markdown => {
+            let table = report.markdown_table(now);
+            match output {
+                Some(path) => std::fs::write(path, table)
+                    .with_context(|| format!("Failed to create {}", path.display()))?,
+                None => print!("{}", table),
+            }
+            return Ok(());
+        }
+    }
+    match output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            serde_json::to_writer_pretty(file, &report)?;
+            println!("The plan is written to {}", path.display());
+            println!("  Review it, then run `git trim apply {}`", path.display());
+        }
+        None => println!("{}", serde_json::to_string_pretty(&report)?),
     }
     Ok(())
 }
 
-fn error_no_bases(repo: &Repository, bases: &ConfigValue<HashSet<String>>) -> Result<()> {
-    fn eprint_bullet(s: &str) {
-        let width = textwrap::termwidth().max(40) - 4;
-        for (i, line) in textwrap::wrap(s, width).iter().enumerate() {
-            if i == 0 {
-                eprintln!(" * {}", line);
-            } else {
-                eprintln!("   {}", line);
+/// Annotations are printed, and the summary is appended to `output` or `$GITHUB_STEP_SUMMARY` if any.
+fn write_github_actions_report(report: &TrimReport, output: Option<&Path>, now: i64) -> Result<()> {
+    for annotation in report.github_actions_annotations() {
+        println!("{}", annotation);
+    }
+    let summary = match output {
+        Some(path) => Some(path.to_owned()),
+        None => std::env::var_os("GITHUB_STEP_SUMMARY").map(PathBuf::from),
+    };
+    if let Some(path) = summary {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        file.write_all(report.markdown_table(now).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Print branches that can be trimmed, and fail if they are more than `max_stale`.
+/// Nothing is deleted.
+fn check(git: &Git, plan: &TrimPlan, max_stale: u64, annotate: bool) -> Result<()> {
+    let mut stale = Vec::new();
+    for branch in &plan.to_delete {
+        if let Some(local) = branch.local() {
+            stale.push((local.refname.clone(), branch.message_local()));
+        }
+        if let Some(upstream) = branch.upstream() {
+            stale.push((upstream.refname.clone(), branch.message_remote()));
+        } else if let Some(remote) = branch.remote(&git.repo)? {
+            stale.push((
+                format!("{}:{}", remote.remote, remote.refname),
+                branch.message_remote(),
+            ));
+        }
+    }
+    stale.sort();
+
+    println!(
+        "{} branches can be trimmed (max {}):",
+        stale.len(),
+        max_stale
+    );
+    for (refname, message) in &stale {
+        if annotate {
+            println!(
+                "::warning title={}::{}",
+                escape_workflow_property("Stale branch"),
+                escape_workflow_data(&format!(
+                    "{} is {}. Run `git trim` to trim it",
+                    refname, message
+                ))
+            );
+        } else {
+            println!("    {}: {}", refname, message);
+        }
+    }
+    if stale.len() as u64 > max_stale {
+        return Err(anyhow::anyhow!(
+            "{} branches can be trimmed, which exceeds `--max-stale {}`",
+            stale.len(),
+            max_stale
+        ));
+    }
+    Ok(())
+}
+
+fn print_timings(timings: &Timings) {
+    let width = timings
+        .phases
+        .iter()
+        .map(|(phase, _)| phase.len())
+        .max()
+        .unwrap_or_default()
+        .max("total".len());
+    eprintln!("Timings:");
+    for (phase, elapsed) in &timings.phases {
+        eprintln!("  {:<width$}  {:>12}", phase, format!("{:.3?}", elapsed));
+    }
+    eprintln!(
+        "  {:<width$}  {:>12}",
+        "total",
+        format!("{:.3?}", timings.total())
+    );
+}
+
+fn apply(
+    git: &Git,
+    config: &Config,
+    path: &Path,
+    dry_run: bool,
+    force_state: bool,
+    unattended: bool,
+) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let report: TrimReport = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Failed to read the plan {}", path.display()))?;
+    let (locals, remotes) = report.branches_to_delete(&git.repo)?;
+    if locals.is_empty() && remotes.is_empty() {
+        println!("Nothing to delete");
+        return Ok(());
+    }
+
+    println!("Delete:");
+    for local in &locals {
+        println!("    {}", local.short_name());
+    }
+    for remote in &remotes {
+        println!("    {}", remote);
+    }
+    println!();
+
+    if !check_max_delete(config, locals.len() + remotes.len(), dry_run)? {
+        println!("Cancelled");
+        return Ok(());
+    }
+    if !dry_run && !force_state && !locals.is_empty() {
+        check_no_operation_in_progress(&git.repo)?;
+    }
+    if !dry_run && unattended {
+        return Err(refuse_unattended());
+    }
+    if !dry_run
+        && *config.confirm
+        && !Confirm::new()
+            .with_prompt("Confirm?")
+            .default(false)
+            .interact()?
+    {
+        println!("Cancelled");
+        return Ok(());
+    }
+
+    let locals: Vec<_> = locals.iter().collect();
+    let planned_tips = report.tips();
+    let (locals, remotes, moved) =
+        skip_moved_branches(&git.repo, &planned_tips, &locals, &remotes)?;
+    print_moved_branches(&moved);
+    let tips = if dry_run {
+        Vec::new()
+    } else {
+        get_branch_tips(&git.repo, &locals, &remotes)?
+    };
+    let remote_failures = delete_remote_branches_paced(
+        &git.repo,
+        &remotes,
+        &planned_tips,
+        dry_run,
+        &config.delete_pacing(),
+    );
+    let pairs = report.pairs_to_delete(&git.config)?;
+    let (locals_to_delete, mut local_failures) =
+        skip_pairs_of_failed_remotes(&pairs, &locals, &remote_failures);
+    if *config.retarget_stacks {
+        // Bases are not in the plan file. Upstreams are unset.
+        retarget_stacked_upstreams(&git.repo, &HashMap::new(), &locals_to_delete, dry_run)?;
+    }
+    // Bases are not in the plan file. The first one of `trim.bases` is checked out for `--detach-to base`.
+    let detach_to = resolve_detach_to(git, config, &HashMap::new())?;
+    local_failures.extend(delete_local_branches_detaching_to(
+        &git.repo,
+        &locals_to_delete,
+        *config.keep_config,
+        detach_to.as_deref(),
+        dry_run,
+    )?);
+    if *config.switch_to_base_after_delete {
+        fast_forward_switched_base(git, &locals_to_delete, dry_run);
+    }
+    if !dry_run {
+        record_reclaimable_bytes(&git.repo, &tips)?;
+    }
+    let num_deleted = locals.len() + remotes.len() - num_failed(&remote_failures, &local_failures);
+    collect_garbage(git, config, num_deleted, dry_run)?;
+    check_failures(&remote_failures, &local_failures)
+}
+
+/// `None` to detach at HEAD. `base` is the base that HEAD is merged into,
+/// or the first one of `trim.bases` if it is unknown. `trim.switchToBaseAfterDelete` implies `base`.
+fn resolve_detach_to(
+    git: &Git,
+    config: &Config,
+    local_bases: &HashMap<LocalBranch, BaseRef>,
+) -> Result<Option<String>> {
+    let detach_to = if *config.switch_to_base_after_delete {
+        "base"
+    } else {
+        config.detach_to.as_str()
+    };
+    match detach_to {
+        "HEAD" => Ok(None),
+        "base" => {
+            let head = git.repo.head()?;
+            let base = head
+                .name()
+                .and_then(|refname| local_bases.get(&LocalBranch::new(refname)));
+            if let Some(base) = base {
+                if let Some(local) = base.refname.strip_prefix("refs/heads/") {
+                    return Ok(Some(local.to_owned()));
+                }
+                let tracking = config::get_bases_tracking(
+                    &git.repo,
+                    &git.config,
+                    std::slice::from_ref(&base.refname),
+                )?;
+                return Ok(tracking.into_iter().next());
+            }
+            let mut bases: Vec<_> = config.bases.iter().collect();
+            bases.sort();
+            Ok(bases.first().map(|base| (*base).clone()))
+        }
+        detach_to => Ok(Some(detach_to.to_owned())),
+    }
+}
+
+/// Fast-forward the base branch that is checked out instead of the deleted HEAD.
+/// It is fine if it can't be fast-forwarded, e.g. it has diverged from its upstream.
+fn fast_forward_switched_base(git: &Git, deleted: &[&LocalBranch], dry_run: bool) {
+    let switched = match git.repo.head() {
+        Ok(head) => {
+            head.is_branch()
+                && !deleted
+                    .iter()
+                    .any(|local| Some(local.refname.as_str()) == head.name())
+        }
+        Err(_) => false,
+    };
+    if !switched {
+        return;
+    }
+    if let Err(err) = fast_forward_to_upstream(&git.repo, dry_run) {
+        eprintln!("Failed to fast-forward the base branch: {:#}", err);
+    }
+}
+
+fn collect_garbage(git: &Git, config: &Config, num_deleted: usize, dry_run: bool) -> Result<()> {
+    let sizes = gc_after_deletion(&git.repo, num_deleted, *config.gc_after, dry_run)?;
+    if let (Some((before, after)), false) = (sizes, dry_run) {
+        println!(
+            "Collected garbage. Objects take {} now, from {}.",
+            format_bytes(after),
+            format_bytes(before)
+        );
+    }
+    Ok(())
+}
+
+fn undo(git: &Git, remote: bool, dry_run: bool) -> Result<()> {
+    let record = match trim_log::read(&git.repo)?.pop() {
+        Some(record) => record,
+        None => {
+            println!("Nothing to undo");
+            return Ok(());
+        }
+    };
+
+    let mut failed = 0;
+    for local in &record.locals {
+        let short_id = &local.oid[..7.min(local.oid.len())];
+        match restore_local_branch(&git.repo, local, dry_run) {
+            Ok(()) if dry_run => println!("Restore branch {} (dry run).", local.refname),
+            Ok(()) => println!("Restored branch {} (at {}).", local.refname, short_id),
+            Err(err) => {
+                eprintln!("Failed to restore {}: {:#}", local.refname, err);
+                failed += 1;
+            }
+        }
+    }
+    if remote {
+        for deleted in &record.remotes {
+            let short_id = &deleted.oid[..7.min(deleted.oid.len())];
+            match restore_remote_branch(&git.repo, deleted, dry_run) {
+                Ok(()) if dry_run => println!(
+                    "Restore remote branch {} on {} (dry run).",
+                    deleted.refname, deleted.remote
+                ),
+                Ok(()) => println!(
+                    "Restored remote branch {} on {} (at {}).",
+                    deleted.refname, deleted.remote, short_id
+                ),
+                Err(err) => {
+                    eprintln!(
+                        "Failed to restore {} on {}: {:#}",
+                        deleted.refname, deleted.remote, err
+                    );
+                    failed += 1;
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!("failed to restore {} branch(es)", failed));
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if !remote && !record.remotes.is_empty() {
+        println!(
+            "{} remote branch(es) are also deleted. Run `git trim undo --remote` to restore them.",
+            record.remotes.len()
+        );
+        trim_log::replace_last(
+            &git.repo,
+            TrimLogRecord {
+                locals: Vec::new(),
+                ..record
+            },
+        )?;
+    } else {
+        trim_log::pop(&git.repo)?;
+    }
+    Ok(())
+}
+
+fn stats(git: &Git, days: u64) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let since = now.saturating_sub(days.saturating_mul(24 * 60 * 60));
+    let stats = TrimStats::aggregate(&trim_log::read(&git.repo)?, since);
+    println!(
+        "Deleted {} branches ({} local, {} remote) in {} runs in the last {} days.",
+        stats.locals + stats.remotes,
+        stats.locals,
+        stats.remotes,
+        stats.runs,
+        days
+    );
+    println!(
+        "About {} can be reclaimed by `git gc` once their reflogs expire.",
+        format_bytes(stats.reclaimable_bytes)
+    );
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "bytes";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+fn print_moved_branches(moved: &[MovedBranch]) {
+    if moved.is_empty() {
+        return;
+    }
+    eprintln!();
+    eprintln!("Skipped branches that are moved after it is planned:");
+    for branch in moved {
+        let planned = &branch.planned[..branch.planned.len().min(7)];
+        match &branch.current {
+            Some(current) => eprintln!(
+                "    {} ({} -> {})",
+                branch.refname,
+                planned,
+                &current[..current.len().min(7)]
+            ),
+            None => eprintln!("    {} ({} -> gone)", branch.refname, planned),
+        }
+    }
+    eprintln!();
+}
+
+fn print_split_pairs(split: &[BranchPair]) {
+    if split.is_empty() {
+        return;
+    }
+    eprintln!("Following branches are kept, since the other halves of them are not deleted:");
+    for pair in split {
+        eprintln!(
+            "    {} ({}:{})",
+            pair.local.short_name(),
+            pair.remote.remote,
+            pair.remote.refname
+        );
+    }
+    eprintln!();
+}
+
+fn print_unreachable_remotes(plan: &TrimPlan) {
+    if plan.unreachable_remotes.is_empty() {
+        return;
+    }
+    if plan.offline {
+        eprintln!("Following remotes are not contacted in offline mode. Branches that need them to be classified are kept:");
+    } else {
+        eprintln!("Following remotes didn't respond in time. Branches that need them to be classified are kept:");
+    }
+    for remote in &plan.unreachable_remotes {
+        eprintln!("    {}", remote);
+    }
+    eprintln!();
+}
+
+fn print_unresolved_bases(plan: &TrimPlan) {
+    if plan.unresolved_bases.is_empty() {
+        return;
+    }
+    eprintln!("Following bases are not found, so they are ignored:");
+    for unresolved in &plan.unresolved_bases {
+        eprintln!(
+            "    {} (tried {})",
+            unresolved.base,
+            unresolved.candidates.join(", ")
+        );
+    }
+    eprintln!();
+}
+
+fn print_non_utf8_refnames(plan: &TrimPlan) {
+    if plan.non_utf8_refnames.is_empty() {
+        return;
+    }
+    eprintln!();
+    eprintln!("Skipped branches whose names are not valid UTF-8:");
+    for refname in &plan.non_utf8_refnames {
+        eprintln!("    {}", refname);
+    }
+    eprintln!();
+}
+
+fn check_failures(
+    remote_failures: &[RemoteDeletionFailure],
+    local_failures: &[LocalDeletionFailure],
+) -> Result<()> {
+    if remote_failures.is_empty() && local_failures.is_empty() {
+        return Ok(());
+    }
+    print_failures(remote_failures, local_failures);
+    Err(anyhow::anyhow!(
+        "failed to delete {} branch(es)",
+        num_failed(remote_failures, local_failures)
+    ))
+}
+
+fn num_failed(
+    remote_failures: &[RemoteDeletionFailure],
+    local_failures: &[LocalDeletionFailure],
+) -> usize {
+    remote_failures
+        .iter()
+        .map(|f| f.branches.len())
+        .sum::<usize>()
+        + local_failures.len()
+}
+
+fn print_failures(
+    remote_failures: &[RemoteDeletionFailure],
+    local_failures: &[LocalDeletionFailure],
+) {
+    eprintln!();
+    eprintln!("Failed to delete:");
+    for failure in remote_failures {
+        for branch in &failure.branches {
+            eprintln!("    {}:{}", failure.remote, branch.refname);
+        }
+        eprintln!("      error: {:#}", failure.error);
+        eprintln!("      hint: {}", failure_hint(failure));
+    }
+    for failure in local_failures {
+        eprintln!("    {}", failure.branch.refname);
+        eprintln!("      error: {:#}", failure.error);
+    }
+}
+
+fn failure_hint(failure: &RemoteDeletionFailure) -> String {
+    if let Error::Authentication(_) = &failure.error {
+        format!(
+            "Set up a credential helper, or add your key to ssh-agent with `ssh-add`, for '{}'.",
+            failure.remote
+        )
+    } else if let Error::PushRejected(rejection) = &failure.error {
+        match rejection.kind {
+            RejectionKind::Protected => format!(
+                "The branch is protected on '{}'. Unprotect it, or remove it from the remote manually.",
+                failure.remote
+            ),
+            RejectionKind::DenyDeletes => format!(
+                "'{}' denies deleting branches by `receive.denyDeletes`. Ask its administrator to delete it.",
+                failure.remote
+            ),
+            RejectionKind::CurrentBranch => format!(
+                "The branch is checked out on '{}'. Switch to another branch there first.",
+                failure.remote
+            ),
+            RejectionKind::PermissionDenied => format!(
+                "You don't have a permission to delete branches on '{}'. Ask its administrator for the write access.",
+                failure.remote
+            ),
+            RejectionKind::Stale => format!(
+                "The branch has moved on '{}' since it is fetched. Run git-trim again after `git fetch {}`.",
+                failure.remote, failure.remote
+            ),
+            RejectionKind::Other if rejection.reason.contains("hook declined") => format!(
+                "A hook of '{}' declined it. The branch may be protected there.",
+                failure.remote
+            ),
+            RejectionKind::Other => format!(
+                "Check whether you have a permission to delete branches on '{}'.",
+                failure.remote
+            ),
+        }
+    } else {
+        format!(
+            "Check the connection and the credentials for '{}', then run git-trim again.",
+            failure.remote
+        )
+    }
+}
+
+/// Ask for each diverged branch whether to delete it or not, showing its diverged commits.
+/// Declined branches are preserved.
+fn confirm_diverged(git: &Git, plan: &mut TrimPlan) -> Result<()> {
+    let mut diverged: Vec<_> = plan
+        .to_delete
+        .iter()
+        .filter(|branch| {
+            matches!(
+                branch,
+                ClassifiedBranch::DivergedRemoteTracking { .. }
+                    | ClassifiedBranch::DivergedDirectFetch { .. }
+                    | ClassifiedBranch::DivergedRemote { .. }
+            )
+        })
+        .cloned()
+        .collect();
+    diverged.sort_by(|a, b| a.local().cmp(&b.local()));
+
+    for branch in diverged {
+        let remote = branch
+            .remote(&git.repo)?
+            .context("diverged branches have remotes")?;
+        println!("{} is diverged:", remote);
+        match &branch {
+            ClassifiedBranch::DivergedRemoteTracking { upstream, .. }
+            | ClassifiedBranch::DivergedRemote {
+                upstream: Some(upstream),
+                ..
+            } => {
+                let base = plan
+                    .classifications
+                    .iter()
+                    .find(|classification| classification.result.contains(&branch))
+                    .map(|classification| classification.base.refname.clone());
+                let range = match base {
+                    Some(base) => format!("{}..{}", base, upstream.refname),
+                    None => upstream.refname.clone(),
+                };
+                for line in log_oneline(&git.repo, &range)?.lines() {
+                    println!("  {}", line);
+                }
             }
+            _ => println!("  (diverged commits are not fetched)"),
         }
+        if !Confirm::new()
+            .with_prompt(format!("Delete diverged {}?", remote))
+            .default(false)
+            .interact()?
+        {
+            plan.to_delete.remove(&branch);
+            plan.preserved.push(Preserved {
+                branch,
+                reason: "declined to delete diverged".to_owned(),
+                base: false,
+            });
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Ask for each remote whether to push the deletions to it or not, showing the refspecs to push.
+/// Declined remote branches are not deleted.
+fn confirm_remotes(git: &Git, remotes: &mut Vec<RemoteBranch>) -> Result<()> {
+    let mut names: Vec<_> = remotes.iter().map(|branch| branch.remote.clone()).collect();
+    names.sort();
+    names.dedup();
+
+    for name in names {
+        // Direct fetch branches are on URLs, not on remotes.
+        let url = match git.repo.find_remote(&name) {
+            Ok(remote) => remote
+                .pushurl()
+                .or_else(|| remote.url())
+                .map(ToOwned::to_owned),
+            Err(_) => None,
+        };
+        match url {
+            Some(url) => println!(
+                "About to push the following deletions to {} ({}):",
+                name, url
+            ),
+            None => println!("About to push the following deletions to {}:", name),
+        }
+        for branch in remotes.iter().filter(|branch| branch.remote == name) {
+            println!("  :{}", branch.refname);
+        }
+        if !Confirm::new()
+            .with_prompt(format!("Push to {}?", name))
+            .default(false)
+            .interact()?
+        {
+            println!("Skipped {}", name);
+            remotes.retain(|branch| branch.remote != name);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn report_progress(progress_bar: &ProgressBar, progress: Progress) {
+    match progress {
+        Progress::ScanRemote {
+            remote,
+            done,
+            total,
+        } => {
+            progress_bar.set_message(format!("Scanned {}", remote));
+            progress_bar.set_length(total as u64);
+            progress_bar.set_position(done as u64);
+        }
+        Progress::Classify { done, total } => {
+            progress_bar.set_message("Classified branches");
+            progress_bar.set_length(total as u64);
+            progress_bar.set_position(done as u64);
+        }
+    }
+}
+
+fn format_command(args: &[String]) -> String {
+    let mut command = String::from("git");
+    for arg in args {
+        command.push(' ');
+        command.push_str(&shell_quote(arg));
     }
-    const GENERAL_HELP: &[&str] = &[
-        "`git config trim.bases develop,master` for a repository.",
-        "`git config --global trim.bases develop,master` to set globally.",
-        "`git trim --bases develop,master` to set temporarily.",
-    ];
+    command
+}
+
+fn plan_param(config: &Config) -> PlanParam<'_> {
+    PlanParam {
+        bases: config.bases.iter().map(String::as_str).collect(),
+        base_commits: config.base_commits.iter().map(String::as_str).collect(),
+        protected_patterns: config.protected.iter().map(String::as_str).collect(),
+        protect_head_of_remotes: *config.protect_head_of_remotes,
+        include_slash_prefixes: config
+            .include_slash_prefixes
+            .iter()
+            .map(String::as_str)
+            .collect(),
+        remote_namespaces: config
+            .remote_namespaces
+            .iter()
+            .map(String::as_str)
+            .collect(),
+        pull_ref_namespaces: config
+            .pull_ref_namespaces
+            .iter()
+            .map(String::as_str)
+            .collect(),
+        delete: config.delete.clone(),
+        detach: *config.detach,
+        scan_push_remote: *config.scan_push_remote,
+        scan: config.scan.clone(),
+        recent_checkout_days: *config.recent_checkout_days,
+        stray_grace_days: *config.stray_grace_days,
+        merge_strategies: config.merge_strategies(),
+        reachability_index: *config.reachability_index,
+        cache: *config.cache,
+        fix_upstreams: *config.fix_upstreams,
+        preserve_signed_by_others: *config.preserve_signed_by_others,
+        signing_keys: config.signing_keys.iter().map(String::as_str).collect(),
+        only_mine: *config.only_mine,
+        sort: *config.sort,
+        limit: *config.limit,
+        retarget_stacks: *config.retarget_stacks,
+        remote_timeout: *config.remote_timeout,
+        offline: *config.offline,
+        detect_remote_protection: *config.detect_remote_protection,
+    }
+}
+
+/// Bases from HEADs of remotes for this run. It offers to remember them with `git remote set-head`.
+/// Messages go to stderr, since stdout might be a script.
+fn detect_bases(git: &Git, config: &Config, dry_run: bool) -> Result<HashSet<String>> {
+    let heads = detect_remote_heads(git, Duration::from_secs(*config.remote_timeout))?;
+    let mut remote_heads = Vec::new();
+    for (remote_branch, remote_tracking) in &heads {
+        let short_name = remote_tracking
+            .refname
+            .trim_start_matches(&format!("refs/remotes/{}/", remote_branch.remote));
+        eprintln!(
+            "HEAD of {} is {}, but `refs/remotes/{}/HEAD` is missing.",
+            remote_branch.remote, remote_branch.refname, remote_branch.remote
+        );
+        let set_head = format!(
+            "git remote set-head {} {}",
+            remote_branch.remote, short_name
+        );
+        let remember = !dry_run
+            && *config.confirm
+            && Confirm::new()
+                .with_prompt(format!("Run `{}` to remember it?", set_head))
+                .default(true)
+                .interact()?;
+        if remember {
+            set_remote_head(&git.repo, &remote_branch.remote, remote_tracking, dry_run)?;
+        } else {
+            eprintln!(
+                "Use it as a base for this run. `{}` to remember it.",
+                set_head
+            );
+        }
+        remote_heads.push(remote_tracking.refname.clone());
+    }
+    Ok(
+        config::get_bases_tracking(&git.repo, &git.config, &remote_heads)?
+            .into_iter()
+            .collect(),
+    )
+}
+
+fn error_no_bases(
+    repo: &Repository,
+    bases: &ConfigValue<HashSet<String>>,
+    hint_filter: &HintFilter,
+) -> Result<()> {
     match bases {
         ConfigValue::Explicit(_) => {
             eprintln!(
@@ -117,20 +1240,29 @@ fn error_no_bases(repo: &Repository, bases: &ConfigValue<HashSet<String>>) -> Re
             );
         }
         ConfigValue::GitConfig(_) => {
-            eprintln!(
-                "I found that `git config trim.bases` is empty! Try any following commands to set valid bases:"
+            let mut hint = Hint::new(
+                HintId::BaseResolution,
+                "I found that `git config trim.bases` is empty! Try any following commands to set valid bases:",
             );
-            for help in GENERAL_HELP {
-                eprint_bullet(help);
+            for help in hints::SET_BASES_MANUALLY {
+                hint = hint.with_suggestion(*help);
             }
+            eprint_hint(&hint, hint_filter);
         }
         ConfigValue::Implicit(_) => {
             let remotes = repo.remotes()?;
             let remotes: Vec<_> = remotes.iter().collect();
-            if remotes.len() == 1 {
+            let mut hint = Hint::new(
+                HintId::BaseResolution,
+                "I can't detect base branch! Try following any resolution, or set bases manually:",
+            );
+            if remotes.is_empty() {
+                hint = hint.with_suggestion(
+                    "There is no remote. Create `main` or `master` branch, or set `init.defaultBranch` to the base branch.",
+                );
+            } else if remotes.len() == 1 {
                 let remote = remotes[0].expect("non utf-8 remote name");
-                eprintln!("I can't detect base branch! Try following any resolution:");
-                eprint_bullet(&format!(
+                hint = hint.with_suggestion(format!(
                     "\
 `git remote set-head {remote} --auto` will help `git-trim` to automatically detect the base branch.
 If you see `{remote}/HEAD set to <base branch>` in the output of the previous command, \
@@ -138,8 +1270,7 @@ then `git branch --set-upstream {remote}/<base branch> <base branch>` to set an
                     remote = remote
                 ));
             } else {
-                eprintln!("I can't detect base branch! Try following any resolution:");
-                eprint_bullet(
+                hint = hint.with_suggestion(
                     "\
 `git remote set-head <remote> --auto` will help `git-trim` to automatically detect the base branch.
 Following command will sync all remotes for you:
@@ -148,24 +1279,97 @@ Pick an appropriate one in mind if you see multiple `<remote>/HEAD set to <base
 Then `git branch --set-upstream <remote>/<base branch> <base branch>` to set an upstream branch for <base branch> if exists.",
                 );
             }
-            println!("You also can set bases manually with any of following commands:");
-            for help in GENERAL_HELP {
-                eprint_bullet(help);
+            for help in hints::SET_BASES_MANUALLY {
+                hint = hint.with_suggestion(*help);
             }
+            eprint_hint(&hint, hint_filter);
         }
     }
 
     Err(anyhow::anyhow!("No base branch is found!"))
 }
 
-pub fn print_summary(plan: &TrimPlan, repo: &Repository) -> Result<()> {
+/// Branches to delete are printed even with `SummaryFormat::None` if it is `confirming` them,
+/// so that they are not confirmed blindly.
+pub fn print_summary(
+    plan: &TrimPlan,
+    repo: &Repository,
+    format: SummaryFormat,
+    sort: SortKey,
+    details: bool,
+    confirming: bool,
+    hint_filter: &HintFilter,
+) -> Result<()> {
+    match format {
+        SummaryFormat::Full => print_remaining(plan, repo, hint_filter)?,
+        SummaryFormat::Short => print_remaining_counts(plan, repo)?,
+        SummaryFormat::None if confirming => {}
+        SummaryFormat::None => return Ok(()),
+    }
+    print_to_delete(plan, repo, sort, details)
+}
+
+fn print_remaining_counts(plan: &TrimPlan, repo: &Repository) -> Result<()> {
+    let local_branches_to_delete = HashSet::<_>::from_iter(plan.locals_to_delete());
+    let mut locals = 0;
+    for local_branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = local_branch?;
+        let refname = match branch.get().name() {
+            Some(refname) => refname,
+            None => continue,
+        };
+        if !local_branches_to_delete.contains(&LocalBranch::new(refname)) {
+            locals += 1;
+        }
+    }
+
+    let remote_refs_to_delete = HashSet::<_>::from_iter(plan.remotes_to_delete(repo)?);
+    let mut remotes = 0;
+    for remote_ref in repo.branches(Some(BranchType::Remote))? {
+        let (branch, _) = remote_ref?;
+        if branch.get().symbolic_target_bytes().is_some() {
+            continue;
+        }
+        let refname = match branch.get().name() {
+            Some(refname) => refname,
+            None => continue,
+        };
+        let remote_branch = match RemoteTrackingBranch::new(refname).to_remote_branch(repo) {
+            Ok(remote_branch) => remote_branch,
+            // Remote tracking branches of removed remotes are printed separately.
+            Err(RemoteBranchError::RemoteNotFound) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if !remote_refs_to_delete.contains(&remote_branch) {
+            remotes += 1;
+        }
+    }
+
+    println!("Branches that will remain:");
+    println!(
+        "  {} local branches kept, {} deleted",
+        locals,
+        local_branches_to_delete.len()
+    );
+    println!(
+        "  {} remote references kept, {} deleted",
+        remotes,
+        remote_refs_to_delete.len()
+    );
+    println!();
+    Ok(())
+}
+
+fn print_remaining(plan: &TrimPlan, repo: &Repository, hint_filter: &HintFilter) -> Result<()> {
     println!("Branches that will remain:");
     println!("  local branches:");
     let local_branches_to_delete = HashSet::<_>::from_iter(plan.locals_to_delete());
     for local_branch in repo.branches(Some(BranchType::Local))? {
         let (branch, _) = local_branch?;
-        let branch_name = branch.name()?.context("non utf-8 local branch name")?;
-        let refname = branch.get().name().context("non utf-8 local refname")?;
+        let (branch_name, refname) = match (branch.name()?, branch.get().name()) {
+            (Some(branch_name), Some(refname)) => (branch_name, refname),
+            _ => continue,
+        };
         let branch = LocalBranch::new(refname);
         if local_branches_to_delete.contains(&branch) {
             continue;
@@ -195,13 +1399,16 @@ pub fn print_summary(plan: &TrimPlan, repo: &Repository) -> Result<()> {
         if branch.get().symbolic_target_bytes().is_some() {
             continue;
         }
-        let refname = branch.get().name().context("non utf-8 remote ref name")?;
-        let shorthand = branch
-            .get()
-            .shorthand()
-            .context("non utf-8 remote ref name")?;
+        let (refname, shorthand) = match (branch.get().name(), branch.get().shorthand()) {
+            (Some(refname), Some(shorthand)) => (refname, shorthand),
+            _ => continue,
+        };
         let upstream = RemoteTrackingBranch::new(refname);
-        let remote_branch = upstream.to_remote_branch(repo)?;
+        let remote_branch = match upstream.to_remote_branch(repo) {
+            Ok(remote_branch) => remote_branch,
+            Err(RemoteBranchError::RemoteNotFound) => continue,
+            Err(err) => return Err(err.into()),
+        };
         if remote_refs_to_delete.contains(&remote_branch) {
             continue;
         }
@@ -228,7 +1435,9 @@ pub fn print_summary(plan: &TrimPlan, repo: &Repository) -> Result<()> {
     for preserved in &plan.preserved {
         match &preserved.branch {
             ClassifiedBranch::MergedDirectFetch { remote, .. }
-            | ClassifiedBranch::DivergedDirectFetch { remote, .. } => {
+            | ClassifiedBranch::DivergedDirectFetch { remote, .. }
+            | ClassifiedBranch::MergedPushRemote { remote, .. }
+            | ClassifiedBranch::MergedRemoteOnly(RemoteOnlyBranch { remote, .. }) => {
                 println!(
                     "    {} [{}, but: {}]",
                     remote,
@@ -240,128 +1449,508 @@ pub fn print_summary(plan: &TrimPlan, repo: &Repository) -> Result<()> {
         }
     }
 
-    if !plan.skipped.is_empty() {
-        println!("  Some branches are skipped. Consider following to scan them:");
-        let tracking = plan
-            .skipped
-            .values()
-            .any(|suggest| suggest == &SkipSuggestion::Tracking);
-        let tracking_remotes: Vec<_> = {
-            let mut tmp = Vec::new();
-            for suggest in plan.skipped.values() {
-                if let SkipSuggestion::TrackingRemote(r) = suggest {
-                    tmp.push(r);
-                }
-            }
-            tmp
-        };
-        if let [single] = tracking_remotes.as_slice() {
-            println!(
-                "    *{}: Add `--delete 'merged:{}'` flag.",
-                SkipSuggestion::KIND_TRACKING,
-                single
-            );
-        } else if tracking_remotes.len() > 1 {
-            println!(
-                "    *{}: Add `--delete 'merged:*'` flag.",
-                SkipSuggestion::KIND_TRACKING,
-            );
-        } else if tracking {
-            println!(
-                "    *{}: Add `--delete 'merged-local'` flag.",
-                SkipSuggestion::KIND_TRACKING,
-            );
-        }
-        let non_tracking = plan
-            .skipped
-            .values()
-            .any(|suggest| suggest == &SkipSuggestion::NonTracking);
-        if non_tracking {
-            println!(
-                "    *{}: Set an upstream to make it a tracking branch or add `--delete 'local'` flag.",
-                SkipSuggestion::KIND_NON_TRACKING,
-            );
-        }
-
-        let non_upstream_remotes: Vec<_> = {
-            let mut tmp = Vec::new();
-            for suggest in plan.skipped.values() {
-                if let SkipSuggestion::NonUpstream(r) = suggest {
-                    tmp.push(r);
-                }
-            }
-            tmp
-        };
-        if let [single] = non_upstream_remotes.as_slice() {
-            println!(
-                "    *{}: Make it upstream of a tracking branch or add `--delete 'remote:{}'` flag.",
-                SkipSuggestion::KIND_NON_UPSTREAM,
-                single
-            );
-        } else if non_upstream_remotes.len() > 1 {
-            println!(
-                "    *{}: Make it upstream of a tracking branch or add `--delete 'remote:*'` flag.",
-                SkipSuggestion::KIND_NON_UPSTREAM,
-            );
-        }
+    if let Some(hint) = hints::skip_suggestions(plan) {
+        print_hint(&hint, hint_filter);
     }
     println!();
+    Ok(())
+}
 
+fn print_to_delete(plan: &TrimPlan, repo: &Repository, sort: SortKey, details: bool) -> Result<()> {
+    // Names to print, and names of their tip commits for `--details`. Empty if the tip is unknown.
     let mut merged_locals = Vec::new();
     let mut merged_remotes = Vec::new();
     let mut stray = Vec::new();
     let mut diverged_remotes = Vec::new();
-    for branch in &plan.to_delete {
+    let mut pull_refs = Vec::new();
+    let mut unrelated = Vec::new();
+    let mut duplicates = Vec::new();
+    let remote_tip = |branch: &ClassifiedBranch| -> Result<String> {
+        Ok(branch
+            .remote_tip(repo)?
+            .map(|tip| tip.to_string())
+            .unwrap_or_default())
+    };
+    for branch in plan.sorted_to_delete(repo, sort)? {
+        let stale = if plan.possibly_stale(branch) {
+            " (possibly stale)"
+        } else {
+            ""
+        };
         match branch {
             ClassifiedBranch::MergedLocal(local) => {
-                merged_locals.push(local.short_name().to_owned())
+                merged_locals.push((local.short_name().to_owned(), local.refname.clone()))
             }
-            ClassifiedBranch::Stray(local) => stray.push(local.short_name().to_owned()),
+            ClassifiedBranch::Stray(local) => stray.push((
+                format!("{}{}", local.short_name(), stale),
+                local.refname.clone(),
+            )),
             ClassifiedBranch::MergedRemoteTracking(upstream) => {
                 let remote = upstream.to_remote_branch(repo)?;
-                merged_remotes.push(remote.to_string())
+                merged_remotes.push((format!("{}{}", remote, stale), upstream.refname.clone()))
             }
             ClassifiedBranch::DivergedRemoteTracking { local, upstream } => {
                 let remote = upstream.to_remote_branch(repo)?;
-                merged_locals.push(local.short_name().to_owned());
-                diverged_remotes.push(remote.to_string())
+                merged_locals.push((
+                    format!("{}{}", local.short_name(), stale),
+                    local.refname.clone(),
+                ));
+                diverged_remotes.push((format!("{}{}", remote, stale), upstream.refname.clone()))
             }
             ClassifiedBranch::MergedDirectFetch { local, remote }
             | ClassifiedBranch::DivergedDirectFetch { local, remote } => {
-                merged_locals.push(local.short_name().to_owned());
-                diverged_remotes.push(remote.to_string())
+                merged_locals.push((local.short_name().to_owned(), local.refname.clone()));
+                diverged_remotes.push((remote.to_string(), remote_tip(branch)?))
+            }
+            ClassifiedBranch::DivergedLocal { local, .. } => {
+                merged_locals.push((local.short_name().to_owned(), local.refname.clone()));
+            }
+            ClassifiedBranch::DivergedRemote {
+                local,
+                remote,
+                upstream,
+            } => {
+                let tip = match upstream {
+                    Some(upstream) => upstream.refname.clone(),
+                    None => local.refname.clone(),
+                };
+                diverged_remotes.push((format!("{}{}", remote, stale), tip))
+            }
+            ClassifiedBranch::MergedPushRemote { local, remote } => {
+                merged_locals.push((local.short_name().to_owned(), local.refname.clone()));
+                merged_remotes.push((format!("{} (push remote)", remote), remote_tip(branch)?));
             }
             ClassifiedBranch::MergedNonTrackingLocal(local) => {
-                merged_locals.push(format!("{} (non-tracking)", local.short_name()));
+                merged_locals.push((
+                    format!("{} (non-tracking)", local.short_name()),
+                    local.refname.clone(),
+                ));
             }
             ClassifiedBranch::MergedNonUpstreamRemoteTracking(upstream) => {
                 let remote = upstream.to_remote_branch(repo)?;
-                merged_remotes.push(format!("{} (non-upstream)", remote));
+                merged_remotes.push((
+                    format!("{} (non-upstream){}", remote, stale),
+                    upstream.refname.clone(),
+                ));
             }
+            ClassifiedBranch::MergedRemoteOnly(remote_only) => {
+                merged_remotes.push((
+                    format!("{} (remote-only)", remote_only.remote),
+                    remote_only.commit.clone(),
+                ));
+            }
+            ClassifiedBranch::Unrelated(local) => {
+                unrelated.push((local.short_name().to_owned(), local.refname.clone()))
+            }
+            ClassifiedBranch::DuplicateOfBase { local, base } => duplicates.push((
+                format!(
+                    "{} (tracks {})",
+                    local.short_name(),
+                    base.refname.trim_start_matches("refs/remotes/")
+                ),
+                local.refname.clone(),
+            )),
+            ClassifiedBranch::MergedPullRef {
+                remote_tracking, ..
+            } => pull_refs.push((
+                format!(
+                    "{}{}",
+                    remote_tracking.refname.trim_start_matches("refs/remotes/"),
+                    stale
+                ),
+                remote_tracking.refname.clone(),
+            )),
         }
     }
 
-    fn print(label: &str, mut branches: Vec<String>) -> Result<()> {
+    let print = |label: &str, branches: Vec<(String, String)>| -> Result<()> {
         if branches.is_empty() {
             return Ok(());
         }
-        branches.sort();
         println!("Delete {}:", label);
-        for branch in branches {
-            println!("  - {}", branch);
+        let width = branches
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, tip) in branches {
+            if details {
+                println!(
+                    "  - {:width$}  {}",
+                    name,
+                    describe_tip(repo, &tip)?,
+                    width = width
+                );
+            } else {
+                println!("  - {}", name);
+            }
         }
         Ok(())
-    }
+    };
 
     print("merged local branches", merged_locals)?;
     print("merged remote refs", merged_remotes)?;
     print("stray local branches", stray)?;
+    print("unrelated local branches", unrelated)?;
+    print("local branches duplicating bases", duplicates)?;
     print("diverged remote refs", diverged_remotes)?;
+    print("merged pull refs locally", pull_refs)?;
+    if plan
+        .to_delete
+        .iter()
+        .any(|branch| plan.possibly_stale(branch))
+    {
+        println!(
+            "Branches marked (possibly stale) are classified with remote tracking branches, \
+            which are not updated in offline mode."
+        );
+    }
 
     Ok(())
 }
 
-fn should_update(git: &Git, interval: u64, config_update: ConfigValue<bool>) -> Result<bool> {
+/// `<date> <author> <subject>` of the tip commit. `name` is a refname or a commit id, or empty if it is unknown.
+fn describe_tip(repo: &Repository, name: &str) -> Result<String> {
+    if name.is_empty() {
+        return Ok("(the tip is not fetched)".to_owned());
+    }
+    let commit = if name.starts_with("refs/") {
+        repo.find_reference(name)?.peel_to_commit()?
+    } else {
+        repo.find_commit(Oid::from_str(name)?)?
+    };
+    let author = commit.author();
+    Ok(format!(
+        "{}  {}  {}",
+        format_date(commit.time()),
+        author.name().unwrap_or("(unknown)"),
+        commit.summary().unwrap_or_default()
+    ))
+}
+
+fn print_broken_upstreams(plan: &TrimPlan, fix_upstreams: bool, hint_filter: &HintFilter) {
+    if plan.broken_upstreams.is_empty() {
+        return;
+    }
+    if fix_upstreams {
+        println!("Unset upstreams of branches tracking removed remotes:");
+    } else {
+        println!("Branches tracking removed remotes:");
+    }
+    for broken in &plan.broken_upstreams {
+        println!(
+            "  - {} (remote '{}' doesn't exist)",
+            broken.local.short_name(),
+            broken.remote
+        );
+    }
+    if let (false, Some(hint)) = (fix_upstreams, hints::fix_upstreams(plan)) {
+        print_hint(&hint, hint_filter);
+    }
+}
+
+fn print_gone_upstreams(plan: &TrimPlan, fix_gone: FixGone, hint_filter: &HintFilter) {
+    if plan.gone_upstreams.is_empty() {
+        return;
+    }
+    if fix_gone == FixGone::Unset {
+        println!("Unset upstreams of kept branches whose upstreams are gone:");
+        for local in &plan.gone_upstreams {
+            println!("  - {}", local.short_name());
+        }
+    } else if let Some(hint) = hints::fix_gone(plan) {
+        print_hint(&hint, hint_filter);
+    }
+}
+
+fn print_renamed_upstreams(plan: &TrimPlan, retarget: bool, hint_filter: &HintFilter) {
+    if plan.renamed_upstreams.is_empty() {
+        return;
+    }
+    if retarget {
+        println!("Set upstreams of branches to renamed ones:");
+    } else {
+        println!("Branches whose upstreams seem to be renamed:");
+    }
+    for renamed in &plan.renamed_upstreams {
+        println!(
+            "  - {} (renamed to {})",
+            renamed.local.short_name(),
+            renamed.upstream.refname.trim_start_matches("refs/remotes/")
+        );
+    }
+    if let (false, Some(hint)) = (retarget, hints::retarget_renamed(plan)) {
+        print_hint(&hint, hint_filter);
+    }
+}
+
+fn print_orphan_namespaces(plan: &TrimPlan, prune: bool, hint_filter: &HintFilter) {
+    if plan.orphan_namespaces.is_empty() {
+        return;
+    }
+    if prune {
+        println!("Delete remote tracking branches of removed remotes:");
+    } else {
+        println!("Remote tracking branches of removed remotes:");
+    }
+    for namespace in &plan.orphan_namespaces {
+        println!(
+            "  - refs/remotes/{}/* ({} refs, remote '{}' doesn't exist)",
+            namespace.remote,
+            namespace.refnames.len(),
+            namespace.remote
+        );
+    }
+    if let (false, Some(hint)) = (prune, hints::prune_removed_remotes(plan)) {
+        print_hint(&hint, hint_filter);
+    }
+}
+
+/// Print a hint about the plan as a part of it.
+fn print_hint(hint: &Hint, hint_filter: &HintFilter) {
+    if !hint_filter.enabled(hint.id) {
+        return;
+    }
+    println!("  {}", hint.message);
+    for suggestion in &hint.suggestions {
+        println!("    {}", suggestion);
+    }
+}
+
+/// Print a hint that is not a part of the plan, e.g. along with an error.
+fn eprint_hint(hint: &Hint, hint_filter: &HintFilter) {
+    if !hint_filter.enabled(hint.id) {
+        return;
+    }
+    eprintln!("{}", hint.message);
+    for suggestion in &hint.suggestions {
+        eprint_bullet(suggestion);
+    }
+}
+
+fn eprint_bullet(s: &str) {
+    let width = textwrap::termwidth().max(40) - 4;
+    for (i, line) in textwrap::wrap(s, width).iter().enumerate() {
+        if i == 0 {
+            eprintln!(" * {}", line);
+        } else {
+            eprintln!("   {}", line);
+        }
+    }
+}
+
+/// Hints along with the plan, except ones for flags that are already given.
+fn plan_hints(plan: &TrimPlan, config: &Config) -> Vec<Hint> {
+    let mut result = Vec::new();
+    result.extend(hints::skip_suggestions(plan));
+    if !*config.fix_upstreams {
+        result.extend(hints::fix_upstreams(plan));
+    }
+    if *config.fix_gone == FixGone::Keep {
+        result.extend(hints::fix_gone(plan));
+    }
+    if !*config.retarget_renamed {
+        result.extend(hints::retarget_renamed(plan));
+    }
+    if !*config.prune_removed_remotes {
+        result.extend(hints::prune_removed_remotes(plan));
+    }
+    result.retain(|hint| config.hints.enabled(hint.id));
+    result
+}
+
+/// Branch names, one per line, as `git branch --list` prints them.
+/// They can be short names (e.g. `feature`, `origin/feature`) or refnames.
+fn read_branches_from_stdin(repo: &Repository) -> Result<Vec<String>> {
+    let mut refnames = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        // The current branch is marked with `*`, and ones checked out in other worktrees with `+`.
+        let name = line.trim().trim_start_matches(['*', '+']).trim();
+        // e.g. `(HEAD detached at 1a2b3c4)`
+        if name.is_empty() || name.starts_with('(') {
+            continue;
+        }
+        match repo.resolve_reference_from_short_name(name) {
+            Ok(reference) => match reference.name() {
+                Some(refname) => refnames.push(refname.to_owned()),
+                None => warn!("Skip a branch of non-utf8 name: {}", name),
+            },
+            Err(err) if err.code() == ErrorCode::NotFound => {
+                warn!("Skip a branch that is not found: {}", name)
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(refnames)
+}
+
+fn explain(git: &Git, config: &Config, branch: &str) -> Result<()> {
+    let reference = git
+        .repo
+        .resolve_reference_from_short_name(branch)
+        .with_context(|| format!("Branch `{}` is not found", branch))?;
+    let refname = reference.name().context("non-utf8 reference name")?;
+    let plan = get_trim_plan_of_branch(git, &plan_param(config), refname)?;
+
+    println!("{}:", refname);
+    let mut classifications: Vec<_> = plan.classifications.iter().collect();
+    classifications.sort_by(|a, b| a.base.refname.cmp(&b.base.refname));
+    for classification in classifications {
+        println!("  compared with the base {}", classification.base.refname);
+        for state in &classification.merge_states {
+            if let Some(strategy) = state.strategy {
+                println!("    {} is merged ({})", state.branch, strategy);
+            } else if state.unknown {
+                println!("    {} is unknown (shallow history)", state.branch);
+            } else {
+                let strategies: Vec<_> = config
+                    .merge_strategies()
+                    .iter()
+                    .map(|strategy| strategy.to_string())
+                    .collect();
+                println!(
+                    "    {} is not merged (none of {} matched)",
+                    state.branch,
+                    strategies.join(", ")
+                );
+            }
+        }
+        println!("    => {}", classification.message);
+    }
+
+    let mut any = false;
+    for branch in &plan.to_delete {
+        print_explained_result(&git.repo, branch, "delete", None)?;
+        any = true;
+    }
+    for preserved in &plan.preserved {
+        print_explained_result(
+            &git.repo,
+            &preserved.branch,
+            "keep",
+            Some(&preserved.reason),
+        )?;
+        any = true;
+    }
+    for (skipped, suggestion) in &plan.skipped {
+        println!(
+            "  keep {}: skipped, see *{} in `git trim --dry-run`",
+            skipped,
+            suggestion.kind()
+        );
+        any = true;
+    }
+    if !any {
+        println!("  keep: it is not merged into any base");
+    }
+    Ok(())
+}
+
+/// Explain all branches to delete or to keep, like `git trim why` does for a branch.
+fn print_explanations(plan: &TrimPlan, repo: &Repository) -> Result<()> {
+    println!("Explanations:");
+    for branch in plan.sorted_to_delete(repo, SortKey::Name)? {
+        print_explained_result(repo, branch, "delete", None)?;
+        print_classified_by(plan, branch);
+    }
+    for preserved in &plan.preserved {
+        print_explained_result(repo, &preserved.branch, "keep", Some(&preserved.reason))?;
+        print_classified_by(plan, &preserved.branch);
+    }
+    println!();
+    Ok(())
+}
+
+fn print_classified_by(plan: &TrimPlan, branch: &ClassifiedBranch) {
+    for classification in plan.explanations(branch) {
+        println!(
+            "    compared with the base {}: {}",
+            classification.base.refname, classification.message
+        );
+    }
+}
+
+fn print_explained_result(
+    repo: &Repository,
+    branch: &ClassifiedBranch,
+    action: &str,
+    reason: Option<&str>,
+) -> Result<()> {
+    let but = reason
+        .map(|reason| format!(", but: {}", reason))
+        .unwrap_or_default();
+    if let Some(local) = branch.local() {
+        println!(
+            "  {} {} [{}{}]",
+            action,
+            local.refname,
+            branch.message_local(),
+            but
+        );
+    }
+    if let Some(remote) = branch.remote(repo)? {
+        println!(
+            "  {} {} [{}{}]",
+            action,
+            remote,
+            branch.message_remote(),
+            but
+        );
+    }
+    Ok(())
+}
+
+/// Returns `false` when the user cancelled the deletion.
+fn check_max_delete(config: &Config, num_to_delete: usize, dry_run: bool) -> Result<bool> {
+    let max_delete = *config.max_delete;
+    if max_delete == 0 || num_to_delete as u64 <= max_delete {
+        return Ok(true);
+    }
+
+    eprintln!(
+        "{} branches are about to be deleted, which exceeds the limit `trim.maxDelete={}`.",
+        num_to_delete, max_delete
+    );
+    if dry_run {
+        return Ok(true);
+    }
+    if !*config.confirm {
+        return Err(anyhow::anyhow!(
+            "Too many branches to delete. Check your `trim.bases` or raise `trim.maxDelete`"
+        ));
+    }
+    Ok(Confirm::new()
+        .with_prompt("Are you sure to delete all of them?")
+        .default(false)
+        .interact()?)
+}
+
+/// Confirm prompts are shown on stderr and answered on stdin. The plan can be piped from stdout.
+fn is_interactive() -> bool {
+    stdin_is_terminal() && Term::stderr().is_term()
+}
+
+// `std::io::IsTerminal` is newer than the MSRV.
+#[cfg(unix)]
+fn stdin_is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+/// It is told by stderr alone on other platforms, so piped stdin isn't detected on Windows. See `--yes`.
+#[cfg(not(unix))]
+fn stdin_is_terminal() -> bool {
+    true
+}
+
+fn refuse_unattended() -> anyhow::Error {
+    anyhow::anyhow!(
+        "Refusing to delete branches since it can't ask confirm without a terminal. \
+         Pass `--yes` to delete them, or `--dry-run` to see what would be deleted"
+    )
+}
+
+fn should_update(git: &Git, interval: u64, config_update: &ConfigValue<bool>) -> Result<bool> {
     if interval == 0 {
         return Ok(true);
     }
@@ -394,7 +1983,17 @@ fn should_update(git: &Git, interval: u64, config_update: ConfigValue<bool>) ->
     Ok(elapsed.as_secs() >= interval)
 }
 
+/// It is shown only once in a repository. `$GIT_DIR/trim-survey-shown` is created next to the trim log.
+/// Set `trim.surveyShown` to never show it.
 fn prompt_survey_on_push_upstream(git: &Git) -> Result<()> {
+    let shown = get(&git.config, "trim.surveyShown")
+        .with_default(false)
+        .read()?
+        .expect("has default");
+    let marker = common_dir(&git.repo).join("trim-survey-shown");
+    if *shown || marker.exists() {
+        return Ok(());
+    }
     for remote_name in git.repo.remotes()?.iter() {
         let remote_name = remote_name.context("non-utf8 remote name")?;
         let key = format!("remote.{}.push", remote_name);
@@ -407,10 +2006,11 @@ I recognize that you've set a config `git config remote.{}.push`!
 I once (mis)used that config to classify branches, but I retracted it after realizing that I don't understand the config well.
 It would be very helpful to me if you share your use cases of the config to me.
 Here's the survey URL: https://github.com/foriequal0/git-trim/issues/134
-Thank you!
+Thank you! This notice won't be shown again.
                 "#,
                 remote_name
             );
+            std::fs::write(&marker, "").with_context(|| format!("path={}", marker.display()))?;
             break;
         }
     }
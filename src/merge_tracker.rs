@@ -1,189 +1,571 @@
-use std::collections::HashSet;
-use std::fmt::Debug;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
-use git2::{Config, ErrorClass, ErrorCode, Oid, Repository, Signature};
+use git2::{Config, Oid, Repository};
 use log::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::branch::{Refname, RemoteTrackingBranch};
-use crate::subprocess::{self, is_merged_by_rev_list};
+use crate::backend::{self, RepoBackend};
+use crate::branch::{BaseRef, Refname};
+use crate::commit_graph::CommitGraph;
+use crate::reachability::ReachabilityIndex;
+use crate::subprocess;
 
 #[derive(Clone)]
 pub struct MergeTracker {
-    merged_set: Arc<Mutex<HashSet<String>>>,
+    merged_set: Arc<Mutex<HashMap<String, MergeStrategy>>>,
+    /// Bases that `git branch --merged` reported for commits in `merged_set`.
+    merged_into: Arc<Mutex<HashMap<String, String>>>,
+    metrics: Arc<MergeCounters>,
+    strategies: Vec<MergeStrategy>,
+    commit_graph: Option<Arc<CommitGraph>>,
+    reachability: Option<Arc<ReachabilityIndex>>,
+    backend: Arc<dyn RepoBackend>,
 }
 
-#[derive(Debug, Clone)]
+/// How many branches are told by the merged set without the detection strategies.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct MergeMetrics {
+    /// Branches whose tips are already in the merged set, e.g. by `git branch --merged`,
+    /// or by other merged branches with the same tips.
+    pub precomputed: usize,
+    /// Branches that are checked with the detection strategies.
+    pub checked: usize,
+    /// Ancestry walks that are skipped since generation numbers in the commit-graph tell they are not ancestors.
+    pub ruled_out_by_commit_graph: usize,
+}
+
+#[derive(Default)]
+struct MergeCounters {
+    precomputed: AtomicUsize,
+    checked: AtomicUsize,
+    ruled_out_by_commit_graph: AtomicUsize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MergeState<B> {
     pub branch: B,
     pub commit: String,
     pub merged: bool,
+    /// The strategy that detected the merge. `None` if none of them did.
+    pub strategy: Option<MergeStrategy>,
+    /// Not merged, but the history is too shallow to tell it for sure.
+    pub unknown: bool,
+}
+
+impl<B: Refname> MergeState<B> {
+    pub fn to_refname_state(&self) -> MergeState<String> {
+        MergeState {
+            branch: self.branch.refname().to_owned(),
+            commit: self.commit.clone(),
+            merged: self.merged,
+            strategy: self.strategy,
+            unknown: self.unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MergeStrategy {
+    /// An ancestor of the base, or of other branches that are already known to be merged.
+    Ancestor,
+    /// Merged with a merge commit, or fast-forwarded. `git branch --merged`
+    NoFastForward,
+    /// Rebase merged or cherry-picked. `git rev-list --cherry-pick`
+    Rebase,
+    /// Squash merged. It compares patch-ids of the squashed changes in memory.
+    Squash,
+    /// Squash merged. It compares `git patch-id` of the whole changes of the branch.
+    PatchId,
+    /// A rebased copy is fast-forwarded, so the base has a commit with the same tree.
+    Tree,
+}
+
+impl MergeStrategy {
+    pub fn defaults() -> Vec<MergeStrategy> {
+        vec![
+            MergeStrategy::Ancestor,
+            MergeStrategy::NoFastForward,
+            MergeStrategy::Rebase,
+            MergeStrategy::Squash,
+        ]
+    }
+
+    /// Whether it compares file contents, so it needs blobs.
+    pub fn reads_blobs(&self) -> bool {
+        match self {
+            MergeStrategy::Rebase | MergeStrategy::Squash | MergeStrategy::PatchId => true,
+            MergeStrategy::Ancestor | MergeStrategy::NoFastForward | MergeStrategy::Tree => false,
+        }
+    }
+}
+
+impl Display for MergeStrategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeStrategy::Ancestor => write!(f, "ancestor"),
+            MergeStrategy::NoFastForward => write!(f, "noff"),
+            MergeStrategy::Rebase => write!(f, "rebase"),
+            MergeStrategy::Squash => write!(f, "squash"),
+            MergeStrategy::PatchId => write!(f, "patch-id"),
+            MergeStrategy::Tree => write!(f, "tree"),
+        }
+    }
 }
 
+impl FromStr for MergeStrategy {
+    type Err = MergeStrategyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "ancestor" => Ok(MergeStrategy::Ancestor),
+            "noff" => Ok(MergeStrategy::NoFastForward),
+            "rebase" => Ok(MergeStrategy::Rebase),
+            "squash" => Ok(MergeStrategy::Squash),
+            "patch-id" => Ok(MergeStrategy::PatchId),
+            "tree" => Ok(MergeStrategy::Tree),
+            other => Err(MergeStrategyParseError(other.to_owned())),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown merge detection strategy `{0}`")]
+pub struct MergeStrategyParseError(String);
+
 impl MergeTracker {
     pub fn with_base_upstreams(
         repo: &Repository,
         config: &Config,
-        base_upstreams: &[RemoteTrackingBranch],
+        base_upstreams: &[BaseRef],
+        strategies: &[MergeStrategy],
     ) -> Result<Self> {
         let tracker = Self {
-            merged_set: Arc::new(Mutex::new(HashSet::new())),
+            merged_set: Arc::new(Mutex::new(HashMap::new())),
+            merged_into: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(MergeCounters::default()),
+            strategies: strategies.to_vec(),
+            commit_graph: CommitGraph::open(repo)?.map(Arc::new),
+            reachability: None,
+            backend: backend::open(repo)?,
         };
         info!("Initializing MergeTracker");
         for base_upstream in base_upstreams {
             debug!("base_upstream: {:?}", base_upstream);
-            tracker.track(repo, base_upstream)?;
+            tracker.track_merged_into(base_upstream, base_upstream)?;
         }
 
-        for merged_local in subprocess::get_noff_merged_locals(repo, config, base_upstreams)? {
-            debug!("merged_local: {:?}", merged_local);
-            tracker.track(repo, &merged_local)?;
+        if !tracker.enabled(MergeStrategy::NoFastForward) {
+            return Ok(tracker);
         }
 
-        for merged_remote in subprocess::get_noff_merged_remotes(repo, base_upstreams)? {
-            debug!("merged_remote: {:?}", merged_remote);
-            tracker.track(repo, &merged_remote)?;
+        for (merged_local, base) in
+            subprocess::get_noff_merged_locals(repo, config, base_upstreams)?
+        {
+            debug!("merged_local: {:?} -> {}", merged_local, base.refname);
+            tracker.track_merged_into(&merged_local, base)?;
+        }
+
+        for (merged_remote, base) in subprocess::get_noff_merged_remotes(repo, base_upstreams)? {
+            debug!("merged_remote: {:?} -> {}", merged_remote, base.refname);
+            tracker.track_merged_into(&merged_remote, base)?;
         }
 
         Ok(tracker)
     }
 
-    pub fn track<T>(&self, repo: &Repository, branch: &T) -> Result<()>
+    /// Tell branches merged by `noff` with an index of commits reachable from the bases,
+    /// instead of merge-bases of each branch and base.
+    /// It pays off with thousands of branches, but it walks the whole history of each base once.
+    pub fn with_reachability_index(
+        mut self,
+        repo: &Repository,
+        base_upstreams: &[BaseRef],
+    ) -> Result<Self> {
+        if !self.enabled(MergeStrategy::NoFastForward) {
+            return Ok(self);
+        }
+        let mut bases = Vec::new();
+        for base in base_upstreams {
+            let oid = self.backend.peel_to_commit_id(&base.refname)?;
+            bases.push((base.refname.clone(), oid));
+        }
+        info!("Building the reachability index");
+        self.reachability = Some(Arc::new(ReachabilityIndex::build(repo, &bases)?));
+        Ok(self)
+    }
+
+    fn enabled(&self, strategy: MergeStrategy) -> bool {
+        self.strategies.contains(&strategy)
+    }
+
+    /// Generation numbers in the commit-graph can tell non-ancestors without walking the history.
+    fn may_be_ancestor(&self, ancestor: Oid, descendant: Oid) -> bool {
+        let may_be = self
+            .commit_graph
+            .as_ref()
+            .map_or(true, |graph| graph.may_be_ancestor(ancestor, descendant));
+        if !may_be {
+            self.metrics
+                .ruled_out_by_commit_graph
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        may_be
+    }
+
+    pub fn track<T>(&self, branch: &T) -> Result<()>
     where
         T: Refname,
     {
-        let oid = repo
-            .find_reference(branch.refname())?
-            .peel_to_commit()?
-            .id()
+        let oid = self
+            .backend
+            .peel_to_commit_id(branch.refname())?
             .to_string();
         let mut set = self.merged_set.lock().unwrap();
         trace!("track: {}", oid);
-        set.insert(oid);
+        set.insert(oid, MergeStrategy::NoFastForward);
         Ok(())
     }
 
-    pub fn check_and_track<T>(
+    /// Track a branch that is known to be merged into the base, so that classification doesn't have to find the base.
+    fn track_merged_into<T>(&self, branch: &T, base: &BaseRef) -> Result<()>
+    where
+        T: Refname,
+    {
+        self.track(branch)?;
+        let oid = self
+            .backend
+            .peel_to_commit_id(branch.refname())?
+            .to_string();
+        self.merged_into
+            .lock()
+            .unwrap()
+            .entry(oid)
+            .or_insert_with(|| base.refname.clone());
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> MergeMetrics {
+        MergeMetrics {
+            precomputed: self.metrics.precomputed.load(Ordering::Relaxed),
+            checked: self.metrics.checked.load(Ordering::Relaxed),
+            ruled_out_by_commit_graph: self
+                .metrics
+                .ruled_out_by_commit_graph
+                .load(Ordering::Relaxed),
+        }
+    }
+
+    /// Check whether `branch` is merged into any of `bases` in one pass.
+    /// Each strategy is tried against all bases before more expensive ones are tried,
+    /// and it stops as soon as one of the bases reports that it is merged.
+    /// Returns the base that it is found to be merged into, if it is known.
+    pub fn check_and_track_bases<'b, T>(
         &self,
         repo: &Repository,
-        base: &str,
+        bases: &'b [BaseRef],
         branch: &T,
-    ) -> Result<MergeState<T>>
+    ) -> Result<(Option<&'b BaseRef>, MergeState<T>)>
     where
         T: Refname + Clone,
     {
-        let base_commit_id = repo.find_reference(base)?.peel_to_commit()?.id();
-        let target_commit_id = repo
-            .find_reference(branch.refname())?
-            .peel_to_commit()?
-            .id();
+        let target_commit_id = self.backend.peel_to_commit_id(branch.refname())?;
         let target_commit_id_string = target_commit_id.to_string();
 
-        // I know the locking is ugly. I'm trying to hold the lock as short as possible.
-        // Operations against `repo` take long time up to several seconds when the disk is slow.
-        {
-            let set = self.merged_set.lock().unwrap().clone();
-            if set.contains(&target_commit_id_string) {
-                debug!(
-                    "tracked: {} ({})",
-                    &target_commit_id_string[0..7],
-                    branch.refname(),
-                );
-                return Ok(MergeState {
+        let mut base_commit_ids = Vec::new();
+        for base in bases {
+            base_commit_ids.push(self.backend.peel_to_commit_id(&base.refname)?);
+        }
+
+        let tracked = self
+            .merged_set
+            .lock()
+            .unwrap()
+            .get(&target_commit_id_string)
+            .copied();
+        if let Some(strategy) = tracked {
+            debug!(
+                "tracked: {} ({})",
+                &target_commit_id_string[0..7],
+                branch.refname(),
+            );
+            self.metrics.precomputed.fetch_add(1, Ordering::Relaxed);
+            let known_base = self
+                .merged_into
+                .lock()
+                .unwrap()
+                .get(&target_commit_id_string)
+                .cloned();
+            let mut merged_into =
+                known_base.and_then(|known| bases.iter().find(|base| base.refname == known));
+            // Otherwise, find the base that it is merged into when it is cheap to tell.
+            if merged_into.is_none() {
+                for (base, base_commit_id) in bases.iter().zip(&base_commit_ids) {
+                    if self.is_merged_by(
+                        repo,
+                        MergeStrategy::NoFastForward,
+                        &base.refname,
+                        *base_commit_id,
+                        branch.refname(),
+                        target_commit_id,
+                    )? {
+                        merged_into = Some(base);
+                        break;
+                    }
+                }
+            }
+            return Ok((
+                merged_into,
+                MergeState {
                     merged: true,
+                    strategy: Some(strategy),
+                    unknown: false,
                     commit: target_commit_id_string,
                     branch: branch.clone(),
-                });
-            }
+                },
+            ));
+        }
 
-            for merged in set.iter() {
-                let merged_oid = Oid::from_str(merged)?;
-                //         B  A
-                //     *--*--*
-                //   /        \
-                // *--*--*--*--* base
-                // In this diagram, `$(git merge-base A B) == B`.
-                // When we're sure that A is merged into base, then we can safely conclude that
-                // B is also merged into base.
-                let noff_merged = match repo.merge_base(merged_oid, target_commit_id) {
-                    Ok(merge_base) if merge_base == target_commit_id => {
-                        let mut set = self.merged_set.lock().unwrap();
-                        set.insert(target_commit_id_string.clone());
-                        true
+        self.metrics.checked.fetch_add(1, Ordering::Relaxed);
+        for strategy in &self.strategies {
+            let mut merged_into = None;
+            if *strategy == MergeStrategy::Ancestor {
+                // It doesn't depend on bases.
+                if self.is_ancestor_of_tracked(target_commit_id)? {
+                    merged_into = bases.first();
+                }
+            } else {
+                for (base, base_commit_id) in bases.iter().zip(&base_commit_ids) {
+                    if self.is_merged_by(
+                        repo,
+                        *strategy,
+                        &base.refname,
+                        *base_commit_id,
+                        branch.refname(),
+                        target_commit_id,
+                    )? {
+                        merged_into = Some(base);
+                        break;
                     }
-                    Ok(_) => continue,
-                    Err(err) if merge_base_not_found(&err) => false,
-                    Err(err) => return Err(err.into()),
-                };
-                debug!("noff merged: ({}) -> {}", branch.refname(), &merged[0..7]);
-                return Ok(MergeState {
-                    merged: noff_merged,
-                    commit: target_commit_id_string,
-                    branch: branch.clone(),
-                });
+                }
             }
-        }
 
-        fn merge_base_not_found(err: &git2::Error) -> bool {
-            err.class() == ErrorClass::Merge && err.code() == ErrorCode::NotFound
+            if let Some(base) = merged_into {
+                let mut set = self.merged_set.lock().unwrap();
+                set.insert(target_commit_id_string.clone(), *strategy);
+                debug!(
+                    "{} merged: {} -> {}",
+                    strategy,
+                    branch.refname(),
+                    &base.refname
+                );
+                return Ok((
+                    Some(base),
+                    MergeState {
+                        merged: true,
+                        strategy: Some(*strategy),
+                        unknown: false,
+                        commit: target_commit_id_string,
+                        branch: branch.clone(),
+                    },
+                ));
+            }
         }
 
-        if is_merged_by_rev_list(repo, base, branch.refname())? {
-            let mut set = self.merged_set.lock().unwrap();
-            set.insert(target_commit_id_string.clone());
-            debug!("rebase merged: {} -> {}", branch.refname(), &base);
-            return Ok(MergeState {
-                merged: true,
+        let mut unknown = false;
+        for base_commit_id in base_commit_ids {
+            if self.is_beyond_shallow_history(base_commit_id, target_commit_id)? {
+                unknown = true;
+                break;
+            }
+        }
+        Ok((
+            None,
+            MergeState {
+                merged: false,
+                strategy: None,
+                unknown,
                 commit: target_commit_id_string,
                 branch: branch.clone(),
-            });
+            },
+        ))
+    }
+
+    /// Whether the commit shares no history with any of the bases, e.g. created by `git checkout --orphan`.
+    /// It can't be told in shallow repositories, where histories might meet beyond the boundary.
+    pub fn is_unrelated(&self, bases: &[BaseRef], commit: &str) -> Result<bool> {
+        if !self.backend.shallow_commits()?.is_empty() {
+            return Ok(false);
+        }
+        let target_commit_id = Oid::from_str(commit)?;
+        for base in bases {
+            let base_commit_id = self.backend.peel_to_commit_id(&base.refname)?;
+            if self
+                .backend
+                .merge_base(base_commit_id, target_commit_id)?
+                .is_some()
+            {
+                return Ok(false);
+            }
         }
+        Ok(true)
+    }
 
-        let squash_merged = match repo.merge_base(base_commit_id, target_commit_id) {
-            Ok(merge_base) => {
-                let merge_base = merge_base.to_string();
-                let squash_merged = is_squash_merged(repo, &merge_base, base, branch.refname())?;
-                if squash_merged {
-                    let mut set = self.merged_set.lock().unwrap();
-                    set.insert(target_commit_id_string.clone());
+    /// The branch might be merged in the commits that are cut off from a shallow clone,
+    /// when the base and the branch don't meet, or only meet at the shallow boundary.
+    fn is_beyond_shallow_history(&self, base: Oid, target: Oid) -> Result<bool> {
+        let shallow_commits = self.backend.shallow_commits()?;
+        if shallow_commits.is_empty() {
+            return Ok(false);
+        }
+        match self.backend.merge_base(base, target)? {
+            Some(merge_base) => Ok(shallow_commits.contains(&merge_base)),
+            None => Ok(true),
+        }
+    }
+
+    fn is_merged_by(
+        &self,
+        repo: &Repository,
+        strategy: MergeStrategy,
+        base: &str,
+        base_commit_id: Oid,
+        refname: &str,
+        target_commit_id: Oid,
+    ) -> Result<bool> {
+        Ok(match strategy {
+            MergeStrategy::Ancestor => self.is_ancestor_of_tracked(target_commit_id)?,
+            MergeStrategy::NoFastForward => {
+                if let Some(reached) = self
+                    .reachability
+                    .as_ref()
+                    .and_then(|index| index.reached_by(base, target_commit_id))
+                {
+                    return Ok(reached);
                 }
-                squash_merged
+                self.may_be_ancestor(target_commit_id, base_commit_id)
+                    && self.backend.merge_base(base_commit_id, target_commit_id)?
+                        == Some(target_commit_id)
             }
-            Err(err) if merge_base_not_found(&err) => false,
-            Err(err) => return Err(err.into()),
-        };
+            MergeStrategy::Rebase => self.backend.is_merged_by_rev_list(base, refname)?,
+            MergeStrategy::Squash => {
+                match self.backend.merge_base(base_commit_id, target_commit_id)? {
+                    Some(merge_base) => {
+                        is_squash_merged(repo, merge_base, base_commit_id, target_commit_id)?
+                    }
+                    None => false,
+                }
+            }
+            MergeStrategy::PatchId => {
+                match self.backend.merge_base(base_commit_id, target_commit_id)? {
+                    Some(merge_base) => {
+                        is_merged_by_patch_id(repo, &merge_base.to_string(), base, refname)?
+                    }
+                    None => false,
+                }
+            }
+            MergeStrategy::Tree => is_merged_by_tree(repo, base_commit_id, target_commit_id)?,
+        })
+    }
 
-        if squash_merged {
-            debug!("squash merged: {} -> {}", branch.refname(), &base);
+    fn is_ancestor_of_tracked(&self, target_commit_id: Oid) -> Result<bool> {
+        // Bases are tracked, and so are their ancestors.
+        // Then only tracked commits that are merged in other ways are left to walk from.
+        if let Some(index) = &self.reachability {
+            if index.is_reachable(target_commit_id) {
+                return Ok(true);
+            }
         }
-        Ok(MergeState {
-            merged: squash_merged,
-            commit: target_commit_id_string,
-            branch: branch.clone(),
-        })
+        // I know the locking is ugly. I'm trying to hold the lock as short as possible.
+        // Operations against `repo` take long time up to several seconds when the disk is slow.
+        let tracked: Vec<_> = self.merged_set.lock().unwrap().keys().cloned().collect();
+        for merged in tracked {
+            let merged_oid = Oid::from_str(&merged)?;
+            if !self.may_be_ancestor(target_commit_id, merged_oid) {
+                continue;
+            }
+            if let Some(index) = &self.reachability {
+                if index.is_reachable(merged_oid) {
+                    continue;
+                }
+            }
+            //         B  A
+            //     *--*--*
+            //   /        \
+            // *--*--*--*--* base
+            // In this diagram, `$(git merge-base A B) == B`.
+            // When we're sure that A is merged into base, then we can safely conclude that
+            // B is also merged into base.
+            if self.backend.merge_base(merged_oid, target_commit_id)? == Some(target_commit_id) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 }
 
-/// Source: https://stackoverflow.com/a/56026209
-fn is_squash_merged(
+/// Compare the patch-id of the whole changes of the branch with patch-ids of commits in the base.
+fn is_merged_by_patch_id(
     repo: &Repository,
     merge_base: &str,
     base: &str,
     refname: &str,
 ) -> Result<bool> {
-    let tree = repo
-        .revparse_single(&format!("{}^{{tree}}", refname))?
-        .peel_to_tree()?;
-    let tmp_sig = Signature::now("git-trim", "git-trim@squash.merge.test.local")?;
-    let dangling_commit = repo.commit(
-        None,
-        &tmp_sig,
-        &tmp_sig,
-        "git-trim: squash merge test",
-        &tree,
-        &[&repo.find_commit(Oid::from_str(merge_base)?)?],
-    )?;
-
-    is_merged_by_rev_list(repo, base, &dangling_commit.to_string())
+    let patch_id = match subprocess::get_patch_id_of_diff(repo, merge_base, refname)? {
+        Some(patch_id) => patch_id,
+        // There are no changes to compare
+        None => return Ok(false),
+    };
+    let range = format!("{}..{}", merge_base, base);
+    Ok(subprocess::get_patch_ids_of_commits(repo, &range)?.contains(&patch_id))
+}
+
+/// Find a commit with the same tree with the branch tip from the history of the base.
+fn is_merged_by_tree(repo: &Repository, base: Oid, target: Oid) -> Result<bool> {
+    let tree_id = repo.find_commit(target)?.tree_id();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(base)?;
+    revwalk.hide(target)?;
+    for oid in revwalk {
+        if repo.find_commit(oid?)?.tree_id() == tree_id {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Source: https://stackoverflow.com/a/56026209
+/// It used to write a temporary squashed commit and test it with `git rev-list --cherry-pick`.
+/// Instead, compare the patch-ids in memory so planning doesn't write to the object database.
+fn is_squash_merged(repo: &Repository, merge_base: Oid, base: Oid, target: Oid) -> Result<bool> {
+    let merge_base_tree = repo.find_commit(merge_base)?.tree()?;
+    let target_tree = repo.find_commit(target)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&merge_base_tree), Some(&target_tree), None)?;
+    if diff.deltas().len() == 0 {
+        // There are no changes to compare
+        return Ok(false);
+    }
+    let patch_id = diff.patchid(None)?;
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(base)?;
+    revwalk.hide(merge_base)?;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        // `git rev-list --no-merges`
+        if commit.parent_count() != 1 {
+            continue;
+        }
+        let parent_tree = commit.parent(0)?.tree()?;
+        let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit.tree()?), None)?;
+        if diff.patchid(None)? == patch_id {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
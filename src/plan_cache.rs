@@ -0,0 +1,99 @@
+//! Classifications of the last plan, so that re-runs skip classifying branches when nothing has changed.
+//! They are stored in `$GIT_DIR/trim-plan-cache.json` with a fingerprint of what they depend on:
+//! refs, configs, remote heads, and the classification requests.
+
+use std::fmt::Write;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use git2::{ObjectType, Oid, Repository};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::core::ClassificationResponse;
+use crate::util::common_dir;
+use crate::Git;
+
+#[derive(Serialize, Deserialize)]
+struct PlanCache {
+    fingerprint: String,
+    classifications: Vec<ClassificationResponse>,
+}
+
+fn cache_path(repo: &Repository) -> PathBuf {
+    common_dir(repo).join("trim-plan-cache.json")
+}
+
+/// A hash of refs, configs, and `inputs`. Any change of them changes the fingerprint.
+pub fn fingerprint(git: &Git, inputs: &[String]) -> Result<String> {
+    let mut lines = vec![
+        format!("version {}", env!("CARGO_PKG_VERSION")),
+        format!("shallow {}", git.repo.is_shallow()),
+    ];
+    let head = git.repo.find_reference("HEAD")?;
+    let references = git.repo.references()?;
+    for reference in std::iter::once(Ok(head)).chain(references) {
+        let reference = reference?;
+        let name = String::from_utf8_lossy(reference.name_bytes()).into_owned();
+        match reference.target() {
+            Some(oid) => lines.push(format!("ref {} {}", name, oid)),
+            None => lines.push(format!(
+                "symref {} {}",
+                name,
+                String::from_utf8_lossy(reference.symbolic_target_bytes().unwrap_or_default())
+            )),
+        }
+    }
+    let mut entries = git.config.entries(None)?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        lines.push(format!(
+            "config {}={}",
+            String::from_utf8_lossy(entry.name_bytes()),
+            String::from_utf8_lossy(entry.value_bytes())
+        ));
+    }
+    lines.extend(inputs.iter().cloned());
+    // Some of them are collected in parallel, so their order doesn't matter.
+    lines.sort();
+
+    let mut content = String::new();
+    for line in lines {
+        writeln!(content, "{}", line)?;
+    }
+    Ok(Oid::hash_object(ObjectType::Blob, content.as_bytes())?.to_string())
+}
+
+/// Classifications of the last plan if its fingerprint is the same.
+/// A broken cache is ignored, since it is going to be overwritten.
+pub fn read(repo: &Repository, fingerprint: &str) -> Result<Option<Vec<ClassificationResponse>>> {
+    let path = cache_path(repo);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("path={}", path.display())),
+    };
+    match serde_json::from_str::<PlanCache>(&content) {
+        Ok(cache) if cache.fingerprint == fingerprint => Ok(Some(cache.classifications)),
+        Ok(_) => Ok(None),
+        Err(err) => {
+            debug!("Ignore the broken plan cache {}: {}", path.display(), err);
+            Ok(None)
+        }
+    }
+}
+
+pub fn write(
+    repo: &Repository,
+    fingerprint: &str,
+    classifications: &[ClassificationResponse],
+) -> Result<()> {
+    let path = cache_path(repo);
+    let cache = PlanCache {
+        fingerprint: fingerprint.to_owned(),
+        classifications: classifications.to_vec(),
+    };
+    std::fs::write(&path, serde_json::to_string(&cache)?)
+        .with_context(|| format!("path={}", path.display()))
+}
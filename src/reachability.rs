@@ -0,0 +1,66 @@
+//! An index of commits reachable from bases, built by walking the history of each base once.
+//!
+//! Each commit has a bitmap of bases that reach it. A branch is merged into a base with a merge commit
+//! or by a fast-forward (`git branch --merged`) iff its tip is reachable from the base.
+//! So it is told by a lookup, instead of a merge-base for each branch and base.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use git2::{Oid, Repository};
+use log::*;
+
+pub struct ReachabilityIndex {
+    /// Refnames of bases for each bit of the bitmaps.
+    bases: Vec<String>,
+    reachable: HashMap<Oid, u64>,
+}
+
+impl ReachabilityIndex {
+    /// The number of bases that a bitmap can hold.
+    pub const MAX_BASES: usize = u64::BITS as usize;
+
+    /// Walk the history of each base. Bases beyond `MAX_BASES` are not indexed.
+    pub fn build(repo: &Repository, bases: &[(String, Oid)]) -> Result<ReachabilityIndex> {
+        if bases.len() > Self::MAX_BASES {
+            warn!(
+                "Only {} of {} bases are indexed for reachability",
+                Self::MAX_BASES,
+                bases.len()
+            );
+        }
+        let mut reachable: HashMap<Oid, u64> = HashMap::new();
+        let mut indexed = Vec::new();
+        for (bit, (refname, oid)) in bases.iter().take(Self::MAX_BASES).enumerate() {
+            let mut revwalk = repo.revwalk()?;
+            revwalk.push(*oid)?;
+            for commit in revwalk {
+                *reachable.entry(commit?).or_default() |= 1 << bit;
+            }
+            indexed.push(refname.clone());
+        }
+        debug!(
+            "reachability index: {} commits from {} bases",
+            reachable.len(),
+            indexed.len()
+        );
+        Ok(ReachabilityIndex {
+            bases: indexed,
+            reachable,
+        })
+    }
+
+    /// Whether the commit is reachable from any of the indexed bases.
+    pub fn is_reachable(&self, commit: Oid) -> bool {
+        self.reachable
+            .get(&commit)
+            .map_or(false, |bitmap| *bitmap != 0)
+    }
+
+    /// Whether the commit is reachable from the base. `None` if the base is not indexed.
+    pub fn reached_by(&self, base: &str, commit: Oid) -> Option<bool> {
+        let bit = self.bases.iter().position(|indexed| indexed == base)?;
+        let bitmap = self.reachable.get(&commit).copied().unwrap_or_default();
+        Some(bitmap & (1 << bit) != 0)
+    }
+}
@@ -1,20 +1,24 @@
 use std::thread::JoinHandle;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use git2::Repository;
 use log::*;
 use rayon::prelude::*;
 
-use crate::{ls_remote_head, ForceSendSync, RemoteHead, RemoteTrackingBranch};
+use crate::hints::{self, Hint, HintId};
+use crate::{ls_remote_head, ForceSendSync, RemoteHead, RemoteTrackingBranch, SubprocessError};
 
 pub struct RemoteHeadChangeChecker {
     join_handle: JoinHandle<Result<Vec<RemoteHead>>>,
 }
 
 impl RemoteHeadChangeChecker {
-    pub fn spawn() -> Result<Self> {
+    /// `repo` is opened again for the background thread.
+    /// Remotes that don't respond in `timeout` are not checked.
+    pub fn spawn(repo: &Repository, timeout: Duration) -> Result<Self> {
         let join_handle = {
-            let repo = ForceSendSync::new(Repository::open_from_env()?);
+            let repo = ForceSendSync::new(Repository::open(repo.path())?);
             let remotes = {
                 let mut tmp = Vec::new();
                 for remote_name in repo.remotes()?.iter() {
@@ -26,14 +30,28 @@ impl RemoteHeadChangeChecker {
             std::thread::spawn(move || {
                 remotes
                     .par_iter()
-                    .map(|remote_name| ls_remote_head(&repo, remote_name))
+                    .filter_map(
+                        |remote_name| match ls_remote_head(&repo, remote_name, timeout) {
+                            Err(err)
+                                if matches!(
+                                    err.downcast_ref::<SubprocessError>(),
+                                    Some(SubprocessError::Timeout { .. })
+                                ) =>
+                            {
+                                debug!("{:#}", err);
+                                None
+                            }
+                            result => Some(result),
+                        },
+                    )
                     .collect()
             })
         };
         Ok(Self { join_handle })
     }
 
-    pub fn check_and_notify(self, repo: &Repository) -> Result<()> {
+    /// A hint if `refs/remotes/*/HEAD`s are out of sync with remotes.
+    pub fn check(self, repo: &Repository) -> Result<Option<Hint>> {
         let fetched_remote_heads_raw = self.join_handle.join().unwrap()?;
         let mut fetched_remote_heads: Vec<RemoteHead> = Vec::new();
         for remote_head in fetched_remote_heads_raw.into_iter() {
@@ -72,35 +90,32 @@ impl RemoteHeadChangeChecker {
         }
 
         if out_of_sync.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
 
-        eprintln!(
-            "You are using default base branches, which is deduced from `refs/remotes/*/HEAD`s."
-        );
-        eprintln!("However, they seems to be out of sync.");
+        let mut message =
+            "You are using default base branches, which is deduced from `refs/remotes/*/HEAD`s.\n\
+            However, they seems to be out of sync:"
+                .to_owned();
         for (remote_head, fetched_remote_head) in &out_of_sync {
-            eprintln!(
-                " * {remote}: {before} -> {after}",
+            message.push_str(&format!(
+                "\n * {remote}: {before} -> {after}",
                 remote = remote_head.remote,
                 before = remote_head.refname,
                 after = fetched_remote_head.refname
-            );
+            ));
         }
-        eprintln!("You can sync them with these commands:");
-        for (remote_head, _) in &out_of_sync {
-            eprintln!(
-                " > git remote set-head {remote} --auto",
-                remote = remote_head.remote,
-            );
+        message.push_str("\nYou can sync them, or set base branches manually:");
+        let sync = out_of_sync
+            .iter()
+            .map(|(remote_head, _)| format!("git remote set-head {} --auto", remote_head.remote))
+            .collect::<Vec<_>>()
+            .join(" && ");
+        let mut hint =
+            Hint::new(HintId::RemoteHeadOutOfSync, message).with_suggestion(format!("`{}`", sync));
+        for help in hints::SET_BASES_MANUALLY {
+            hint = hint.with_suggestion(*help);
         }
-        eprintln!(
-            r#"Or you can set base branches manually:
- * `git config trim.bases develop,master` will set base branches for git-trim for a repository.
- * `git config --global trim.bases develop,master` will set base branches for `git-trim` globally.
- * `git trim --bases develop,master` will temporarily set base branches for `git-trim`"#
-        );
-
-        Ok(())
+        Ok(Some(hint))
     }
 }
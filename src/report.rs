@@ -0,0 +1,613 @@
+//! Owned and serializable representation of a `TrimPlan`.
+//! Unlike `TrimPlan`, it doesn't need a `Repository` to be interpreted,
+//! so it can be persisted or passed to other processes.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use git2::{Config, ErrorCode, Oid, Repository};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::branch::{LocalBranch, RemoteBranch};
+use crate::core::{
+    upstream_remote_branch, BranchPair, BrokenUpstream, ClassifiedBranch, OrphanNamespace,
+    Preserved, SkipSuggestion, TrimPlan,
+};
+use crate::hints::Hint;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct TrimReport {
+    pub to_delete: Vec<BranchReport>,
+    pub preserved: Vec<PreservedReport>,
+    pub skipped: Vec<SkippedReport>,
+    pub broken_upstreams: Vec<BrokenUpstreamReport>,
+    pub orphan_namespaces: Vec<OrphanNamespaceReport>,
+    /// Hints that are shown along with the plan.
+    #[serde(default)]
+    pub hints: Vec<Hint>,
+    /// It is planned without contacting remotes.
+    #[serde(default)]
+    pub offline: bool,
+    /// Time spent in each phase of planning, when it is asked with `--timings`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<TimingReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct TimingReport {
+    pub phase: String,
+    pub microseconds: u64,
+}
+
+impl From<&(&'static str, Duration)> for TimingReport {
+    fn from((phase, elapsed): &(&'static str, Duration)) -> Self {
+        TimingReport {
+            phase: (*phase).to_owned(),
+            microseconds: elapsed.as_micros() as u64,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum Classification {
+    MergedLocal,
+    Stray,
+    MergedRemoteTracking,
+    DivergedRemoteTracking,
+    MergedDirectFetch,
+    DivergedDirectFetch,
+    DivergedLocal,
+    DivergedRemote,
+    MergedPushRemote,
+    MergedNonTrackingLocal,
+    MergedNonUpstreamRemoteTracking,
+    MergedRemoteOnly,
+    MergedPullRef,
+    Unrelated,
+    DuplicateOfBase,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct BranchReport {
+    pub classification: Classification,
+    pub local: Option<LocalReport>,
+    pub remote: Option<RemoteReport>,
+    /// Why it is classified so, per base.
+    #[serde(default)]
+    pub explanations: Vec<ExplanationReport>,
+    /// It is classified with remote tracking branches that might be stale, since it is planned offline.
+    #[serde(default)]
+    pub possibly_stale: bool,
+    /// The tip commit of the local branch, or of the remote branch if there isn't one.
+    #[serde(default)]
+    pub last_commit: Option<CommitReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct CommitReport {
+    pub author: String,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ExplanationReport {
+    /// The base that the branch is compared with
+    pub base: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct LocalReport {
+    pub refname: String,
+    pub oid: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct RemoteReport {
+    /// A remote name, or an URL for direct fetch branches.
+    pub remote: String,
+    /// A refname on the remote.
+    pub refname: String,
+    /// A remote tracking branch of the remote ref, if exists.
+    pub remote_tracking: Option<String>,
+    pub oid: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct PreservedReport {
+    pub branch: BranchReport,
+    pub reason: String,
+    pub base: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct SkippedReport {
+    pub refname: String,
+    pub skipped_kind: SkippedKind,
+    /// A remote to add to the delete range to scan this branch.
+    pub remote: Option<String>,
+    /// A delete range to add to scan this branch, e.g. `merged:origin`.
+    #[serde(default)]
+    pub delete_range: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkippedKind {
+    /// A tracking branch
+    Tracking,
+    /// A local branch that doesn't track any
+    NonTracking,
+    /// A remote tracking branch that no local branch tracks
+    NonUpstream,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct BrokenUpstreamReport {
+    pub refname: String,
+    /// The removed remote that the branch still tracks.
+    pub remote: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct OrphanNamespaceReport {
+    /// The removed remote
+    pub remote: String,
+    /// Remote tracking branches under `refs/remotes/<remote>/`
+    pub refnames: Vec<String>,
+}
+
+impl TrimReport {
+    pub fn new(repo: &Repository, plan: &TrimPlan) -> Result<Self> {
+        let mut to_delete = plan
+            .to_delete
+            .iter()
+            .map(|branch| BranchReport::new(repo, plan, branch))
+            .collect::<Result<Vec<_>>>()?;
+        to_delete.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+        let preserved = plan
+            .preserved
+            .iter()
+            .map(|preserved| PreservedReport::new(repo, plan, preserved))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut skipped: Vec<_> = plan
+            .skipped
+            .iter()
+            .map(|(refname, suggestion)| SkippedReport::new(refname, suggestion))
+            .collect();
+        skipped.sort_by(|a, b| a.refname.cmp(&b.refname));
+
+        let broken_upstreams = plan
+            .broken_upstreams
+            .iter()
+            .map(BrokenUpstreamReport::from)
+            .collect();
+
+        let orphan_namespaces = plan
+            .orphan_namespaces
+            .iter()
+            .map(OrphanNamespaceReport::from)
+            .collect();
+
+        Ok(TrimReport {
+            to_delete,
+            preserved,
+            skipped,
+            broken_upstreams,
+            orphan_namespaces,
+            hints: Vec::new(),
+            offline: plan.offline,
+            timings: Vec::new(),
+        })
+    }
+}
+
+impl TrimReport {
+    /// Branches to delete. It checks that they still point to the commits when it is planned,
+    /// so it doesn't delete new commits made after that.
+    pub fn branches_to_delete(
+        &self,
+        repo: &Repository,
+    ) -> Result<(Vec<LocalBranch>, Vec<RemoteBranch>)> {
+        let mut locals = Vec::new();
+        let mut remotes = Vec::new();
+        let mut changed = Vec::new();
+        for branch in &self.to_delete {
+            if let Some(local) = &branch.local {
+                if get_oid(repo, &local.refname)? != local.oid {
+                    changed.push(local.refname.clone());
+                }
+                locals.push(LocalBranch::new(&local.refname));
+            }
+            if let Some(remote) = &branch.remote {
+                // Pull refs are read-only on remotes. `git trim` deletes them locally.
+                if branch.classification == Classification::MergedPullRef {
+                    continue;
+                }
+                // Direct fetch branches don't have remote tracking branches to check.
+                if let Some(remote_tracking) = &remote.remote_tracking {
+                    if get_oid(repo, remote_tracking)? != remote.oid {
+                        changed.push(remote_tracking.clone());
+                    }
+                }
+                remotes.push(RemoteBranch {
+                    remote: remote.remote.clone(),
+                    refname: remote.refname.clone(),
+                });
+            }
+        }
+        if !changed.is_empty() {
+            return Err(OutdatedReport(changed).into());
+        }
+        Ok((locals, remotes))
+    }
+
+    /// Local branches to delete, paired with their remote branches to delete, like `TrimPlan::pairs_to_delete`.
+    pub fn pairs_to_delete(&self, config: &Config) -> Result<Vec<BranchPair>> {
+        let remotes: HashSet<_> = self
+            .to_delete
+            .iter()
+            .filter_map(|branch| branch.remote.as_ref())
+            .map(|remote| RemoteBranch {
+                remote: remote.remote.clone(),
+                refname: remote.refname.clone(),
+            })
+            .collect();
+        let mut result = Vec::new();
+        for branch in &self.to_delete {
+            let local = match &branch.local {
+                Some(local) => LocalBranch::new(&local.refname),
+                None => continue,
+            };
+            let remote = match &branch.remote {
+                Some(remote) => RemoteBranch {
+                    remote: remote.remote.clone(),
+                    refname: remote.refname.clone(),
+                },
+                None => match upstream_remote_branch(config, &local)? {
+                    Some(remote) => remote,
+                    None => continue,
+                },
+            };
+            if remotes.contains(&remote) {
+                result.push(BranchPair { local, remote });
+            }
+        }
+        result.sort_by(|a, b| a.local.cmp(&b.local));
+        Ok(result)
+    }
+
+    /// Commits of branches to delete when it is planned, like `TrimPlan::tips`.
+    pub fn tips(&self) -> HashMap<String, String> {
+        let mut tips = HashMap::new();
+        for branch in &self.to_delete {
+            if let Some(LocalReport {
+                refname,
+                oid: Some(oid),
+                ..
+            }) = &branch.local
+            {
+                tips.insert(refname.clone(), oid.clone());
+            }
+            if let Some(RemoteReport {
+                remote_tracking: Some(remote_tracking),
+                oid: Some(oid),
+                ..
+            }) = &branch.remote
+            {
+                tips.insert(remote_tracking.clone(), oid.clone());
+            }
+        }
+        tips
+    }
+}
+
+impl TrimReport {
+    /// GitHub Actions workflow commands: a warning annotation for each branch to delete,
+    /// and a notice for the count and each hint.
+    pub fn github_actions_annotations(&self) -> Vec<String> {
+        let mut result = vec![format!(
+            "::notice title=git-trim::{}",
+            escape_workflow_data(&format!("{} branches can be trimmed", self.to_delete.len()))
+        )];
+        for branch in &self.to_delete {
+            result.push(format!(
+                "::warning title={}::{}",
+                escape_workflow_property("Deletable branch"),
+                escape_workflow_data(&format!("{} is {}", branch.name(), branch.message()))
+            ));
+        }
+        for hint in &self.hints {
+            result.push(format!(
+                "::notice title={}::{}",
+                escape_workflow_property(&format!("Hint ({})", hint.id)),
+                escape_workflow_data(&hint.message)
+            ));
+        }
+        result
+    }
+
+    /// A Markdown table of branches to delete, e.g. for `$GITHUB_STEP_SUMMARY` or an issue comment.
+    /// Ages of the last commits are relative to `now`, in seconds since the Unix epoch.
+    pub fn markdown_table(&self, now: i64) -> String {
+        let mut result = format!("### {} branches can be trimmed\n\n", self.to_delete.len());
+        if self.to_delete.is_empty() {
+            return result;
+        }
+        result.push_str("| Branch | Classification | Merged into | Last commit | Author | Why |\n");
+        result.push_str("| --- | --- | --- | --- | --- | --- |\n");
+        for branch in &self.to_delete {
+            let (age, author) = match &branch.last_commit {
+                Some(commit) => (format_age(now - commit.time), commit.author.as_str()),
+                None => (String::new(), ""),
+            };
+            result.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} |\n",
+                escape_markdown_cell(&format!("`{}`", branch.name())),
+                escape_markdown_cell(&branch.classification.to_string()),
+                escape_markdown_cell(&branch.bases().join(", ")),
+                escape_markdown_cell(&age),
+                escape_markdown_cell(author),
+                escape_markdown_cell(&branch.message()),
+            ));
+        }
+        result
+    }
+}
+
+/// Days since the last commit, e.g. `3 days ago`.
+fn format_age(seconds: i64) -> String {
+    match seconds.max(0) / (24 * 60 * 60) {
+        0 => "today".to_owned(),
+        1 => "1 day ago".to_owned(),
+        days => format!("{} days ago", days),
+    }
+}
+
+/// `%`, CR, and LF are escaped in messages of workflow commands.
+pub fn escape_workflow_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// `:` and `,` are also escaped in properties of workflow commands, e.g. `title=`.
+pub fn escape_workflow_property(property: &str) -> String {
+    escape_workflow_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+fn escape_markdown_cell(cell: &str) -> String {
+    cell.replace('|', "\\|").replace('\n', " ")
+}
+
+impl std::fmt::Display for Classification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The same as it is serialized
+        let name = serde_json::to_value(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(name.as_str().unwrap_or_default())
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("branches are changed after it is planned: {}", .0.join(", "))]
+pub struct OutdatedReport(pub Vec<String>);
+
+impl BranchReport {
+    fn new(repo: &Repository, plan: &TrimPlan, branch: &ClassifiedBranch) -> Result<Self> {
+        let local = match branch.local() {
+            Some(local) => Some(LocalReport {
+                refname: local.refname.clone(),
+                oid: get_oid(repo, &local.refname)?,
+                message: branch.message_local(),
+            }),
+            None => None,
+        };
+        let remote = match branch {
+            ClassifiedBranch::MergedPullRef { pull, .. } => Some(pull.clone()),
+            _ => branch.remote(repo)?,
+        };
+        let remote = match remote {
+            Some(RemoteBranch { remote, refname }) => {
+                let remote_tracking = branch.upstream().map(|upstream| upstream.refname.clone());
+                let oid = match &remote_tracking {
+                    Some(remote_tracking) => get_oid(repo, remote_tracking)?,
+                    None => None,
+                };
+                Some(RemoteReport {
+                    remote,
+                    refname,
+                    remote_tracking,
+                    oid,
+                    message: branch.message_remote(),
+                })
+            }
+            None => None,
+        };
+        let explanations = plan
+            .explanations(branch)
+            .map(|classification| ExplanationReport {
+                base: classification.base.refname.clone(),
+                message: classification.message.to_owned(),
+            })
+            .collect();
+        let tip = local
+            .as_ref()
+            .and_then(|local| local.oid.as_ref())
+            .or_else(|| remote.as_ref().and_then(|remote| remote.oid.as_ref()));
+        let last_commit = match tip {
+            Some(oid) => {
+                let commit = repo.find_commit(Oid::from_str(oid)?)?;
+                let author = commit.author();
+                Some(CommitReport {
+                    author: author.name().unwrap_or("(unknown)").to_owned(),
+                    time: commit.time().seconds(),
+                })
+            }
+            None => None,
+        };
+        Ok(BranchReport {
+            classification: Classification::from(branch),
+            local,
+            remote,
+            explanations,
+            possibly_stale: plan.possibly_stale(branch),
+            last_commit,
+        })
+    }
+
+    /// The short name of the local branch, or `<remote>/<branch>` (e.g. `origin/feature`) if there isn't one.
+    pub fn name(&self) -> String {
+        match (&self.local, &self.remote) {
+            (Some(local), _) => local
+                .refname
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&local.refname)
+                .to_owned(),
+            (None, Some(remote)) => format!(
+                "{}/{}",
+                remote.remote,
+                remote
+                    .refname
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(&remote.refname)
+            ),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Short names of the bases that it is compared with, e.g. `origin/master`.
+    pub fn bases(&self) -> Vec<&str> {
+        let mut bases = Vec::new();
+        for explanation in &self.explanations {
+            let base = explanation.base.as_str();
+            let base = base
+                .strip_prefix("refs/remotes/")
+                .or_else(|| base.strip_prefix("refs/heads/"))
+                .unwrap_or(base);
+            if !bases.contains(&base) {
+                bases.push(base);
+            }
+        }
+        bases
+    }
+
+    /// Messages of both sides, e.g. `merged locally, and merged on origin`.
+    pub fn message(&self) -> String {
+        match (&self.local, &self.remote) {
+            (Some(local), Some(remote)) => format!(
+                "{} locally, and {} on {}",
+                local.message, remote.message, remote.remote
+            ),
+            (Some(local), None) => local.message.clone(),
+            (None, Some(remote)) => remote.message.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    fn sort_key(&self) -> (Option<&str>, Option<&str>, Option<&str>) {
+        (
+            self.local.as_ref().map(|local| local.refname.as_str()),
+            self.remote.as_ref().map(|remote| remote.remote.as_str()),
+            self.remote.as_ref().map(|remote| remote.refname.as_str()),
+        )
+    }
+}
+
+impl PreservedReport {
+    fn new(repo: &Repository, plan: &TrimPlan, preserved: &Preserved) -> Result<Self> {
+        Ok(PreservedReport {
+            branch: BranchReport::new(repo, plan, &preserved.branch)?,
+            reason: preserved.reason.clone(),
+            base: preserved.base,
+        })
+    }
+}
+
+impl SkippedReport {
+    fn new(refname: &str, suggestion: &SkipSuggestion) -> Self {
+        let remote = match suggestion {
+            SkipSuggestion::TrackingRemote(remote) | SkipSuggestion::NonUpstream(remote) => {
+                Some(remote.clone())
+            }
+            SkipSuggestion::Tracking | SkipSuggestion::NonTracking => None,
+        };
+        SkippedReport {
+            refname: refname.to_owned(),
+            skipped_kind: SkippedKind::from(suggestion),
+            remote,
+            delete_range: suggestion.delete_range().to_string(),
+        }
+    }
+}
+
+impl From<&SkipSuggestion> for SkippedKind {
+    fn from(suggestion: &SkipSuggestion) -> Self {
+        match suggestion {
+            SkipSuggestion::Tracking | SkipSuggestion::TrackingRemote(_) => SkippedKind::Tracking,
+            SkipSuggestion::NonTracking => SkippedKind::NonTracking,
+            SkipSuggestion::NonUpstream(_) => SkippedKind::NonUpstream,
+        }
+    }
+}
+
+impl From<&BrokenUpstream> for BrokenUpstreamReport {
+    fn from(broken: &BrokenUpstream) -> Self {
+        BrokenUpstreamReport {
+            refname: broken.local.refname.clone(),
+            remote: broken.remote.clone(),
+        }
+    }
+}
+
+impl From<&OrphanNamespace> for OrphanNamespaceReport {
+    fn from(namespace: &OrphanNamespace) -> Self {
+        OrphanNamespaceReport {
+            remote: namespace.remote.clone(),
+            refnames: namespace.refnames.clone(),
+        }
+    }
+}
+
+impl From<&ClassifiedBranch> for Classification {
+    fn from(branch: &ClassifiedBranch) -> Self {
+        match branch {
+            ClassifiedBranch::MergedLocal(_) => Classification::MergedLocal,
+            ClassifiedBranch::Stray(_) => Classification::Stray,
+            ClassifiedBranch::MergedRemoteTracking(_) => Classification::MergedRemoteTracking,
+            ClassifiedBranch::DivergedRemoteTracking { .. } => {
+                Classification::DivergedRemoteTracking
+            }
+            ClassifiedBranch::MergedDirectFetch { .. } => Classification::MergedDirectFetch,
+            ClassifiedBranch::DivergedDirectFetch { .. } => Classification::DivergedDirectFetch,
+            ClassifiedBranch::DivergedLocal { .. } => Classification::DivergedLocal,
+            ClassifiedBranch::DivergedRemote { .. } => Classification::DivergedRemote,
+            ClassifiedBranch::MergedPushRemote { .. } => Classification::MergedPushRemote,
+            ClassifiedBranch::MergedNonTrackingLocal(_) => Classification::MergedNonTrackingLocal,
+            ClassifiedBranch::MergedNonUpstreamRemoteTracking(_) => {
+                Classification::MergedNonUpstreamRemoteTracking
+            }
+            ClassifiedBranch::MergedRemoteOnly(_) => Classification::MergedRemoteOnly,
+            ClassifiedBranch::MergedPullRef { .. } => Classification::MergedPullRef,
+            ClassifiedBranch::Unrelated(_) => Classification::Unrelated,
+            ClassifiedBranch::DuplicateOfBase { .. } => Classification::DuplicateOfBase,
+        }
+    }
+}
+
+fn get_oid(repo: &Repository, refname: &str) -> Result<Option<String>> {
+    match repo.refname_to_id(refname) {
+        Ok(oid) => Ok(Some(oid.to_string())),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("refname={}", refname)),
+    }
+}
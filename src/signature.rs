@@ -0,0 +1,220 @@
+//! Minimal parsers to identify who signed a commit, without `gpg` or `ssh-keygen`.
+//! It only reads which key made a signature. It doesn't verify the signature.
+
+/// A key that made a commit signature.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Signer {
+    /// Hex encoded issuer key IDs and fingerprints of an OpenPGP signature.
+    OpenPgp(Vec<String>),
+    /// A public key blob of an SSH signature.
+    Ssh(Vec<u8>),
+    /// Signatures of other formats (e.g. X.509), or malformed ones.
+    Unknown,
+}
+
+impl Signer {
+    pub fn parse(signature: &str) -> Signer {
+        if let Some(body) = dearmor(signature, "PGP SIGNATURE") {
+            let key_ids = parse_openpgp_issuers(&body).unwrap_or_default();
+            if !key_ids.is_empty() {
+                return Signer::OpenPgp(key_ids);
+            }
+        } else if let Some(body) = dearmor(signature, "SSH SIGNATURE") {
+            if let Some(public_key) = parse_sshsig_public_key(&body) {
+                return Signer::Ssh(public_key);
+            }
+        }
+        Signer::Unknown
+    }
+
+    /// `key` is an OpenPGP key ID or a fingerprint (e.g. `0xDEADBEEF`),
+    /// or an SSH public key (e.g. `ssh-ed25519 AAAA...` or `key::ssh-ed25519 AAAA...`).
+    pub fn is_signed_by(&self, key: &str) -> bool {
+        match self {
+            Signer::OpenPgp(key_ids) => {
+                let key = normalize_key_id(key);
+                if key.len() < 8 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return false;
+                }
+                key_ids
+                    .iter()
+                    .any(|id| id.ends_with(&key) || key.ends_with(id.as_str()))
+            }
+            Signer::Ssh(public_key) => {
+                let key = key.strip_prefix("key::").unwrap_or(key);
+                key.split_whitespace()
+                    .nth(1)
+                    .and_then(decode_base64)
+                    .map_or(false, |decoded| &decoded == public_key)
+            }
+            Signer::Unknown => false,
+        }
+    }
+}
+
+fn normalize_key_id(key: &str) -> String {
+    let key = key.trim();
+    let key = key
+        .strip_prefix("0x")
+        .or_else(|| key.strip_prefix("0X"))
+        .unwrap_or(key);
+    key.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+/// Decode an ASCII armored block like `-----BEGIN <label>-----`.
+fn dearmor(armored: &str, label: &str) -> Option<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let mut lines = armored
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != begin)
+        .skip(1)
+        .peekable();
+    lines.peek()?;
+
+    // OpenPGP armor may have headers (e.g. `Comment: ...`) followed by an empty line.
+    let lines: Vec<_> = lines.take_while(|line| *line != end).collect();
+    let body = match lines.iter().position(|line| line.is_empty()) {
+        Some(blank) if lines[..blank].iter().all(|line| line.contains(": ")) => &lines[blank + 1..],
+        _ => &lines[..],
+    };
+    let encoded: String = body
+        .iter()
+        // A checksum line of OpenPGP armor
+        .filter(|line| !line.starts_with('='))
+        .copied()
+        .collect();
+    decode_base64(&encoded)
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in encoded.bytes().filter(|c| *c != b'=') {
+        buffer = (buffer << 6) | value(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(result)
+}
+
+/// Get issuer key IDs and fingerprints from the first signature packet. RFC 4880, RFC 9580
+fn parse_openpgp_issuers(data: &[u8]) -> Option<Vec<String>> {
+    let (tag, packet) = read_packet(data)?;
+    if tag != 2 {
+        return None;
+    }
+
+    let mut result = Vec::new();
+    match *packet.first()? {
+        3 => {
+            // version, hashed length (5), signature type, creation time (4), key id (8)
+            result.push(hex(packet.get(7..15)?));
+        }
+        version @ 4..=6 => {
+            let count_len = if version == 6 { 4 } else { 2 };
+            // version, signature type, public key algorithm, hash algorithm
+            let mut rest = packet.get(4..)?;
+            // hashed, then unhashed subpackets
+            for _ in 0..2 {
+                let len = read_be(rest.get(..count_len)?);
+                let subpackets = rest.get(count_len..count_len + len)?;
+                parse_issuer_subpackets(subpackets, &mut result)?;
+                rest = rest.get(count_len + len..)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(result)
+}
+
+fn parse_issuer_subpackets(mut data: &[u8], result: &mut Vec<String>) -> Option<()> {
+    while !data.is_empty() {
+        let (len, header_len) = match *data.first()? {
+            first @ 0..=191 => (first as usize, 1),
+            first @ 192..=254 => (
+                (((first as usize) - 192) << 8) + *data.get(1)? as usize + 192,
+                2,
+            ),
+            255 => (read_be(data.get(1..5)?), 5),
+        };
+        let subpacket = data.get(header_len..header_len + len)?;
+        match subpacket.first()? & 0x7f {
+            // Issuer key ID
+            16 => result.push(hex(subpacket.get(1..)?)),
+            // Issuer fingerprint, prefixed by the key version
+            33 => result.push(hex(subpacket.get(2..)?)),
+            _ => {}
+        }
+        data = data.get(header_len + len..)?;
+    }
+    Some(())
+}
+
+fn read_packet(data: &[u8]) -> Option<(u8, &[u8])> {
+    let first = *data.first()?;
+    if first & 0x80 == 0 {
+        return None;
+    }
+    let (tag, len, header_len) = if first & 0x40 != 0 {
+        let tag = first & 0x3f;
+        match *data.get(1)? {
+            second @ 0..=191 => (tag, second as usize, 2),
+            second @ 192..=223 => (
+                tag,
+                (((second as usize) - 192) << 8) + *data.get(2)? as usize + 192,
+                3,
+            ),
+            255 => (tag, read_be(data.get(2..6)?), 6),
+            // Partial body lengths are not used for signatures.
+            _ => return None,
+        }
+    } else {
+        let tag = (first >> 2) & 0x0f;
+        match first & 0x03 {
+            0 => (tag, *data.get(1)? as usize, 2),
+            1 => (tag, read_be(data.get(1..3)?), 3),
+            2 => (tag, read_be(data.get(1..5)?), 5),
+            _ => (tag, data.len() - 1, 1),
+        }
+    };
+    Some((tag, data.get(header_len..header_len + len)?))
+}
+
+/// Get the public key from the `SSHSIG` blob. https://github.com/openssh/openssh-portable/blob/master/PROTOCOL.sshsig
+fn parse_sshsig_public_key(data: &[u8]) -> Option<Vec<u8>> {
+    let rest = data.strip_prefix(b"SSHSIG")?;
+    // version (4), then length prefixed public key
+    let len = read_be(rest.get(4..8)?);
+    rest.get(8..8 + len).map(<[u8]>::to_vec)
+}
+
+fn read_be(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0usize, |acc, byte| (acc << 8) | *byte as usize)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
@@ -10,61 +10,250 @@ pub enum ExpansionSide {
     Left,
 }
 
+/// Expand `reference` with the refspecs of the remote in the direction.
+/// The first refspec that matches wins, as git does for `@{upstream}`.
 pub fn expand_refspec(
     remote: &Remote,
     reference: &str,
     direction: Direction,
     side: ExpansionSide,
 ) -> Result<Option<String>> {
+    for (left, right) in refspec_pairs(remote, direction)? {
+        let expanded = match side {
+            ExpansionSide::Right => expand(&left, &right, reference),
+            ExpansionSide::Left => expand(&right, &left, reference),
+        };
+        if expanded.is_some() {
+            return Ok(expanded);
+        }
+    }
+    Ok(None)
+}
+
+/// Sources and destinations of the refspecs of the remote in the direction, without `+`.
+/// Refspecs without destinations (e.g. `refs/heads/master`) are skipped.
+pub fn refspec_pairs(remote: &Remote, direction: Direction) -> Result<Vec<(String, String)>> {
+    let mut result = Vec::new();
     for refspec in remote.refspecs() {
-        let left = refspec.src().context("non-utf8 refspec src")?;
-        let right = refspec.dst().context("non-utf8 refspec dst")?;
         if matches!(
             (direction, refspec.direction()),
             (Direction::Fetch, Direction::Push) | (Direction::Push, Direction::Fetch)
         ) {
             continue;
         }
-        match side {
-            ExpansionSide::Right => return Ok(expand(left, right, reference)),
-            ExpansionSide::Left => return Ok(expand(right, left, reference)),
+        let refspec = refspec.str().context("non-utf8 refspec")?;
+        // `Refspec::dst` panics if the refspec has no destination.
+        match refspec.trim_start_matches('+').split_once(':') {
+            Some((left, right)) if !left.is_empty() && !right.is_empty() => {
+                result.push((left.to_owned(), right.to_owned()))
+            }
+            _ => continue,
+        }
+    }
+    Ok(result)
+}
+
+/// Git allows only one `*` on each side of a refspec.
+/// Others are expanded in order, e.g. `refs/heads/*/*:refs/remotes/origin/*/*`,
+/// but refspecs with different numbers of `*` on each side can't be expanded.
+pub fn expand(src: &str, dest: &str, reference: &str) -> Option<String> {
+    let src_stars = src.matches('*').count();
+    let dst_stars = dest.matches('*').count();
+    if src_stars != dst_stars {
+        warn!("Unsupported refspec patterns: {}:{}", src, dest);
+        return None;
+    }
+
+    let matched = simple_match(src, reference)?;
+    let mut result = String::new();
+    let mut parts = dest.split('*');
+    result.push_str(parts.next().unwrap_or_default());
+    for (part, matched) in parts.zip(matched) {
+        result.push_str(matched);
+        result.push_str(part);
+    }
+    Some(result)
+}
+
+/// Substrings of `reference` that each `*` of `pattern` matches.
+fn simple_match<'a>(pattern: &str, reference: &'a str) -> Option<Vec<&'a str>> {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = reference.strip_prefix(first)?;
+    let parts: Vec<_> = parts.collect();
+    let mut matched = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        // The last part is anchored to the end. Others match lazily.
+        let end = if i == parts.len() - 1 {
+            if !rest.ends_with(part) {
+                return None;
+            }
+            rest.len() - part.len()
+        } else {
+            rest.find(part)?
         };
+        matched.push(&rest[..end]);
+        rest = &rest[end + part.len()..];
     }
-    Ok(None)
+    if parts.is_empty() && !rest.is_empty() {
+        return None;
+    }
+    Some(matched)
 }
 
-fn expand(src: &str, dest: &str, reference: &str) -> Option<String> {
-    let src_stars = src.chars().filter(|&c| c == '*').count();
-    let dst_stars = dest.chars().filter(|&c| c == '*').count();
-    assert!(
-        src_stars <= 1 && src_stars == dst_stars,
-        "Unsupported refspec patterns: {}:{}",
-        src,
-        dest
-    );
+enum Token {
+    /// `*` or `**`. They also match `/`.
+    Star,
+    /// `?`
+    Any,
+    Literal(char),
+    /// `[a-z]`, `[!0-9]`, `[[:alpha:]]`, ...
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+}
 
-    simple_match(src, reference).map(|matched| dest.replace('*', matched))
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(fn(&char) -> bool),
 }
 
-fn simple_match<'a>(pattern: &str, reference: &'a str) -> Option<&'a str> {
-    let src_stars = pattern.chars().filter(|&c| c == '*').count();
-    if src_stars <= 1 {
-        if let Some(star) = pattern.find('*') {
-            let left = &pattern[..star];
-            let right = &pattern[star + 1..];
-            if reference.starts_with(left) && reference.ends_with(right) {
-                let matched = &reference[left.len()..reference.len() - right.len()];
-                return Some(matched);
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Char(expected) => c == *expected,
+            ClassItem::Range(from, to) => (*from..=*to).contains(&c),
+            ClassItem::Named(predicate) => predicate(&c),
+        }
+    }
+}
+
+fn named_class(name: &str) -> Option<fn(&char) -> bool> {
+    Some(match name {
+        "alnum" => char::is_ascii_alphanumeric,
+        "alpha" => char::is_ascii_alphabetic,
+        "blank" => |c: &char| *c == ' ' || *c == '\t',
+        "cntrl" => char::is_ascii_control,
+        "digit" => char::is_ascii_digit,
+        "graph" => char::is_ascii_graphic,
+        "lower" => char::is_ascii_lowercase,
+        "print" => |c: &char| c.is_ascii_graphic() || *c == ' ',
+        "punct" => char::is_ascii_punctuation,
+        "space" => char::is_ascii_whitespace,
+        "upper" => char::is_ascii_uppercase,
+        "xdigit" => char::is_ascii_hexdigit,
+        _ => return None,
+    })
+}
+
+/// Parse a bracket expression after `[`. Returns `None` if it is not closed, then `[` is a literal.
+fn parse_class(pattern: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut items = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *pattern.get(i)?;
+        if c == ']' && !first {
+            return Some((Token::Class { negated, items }, i + 1));
+        }
+        first = false;
+        if c == '[' && pattern.get(i + 1) == Some(&':') {
+            let rest = &pattern[i + 2..];
+            if let Some(end) = rest.windows(2).position(|w| w == [':', ']']) {
+                let name: String = rest[..end].iter().collect();
+                items.push(ClassItem::Named(named_class(&name)?));
+                i += 2 + end + 2;
+                continue;
             }
-        } else if pattern == reference {
-            return Some("");
         }
-        return None;
-    } else {
-        warn!(
-            "Unsupported refspec patterns, too many asterisks: {}",
-            pattern
-        );
+        let c = if c == '\\' {
+            i += 1;
+            *pattern.get(i)?
+        } else {
+            c
+        };
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).map_or(false, |&end| end != ']') {
+            items.push(ClassItem::Range(c, pattern[i + 2]));
+            i += 3;
+        } else {
+            items.push(ClassItem::Char(c));
+            i += 1;
+        }
+    }
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            '*' => {
+                while pattern.get(i + 1) == Some(&'*') {
+                    i += 1;
+                }
+                tokens.push(Token::Star);
+            }
+            '?' => tokens.push(Token::Any),
+            '\\' if i + 1 < pattern.len() => {
+                i += 1;
+                tokens.push(Token::Literal(pattern[i]));
+            }
+            '[' => match parse_class(&pattern[i + 1..]) {
+                Some((class, len)) => {
+                    tokens.push(class);
+                    i += len;
+                }
+                None => tokens.push(Token::Literal('[')),
+            },
+            c => tokens.push(Token::Literal(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Match a glob pattern like git's wildmatch without `WM_PATHNAME`, as libgit2 matches `refs/*` globs.
+/// `*` and `**` also match `/`. `?`, bracket expressions (e.g. `[0-9]`, `[!a-z]`, `[[:digit:]]`),
+/// and escapes by `\` are supported.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let tokens = tokenize(pattern);
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        let matched = match tokens.get(p) {
+            Some(Token::Star) => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some(Token::Any) => true,
+            Some(Token::Literal(c)) => *c == text[t],
+            Some(Token::Class { negated, items }) => {
+                items.iter().any(|item| item.matches(text[t])) != *negated
+            }
+            None => false,
+        };
+        if matched {
+            p += 1;
+            t += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star, matched)) => {
+                p = star + 1;
+                t = matched + 1;
+                backtrack = Some((star, matched + 1));
+            }
+            None => return false,
+        }
     }
-    None
+    tokens[p..].iter().all(|token| matches!(token, Token::Star))
 }
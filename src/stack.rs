@@ -0,0 +1,79 @@
+//! Stacked branches are local branches whose upstreams are other local branches,
+//! i.e. `branch.<child>.remote` is `.` and `branch.<child>.merge` is `refs/heads/<parent>`.
+//! Stacking tools and `git branch --track <child> <parent>` set them up.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::{BranchType, Config, Repository};
+
+use crate::branch::{utf8_branches, LocalBranch};
+use crate::config;
+
+#[derive(Debug, Default)]
+pub struct Stacks {
+    parents: HashMap<LocalBranch, LocalBranch>,
+}
+
+impl Stacks {
+    pub fn read(repo: &Repository, config: &Config) -> Result<Self> {
+        let mut parents = HashMap::new();
+        for branch in utf8_branches(repo, BranchType::Local)? {
+            let child = LocalBranch::try_from(&branch)?;
+            if config::get_remote_name(config, &child)?.as_deref() != Some(".") {
+                continue;
+            }
+            let merge = match config::get_merge(config, &child)? {
+                Some(merge) if merge.starts_with("refs/heads/") => merge,
+                _ => continue,
+            };
+            // A parent might be deleted already.
+            if repo.find_reference(&merge).is_err() {
+                continue;
+            }
+            parents.insert(child, LocalBranch::new(&merge));
+        }
+        Ok(Stacks { parents })
+    }
+
+    /// Stacked branches, which are children of other branches.
+    pub fn stacked(&self) -> impl Iterator<Item = &LocalBranch> {
+        self.parents.keys()
+    }
+
+    pub fn parent(&self, child: &LocalBranch) -> Option<&LocalBranch> {
+        self.parents.get(child)
+    }
+
+    pub fn children<'a>(
+        &'a self,
+        parent: &'a LocalBranch,
+    ) -> impl Iterator<Item = &'a LocalBranch> {
+        self.parents
+            .iter()
+            .filter(move |(_, p)| *p == parent)
+            .map(|(child, _)| child)
+    }
+
+    /// The number of ancestors. A cycle of upstreams is cut at where it starts to repeat.
+    fn depth(&self, branch: &LocalBranch) -> usize {
+        let mut depth = 0;
+        let mut current = branch;
+        while let Some(parent) = self.parent(current) {
+            depth += 1;
+            if depth > self.parents.len() {
+                break;
+            }
+            current = parent;
+        }
+        depth
+    }
+
+    /// Deleting a parent before its children leaves them with dangling upstreams, even for a moment.
+    pub fn children_first<'a>(&self, branches: &[&'a LocalBranch]) -> Vec<&'a LocalBranch> {
+        let mut result = branches.to_vec();
+        result.sort_by_key(|branch| std::cmp::Reverse(self.depth(branch)));
+        result
+    }
+}
@@ -1,66 +1,541 @@
 use std::collections::{HashMap, HashSet};
-use std::process::{Command, Stdio};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use git2::{Config, Reference, Repository};
+use git2::{Config, Repository};
 use log::*;
+use thiserror::Error;
 
-use crate::branch::{LocalBranch, RemoteBranch, RemoteTrackingBranch, RemoteTrackingBranchStatus};
+use crate::branch::{
+    BaseRef, LocalBranch, RemoteBranch, RemoteTrackingBranch, RemoteTrackingBranchStatus,
+};
+use crate::config;
 
-fn git(repo: &Repository, args: &[&str], level: log::Level) -> Result<()> {
-    let workdir = repo.workdir().context("Bare repository is not supported")?;
-    let workdir = workdir.to_str().context("non utf-8 workdir")?;
+#[derive(Error, Debug)]
+pub enum SubprocessError {
+    #[error("`git {}` exited with {}", .args.join(" "), display_code(*.code))]
+    ExitStatus {
+        args: Vec<String>,
+        code: Option<i32>,
+    },
+    #[error("`git {}` didn't finish in {} seconds", .args.join(" "), .timeout.as_secs())]
+    Timeout {
+        args: Vec<String>,
+        timeout: Duration,
+    },
+}
+
+fn display_code(code: Option<i32>) -> String {
+    match code {
+        Some(code) => format!("code {}", code),
+        None => "signal".to_owned(),
+    }
+}
+
+impl SubprocessError {
+    fn exit_status(args: &[&str], code: Option<i32>) -> Self {
+        SubprocessError::ExitStatus {
+            args: args.iter().map(|arg| (*arg).to_owned()).collect(),
+            code,
+        }
+    }
+
+    fn timeout(args: &[&str], timeout: Duration) -> Self {
+        SubprocessError::Timeout {
+            args: args.iter().map(|arg| (*arg).to_owned()).collect(),
+            timeout,
+        }
+    }
+
+    pub fn args(&self) -> &[String] {
+        match self {
+            SubprocessError::ExitStatus { args, .. } | SubprocessError::Timeout { args, .. } => {
+                args
+            }
+        }
+    }
+
+    /// `None` if it is killed by a signal, or timed out.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            SubprocessError::ExitStatus { code, .. } => *code,
+            SubprocessError::Timeout { .. } => None,
+        }
+    }
+}
+
+/// A ref that the remote refused to delete.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("remote rejected to delete `{refname}`: {reason}")]
+pub struct PushRejection {
+    pub refname: String,
+    pub reason: String,
+    pub kind: RejectionKind,
+}
+
+/// Why the remote refused to delete a ref, as far as it can be told from what the remote printed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RejectionKind {
+    /// The branch is protected by the hosting service, e.g. GitHub, GitLab, or Bitbucket.
+    Protected,
+    /// The remote denies all deletions, by `receive.denyDeletes`.
+    DenyDeletes,
+    /// The branch is checked out on the non-bare remote, by `receive.denyCurrentBranch` or `receive.denyDeleteCurrent`.
+    CurrentBranch,
+    /// The user is not allowed to push to the remote.
+    PermissionDenied,
+    /// The branch has moved since it is fetched, so the lease is stale.
+    Stale,
+    Other,
+}
+
+/// Messages of remotes for each kind of rejections, checked in order.
+const REJECTION_MESSAGES: &[(RejectionKind, &[&str])] = &[
+    (RejectionKind::DenyDeletes, &["deletion prohibited"]),
+    (
+        RejectionKind::CurrentBranch,
+        &[
+            "refusing to delete the current branch",
+            "branch is currently checked out",
+        ],
+    ),
+    (RejectionKind::Stale, &["stale info"]),
+    (
+        RejectionKind::Protected,
+        &[
+            "protected branch",
+            "Protected branch",
+            "GH006",
+            "is protected",
+        ],
+    ),
+    (
+        RejectionKind::PermissionDenied,
+        &[
+            "permission denied",
+            "Permission denied",
+            "not allowed to",
+            "insufficient permission",
+            "GH013",
+        ],
+    ),
+];
+
+impl RejectionKind {
+    /// Classify a rejection by its summary, e.g. `[remote rejected] (deletion prohibited)`,
+    /// and the messages that the remote printed, e.g. `error: GH006: Protected branch update failed`.
+    fn classify(summary: &str, remote_messages: &[String]) -> RejectionKind {
+        let texts = || std::iter::once(summary).chain(remote_messages.iter().map(String::as_str));
+        for (kind, messages) in REJECTION_MESSAGES {
+            if texts().any(|text| messages.iter().any(|message| text.contains(message))) {
+                return *kind;
+            }
+        }
+        RejectionKind::Other
+    }
+}
+
+impl std::fmt::Display for RejectionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            RejectionKind::Protected => "protected branch",
+            RejectionKind::DenyDeletes => "deletions are denied",
+            RejectionKind::CurrentBranch => "current branch of the remote",
+            RejectionKind::PermissionDenied => "permission denied",
+            RejectionKind::Stale => "stale",
+            RejectionKind::Other => "rejected",
+        };
+        f.write_str(description)
+    }
+}
+
+/// The remote refused the credentials, or they couldn't be read, e.g. there is no terminal to prompt.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("authentication to {remote} failed: {message}")]
+pub struct AuthenticationFailure {
+    pub remote: String,
+    /// What git or ssh printed about the failure.
+    pub message: String,
+}
+
+/// What hosting services print when the user is authenticated, but not allowed to push.
+/// They are checked before `AUTHENTICATION_FAILURES`, since git also prints e.g. a 403 error for them.
+const PERMISSION_DENIALS: &[&str] = &[
+    "Permission to ",
+    "You are not allowed to push",
+    "You are not allowed to delete",
+    "not allowed to push code",
+];
+
+fn find_permission_denial(stderr: &str) -> Option<&str> {
+    stderr
+        .lines()
+        .map(str::trim)
+        .map(|line| line.strip_prefix("remote:").map_or(line, str::trim))
+        .find(|line| {
+            PERMISSION_DENIALS
+                .iter()
+                .any(|denial| line.contains(denial))
+        })
+}
+
+/// What git and ssh print when they fail to authenticate, regardless of the transport.
+const AUTHENTICATION_FAILURES: &[&str] = &[
+    "Authentication failed",
+    "Permission denied (publickey",
+    "could not read Username",
+    "could not read Password",
+    "terminal prompts disabled",
+    "Invalid username or password",
+    "HTTP Basic: Access denied",
+    "The requested URL returned error: 401",
+    "The requested URL returned error: 403",
+];
+
+fn find_authentication_failure(stderr: &str) -> Option<&str> {
+    stderr.lines().map(str::trim).find(|line| {
+        AUTHENTICATION_FAILURES
+            .iter()
+            .any(|failure| line.contains(failure))
+    })
+}
+
+/// Locate the `git` executable.
+/// `GIT_EXEC_PATH` is honored first, so that the same git installation that invoked `git trim` is used
+/// even when it is not in `PATH`, which is common with Git for Windows.
+fn git_executable() -> PathBuf {
+    if let Some(exec_path) = std::env::var_os("GIT_EXEC_PATH") {
+        let git = Path::new(&exec_path).join(format!("git{}", std::env::consts::EXE_SUFFIX));
+        if git.is_file() {
+            return git;
+        }
+    }
+    PathBuf::from("git")
+}
+
+/// libgit2 and `std::fs::canonicalize` may give verbatim paths (`\\?\C:\...`) on Windows,
+/// but git doesn't understand them.
+fn normalize_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        if let Some(stripped) = path.to_str().and_then(|path| path.strip_prefix(r"\\?\")) {
+            if stripped.as_bytes().get(1) == Some(&b':') {
+                return PathBuf::from(stripped);
+            }
+        }
+    }
+    path.to_owned()
+}
+
+/// Windows can't spawn a process in a directory whose path is longer than `MAX_PATH`.
+const MAX_PATH: usize = 260;
+
+/// Prepare `git <args>` to run in the working directory of the repository.
+/// Arguments are passed as they are without a shell, so paths with spaces don't need to be quoted.
+fn git_command(repo: &Repository, args: &[&str], level: log::Level) -> Result<Command> {
+    Ok(git_command_in(workdir(repo)?, args, level))
+}
+
+fn workdir(repo: &Repository) -> Result<&Path> {
+    repo.workdir().context("Bare repository is not supported")
+}
+
+fn git_command_in(workdir: &Path, args: &[&str], level: log::Level) -> Command {
+    let workdir = normalize_path(workdir);
     log!(level, "> git {}", args.join(" "));
 
-    let mut cd_args = vec!["-C", workdir];
-    cd_args.extend_from_slice(args);
-    let exit_status = Command::new("git").args(cd_args).status()?;
+    let mut command = Command::new(git_executable());
+    if cfg!(windows) && workdir.as_os_str().len() >= MAX_PATH {
+        // git itself can handle long paths with `core.longpaths`.
+        command.arg("-C").arg(&workdir);
+    } else {
+        command.current_dir(&workdir);
+    }
+    command.args(args);
+    command
+}
+
+fn git(repo: &Repository, args: &[&str], level: log::Level) -> Result<()> {
+    let exit_status = output(&mut git_command(repo, args, level)?)?.status;
     if !exit_status.success() {
-        Err(std::io::Error::from_raw_os_error(exit_status.code().unwrap_or(-1)).into())
+        Err(SubprocessError::exit_status(args, exit_status.code()).into())
     } else {
         Ok(())
     }
 }
 
 fn git_output(repo: &Repository, args: &[&str], level: log::Level) -> Result<String> {
-    let workdir = repo.workdir().context("Bare repository is not supported")?;
-    let workdir = workdir.to_str().context("non utf-8 workdir")?;
-    log!(level, "> git {}", args.join(" "));
+    git_output_in(workdir(repo)?, args, level)
+}
+
+fn git_output_in(workdir: &Path, args: &[&str], level: log::Level) -> Result<String> {
+    let stdout = git_output_bytes_in(workdir, args, level)?;
+    let str = std::str::from_utf8(&stdout)?.trim();
+    for line in str.lines() {
+        trace!("| {}", line);
+    }
+    Ok(str.to_string())
+}
+
+/// Lines of the output that are not valid UTF-8 (e.g. refnames) are skipped.
+fn git_output_utf8_lines(
+    repo: &Repository,
+    args: &[&str],
+    level: log::Level,
+) -> Result<Vec<String>> {
+    let stdout = git_output_bytes(repo, args, level)?;
+    let mut result = Vec::new();
+    for line in stdout.split(|b| *b == b'\n') {
+        match std::str::from_utf8(line) {
+            Ok(line) if line.trim().is_empty() => {}
+            Ok(line) => {
+                trace!("| {}", line);
+                result.push(line.trim().to_owned());
+            }
+            Err(_) => trace!("| (skipped) {}", String::from_utf8_lossy(line)),
+        }
+    }
+    Ok(result)
+}
+
+fn git_output_bytes(repo: &Repository, args: &[&str], level: log::Level) -> Result<Vec<u8>> {
+    git_output_bytes_in(workdir(repo)?, args, level)
+}
 
-    let mut cd_args = vec!["-C", workdir];
-    cd_args.extend_from_slice(args);
-    let output = Command::new("git")
-        .args(cd_args)
+fn git_output_bytes_in(workdir: &Path, args: &[&str], level: log::Level) -> Result<Vec<u8>> {
+    let output = output(
+        git_command_in(workdir, args, level)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped()),
+    )?;
+    if !output.status.success() {
+        return Err(SubprocessError::exit_status(args, output.status.code()).into());
+    }
+    Ok(output.stdout)
+}
+
+pub fn is_timeout(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<SubprocessError>(),
+        Some(SubprocessError::Timeout { .. })
+    )
+}
+
+/// Child processes that are running. They are killed on cancellation.
+static RUNNING: Mutex<Vec<Arc<Mutex<Child>>>> = Mutex::new(Vec::new());
+
+/// Kill running child processes and reap them, e.g. on Ctrl-C.
+pub fn kill_running() {
+    for child in RUNNING.lock().unwrap().drain(..) {
+        let mut child = child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Wait for the child while it is registered in `RUNNING`, so that it can be killed on cancellation.
+/// It is killed if it doesn't finish in `timeout`, and then `None` is returned. A zero `timeout` waits forever.
+fn wait_running(child: Child, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let child = Arc::new(Mutex::new(child));
+    RUNNING.lock().unwrap().push(child.clone());
+    let deadline = Instant::now() + timeout;
+    // Most of them finish quickly. Poll frequently at first.
+    let mut interval = Duration::from_millis(1);
+    let status = loop {
+        if let Some(status) = child.lock().unwrap().try_wait()? {
+            break Some(status);
+        }
+        if !timeout.is_zero() && Instant::now() >= deadline {
+            let mut child = child.lock().unwrap();
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(interval);
+        interval = (interval * 2).min(Duration::from_millis(20));
+    };
+    RUNNING
+        .lock()
+        .unwrap()
+        .retain(|running| !Arc::ptr_eq(running, &child));
+    Ok(status)
+}
+
+/// Same as `Command::output`, but the child is registered in `RUNNING` while it runs.
+/// Only piped stdout and stderr are captured. Others are inherited as `Command::status` does.
+pub(crate) fn output(command: &mut Command) -> Result<Output> {
+    let mut child = command.spawn()?;
+    let stdout = read_concurrently(child.stdout.take());
+    let stderr = read_concurrently(child.stderr.take());
+    let status = wait_running(child, Duration::ZERO)?.expect("waits forever");
+    Ok(Output {
+        status,
+        stdout: stdout.join().expect("reader doesn't panic")?,
+        stderr: stderr.join().expect("reader doesn't panic")?,
+    })
+}
+
+/// Read it concurrently, so that the child doesn't block on a full pipe.
+fn read_concurrently(
+    pipe: Option<impl Read + Send + 'static>,
+) -> thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
+/// Same as `git_output`, but the process is killed if it doesn't finish in `timeout`.
+/// A zero `timeout` waits forever.
+fn git_output_with_timeout(
+    repo: &Repository,
+    args: &[&str],
+    level: log::Level,
+    timeout: Duration,
+) -> Result<String> {
+    if timeout.is_zero() {
+        return git_output(repo, args, level);
+    }
+    let mut child = git_command(repo, args, level)?
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
-        .output()?;
+        .spawn()?;
+    let reader = read_concurrently(child.stdout.take());
+    let status = match wait_running(child, timeout)? {
+        Some(status) => status,
+        // Don't wait for the reader. Grandchildren like `ssh` might still hold the pipe.
+        None => return Err(SubprocessError::timeout(args, timeout).into()),
+    };
+    let stdout = reader.join().expect("reader doesn't panic")?;
+    if !status.success() {
+        return Err(SubprocessError::exit_status(args, status.code()).into());
+    }
+    let str = std::str::from_utf8(&stdout)?.trim();
+    for line in str.lines() {
+        trace!("| {}", line);
+    }
+    Ok(str.to_string())
+}
+
+/// `git <args> | git patch-id --stable`
+/// The patches are piped as they are, since they may not be UTF-8, and whitespaces are significant.
+fn git_patch_ids(repo: &Repository, args: &[&str]) -> Result<String> {
+    let mut producer = git_command(repo, args, Level::Trace)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let patches = producer.stdout.take().context("stdout is piped")?;
+    let patch_id_args = ["patch-id", "--stable"];
+    let output = output(
+        git_command(repo, &patch_id_args, Level::Trace)?
+            .stdin(Stdio::from(patches))
+            .stdout(Stdio::piped()),
+    );
+    // Reap the producer even if the consumer fails.
+    let status = wait_running(producer, Duration::ZERO)?.expect("waits forever");
+    let output = output?;
+    if !status.success() {
+        return Err(SubprocessError::exit_status(args, status.code()).into());
+    }
     if !output.status.success() {
-        return Err(std::io::Error::from_raw_os_error(output.status.code().unwrap_or(-1)).into());
+        return Err(SubprocessError::exit_status(&patch_id_args, output.status.code()).into());
     }
 
-    let str = std::str::from_utf8(&output.stdout)?.trim();
+    // Patch ids and commit ids are hexadecimal.
+    let str = String::from_utf8_lossy(&output.stdout);
     for line in str.lines() {
         trace!("| {}", line);
     }
-    Ok(str.to_string())
+    Ok(str.into_owned())
 }
 
+/// Remotes with `remote.<name>.skipFetchAll` are not updated, as `git fetch --all` doesn't.
 pub fn remote_update(repo: &Repository, dry_run: bool) -> Result<()> {
+    let remotes = config::get_remotes_to_update(repo, &repo.config()?.snapshot()?)?;
+    let all_remotes = repo.remotes()?.len();
+    let mut args = vec!["remote", "update", "--prune"];
+    if remotes.len() < all_remotes {
+        if remotes.is_empty() {
+            info!("Every remote skips fetch. Skip to update them");
+            return Ok(());
+        }
+        args.extend(remotes.iter().map(String::as_str));
+    }
     if !dry_run {
-        git(repo, &["remote", "update", "--prune"], Level::Info)
+        git(repo, &args, Level::Info)
     } else {
-        info!("> git remote update --prune (dry-run)");
+        info!("> git {} (dry-run)", args.join(" "));
         Ok(())
     }
 }
 
+/// Fetch objects of `revs` (commit ids or refnames) from the remote without updating any refs except `FETCH_HEAD`.
+/// The empty `--refmap` keeps git from updating remote tracking branches with the fetch refspecs.
+pub fn fetch_objects(repo: &Repository, remote_name: &str, revs: &[&str]) -> Result<()> {
+    let mut args = vec!["fetch", "--no-tags", "--refmap=", remote_name];
+    args.extend(revs);
+    git(repo, &args, Level::Info)
+}
+
+pub fn commit_graph_write(repo: &Repository, dry_run: bool) -> Result<()> {
+    if !dry_run {
+        git(repo, &["commit-graph", "write", "--reachable"], Level::Info)
+    } else {
+        info!("> git commit-graph write --reachable (dry-run)");
+        Ok(())
+    }
+}
+
+/// Collect garbage. `git maintenance run --task=gc`, or `git gc --auto` if git is too old to have it.
+pub fn gc(repo: &Repository, dry_run: bool) -> Result<()> {
+    if dry_run {
+        info!("> git maintenance run --task=gc (dry-run)");
+        return Ok(());
+    }
+    git(repo, &["maintenance", "run", "--task=gc"], Level::Info).or_else(|err| {
+        debug!("Fall back to `git gc --auto`: {:#}", err);
+        git(repo, &["gc", "--auto"], Level::Info)
+    })
+}
+
+/// Bytes of loose and packed objects. `git count-objects -v`
+pub fn count_objects_size(repo: &Repository) -> Result<u64> {
+    let output = git_output(repo, &["count-objects", "-v"], Level::Trace)?;
+    let mut kibibytes = 0;
+    for line in output.lines() {
+        if let Some((key, value)) = line.split_once(':') {
+            if key == "size" || key == "size-pack" {
+                kibibytes += value.trim().parse::<u64>()?;
+            }
+        }
+    }
+    Ok(kibibytes * 1024)
+}
+
+/// Bytes of objects that are reachable only from `commits`, and no longer from any refs.
+/// `git rev-list --objects --disk-usage <commits>... --not --all`
+pub fn disk_usage(repo: &Repository, commits: &[String]) -> Result<u64> {
+    let mut args = vec!["rev-list", "--objects", "--disk-usage"];
+    args.extend(commits.iter().map(String::as_str));
+    args.extend(&["--not", "--all"]);
+    let output = git_output(repo, &args, Level::Trace)?;
+    Ok(output.trim().parse()?)
+}
+
 /// Get whether there any commits are not in the `base` from the `commit`
 /// `git rev-list --cherry-pick --right-only --no-merges -n1 <base>..<commit>`
-pub fn is_merged_by_rev_list(repo: &Repository, base: &str, commit: &str) -> Result<bool> {
+pub fn is_merged_by_rev_list(workdir: &Path, base: &str, commit: &str) -> Result<bool> {
     let range = format!("{}...{}", base, commit);
     // Is there any revs that are not applied to the base in the branch?
-    let output = git_output(
-        repo,
+    let output = git_output_in(
+        workdir,
         &[
             "rev-list",
             "--cherry-pick",
@@ -76,16 +551,51 @@ pub fn is_merged_by_rev_list(repo: &Repository, base: &str, commit: &str) -> Res
     Ok(output.is_empty())
 }
 
-/// Get branches that are merged with merge commit.
+/// `git log --oneline <range>`
+pub fn log_oneline(repo: &Repository, range: &str) -> Result<String> {
+    git_output(repo, &["log", "--oneline", range], Level::Trace)
+}
+
+/// `git diff <from> <to> | git patch-id --stable`
+/// Returns `None` when there are no changes.
+pub fn get_patch_id_of_diff(repo: &Repository, from: &str, to: &str) -> Result<Option<String>> {
+    let output = git_patch_ids(repo, &["diff", "--no-color", "--no-ext-diff", from, to])?;
+    Ok(output
+        .split_whitespace()
+        .next()
+        .map(|patch_id| patch_id.to_owned()))
+}
+
+/// `git log --patch <range> | git patch-id --stable`
+pub fn get_patch_ids_of_commits(repo: &Repository, range: &str) -> Result<HashSet<String>> {
+    let output = git_patch_ids(
+        repo,
+        &[
+            "log",
+            "--patch",
+            "--no-color",
+            "--no-ext-diff",
+            "--no-merges",
+            range,
+        ],
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|patch_id| patch_id.to_owned())
+        .collect())
+}
+
+/// Get branches that are merged with merge commit, with the first base that they are merged into.
 /// `git branch --format '%(refname)' --merged <base>`
-pub fn get_noff_merged_locals(
+pub fn get_noff_merged_locals<'b>(
     repo: &Repository,
     config: &Config,
-    bases: &[RemoteTrackingBranch],
-) -> Result<HashSet<LocalBranch>> {
-    let mut result = HashSet::new();
+    bases: &'b [BaseRef],
+) -> Result<HashMap<LocalBranch, &'b BaseRef>> {
+    let mut result = HashMap::new();
     for base in bases {
-        let refnames = git_output(
+        let refnames = git_output_utf8_lines(
             repo,
             &[
                 "branch",
@@ -96,15 +606,15 @@ pub fn get_noff_merged_locals(
             ],
             Level::Trace,
         )?;
-        for refname in refnames.lines() {
-            if !refnames.starts_with("refs/") {
+        for refname in &refnames {
+            if !refname.starts_with("refs/") {
                 // Detached HEAD is printed as '(HEAD detached at 1234abc)'
                 continue;
             }
             let branch = LocalBranch::new(refname);
             let upstream = branch.fetch_upstream(repo, config)?;
             if let RemoteTrackingBranchStatus::Exists(upstream) = upstream {
-                if base == &upstream {
+                if base.refname == upstream.refname {
                     continue;
                 }
             }
@@ -112,21 +622,21 @@ pub fn get_noff_merged_locals(
             if reference.symbolic_target().is_some() {
                 continue;
             }
-            result.insert(branch);
+            result.entry(branch).or_insert(base);
         }
     }
     Ok(result)
 }
 
-/// Get remote tracking branches that are merged with merge commit.
+/// Get remote tracking branches that are merged with merge commit, with the first base that they are merged into.
 /// `git branch --format '%(refname)' --remote --merged <base>`
-pub fn get_noff_merged_remotes(
+pub fn get_noff_merged_remotes<'b>(
     repo: &Repository,
-    bases: &[RemoteTrackingBranch],
-) -> Result<HashSet<RemoteTrackingBranch>> {
-    let mut result = HashSet::new();
+    bases: &'b [BaseRef],
+) -> Result<HashMap<RemoteTrackingBranch, &'b BaseRef>> {
+    let mut result = HashMap::new();
     for base in bases {
-        let refnames = git_output(
+        let refnames = git_output_utf8_lines(
             repo,
             &[
                 "branch",
@@ -138,16 +648,16 @@ pub fn get_noff_merged_remotes(
             ],
             Level::Trace,
         )?;
-        for refname in refnames.lines() {
+        for refname in &refnames {
             let branch = RemoteTrackingBranch::new(refname);
-            if base == &branch {
+            if base.refname == branch.refname {
                 continue;
             }
             let reference = repo.find_reference(refname)?;
             if reference.symbolic_target().is_some() {
                 continue;
             }
-            result.insert(branch);
+            result.entry(branch).or_insert(base);
         }
     }
     Ok(result)
@@ -160,9 +670,22 @@ pub struct RemoteHead {
     pub commit: String,
 }
 
-pub fn ls_remote_heads(repo: &Repository, remote_name: &str) -> Result<Vec<RemoteHead>> {
+/// With a non-zero `timeout`, it fails with `SubprocessError::Timeout` if the remote doesn't respond in time.
+pub fn ls_remote_heads(
+    repo: &Repository,
+    remote_name: &str,
+    timeout: Duration,
+) -> Result<Vec<RemoteHead>> {
+    // libgit2 can't be timed out.
+    if timeout.is_zero() && config::use_libgit2_ls_remote(&repo.config()?.snapshot()?)? {
+        match crate::ls_remote::ls_remote_heads(repo, remote_name) {
+            Ok(heads) => return Ok(heads),
+            Err(err) => debug!("Fall back to `git ls-remote`: {:#}", err),
+        }
+    }
     let mut result = Vec::new();
-    for line in git_output(repo, &["ls-remote", "--heads", remote_name], Level::Trace)?.lines() {
+    let args = &["ls-remote", "--heads", remote_name];
+    for line in git_output_with_timeout(repo, args, Level::Trace, timeout)?.lines() {
         let records = line.split_whitespace().collect::<Vec<_>>();
         let commit = records[0].to_string();
         let refname = records[1].to_string();
@@ -175,9 +698,21 @@ pub fn ls_remote_heads(repo: &Repository, remote_name: &str) -> Result<Vec<Remot
     Ok(result)
 }
 
-pub fn ls_remote_head(repo: &Repository, remote_name: &str) -> Result<RemoteHead> {
+/// With a non-zero `timeout`, it fails with `SubprocessError::Timeout` if the remote doesn't respond in time.
+pub fn ls_remote_head(
+    repo: &Repository,
+    remote_name: &str,
+    timeout: Duration,
+) -> Result<RemoteHead> {
+    // libgit2 can't be timed out.
+    if timeout.is_zero() && config::use_libgit2_ls_remote(&repo.config()?.snapshot()?)? {
+        match crate::ls_remote::ls_remote_head(repo, remote_name) {
+            Ok(head) => return Ok(head),
+            Err(err) => debug!("Fall back to `git ls-remote`: {:#}", err),
+        }
+    }
     let command = &["ls-remote", "--symref", remote_name, "HEAD"];
-    let lines = git_output(repo, command, Level::Trace)?;
+    let lines = git_output_with_timeout(repo, command, Level::Trace, timeout)?;
     let mut refname = None;
     let mut commit = None;
     for line in lines.lines() {
@@ -203,14 +738,16 @@ pub fn ls_remote_head(repo: &Repository, remote_name: &str) -> Result<RemoteHead
 }
 
 /// Get worktrees and its paths without HEAD
-pub fn get_worktrees(repo: &Repository) -> Result<HashMap<LocalBranch, String>> {
+pub fn get_worktrees(repo: &Repository) -> Result<HashMap<LocalBranch, PathBuf>> {
     // TODO: `libgit2` has `git2_worktree_*` APIs. However it is not ported to `git2`. Use subprocess directly.
     let mut result = HashMap::new();
     let mut worktree = None;
     let mut branch = None;
     for line in git_output(repo, &["worktree", "list", "--porcelain"], Level::Trace)?.lines() {
         if let Some(stripped) = line.strip_prefix("worktree ") {
-            worktree = Some(stripped.to_owned());
+            // Git for Windows prints `C:/path/to/worktree`, but it can be `C:\path\to\worktree`
+            // when it is given that way. Both are fine for `PathBuf` there.
+            worktree = Some(normalize_path(Path::new(stripped)));
         } else if let Some(stripped) = line.strip_prefix("branch ") {
             branch = Some(LocalBranch::new(stripped));
         } else if line.is_empty() {
@@ -232,16 +769,36 @@ pub fn get_worktrees(repo: &Repository) -> Result<HashMap<LocalBranch, String>>
     Ok(result)
 }
 
-pub fn checkout(repo: &Repository, head: Reference, dry_run: bool) -> Result<()> {
-    let head_refname = head.name().context("non-utf8 head ref name")?;
+/// What `git checkout` is given. A local branch is switched to, and HEAD is detached at others.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CheckoutTarget {
+    pub target: String,
+    /// Pass `--detach`, which is not necessary for full refnames of branches.
+    pub detach: bool,
+}
+
+pub fn checkout_args(checkout: &CheckoutTarget) -> Vec<String> {
+    let mut args = vec!["checkout".to_owned()];
+    if checkout.detach {
+        args.push("--detach".to_owned());
+    }
+    args.push(checkout.target.clone());
+    args
+}
+
+pub fn checkout(repo: &Repository, checkout: &CheckoutTarget, dry_run: bool) -> Result<()> {
+    let args = checkout_args(checkout);
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
     if !dry_run {
-        git(repo, &["checkout", head_refname], Level::Info)
+        git(repo, &args, Level::Info)
     } else {
-        info!("> git checkout {} (dry-run)", head_refname);
+        info!("> git {} (dry-run)", args.join(" "));
 
-        println!("Note: switching to '{}' (dry run)", head_refname);
-        println!("You are in 'detached HED' state... blah blah...");
-        let commit = head.peel_to_commit()?;
+        println!("Note: switching to '{}' (dry run)", checkout.target);
+        if checkout.detach || checkout.target.starts_with("refs/") {
+            println!("You are in 'detached HED' state... blah blah...");
+        }
+        let commit = repo.revparse_single(&checkout.target)?.peel_to_commit()?;
         let message = commit.message().context("non-utf8 head ref name")?;
         println!(
             "HEAD is now at {} {} (dry run)",
@@ -252,44 +809,206 @@ pub fn checkout(repo: &Repository, head: Reference, dry_run: bool) -> Result<()>
     }
 }
 
-pub fn branch_delete(repo: &Repository, branches: &[&LocalBranch], dry_run: bool) -> Result<()> {
-    let mut args = vec!["branch", "--delete", "--force"];
-    let mut branch_names = Vec::new();
+/// Fast-forward the current branch to its upstream. It never creates merge commits.
+pub fn fast_forward_to_upstream(repo: &Repository, dry_run: bool) -> Result<()> {
+    let args = ["merge", "--ff-only", "@{upstream}"];
+    if !dry_run {
+        git(repo, &args, Level::Info)
+    } else {
+        info!("> git {} (dry-run)", args.join(" "));
+        Ok(())
+    }
+}
+
+pub fn branch_delete_args(repo: &Repository, branches: &[&LocalBranch]) -> Result<Vec<String>> {
+    let mut args = vec![
+        "branch".to_owned(),
+        "--delete".to_owned(),
+        "--force".to_owned(),
+    ];
     for branch in branches {
         let reference = repo.find_reference(&branch.refname)?;
         assert!(reference.is_branch());
         let branch_name = reference.shorthand().context("non utf-8 branch name")?;
-        branch_names.push(branch_name.to_owned());
+        args.push(branch_name.to_owned());
     }
-    args.extend(branch_names.iter().map(|x| x.as_str()));
+    Ok(args)
+}
 
-    if !dry_run {
-        git(repo, &args, Level::Info)
-    } else {
-        info!("> git {} (dry-run)", args.join(" "));
-        for branch_name in branch_names {
-            println!("Delete branch {} (dry run).", branch_name);
-        }
-        Ok(())
+/// `git branch --delete --remotes` to delete remote tracking refs only locally.
+pub fn branch_delete_remotes_args(remote_trackings: &[&RemoteTrackingBranch]) -> Vec<String> {
+    let mut args = vec![
+        "branch".to_owned(),
+        "--delete".to_owned(),
+        "--remotes".to_owned(),
+    ];
+    for remote_tracking in remote_trackings {
+        args.push(
+            remote_tracking
+                .refname
+                .trim_start_matches("refs/remotes/")
+                .to_owned(),
+        );
     }
+    args
 }
 
-pub fn push_delete(
+pub fn push_delete_args(remote_name: &str, remote_branches: &[&RemoteBranch]) -> Vec<String> {
+    assert!(remote_branches
+        .iter()
+        .all(|branch| branch.remote == remote_name));
+    let mut args = vec![
+        "push".to_owned(),
+        "--delete".to_owned(),
+        remote_name.to_owned(),
+    ];
+    for remote_branch in remote_branches {
+        args.push(remote_branch.refname.clone());
+    }
+    args
+}
+
+/// Push a commit to a remote ref, only if the ref doesn't exist, e.g. to restore a deleted branch.
+pub fn push_restore(
     repo: &Repository,
     remote_name: &str,
-    remote_branches: &[&RemoteBranch],
+    oid: &str,
+    refname: &str,
     dry_run: bool,
 ) -> Result<()> {
-    assert!(remote_branches
-        .iter()
-        .all(|branch| branch.remote == remote_name));
-    let mut command = vec!["push", "--delete"];
+    let refspec = format!("{}:{}", oid, refname);
+    let lease = format!("--force-with-lease={}:", refname);
+    let mut args = vec!["push", lease.as_str(), remote_name, refspec.as_str()];
     if dry_run {
-        command.push("--dry-run");
+        args.insert(1, "--dry-run");
     }
-    command.push(remote_name);
+    git(repo, &args, Level::Info)
+}
+
+/// What a remote responded to `git push --delete`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PushDeleteOutput {
+    /// Refs that the remote rejected to delete.
+    pub rejections: Vec<PushRejection>,
+    /// `(refname, summary)` of each ref, e.g. `[deleted]`, or `[remote rejected] (<reason>)`.
+    pub summaries: Vec<(String, String)>,
+    /// Messages that the remote printed, without the `remote: ` prefix.
+    pub remote_messages: Vec<String>,
+}
+
+/// Delete remote branches, and get refs that the remote rejected to delete.
+/// The remote deletes other refs even though some of them are rejected.
+/// It fails only when the push itself failed, e.g. a connection failure,
+/// or with `AuthenticationFailure` when the credentials are refused.
+/// Branches in `leases` are deleted only if they still point to the commits, by `--force-with-lease`.
+/// With `dry_run`, the remote reports rejections it can tell without running its hooks,
+/// e.g. stale leases, or refs that don't exist.
+/// Credential helpers and ssh-agent are used either way,
+/// but git prompts for a username and a password on the terminal only with `prompt`.
+pub fn push_delete(
+    repo: &Repository,
+    remote_name: &str,
+    remote_branches: &[&RemoteBranch],
+    leases: &HashMap<(String, String), String>,
+    dry_run: bool,
+    prompt: bool,
+) -> Result<PushDeleteOutput> {
+    let mut args = push_delete_args(remote_name, remote_branches);
     for remote_branch in remote_branches {
-        command.push(&remote_branch.refname);
+        let key = (remote_branch.remote.clone(), remote_branch.refname.clone());
+        if let Some(expected) = leases.get(&key) {
+            args.insert(
+                2,
+                format!("--force-with-lease={}:{}", remote_branch.refname, expected),
+            );
+        }
+    }
+    args.insert(2, "--porcelain".to_owned());
+    if dry_run {
+        args.insert(2, "--dry-run".to_owned());
+    }
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+
+    let mut command = git_command(repo, &args, Level::Trace)?;
+    if !prompt {
+        command.env("GIT_TERMINAL_PROMPT", "0");
+    }
+    let output = output(
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut remote_messages = Vec::new();
+    for line in stderr.lines() {
+        trace!("| {}", line);
+        if let Some(message) = line.strip_prefix("remote:") {
+            let message = message.trim();
+            if !message.is_empty() {
+                remote_messages.push(message.to_owned());
+            }
+        }
+    }
+
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    let mut rejections = Vec::new();
+    let mut summaries = Vec::new();
+    for line in stdout.lines() {
+        trace!("| {}", line);
+        // <flag> \t <from>:<to> \t <summary> (<reason>)
+        let mut fields = line.split('\t');
+        let (flag, refspec, summary) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(flag), Some(refspec), Some(summary)) => (flag, refspec, summary),
+            _ => continue,
+        };
+        let refname = refspec.split_once(':').map_or(refspec, |(_, dst)| dst);
+        summaries.push((refname.to_owned(), summary.to_owned()));
+        if flag == "!" {
+            rejections.push(PushRejection {
+                refname: refname.to_owned(),
+                reason: summary.to_owned(),
+                kind: RejectionKind::classify(summary, &remote_messages),
+            });
+        }
+    }
+
+    if !output.status.success() && rejections.is_empty() {
+        // The remote refused the whole push before it reports each ref.
+        if let Some(message) = find_permission_denial(&stderr) {
+            let rejections = remote_branches
+                .iter()
+                .map(|remote_branch| PushRejection {
+                    refname: remote_branch.refname.clone(),
+                    reason: message.to_owned(),
+                    kind: RejectionKind::PermissionDenied,
+                })
+                .collect();
+            return Ok(PushDeleteOutput {
+                rejections,
+                summaries,
+                remote_messages,
+            });
+        }
+        if let Some(message) = find_authentication_failure(&stderr) {
+            return Err(AuthenticationFailure {
+                remote: remote_name.to_owned(),
+                message: message.to_owned(),
+            }
+            .into());
+        }
+        let error = anyhow::Error::from(SubprocessError::exit_status(&args, output.status.code()));
+        return Err(
+            match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+                Some(last_line) => error.context(last_line.trim().to_owned()),
+                None => error,
+            },
+        );
     }
-    git(repo, &command, Level::Trace)
+    Ok(PushDeleteOutput {
+        rejections,
+        summaries,
+        remote_messages,
+    })
 }
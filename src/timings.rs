@@ -0,0 +1,45 @@
+//! Wall-clock time spent in each phase of planning, to tell which one is slow on big repositories.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Timings {
+    /// Phases in the order they ran.
+    pub phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, elapsed)| *elapsed).sum()
+    }
+
+    /// Record a phase that ran before planning, e.g. `remote update`.
+    pub fn prepend(&mut self, phase: &'static str, elapsed: Duration) {
+        self.phases.insert(0, (phase, elapsed));
+    }
+}
+
+/// Records the time since the previous lap as a phase.
+pub(crate) struct Stopwatch {
+    last: Instant,
+    timings: Timings,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch {
+            last: Instant::now(),
+            timings: Timings::default(),
+        }
+    }
+
+    pub fn lap(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.timings.phases.push((phase, now - self.last));
+        self.last = now;
+    }
+
+    pub fn finish(self) -> Timings {
+        self.timings
+    }
+}
@@ -0,0 +1,160 @@
+//! A log of branches deleted by git-trim, so that `git trim undo` can restore them.
+//! Each line of `$GIT_DIR/trim-log` is a JSON record of a run, and the last one is undone first.
+//! Remote branches are restored by pushing their last known commits, with `git trim undo --remote`.
+//! `git trim stats` aggregates the records too, so runs that are undone are not counted.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+
+use crate::util::common_dir;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct TrimLogRecord {
+    /// Seconds since the UNIX epoch when the run started.
+    /// Deletions of local and remote branches in a run are merged into a record with it.
+    pub time: u64,
+    pub locals: Vec<DeletedLocal>,
+    #[serde(default)]
+    pub remotes: Vec<DeletedRemote>,
+    /// Estimated bytes of objects that `git gc` can reclaim after the deletion.
+    /// `None` if it is unknown.
+    #[serde(default)]
+    pub reclaimable_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DeletedLocal {
+    pub refname: String,
+    pub oid: String,
+    /// `branch.<name>.*` config entries that are removed along with the branch.
+    pub config: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DeletedRemote {
+    pub remote: String,
+    pub refname: String,
+    /// The last known commit of the branch
+    pub oid: String,
+}
+
+static RUN_STARTED: AtomicU64 = AtomicU64::new(0);
+
+impl TrimLogRecord {
+    pub fn now(locals: Vec<DeletedLocal>, remotes: Vec<DeletedRemote>) -> Result<Self> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let time = match RUN_STARTED.compare_exchange(0, now, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => now,
+            Err(started) => started,
+        };
+        Ok(TrimLogRecord {
+            time,
+            locals,
+            remotes,
+            reclaimable_bytes: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TrimStats {
+    pub runs: usize,
+    pub locals: usize,
+    pub remotes: usize,
+    pub reclaimable_bytes: u64,
+}
+
+impl TrimStats {
+    /// Aggregate records since `since` (seconds since the UNIX epoch).
+    pub fn aggregate(records: &[TrimLogRecord], since: u64) -> TrimStats {
+        let mut stats = TrimStats::default();
+        for record in records.iter().filter(|record| record.time >= since) {
+            stats.runs += 1;
+            stats.locals += record.locals.len();
+            stats.remotes += record.remotes.len();
+            stats.reclaimable_bytes += record.reclaimable_bytes.unwrap_or(0);
+        }
+        stats
+    }
+}
+
+fn log_path(repo: &Repository) -> PathBuf {
+    common_dir(repo).join("trim-log")
+}
+
+/// The record is merged into the last one if they are of the same run.
+pub fn append(repo: &Repository, record: &TrimLogRecord) -> Result<()> {
+    let mut records = read(repo)?;
+    if let Some(last) = records.last_mut().filter(|last| last.time == record.time) {
+        last.locals.extend(record.locals.iter().cloned());
+        last.remotes.extend(record.remotes.iter().cloned());
+        return write(repo, &records);
+    }
+    let path = log_path(repo);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("path={}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+pub fn read(repo: &Repository) -> Result<Vec<TrimLogRecord>> {
+    let path = log_path(repo);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("path={}", path.display())),
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).with_context(|| format!("path={}", path.display())))
+        .collect()
+}
+
+/// Set the reclaimable bytes of the record of this run. It does nothing if this run hasn't deleted anything.
+pub fn set_reclaimable_bytes(repo: &Repository, reclaimable_bytes: Option<u64>) -> Result<()> {
+    let started = RUN_STARTED.load(Ordering::SeqCst);
+    let mut records = read(repo)?;
+    match records.last_mut().filter(|last| last.time == started) {
+        Some(last) if started != 0 => last.reclaimable_bytes = reclaimable_bytes,
+        _ => return Ok(()),
+    }
+    write(repo, &records)
+}
+
+/// Remove the last record after it is undone.
+pub fn pop(repo: &Repository) -> Result<Option<TrimLogRecord>> {
+    let mut records = read(repo)?;
+    let last = records.pop();
+    write(repo, &records)?;
+    Ok(last)
+}
+
+/// Replace the last record, e.g. with remote branches that are left to restore.
+pub fn replace_last(repo: &Repository, record: TrimLogRecord) -> Result<()> {
+    let mut records = read(repo)?;
+    records.pop();
+    records.push(record);
+    write(repo, &records)
+}
+
+fn write(repo: &Repository, records: &[TrimLogRecord]) -> Result<()> {
+    let mut content = String::new();
+    for record in records {
+        content.push_str(&serde_json::to_string(record)?);
+        content.push('\n');
+    }
+    let path = log_path(repo);
+    std::fs::write(&path, content).with_context(|| format!("path={}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! `--tui` lists branches to delete with why they are deleted and their tip commits,
+//! and lets you toggle each of them, instead of confirming all of them at once.
+
+use anyhow::{bail, Result};
+use dialoguer::MultiSelect;
+use git2::Repository;
+
+use git_trim::args::SortKey;
+use git_trim::{ClassifiedBranch, TrimPlan};
+
+use crate::describe_tip;
+
+/// Returns `false` if it is cancelled. Branches that are toggled off are preserved.
+pub fn select_branches(repo: &Repository, plan: &mut TrimPlan, sort: SortKey) -> Result<bool> {
+    let branches: Vec<_> = plan
+        .sorted_to_delete(repo, sort)?
+        .into_iter()
+        .cloned()
+        .collect();
+    if branches.is_empty() {
+        return Ok(true);
+    }
+
+    let mut rows = Vec::new();
+    for branch in &branches {
+        rows.push((
+            branch_name(repo, branch)?,
+            reason(branch),
+            describe_tip(repo, &tip_name(branch)?)?,
+        ));
+    }
+    let name_width = rows
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let reason_width = rows.iter().map(|(_, r, _)| r.len()).max().unwrap_or(0);
+    let items: Vec<_> = rows
+        .iter()
+        .map(|(name, reason, tip)| {
+            format!(
+                "{:name_width$}  {:reason_width$}  {}",
+                name,
+                reason,
+                tip,
+                name_width = name_width,
+                reason_width = reason_width
+            )
+        })
+        .collect();
+
+    let selected = MultiSelect::new()
+        .with_prompt("Branches to delete ([space] toggle, [enter] delete, [esc] cancel)")
+        .items(&items)
+        .defaults(&vec![true; items.len()])
+        .report(false)
+        .interact_opt()?;
+    let selected = match selected {
+        Some(selected) => selected,
+        None => return Ok(false),
+    };
+
+    plan.preserve_unselected(&branches, &selected);
+    Ok(true)
+}
+
+fn branch_name(repo: &Repository, branch: &ClassifiedBranch) -> Result<String> {
+    let remote = match branch.upstream() {
+        Some(upstream) => Some(
+            upstream
+                .refname
+                .trim_start_matches("refs/remotes/")
+                .to_owned(),
+        ),
+        None => branch.remote(repo)?.map(|remote| remote.to_string()),
+    };
+    Ok(match (branch.local(), remote) {
+        (Some(local), Some(remote)) => format!("{} ({})", local.short_name(), remote),
+        (Some(local), None) => local.short_name().to_owned(),
+        (None, Some(remote)) => remote,
+        (None, None) => bail!("{:?} is neither local nor remote", branch),
+    })
+}
+
+fn reason(branch: &ClassifiedBranch) -> String {
+    if branch.local().is_some() {
+        branch.message_local()
+    } else {
+        branch.message_remote()
+    }
+}
+
+/// A refname or a commit id to peel to the tip commit.
+fn tip_name(branch: &ClassifiedBranch) -> Result<String> {
+    if let Some(local) = branch.local() {
+        return Ok(local.refname.clone());
+    }
+    if let Some(upstream) = branch.upstream() {
+        return Ok(upstream.refname.clone());
+    }
+    match branch {
+        ClassifiedBranch::MergedRemoteOnly(remote_only) => Ok(remote_only.commit.clone()),
+        // There is no ref of the direct fetched remote branch to peel.
+        ClassifiedBranch::DivergedRemote { local, .. } => Ok(local.refname.clone()),
+        _ => bail!("There is no tip to show for {:?}", branch),
+    }
+}
@@ -1,4 +1,8 @@
+use std::borrow::Cow;
 use std::ops::Deref;
+use std::path::PathBuf;
+
+use git2::Repository;
 
 /// Use with caution.
 /// It makes wrapping type T to be Send + Sync.
@@ -25,3 +29,38 @@ impl<T> Deref for ForceSendSync<T> {
         &self.0
     }
 }
+
+/// Quote an argument for POSIX shells if it is necessary.
+pub fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@,+%^".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', "'\\''")))
+    }
+}
+
+/// `$GIT_DIR` shared with worktrees. Worktrees have `$GIT_DIR/commondir` pointing to it.
+pub fn common_dir(repo: &Repository) -> PathBuf {
+    match std::fs::read_to_string(repo.path().join("commondir")) {
+        Ok(commondir) => repo.path().join(commondir.trim()),
+        Err(_) => repo.path().to_owned(),
+    }
+}
+
+/// `YYYY-MM-DD` in the timezone of the commit.
+pub fn format_date(time: git2::Time) -> String {
+    let seconds = time.seconds() + i64::from(time.offset_minutes()) * 60;
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = seconds.div_euclid(86400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,128 @@
+mod fixture;
+
+use anyhow::Result;
+use git2::{Oid, Repository};
+
+use git_trim::backend::{Git2Backend, RepoBackend};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git tag -a v1.0 -m "Release 1.0"
+            git checkout master
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+            git cherry-pick feature
+            git checkout --orphan gh-pages
+            git commit -m "Pages"
+        EOF
+        git clone origin local
+        git clone --depth 1 --no-single-branch "file://$PWD/origin" shallow
+        "#,
+    )
+}
+
+fn oid(repo: &Repository, spec: &str) -> Result<Oid> {
+    Ok(repo.revparse_single(spec)?.peel_to_commit()?.id())
+}
+
+fn check_backend(backend: &dyn RepoBackend, repo: &Repository) -> Result<()> {
+    let master = oid(repo, "origin/master")?;
+    let feature = oid(repo, "origin/feature")?;
+    let gh_pages = oid(repo, "origin/gh-pages")?;
+    let initial = oid(repo, "origin/master~2")?;
+
+    assert_eq!(
+        backend.peel_to_commit_id("refs/remotes/origin/master")?,
+        master
+    );
+    assert_eq!(backend.peel_to_commit_id(&feature.to_string())?, feature);
+    assert_eq!(backend.peel_to_commit_id("v1.0")?, feature);
+    assert!(backend.peel_to_commit_id("refs/heads/nonexistent").is_err());
+
+    assert_eq!(backend.merge_base(master, feature)?, Some(initial));
+    assert_eq!(backend.merge_base(master, gh_pages)?, None);
+    assert_eq!(backend.shallow_commits()?, Vec::new());
+
+    assert!(backend
+        .is_merged_by_rev_list("refs/remotes/origin/master", "refs/remotes/origin/feature")?);
+    assert!(!backend
+        .is_merged_by_rev_list("refs/remotes/origin/feature", "refs/remotes/origin/master")?);
+    Ok(())
+}
+
+fn check_shallow_backend(backend: &dyn RepoBackend, repo: &Repository) -> Result<()> {
+    let mut expected = vec![
+        oid(repo, "origin/master")?,
+        oid(repo, "origin/feature")?,
+        oid(repo, "origin/gh-pages")?,
+    ];
+    expected.sort();
+    let mut shallow_commits = backend.shallow_commits()?;
+    shallow_commits.sort();
+    assert_eq!(shallow_commits, expected);
+    Ok(())
+}
+
+#[test]
+fn test_git2_backend() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    let backend = Git2Backend::open(&repo);
+    assert_eq!(backend.name(), "git2");
+    check_backend(&backend, &repo)
+}
+
+#[test]
+fn test_git2_backend_shallow() -> Result<()> {
+    let guard = fixture().prepare("shallow", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    check_shallow_backend(&Git2Backend::open(&repo), &repo)
+}
+
+#[cfg(feature = "gix")]
+#[test]
+fn test_gix_backend() -> Result<()> {
+    use git_trim::backend::GixBackend;
+
+    let guard = fixture().prepare("local", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    let backend = GixBackend::open(&repo)?;
+    assert_eq!(backend.name(), "gix");
+    check_backend(&backend, &repo)
+}
+
+#[cfg(feature = "gix")]
+#[test]
+fn test_gix_backend_shallow() -> Result<()> {
+    use git_trim::backend::GixBackend;
+
+    let guard = fixture().prepare("shallow", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    check_shallow_backend(&GixBackend::open(&repo)?, &repo)
+}
+
+/// Built with the `gix` feature, the merge detection runs on gix.
+#[test]
+fn test_default_backend() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    let backend = git_trim::backend::open(&repo)?;
+    let expected = if cfg!(feature = "gix") { "gix" } else { "git2" };
+    assert_eq!(backend.name(), expected);
+    Ok(())
+}
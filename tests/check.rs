@@ -0,0 +1,141 @@
+mod fixture;
+
+use anyhow::Result;
+use git2::Repository;
+
+use fixture::{git_trim, rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git checkout -b 100%-done
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin 100%-done
+            git checkout master
+        EOF
+        origin <<EOF
+            git merge 100%-done
+            git branch -d 100%-done
+        EOF
+        "#,
+    )
+}
+
+#[test]
+fn test_check_fails_over_max_stale() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let output = git_trim(&guard.working_directory(), &["--bases", "master", "check"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("exceeds `--max-stale 0`"));
+
+    let output = git_trim(
+        &guard.working_directory(),
+        &["--bases", "master", "check", "--max-stale", "1"],
+    )?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Nothing is deleted.
+    let output = git_trim(&guard.working_directory(), &["--bases", "master", "check"])?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_check_annotations_are_escaped() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let output = git_trim(
+        &guard.working_directory(),
+        &[
+            "--bases",
+            "master",
+            "check",
+            "--annotate",
+            "--max-stale",
+            "1",
+        ],
+    )?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let annotations: Vec<_> = stdout
+        .lines()
+        .filter(|line| line.starts_with("::"))
+        .collect();
+    assert_eq!(
+        annotations,
+        vec![
+            "::warning title=Stale branch::refs/heads/100%25-done is merged. \
+             Run `git trim` to trim it"
+        ],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_check_and_plan_dont_fast_forward_bases() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout --detach
+        EOF
+        "#,
+    )?;
+    let repo = Repository::open(guard.working_directory())?;
+    let master = repo.refname_to_id("refs/heads/master")?;
+    assert_ne!(repo.refname_to_id("refs/remotes/origin/master")?, master);
+
+    let output = git_trim(
+        &guard.working_directory(),
+        &[
+            "--bases",
+            "master",
+            "--ff-bases",
+            "check",
+            "--max-stale",
+            "1",
+        ],
+    )?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("(dry run)"));
+    assert_eq!(repo.refname_to_id("refs/heads/master")?, master);
+
+    let plan = guard.working_directory().join("../plan.json");
+    let output = git_trim(
+        &guard.working_directory(),
+        &[
+            "--bases",
+            "master",
+            "--ff-bases",
+            "plan",
+            "--output",
+            plan.to_str().unwrap(),
+        ],
+    )?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.refname_to_id("refs/heads/master")?, master);
+    Ok(())
+}
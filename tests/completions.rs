@@ -0,0 +1,36 @@
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use git_trim::args::Args;
+
+fn completion_script(shell: Shell) -> String {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Args::command(), "git-trim", &mut script);
+    String::from_utf8(script).unwrap()
+}
+
+#[test]
+fn test_completions_follow_args() {
+    let command = Args::command();
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        let script = completion_script(shell);
+        for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+            if let Some(long) = arg.get_long() {
+                assert!(
+                    script.contains(long),
+                    "{:?} doesn't complete --{}",
+                    shell,
+                    long
+                );
+            }
+        }
+        for subcommand in command.get_subcommands() {
+            assert!(
+                script.contains(subcommand.get_name()),
+                "{:?} doesn't complete {}",
+                shell,
+                subcommand.get_name()
+            );
+        }
+    }
+}
@@ -7,11 +7,15 @@ use std::iter::FromIterator;
 use anyhow::Result;
 use git2::Repository;
 
-use git_trim::args::{Args, DeleteFilter, DeleteUnit, Scope};
+use git_trim::args::{Args, DeleteFilter, DeleteRange, DeleteUnit, Scope, SummaryFormat};
 use git_trim::config::{Config, ConfigValue};
-use git_trim::Git;
+use git_trim::hints::{HintId, HintToggle};
+use git_trim::{
+    get_trim_plan, ClassifiedBranch, Git, InvalidConfig, LocalBranch, MergeStrategy, PlanParam,
+    RemoteBranch, RemoteBranchError, RemoteTrackingBranch,
+};
 
-use fixture::{rc, Fixture};
+use fixture::{git_trim, rc, test_default_param, Fixture, FixtureGuard};
 
 fn fixture() -> Fixture {
     rc().append_fixture_trace(
@@ -50,6 +54,55 @@ fn test_bases_implicit_value() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_local_only() -> Result<()> {
+    let guard = fixture().prepare(
+        "origin",
+        r#"
+        origin <<EOF
+            git checkout -b merged
+            touch merged-patch
+            git add merged-patch
+            git commit -m "Merged patch"
+            git checkout -b unmerged master
+            touch unmerged-patch
+            git add unmerged-patch
+            git commit -m "Unmerged patch"
+            git checkout master
+            git merge merged
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(
+        config.bases,
+        ConfigValue::Implicit(HashSet::from_iter(vec!["master".to_owned()]))
+    );
+    assert_eq!(config.update, ConfigValue::Implicit(false));
+    assert_eq!(
+        *config.delete,
+        DeleteFilter::from_iter(vec![DeleteRange::MergedLocal, DeleteRange::Local])
+    );
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            bases: config.bases.iter().map(String::as_str).collect(),
+            delete: config.delete.clone(),
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        HashSet::from_iter(vec![ClassifiedBranch::MergedNonTrackingLocal(
+            LocalBranch::new("refs/heads/merged")
+        )])
+    );
+    Ok(())
+}
+
 #[test]
 fn test_bases_config_value() -> Result<()> {
     let guard = fixture().prepare(
@@ -150,11 +203,64 @@ fn test_protected_multiple_comma_separated_values() -> Result<()> {
             "b".to_owned(),
             "c".to_owned(),
             "d".to_owned(),
+            "gh-pages".to_owned(),
+            "*/gh-pages".to_owned(),
         ]),
     );
     Ok(())
 }
 
+#[test]
+fn test_builtin_protection() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.protected gh-pages,release-*
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(
+        config.protected,
+        ConfigValue::GitConfig(vec![
+            "gh-pages".to_owned(),
+            "release-*".to_owned(),
+            "*/gh-pages".to_owned(),
+        ]),
+    );
+
+    git.repo
+        .config()?
+        .set_bool("trim.builtinProtection", false)?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(
+        config.protected,
+        ConfigValue::GitConfig(vec!["gh-pages".to_owned(), "release-*".to_owned()]),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_protected_invalid_regex() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.protected 're:release-(\d+'
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let err = Config::read(&git.repo, &git.config, &Args::default()).unwrap_err();
+    assert!(err.is::<InvalidConfig>());
+    Ok(())
+}
+
 #[test]
 fn test_delete_filter_multiple_comma_separated_values() -> Result<()> {
     let guard = fixture().prepare(
@@ -182,3 +288,639 @@ fn test_delete_filter_multiple_comma_separated_values() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn test_delete_filter_diverged_sides() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config --add trim.delete diverged-local,diverged-remote:upstream
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+
+    assert_eq!(
+        config.delete,
+        ConfigValue::GitConfig(DeleteFilter::from_iter(vec![
+            DeleteUnit::DivergedLocal,
+            DeleteUnit::DivergedRemote(Scope::Scoped("upstream".to_owned())),
+        ])),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_delete_filter_url_pattern_scope() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config --add trim.delete merged:url:github.com/myorg/*
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+
+    assert_eq!(
+        config.delete,
+        ConfigValue::GitConfig(DeleteFilter::from_iter(vec![
+            DeleteUnit::MergedLocal,
+            DeleteUnit::MergedRemote(Scope::UrlPattern("github.com/myorg/*".to_owned())),
+        ])),
+    );
+    for url in [
+        "https://github.com/myorg/repo.git",
+        "ssh://git@github.com/myorg/repo.git",
+        "git@github.com:myorg/repo.git",
+    ] {
+        assert!(config.delete.delete_merged_remote("origin", Some(url)));
+    }
+    assert!(!config
+        .delete
+        .delete_merged_remote("origin", Some("https://github.com/other/repo.git")));
+    assert!(!config.delete.delete_merged_remote("origin", None));
+    Ok(())
+}
+
+#[test]
+fn test_delete_filter_slashed_remote_scope() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config --add trim.delete merged:team/origin
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+
+    assert_eq!(
+        config.delete,
+        ConfigValue::GitConfig(DeleteFilter::from_iter(vec![
+            DeleteUnit::MergedLocal,
+            DeleteUnit::MergedRemote(Scope::Scoped("team/origin".to_owned())),
+        ])),
+    );
+    assert!(config
+        .delete
+        .delete_merged_remote("team/origin", Some("https://github.com/team/repo.git")));
+    assert!(!config
+        .delete
+        .delete_merged_remote("origin", Some("https://github.com/team/repo.git")));
+    Ok(())
+}
+
+#[test]
+fn test_max_delete_values() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.maxDelete 10
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.max_delete, ConfigValue::GitConfig(10));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            max_delete: Some(3),
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.max_delete, ConfigValue::Explicit(3));
+    Ok(())
+}
+
+/// Two merged branches whose remote branches are already deleted.
+fn prepare_max_delete(max_delete: u64) -> std::io::Result<FixtureGuard> {
+    fixture().prepare(
+        "local",
+        &format!(
+            r#"
+            local <<EOF
+                git config user.name "Local Test"
+                git config user.email "local@test"
+                git config trim.maxDelete {}
+                git checkout -b feature-a
+                touch feature-a
+                git add feature-a
+                git commit -m "Add feature-a"
+                git push -u origin feature-a
+                git checkout -b feature-b master
+                touch feature-b
+                git add feature-b
+                git commit -m "Add feature-b"
+                git push -u origin feature-b
+                git checkout master
+            EOF
+            origin <<EOF
+                git merge feature-a --no-ff
+                git branch -D feature-a
+                git merge feature-b --no-ff
+                git branch -D feature-b
+            EOF
+            "#,
+            max_delete
+        ),
+    )
+}
+
+fn local_branch_exists(guard: &FixtureGuard, name: &str) -> Result<bool> {
+    let repo = Repository::open(guard.working_directory())?;
+    let exists = repo.find_reference(&format!("refs/heads/{}", name)).is_ok();
+    Ok(exists)
+}
+
+#[test]
+fn test_max_delete_exceeded() -> Result<()> {
+    let guard = prepare_max_delete(1)?;
+    let output = git_trim(&guard.working_directory(), &["--bases", "master", "--yes"])?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(
+        "2 branches are about to be deleted, which exceeds the limit `trim.maxDelete=1`"
+    ));
+    assert!(stderr.contains("Too many branches to delete"));
+
+    assert!(local_branch_exists(&guard, "feature-a")?);
+    assert!(local_branch_exists(&guard, "feature-b")?);
+    Ok(())
+}
+
+#[test]
+fn test_max_delete_not_exceeded() -> Result<()> {
+    for max_delete in [2, 3] {
+        let guard = prepare_max_delete(max_delete)?;
+        let output = git_trim(&guard.working_directory(), &["--bases", "master", "--yes"])?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("exceeds the limit"));
+
+        assert!(!local_branch_exists(&guard, "feature-a")?);
+        assert!(!local_branch_exists(&guard, "feature-b")?);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_jobs_values() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.jobs 2
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.jobs, ConfigValue::GitConfig(2));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            jobs: Some(1),
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.jobs, ConfigValue::Explicit(1));
+    Ok(())
+}
+
+#[test]
+fn test_summary_values() -> Result<()> {
+    let guard = fixture().prepare("local", r#""#)?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.summary, ConfigValue::Implicit(SummaryFormat::Full));
+
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.summary short
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.summary, ConfigValue::GitConfig(SummaryFormat::Short));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            summary: Some(SummaryFormat::None),
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.summary, ConfigValue::Explicit(SummaryFormat::None));
+    Ok(())
+}
+
+#[test]
+fn test_confirm_diverged_values() -> Result<()> {
+    let guard = fixture().prepare("local", r#""#)?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.confirm_diverged, ConfigValue::Implicit(true));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            no_confirm_diverged: true,
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.confirm_diverged, ConfigValue::Explicit(false));
+    Ok(())
+}
+
+#[test]
+fn test_confirm_remotes_values() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.confirmRemotes true
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.confirm_remotes, ConfigValue::GitConfig(true));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            no_confirm_remotes: true,
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.confirm_remotes, ConfigValue::Explicit(false));
+    Ok(())
+}
+
+#[test]
+fn test_detection_strategies_values() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.detectionStrategies ancestor,patch-id
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(
+        config.detection_strategies,
+        ConfigValue::GitConfig(vec![MergeStrategy::Ancestor, MergeStrategy::PatchId]),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_deprecated_merge_detection_values() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.mergeDetection ancestor,rebase
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(
+        config.detection_strategies,
+        ConfigValue::GitConfig(vec![MergeStrategy::Ancestor, MergeStrategy::Rebase]),
+    );
+
+    git.repo
+        .config()?
+        .set_str("trim.detectionStrategies", "noff")?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(
+        config.detection_strategies,
+        ConfigValue::GitConfig(vec![MergeStrategy::NoFastForward]),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_profile_values() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.maxDelete 10
+            git config trim.builtinProtection false
+            git config trim.protected release
+            git config trim.profile.Work.maxDelete 100
+            git config trim.profile.Work.protected main,develop
+            git config trim.profile.oss.confirm false
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.max_delete, ConfigValue::GitConfig(10));
+    assert_eq!(
+        config.protected,
+        ConfigValue::GitConfig(vec!["release".to_owned()])
+    );
+
+    let work = Args {
+        profile: Some("Work".to_owned()),
+        ..Args::default()
+    };
+    let config = Config::read(&git.repo, &git.config, &work)?;
+    assert_eq!(config.max_delete, ConfigValue::GitConfig(100));
+    assert_eq!(
+        config.protected,
+        ConfigValue::GitConfig(vec!["main".to_owned(), "develop".to_owned()])
+    );
+    assert_eq!(config.confirm, ConfigValue::Implicit(true));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            max_delete: Some(3),
+            ..work
+        },
+    )?;
+    assert_eq!(config.max_delete, ConfigValue::Explicit(3));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            profile: Some("oss".to_owned()),
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.max_delete, ConfigValue::GitConfig(10));
+    assert_eq!(config.confirm, ConfigValue::GitConfig(false));
+
+    let result = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            profile: Some("missing".to_owned()),
+            ..Args::default()
+        },
+    );
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_config_overrides() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.maxDelete 10
+            git config trim.protected release
+            git config trim.profile.Work.maxDelete 100
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let args = Args {
+        profile: Some("Work".to_owned()),
+        config: vec![
+            "trim.maxDelete=3".to_owned(),
+            "trim.builtinProtection=false".to_owned(),
+            "trim.protected=a,b".to_owned(),
+            "trim.protected=c".to_owned(),
+            "trim.Detach=no".to_owned(),
+            "trim.confirm".to_owned(),
+        ],
+        ..Args::default()
+    };
+    let config = Config::read(&git.repo, &git.config, &args)?;
+    assert_eq!(config.max_delete, ConfigValue::Explicit(3));
+    assert_eq!(
+        config.protected,
+        ConfigValue::Explicit(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]),
+    );
+    assert_eq!(config.detach, ConfigValue::Explicit(false));
+    assert_eq!(config.confirm, ConfigValue::Explicit(true));
+
+    // Flags take precedence over overrides.
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            max_delete: Some(5),
+            ..args
+        },
+    )?;
+    assert_eq!(config.max_delete, ConfigValue::Explicit(5));
+
+    for invalid in ["core.editor=vim", "trim.maxDelete=-1", "trim.detach=maybe"] {
+        let result = Config::read(
+            &git.repo,
+            &git.config,
+            &Args {
+                config: vec![invalid.to_owned()],
+                ..Args::default()
+            },
+        );
+        assert!(result.is_err(), "{}", invalid);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_partial_clone_skips_strategies_reading_blobs() -> Result<()> {
+    let guard = fixture().prepare(
+        "partial",
+        r#"
+        git clone --filter=blob:none "file://$PWD/origin" partial
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.partial_clone, ConfigValue::Implicit(true));
+    assert_eq!(
+        config.merge_strategies(),
+        vec![MergeStrategy::Ancestor, MergeStrategy::NoFastForward],
+    );
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            no_partial_clone: true,
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.partial_clone, ConfigValue::Explicit(false));
+    assert_eq!(config.merge_strategies(), MergeStrategy::defaults());
+    Ok(())
+}
+
+#[test]
+fn test_triangular_defaults() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        git clone origin fork
+        local <<EOF
+            git remote add fork ../fork
+            git fetch fork
+            git remote set-head fork master
+            git config remote.pushDefault fork
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert_eq!(config.triangular, ConfigValue::Implicit(true));
+    assert_eq!(
+        config.bases,
+        ConfigValue::Implicit(HashSet::from_iter(vec!["master".to_owned()]))
+    );
+    assert_eq!(
+        config.delete,
+        ConfigValue::Implicit(DeleteFilter::from_iter(vec![
+            DeleteUnit::MergedLocal,
+            DeleteUnit::MergedRemote(Scope::Scoped("fork".to_owned())),
+        ])),
+    );
+    assert_eq!(config.scan_push_remote, ConfigValue::Implicit(true));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            no_triangular: true,
+            ..Args::default()
+        },
+    )?;
+    assert_eq!(config.triangular, ConfigValue::Explicit(false));
+    assert_eq!(
+        config.delete,
+        ConfigValue::Implicit(DeleteFilter::from_iter(vec![
+            DeleteUnit::MergedLocal,
+            DeleteUnit::MergedRemote(Scope::Scoped("origin".to_owned())),
+        ])),
+    );
+    assert_eq!(config.scan_push_remote, ConfigValue::Implicit(false));
+    Ok(())
+}
+
+#[test]
+fn test_remote_tracking_branch_fetched_from_many_remotes() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git branch feature
+        EOF
+        local <<EOF
+            git remote add mirror ../origin
+            git config remote.mirror.fetch "+refs/heads/*:refs/remotes/origin/*"
+            git fetch origin
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    // `master` tracks `origin/master`
+    assert_eq!(
+        RemoteTrackingBranch::new("refs/remotes/origin/master").to_remote_branch(&git.repo)?,
+        RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/master".to_owned(),
+        },
+    );
+    let feature = RemoteTrackingBranch::new("refs/remotes/origin/feature");
+    match feature.to_remote_branch(&git.repo) {
+        Err(RemoteBranchError::AmbiguousRemotes { remotes, .. }) => {
+            assert_eq!(remotes, vec!["mirror".to_owned(), "origin".to_owned()])
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    git.repo
+        .config()?
+        .set_str("checkout.defaultRemote", "mirror")?;
+    assert_eq!(
+        feature.to_remote_branch(&git.repo)?,
+        RemoteBranch {
+            remote: "mirror".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_hints() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.hints no-skip-suggestions,no-fix-upstreams
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let config = Config::read(&git.repo, &git.config, &Args::default())?;
+    assert!(!config.hints.enabled(HintId::SkipSuggestions));
+    assert!(!config.hints.enabled(HintId::FixUpstreams));
+    assert!(config.hints.enabled(HintId::BaseResolution));
+
+    let config = Config::read(
+        &git.repo,
+        &git.config,
+        &Args {
+            hint: vec![
+                "no-base-resolution".parse()?,
+                "no-skip-suggestions".parse()?,
+                "skip-suggestions".parse()?,
+            ],
+            ..Args::default()
+        },
+    )?;
+    assert!(config.hints.enabled(HintId::SkipSuggestions));
+    assert!(config.hints.enabled(HintId::FixUpstreams));
+    assert!(!config.hints.enabled(HintId::BaseResolution));
+
+    assert!("no-such-hint".parse::<HintToggle>().is_err());
+    Ok(())
+}
@@ -7,8 +7,10 @@ use anyhow::Result;
 use git2::Repository;
 
 use git_trim::args::{DeleteFilter, DeleteRange, Scope};
+use git_trim::report::{SkippedKind, TrimReport};
 use git_trim::{
     get_trim_plan, ClassifiedBranch, Git, LocalBranch, PlanParam, RemoteTrackingBranch,
+    SkipSuggestion,
 };
 
 use fixture::{rc, test_default_param, Fixture};
@@ -129,3 +131,116 @@ fn test_accidential_track() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn test_skip_out_of_delete_range_before_classification() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout --track contributer/feature
+
+            git checkout master
+            git merge feature --no-ff
+            git push -u origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            delete: DeleteFilter::from_iter(vec![DeleteRange::MergedRemote(Scope::Scoped(
+                "origin".to_string(),
+            ))]),
+            ..param()
+        },
+    )?;
+    assert!(plan.to_delete.is_empty());
+    let suggestion = SkipSuggestion::TrackingRemote("contributer".to_owned());
+    assert_eq!(plan.skipped.get("refs/heads/feature"), Some(&suggestion));
+    assert_eq!(
+        plan.skipped.get("refs/remotes/contributer/feature"),
+        Some(&suggestion)
+    );
+    assert!(plan.classifications.iter().all(|classification| {
+        classification
+            .merge_states
+            .iter()
+            .all(|state| state.branch != "refs/heads/feature")
+    }));
+
+    let report = TrimReport::new(&git.repo, &plan)?;
+    assert_eq!(
+        report
+            .skipped
+            .iter()
+            .map(|skipped| (skipped.refname.as_str(), skipped.skipped_kind))
+            .collect::<Vec<_>>(),
+        vec![
+            ("refs/heads/feature", SkippedKind::Tracking),
+            ("refs/remotes/contributer/feature", SkippedKind::Tracking),
+            ("refs/remotes/contributer/master", SkippedKind::NonUpstream),
+        ],
+    );
+    let json = serde_json::to_value(&report.skipped[0])?;
+    assert_eq!(json["skipped_kind"], "tracking");
+    Ok(())
+}
+
+#[test]
+fn test_apply_suggestions() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout --track contributer/feature
+
+            git checkout master
+            git merge feature --no-ff
+            git push -u origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let param = PlanParam {
+        delete: DeleteFilter::from_iter(vec![DeleteRange::MergedRemote(Scope::Scoped(
+            "origin".to_string(),
+        ))]),
+        ..param()
+    };
+    let plan = get_trim_plan(&git, &param)?;
+    let ranges = plan.suggested_delete_ranges();
+    assert_eq!(
+        ranges,
+        vec![
+            DeleteRange::Merged(Scope::Scoped("contributer".to_owned())),
+            DeleteRange::Remote(Scope::Scoped("contributer".to_owned())),
+        ]
+    );
+    assert_eq!(ranges[0].to_string(), "merged:contributer");
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            delete: param.delete.extended(ranges),
+            ..param
+        },
+    )?;
+    assert!(plan.skipped.is_empty());
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/contributer/feature"
+            )),
+            ClassifiedBranch::MergedNonUpstreamRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/contributer/master"
+            )),
+        },
+    );
+    Ok(())
+}
@@ -1,15 +1,15 @@
 use std::fmt::Write;
 use std::io::{BufRead, BufReader, Error, Write as _};
 use std::iter::FromIterator;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
 use std::thread::spawn;
 
 use log::*;
 use tempfile::{tempdir, TempDir};
 
-use git_trim::args::{DeleteFilter, DeleteRange, Scope};
-use git_trim::PlanParam;
+use git_trim::args::{DeleteFilter, DeleteRange, Scope, SortKey};
+use git_trim::{MergeStrategy, PlanParam};
 
 #[derive(Default)]
 pub struct Fixture {
@@ -66,7 +66,7 @@ impl Fixture {
         println!("{:?}", tempdir.path());
         let mut command = Command::new("bash");
         command
-            .args(&["--noprofile", "--norc", "-xeo", "pipefail"])
+            .args(["--noprofile", "--norc", "-xeo", "pipefail"])
             .current_dir(tempdir.path())
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
@@ -167,7 +167,7 @@ pub fn rc() -> Fixture {
             r#"
             shopt -s expand_aliases
             within() {
-                pushd $1 > /dev/null
+                pushd "$1" > /dev/null
                 source /dev/stdin
                 popd > /dev/null
             }
@@ -204,12 +204,29 @@ macro_rules! set {
     {$($x:expr,)*} => ($crate::set!{$($x),*})
 }
 
+/// Run the `git-trim` binary without a terminal, as in scripts and CI jobs.
+#[allow(unused)]
+pub fn git_trim(working_directory: &Path, args: &[&str]) -> std::io::Result<Output> {
+    Command::new(env!("CARGO_BIN_EXE_git-trim"))
+        .args(args)
+        .current_dir(working_directory)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+}
+
 #[allow(unused)]
 pub fn test_default_param() -> PlanParam<'static> {
     use DeleteRange::*;
     PlanParam {
         bases: vec!["master"],
+        base_commits: vec![],
         protected_patterns: Vec::new(),
+        protect_head_of_remotes: true,
+        include_slash_prefixes: Vec::new(),
+        remote_namespaces: Vec::new(),
+        pull_ref_namespaces: vec!["refs/pull/*/head", "refs/merge-requests/*/head"],
         delete: DeleteFilter::from_iter(vec![
             MergedLocal,
             MergedRemote(Scope::All),
@@ -217,6 +234,23 @@ pub fn test_default_param() -> PlanParam<'static> {
             Diverged(Scope::All),
         ]),
         detach: true,
+        scan_push_remote: false,
+        scan: Vec::new(),
+        recent_checkout_days: 0,
+        stray_grace_days: 0,
+        merge_strategies: MergeStrategy::defaults(),
+        reachability_index: false,
+        cache: false,
+        fix_upstreams: false,
+        preserve_signed_by_others: false,
+        signing_keys: Vec::new(),
+        only_mine: false,
+        sort: SortKey::Name,
+        limit: 0,
+        retarget_stacks: false,
+        remote_timeout: 0,
+        offline: false,
+        detect_remote_protection: false,
     }
 }
 
@@ -0,0 +1,23 @@
+use git2::Time;
+
+use git_trim::format_date;
+
+#[test]
+fn test_format_date() {
+    assert_eq!(format_date(Time::new(0, 0)), "1970-01-01");
+    assert_eq!(format_date(Time::new(-1, 0)), "1969-12-31");
+    assert_eq!(format_date(Time::new(951_782_400, 0)), "2000-02-29");
+    assert_eq!(format_date(Time::new(1_709_164_800, 0)), "2024-02-29");
+    assert_eq!(
+        format_date(Time::new(1_709_164_800 + 86400, 0)),
+        "2024-03-01"
+    );
+}
+
+#[test]
+fn test_format_date_in_the_timezone_of_the_commit() {
+    // 2023-11-14 22:13:20 UTC
+    assert_eq!(format_date(Time::new(1_700_000_000, 0)), "2023-11-14");
+    assert_eq!(format_date(Time::new(1_700_000_000, 9 * 60)), "2023-11-15");
+    assert_eq!(format_date(Time::new(951_782_400, -60)), "2000-02-28");
+}
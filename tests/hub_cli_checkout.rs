@@ -1,11 +1,16 @@
 mod fixture;
 
 use std::convert::TryFrom;
+use std::iter::FromIterator;
 
 use anyhow::Result;
 use git2::Repository;
 
-use git_trim::{get_trim_plan, ClassifiedBranch, Git, LocalBranch, RemoteBranch};
+use git_trim::args::{DeleteFilter, DeleteRange, Scope};
+use git_trim::{
+    delete_pull_refs, get_trim_plan, ClassifiedBranch, Git, LocalBranch, PlanParam, RemoteBranch,
+    RemoteTrackingBranch,
+};
 
 use fixture::{rc, test_default_param, Fixture};
 
@@ -208,6 +213,72 @@ fn test_modified_and_accepted_but_forgot_to_delete() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_modified_and_accepted_but_forgot_to_delete_one_side() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git fetch ../origin feature:feature
+            git config branch.feature.remote "../origin"
+            git config branch.feature.merge "refs/heads/feature"
+        EOF
+        origin <<EOF
+            touch another-patch
+            git add another-patch
+            git commit -m "another patch"
+            git push upstream feature:refs/pull/1/head
+        EOF
+        upstream <<EOF
+            git merge refs/pull/1/head
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let local = LocalBranch::new("refs/heads/feature");
+    let remote = RemoteBranch {
+        remote: "../origin".to_string(),
+        refname: "refs/heads/feature".to_string(),
+    };
+    let local_side = ClassifiedBranch::DivergedLocal {
+        local: local.clone(),
+        remote: remote.clone(),
+    };
+    let remote_side = ClassifiedBranch::DivergedRemote {
+        local,
+        remote,
+        upstream: None,
+    };
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            delete: DeleteFilter::from_iter(vec![DeleteRange::DivergedLocal]),
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(plan.to_delete, set! { local_side.clone() });
+    assert!(plan
+        .preserved
+        .iter()
+        .any(|preserved| preserved.branch == remote_side));
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            delete: DeleteFilter::from_iter(vec![DeleteRange::DivergedRemote(Scope::All)]),
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(plan.to_delete, set! { remote_side });
+    assert!(plan
+        .preserved
+        .iter()
+        .any(|preserved| preserved.branch == local_side));
+    Ok(())
+}
+
 #[test]
 fn test_rejected() -> Result<()> {
     let guard = fixture().prepare(
@@ -263,3 +334,114 @@ fn test_should_not_push_delete_non_heads() -> Result<()> {
     );
     Ok(())
 }
+
+fn pull_refs_fixture() -> Fixture {
+    fixture().append_fixture_trace(
+        r#"
+        local <<EOF
+            git remote add pr ../upstream
+            git config remote.pr.fetch '+refs/pull/*/head:refs/remotes/pr/*'
+            git fetch pr
+        EOF
+        upstream <<EOF
+            git merge refs/pull/1/head
+        EOF
+        "#,
+    )
+}
+
+fn pull_refs_param() -> PlanParam<'static> {
+    use DeleteRange::*;
+    PlanParam {
+        delete: DeleteFilter::from_iter(vec![
+            MergedLocal,
+            MergedRemote(Scope::All),
+            Remote(Scope::Scoped("pr".to_owned())),
+        ]),
+        ..test_default_param()
+    }
+}
+
+#[test]
+fn test_should_not_push_delete_pull_refs_by_default() -> Result<()> {
+    let guard = pull_refs_fixture().prepare("local", r#""#)?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &pull_refs_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    assert!(plan.preserved.iter().any(|p| {
+        p.branch
+            == ClassifiedBranch::MergedNonUpstreamRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/pr/1",
+            ))
+            && p.reason == "a non-heads remote"
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_push_delete_pull_refs_in_remote_namespaces() -> Result<()> {
+    let guard = pull_refs_fixture().prepare("local", r#""#)?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            remote_namespaces: vec!["refs/pull/*"],
+            ..pull_refs_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedNonUpstreamRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/pr/1",
+            )),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_delete_merged_pull_refs_locally() -> Result<()> {
+    let guard = pull_refs_fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout -b another-feature master
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+            git push upstream another-feature:refs/pull/2/head
+        EOF
+        local <<EOF
+            git fetch pr
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let mut param = pull_refs_param();
+    param.delete = param
+        .delete
+        .extended(vec![DeleteRange::PullRefs(Scope::Scoped("pr".to_owned()))]);
+    let plan = get_trim_plan(&git, &param)?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedPullRef {
+                remote_tracking: RemoteTrackingBranch::new("refs/remotes/pr/1"),
+                pull: RemoteBranch {
+                    remote: "pr".to_owned(),
+                    refname: "refs/pull/1/head".to_owned(),
+                },
+            },
+        },
+    );
+    assert!(plan.remotes_to_delete(&git.repo)?.is_empty());
+
+    delete_pull_refs(&git.repo, &plan.pull_refs_to_delete(), false)?;
+    assert!(git.repo.find_reference("refs/remotes/pr/1").is_err());
+    assert!(git.repo.find_reference("refs/remotes/pr/2").is_ok());
+    Ok(())
+}
@@ -0,0 +1,43 @@
+mod fixture;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::ls_remote_head;
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        "#,
+    )
+}
+
+#[test]
+fn test_libgit2_ls_remote_without_git() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    let master = repo.revparse_single("origin/master")?.id().to_string();
+
+    // This is the only test of this file, so it doesn't take `git` away from others.
+    std::env::set_var("PATH", "");
+    assert!(ls_remote_head(&repo, "origin", Duration::ZERO).is_err());
+
+    repo.config()?.set_bool("trim.libgit2LsRemote", true)?;
+    let head = ls_remote_head(&repo, "origin", Duration::ZERO)?;
+    assert_eq!(head.refname, "refs/heads/master");
+    assert_eq!(head.commit, master);
+    Ok(())
+}
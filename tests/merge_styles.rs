@@ -5,7 +5,10 @@ use std::convert::TryFrom;
 use anyhow::Result;
 use git2::Repository;
 
-use git_trim::{get_trim_plan, ClassifiedBranch, Git, LocalBranch};
+use git_trim::{
+    get_trim_plan, get_trim_plan_of_branch, ClassifiedBranch, Git, LocalBranch, MergeMetrics,
+    MergeStrategy, PlanParam,
+};
 
 use fixture::{rc, test_default_param, Fixture};
 
@@ -63,6 +66,180 @@ fn test_noff() -> Result<()> {
             ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
         },
     );
+    // `git branch --merged` already found `feature`, and `origin/master` is the base itself.
+    // `master` is left to the strategies, since `git branch --merged` skips branches that track the base.
+    assert_eq!(
+        plan.merge_metrics,
+        MergeMetrics {
+            precomputed: 2,
+            checked: 1,
+            ruled_out_by_commit_graph: 0,
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_noff_with_commit_graph() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b wip master
+            touch wip-patch
+            git add wip-patch
+            git commit -m "WIP patch"
+            # Longer than the history of the base, so that generation numbers tell it is not merged.
+            for i in 1 2 3 4 5; do git commit --allow-empty -m "WIP \$i"; done
+            git push -u origin wip
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature --no-ff
+            git branch -D feature
+            git commit --allow-empty -m "Later commit"
+        EOF
+        local <<EOF
+            git fetch --prune
+            git commit-graph write --reachable
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            merge_strategies: vec![MergeStrategy::Ancestor, MergeStrategy::NoFastForward],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    assert!(plan.merge_metrics.ruled_out_by_commit_graph > 0);
+    Ok(())
+}
+
+#[test]
+fn test_noff_with_corrupt_commit_graph() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b wip master
+            for i in 1 2 3 4 5 6; do git commit --allow-empty -m "WIP \$i"; done
+            git push -u origin wip
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature --no-ff
+            git branch -D feature
+            git commit --allow-empty -m "Later commit"
+        EOF
+        local <<EOF
+            git fetch --prune
+            git commit-graph write --reachable
+            # Only git-trim reads the corrupted files.
+            git config core.commitGraph false
+        EOF
+        "#,
+    )?;
+
+    let path = guard
+        .working_directory()
+        .join(".git/objects/info/commit-graph");
+    let original = std::fs::read(&path)?;
+    // Positions of offsets in the chunk table
+    let chunk_entry = |id: &[u8]| -> usize {
+        let entry = (0..original[6] as usize)
+            .map(|i| 8 + i * 12)
+            .find(|entry| &original[*entry..*entry + 4] == id)
+            .unwrap();
+        entry + 4
+    };
+    let mut fanout = [0u8; 8];
+    fanout.copy_from_slice(&original[chunk_entry(b"OIDF")..chunk_entry(b"OIDF") + 8]);
+    let fanout = u64::from_be_bytes(fanout) as usize;
+
+    let truncated = original[..original.len() / 2].to_vec();
+    let mut huge_offset = original.clone();
+    let entry = chunk_entry(b"OIDL");
+    huge_offset[entry..entry + 8].copy_from_slice(&u64::MAX.to_be_bytes());
+    let mut huge_count = original.clone();
+    huge_count[fanout + 255 * 4..fanout + 256 * 4].copy_from_slice(&u32::MAX.to_be_bytes());
+    let mut decreasing = original.clone();
+    decreasing[fanout..fanout + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+    for (corrupted, valid) in [
+        (original.clone(), true),
+        (truncated, false),
+        (huge_offset, false),
+        (huge_count, false),
+        (decreasing, false),
+    ] {
+        std::fs::write(&path, corrupted)?;
+        let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+        let plan = get_trim_plan(
+            &git,
+            &PlanParam {
+                merge_strategies: vec![MergeStrategy::Ancestor, MergeStrategy::NoFastForward],
+                ..test_default_param()
+            },
+        )?;
+        assert_eq!(
+            plan.to_delete,
+            set! {
+                ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            },
+        );
+        assert_eq!(plan.merge_metrics.ruled_out_by_commit_graph > 0, valid);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_noff_with_reachability_index() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b wip master
+            touch wip-patch
+            git add wip-patch
+            git commit -m "WIP patch"
+            git push -u origin wip
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature --no-ff
+            git branch -D feature
+            git commit --allow-empty -m "Later commit"
+        EOF
+        local <<EOF
+            git fetch --prune
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            merge_strategies: vec![MergeStrategy::Ancestor, MergeStrategy::NoFastForward],
+            reachability_index: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
     Ok(())
 }
 
@@ -116,6 +293,192 @@ fn test_squash() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_squash_doesnt_write_objects() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature --squash && git commit --no-edit
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let count_objects = |repo: &Repository| -> Result<usize> {
+        let mut count = 0;
+        repo.odb()?.foreach(|_| {
+            count += 1;
+            true
+        })?;
+        Ok(count)
+    };
+    let before = count_objects(&git.repo)?;
+    get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(count_objects(&git.repo)?, before);
+    Ok(())
+}
+
+#[test]
+fn test_rebased_copy_fast_forwarded() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        # Commits are split differently from the original, but the final tree is the same.
+        origin <<EOF
+            git checkout master
+            echo "draft" > awesome-patch
+            touch another-patch
+            git add awesome-patch another-patch
+            git commit -m "Rework patches"
+            truncate -s 0 awesome-patch
+            git add awesome-patch
+            git commit -m "Fixup"
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            merge_strategies: vec![MergeStrategy::NoFastForward, MergeStrategy::Tree],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_squash_by_patch_id() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature --squash && git commit --no-edit
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            merge_strategies: vec![MergeStrategy::Ancestor, MergeStrategy::Rebase],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            merge_strategies: vec![MergeStrategy::Ancestor, MergeStrategy::PatchId],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_squash_of_non_utf8_patch_by_patch_id() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            printf 'caf\351\n  \n' > latin1.txt
+            git add latin1.txt
+            git commit -m "Latin-1 patch"
+            git push origin feature
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature --squash && git commit --no-edit
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            merge_strategies: vec![MergeStrategy::Ancestor, MergeStrategy::PatchId],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_squash_explained() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature --squash && git commit --no-edit
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan_of_branch(&git, &test_default_param(), "refs/heads/feature")?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    assert_eq!(plan.classifications.len(), 1);
+    let classification = &plan.classifications[0];
+    assert_eq!(classification.base.refname, "refs/remotes/origin/master");
+    assert_eq!(classification.merge_states.len(), 1);
+    assert_eq!(classification.merge_states[0].branch, "refs/heads/feature");
+    assert_eq!(
+        classification.merge_states[0].strategy,
+        Some(MergeStrategy::Squash)
+    );
+    Ok(())
+}
+
 #[test]
 fn test_mixed() -> Result<()> {
     let fixture = rc().append_fixture_trace(
@@ -0,0 +1,169 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::{
+    get_trim_plan, ClassifiedBranch, Git, LocalBranch, PlanParam, RemoteTrackingBranch,
+    UnresolvedBase,
+};
+
+use fixture::{rc, test_default_param, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+        EOF
+        "#,
+    )
+}
+
+fn param() -> PlanParam<'static> {
+    test_default_param()
+}
+
+#[test]
+fn test_heads_after_other_refspecs() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config --replace-all remote.origin.fetch '+refs/notes/*:refs/notes/*'
+            git config --add remote.origin.fetch '+refs/heads/master:refs/remotes/origin/master'
+            git config --add remote.origin.fetch 'refs/heads/master'
+            git config --add remote.origin.fetch '+refs/heads/*:refs/remotes/origin/*'
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+        EOF
+
+        origin <<EOF
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &param())?;
+
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_single_branch_refspec_added_later() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config --replace-all remote.origin.fetch '+refs/heads/master:refs/remotes/origin/master'
+            git remote set-branches --add origin feature
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+        EOF
+
+        origin <<EOF
+            git merge feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &param())?;
+
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_base_of_remote_with_custom_namespace() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git remote add upstream ../origin
+            git config --replace-all remote.upstream.fetch '+refs/heads/*:refs/remotes/mirror/*'
+            git fetch upstream
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+        EOF
+
+        origin <<EOF
+            git merge feature
+        EOF
+        local <<EOF
+            git fetch upstream
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            bases: vec!["upstream/master", "upstream/nonexistent"],
+            ..param()
+        },
+    )?;
+
+    assert!(plan
+        .to_delete
+        .contains(&ClassifiedBranch::MergedLocal(LocalBranch::new(
+            "refs/heads/feature"
+        ))));
+    assert_eq!(
+        plan.unresolved_bases,
+        vec![UnresolvedBase {
+            base: "upstream/nonexistent".to_owned(),
+            candidates: vec![
+                "upstream/nonexistent".to_owned(),
+                "refs/upstream/nonexistent".to_owned(),
+                "refs/tags/upstream/nonexistent".to_owned(),
+                "refs/heads/upstream/nonexistent".to_owned(),
+                "refs/remotes/upstream/nonexistent".to_owned(),
+                "refs/remotes/upstream/nonexistent/HEAD".to_owned(),
+                "refs/remotes/mirror/nonexistent".to_owned(),
+            ],
+        }],
+    );
+    Ok(())
+}
@@ -108,3 +108,41 @@ fn test_merged_non_upstream() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn test_merged_non_upstream_of_mirror() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git config core.bare true
+        EOF
+        local <<EOF
+            git checkout master
+            git merge feature
+            git branch -D feature
+            git push origin master
+            git config remote.origin.mirror true
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            delete: DeleteFilter::from_iter(vec![DeleteRange::Remote(Scope::All)]),
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(plan.to_delete, set! {});
+
+    let plan = get_trim_plan(&git, &param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedNonUpstreamRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+        },
+    );
+    Ok(())
+}
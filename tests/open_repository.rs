@@ -0,0 +1,110 @@
+mod fixture;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use git2::Repository;
+
+use fixture::{git_trim, rc, Fixture, FixtureGuard};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+            git checkout master
+        EOF
+        origin <<EOF
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )
+}
+
+/// The directory that has `origin` and `local`, which is not a repository itself.
+fn unrelated_directory(guard: &FixtureGuard) -> PathBuf {
+    guard.working_directory().parent().unwrap().to_owned()
+}
+
+fn trim_from_unrelated_directory(guard: &FixtureGuard, options: &[&str]) -> Result<bool> {
+    let mut args = options.to_vec();
+    args.extend(["--bases", "master", "--yes"]);
+    let output = git_trim(&unrelated_directory(guard), &args)?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let repo = Repository::open(guard.working_directory())?;
+    let trimmed = repo.find_reference("refs/heads/feature").is_err();
+    Ok(trimmed)
+}
+
+#[test]
+fn test_not_a_repository() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let output = git_trim(
+        &unrelated_directory(&guard),
+        &["--bases", "master", "--yes"],
+    )?;
+    assert!(!output.status.success());
+
+    let repo = Repository::open(guard.working_directory())?;
+    assert!(repo.find_reference("refs/heads/feature").is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_directory() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    assert!(trim_from_unrelated_directory(&guard, &["-C", "local"])?);
+    Ok(())
+}
+
+#[test]
+fn test_git_dir() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    assert!(trim_from_unrelated_directory(
+        &guard,
+        &["--git-dir", "local/.git"]
+    )?);
+    Ok(())
+}
+
+#[test]
+fn test_git_dir_and_work_tree() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    assert!(trim_from_unrelated_directory(
+        &guard,
+        &["--git-dir", "local/.git", "--work-tree", "local"]
+    )?);
+    Ok(())
+}
+
+/// `--git-dir` and `--work-tree` are relative to `-C`.
+#[test]
+fn test_directory_with_git_dir_and_work_tree() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    assert!(trim_from_unrelated_directory(
+        &guard,
+        &["-C", "local", "--git-dir", ".git", "--work-tree", "."]
+    )?);
+    Ok(())
+}
@@ -8,7 +8,7 @@ use git2::Repository;
 
 use git_trim::args::{DeleteFilter, DeleteRange, Scope};
 
-use git_trim::{get_trim_plan, Git, PlanParam};
+use git_trim::{get_trim_plan, ClassifiedBranch, Git, LocalBranch, PlanParam};
 
 use fixture::{rc, test_default_param, Fixture};
 
@@ -68,3 +68,71 @@ fn test_bases_implicit_value() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_unrelated_is_kept() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout --orphan gh-pages
+            touch index.html
+            git add index.html
+            git commit -m "Publish"
+            git push -u origin gh-pages
+            git checkout master
+        EOF
+    "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+
+    assert_eq!(plan.to_delete, set! {});
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch == ClassifiedBranch::Unrelated(LocalBranch::new("refs/heads/gh-pages"))
+            && preserved.reason == "delete range `unrelated` was not given"
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_delete_unrelated() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout --orphan gh-pages
+            touch index.html
+            git add index.html
+            git commit -m "Publish"
+            git push -u origin gh-pages
+
+            git checkout --orphan docs
+            git commit -m "Docs"
+
+            git checkout -b feature master
+            git commit --allow-empty -m "Not merged"
+            git checkout master
+        EOF
+    "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            delete: DeleteFilter::from_iter(vec![DeleteRange::Unrelated]),
+            ..test_default_param()
+        },
+    )?;
+
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::Unrelated(LocalBranch::new("refs/heads/gh-pages")),
+            ClassifiedBranch::Unrelated(LocalBranch::new("refs/heads/docs")),
+        },
+    );
+    Ok(())
+}
@@ -0,0 +1,100 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::{
+    get_trim_plan, ClassifiedBranch, Git, LocalBranch, PlanParam, RemoteTrackingBranch,
+};
+
+use fixture::{rc, test_default_param, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+        EOF
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+            git checkout -b release/1.0
+            git push -u origin release/1.0
+            git checkout master
+        EOF
+        origin <<EOF
+            git merge feature
+        EOF
+        # A fake `gh` that answers which branches are protected
+        mkdir bin
+        cat > bin/gh <<'EOF'
+        #!/bin/sh
+        case "$*" in
+            *repos/myorg/myrepo/branches*) echo 'release/*' ;;
+            *) exit 1 ;;
+        esac
+        EOF
+        chmod +x bin/gh
+        "#,
+    )
+}
+
+// It is the only test in this file, since it changes `PATH` of the process.
+#[test]
+fn test_protected_on_github() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let bin = guard.working_directory().parent().unwrap().join("bin");
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut paths = vec![bin];
+    paths.extend(std::env::split_paths(&path));
+    std::env::set_var("PATH", std::env::join_paths(paths)?);
+
+    // Set after the fixture, since it updates all remotes.
+    let repo = Repository::open(guard.working_directory())?;
+    repo.remote_set_url("origin", "https://github.com/myorg/myrepo.git")?;
+    let git = Git::try_from(repo)?;
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            detect_remote_protection: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/feature"
+            )),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/release/1.0")),
+        },
+    );
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch
+            == ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/release/1.0",
+            ))
+            && preserved.reason == "protected on remote"
+    }));
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use git_trim::Signer;
+
+const PGP_FINGERPRINT: &str = "A0A1A2A3A4A5A6A7A8A9AAABACADAEAFB0B1B2B3";
+
+/// A v4 signature packet with an issuer fingerprint in the hashed subpackets,
+/// and an issuer key ID in the unhashed subpackets.
+const PGP_SIGNATURE: &str = "-----BEGIN PGP SIGNATURE-----
+Comment: test
+
+wi4EAAEIABcWIQSgoaKjpKWmp6ipqqusra6vsLGyswAKCRCsra6vsLGysxI0AAj/
+=abcd
+-----END PGP SIGNATURE-----
+";
+
+const SSH_PUBLIC_KEY: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f mine";
+
+/// A `SSHSIG` blob of the public key above. The signature itself is omitted.
+const SSH_SIGNATURE: &str = "-----BEGIN SSH SIGNATURE-----
+U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgAAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8AAAADZ2l0
+-----END SSH SIGNATURE-----
+";
+
+#[test]
+fn test_openpgp_signer() {
+    let signer = Signer::parse(PGP_SIGNATURE);
+    assert_eq!(
+        signer,
+        Signer::OpenPgp(vec![
+            PGP_FINGERPRINT.to_owned(),
+            "ACADAEAFB0B1B2B3".to_owned()
+        ]),
+    );
+    assert!(signer.is_signed_by(PGP_FINGERPRINT));
+    assert!(signer.is_signed_by("0xacadaeafb0b1b2b3"));
+    assert!(signer.is_signed_by("B0B1B2B3"));
+    assert!(!signer.is_signed_by("0xDEADBEEF"));
+    // Too short to identify a key
+    assert!(!signer.is_signed_by("B2B3"));
+    assert!(!signer.is_signed_by(SSH_PUBLIC_KEY));
+}
+
+#[test]
+fn test_ssh_signer() {
+    let signer = Signer::parse(SSH_SIGNATURE);
+    assert!(matches!(signer, Signer::Ssh(_)));
+    assert!(signer.is_signed_by(SSH_PUBLIC_KEY));
+    assert!(signer.is_signed_by(&format!("key::{}", SSH_PUBLIC_KEY)));
+    assert!(!signer.is_signed_by(
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIP//////////////////////////////////////////"
+    ));
+    assert!(!signer.is_signed_by(PGP_FINGERPRINT));
+}
+
+#[test]
+fn test_unsigned() {
+    let signer = Signer::parse("");
+    assert_eq!(signer, Signer::Unknown);
+    assert!(!signer.is_signed_by(PGP_FINGERPRINT));
+    assert!(!signer.is_signed_by(SSH_PUBLIC_KEY));
+
+    let x509 = "-----BEGIN SIGNED MESSAGE-----\nMIAGCSqGSIb3DQEHAqCAMIACAQEx\n-----END SIGNED MESSAGE-----\n";
+    assert_eq!(Signer::parse(x509), Signer::Unknown);
+}
+
+#[test]
+fn test_malformed_signatures() {
+    for signature in [
+        // Truncated in the middle of the hashed subpackets
+        "-----BEGIN PGP SIGNATURE-----\n\nwi4EAAEIABcWIQSgoaKjpKWmp6g=\n-----END PGP SIGNATURE-----\n",
+        // Not base64
+        "-----BEGIN PGP SIGNATURE-----\n\n!!!!\n-----END PGP SIGNATURE-----\n",
+        // Not a signature packet
+        "-----BEGIN PGP SIGNATURE-----\n\nxgQE\n-----END PGP SIGNATURE-----\n",
+        // No end line, and an empty body
+        "-----BEGIN PGP SIGNATURE-----\n",
+        // The public key is longer than the blob
+        "-----BEGIN SSH SIGNATURE-----\nU1NIU0lHAAAAAQAAAP8AAAAL\n-----END SSH SIGNATURE-----\n",
+        // Not a SSHSIG blob
+        "-----BEGIN SSH SIGNATURE-----\nAAAA\n-----END SSH SIGNATURE-----\n",
+    ] {
+        let signer = Signer::parse(signature);
+        assert_eq!(signer, Signer::Unknown, "{}", signature);
+        assert!(!signer.is_signed_by(PGP_FINGERPRINT));
+    }
+}
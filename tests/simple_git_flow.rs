@@ -6,7 +6,7 @@ use anyhow::Result;
 use git2::Repository;
 
 use git_trim::{
-    get_trim_plan, ClassifiedBranch, Git, LocalBranch, PlanParam, RemoteTrackingBranch,
+    get_trim_plan, ClassifiedBranch, Error, Git, LocalBranch, PlanParam, RemoteTrackingBranch,
 };
 
 use fixture::{rc, test_default_param, Fixture};
@@ -217,6 +217,47 @@ fn test_hotfix_to_master() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_classify_once_against_all_bases() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        # prepare awesome patch
+        local <<EOF
+            git checkout master
+            git checkout -b hotfix
+            touch hotfix
+            git add hotfix
+            git commit -m "Hotfix"
+            git push -u origin hotfix
+        EOF
+
+        origin <<EOF
+            git checkout master
+            git merge hotfix
+            git branch -D hotfix
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &param())?;
+
+    let hotfix: Vec<_> = plan
+        .classifications
+        .iter()
+        .filter(|classification| {
+            classification
+                .merge_states
+                .iter()
+                .any(|state| state.branch == "refs/heads/hotfix")
+        })
+        .collect();
+    assert_eq!(hotfix.len(), 1);
+    assert_eq!(hotfix[0].base.refname, "refs/remotes/origin/master");
+    Ok(())
+}
+
 #[test]
 fn test_hotfix_to_master_forgot_to_delete() -> Result<()> {
     let guard = fixture().prepare(
@@ -350,6 +391,150 @@ fn test_protected_feature_to_develop() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_protected_by_regex() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b release-1.0
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin release-1.0
+            git checkout -b release-1.0-rc
+            git push -u origin release-1.0-rc
+        EOF
+
+        origin <<EOF
+            git checkout develop
+            git merge release-1.0-rc
+            git branch -d release-1.0
+            git branch -d release-1.0-rc
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            protected_patterns: vec![r"re:release-\d+\.\d+"],
+            ..param()
+        },
+    )?;
+
+    // The whole name should match.
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/release-1.0-rc")),
+        },
+    );
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch
+            == ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/release-1.0"))
+            && preserved.reason == r"protected by a pattern `re:release-\d+\.\d+`"
+    }));
+
+    let result = get_trim_plan(
+        &git,
+        &PlanParam {
+            protected_patterns: vec![r"re:release-(\d+"],
+            ..param()
+        },
+    );
+    assert!(matches!(result, Err(Error::Config(_))));
+    Ok(())
+}
+
+#[test]
+fn test_protected_by_wildmatch() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature/nested/deep
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature/nested/deep
+            git checkout -b release-1
+            git push -u origin release-1
+            git checkout -b release-x
+            git push -u origin release-x
+        EOF
+
+        origin <<EOF
+            git checkout develop
+            git merge release-x
+            git branch -d feature/nested/deep
+            git branch -d release-1
+            git branch -d release-x
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            protected_patterns: vec!["refs/heads/feature/**", "release-[0-9]*"],
+            ..param()
+        },
+    )?;
+
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/release-x")),
+        },
+    );
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch
+            == ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature/nested/deep"))
+            && preserved.reason == "protected by a pattern `refs/heads/feature/**`"
+    }));
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch == ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/release-1"))
+            && preserved.reason == "protected by a pattern `release-[0-9]*`"
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_trim_ignore() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+            git checkout -b hotfix
+            git push -u origin hotfix
+            printf '# kept until the release\nfeature\n\n' > .git/trim-ignore
+            echo 'hot*' > .trim-ignore
+        EOF
+
+        origin <<EOF
+            git checkout develop
+            git merge feature
+            git branch -d feature
+            git branch -d hotfix
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &param())?;
+
+    assert_eq!(plan.to_delete, set! {});
+    Ok(())
+}
+
 #[test]
 fn test_protected_feature_to_master() -> Result<()> {
     let guard = fixture().prepare(
@@ -442,3 +627,54 @@ fn test_protected_branch_shouldnt_be_stray() -> Result<()> {
     assert_eq!(plan.to_delete, set! {});
     Ok(())
 }
+
+#[test]
+fn test_fix_to_release_glob_base() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git branch release/1 master
+            git branch release/2 master
+        EOF
+        local <<EOF
+            git fetch origin
+            git checkout -b fix origin/release/2
+            touch fix
+            git add fix
+            git commit -m "Fix"
+            git push -u origin fix
+        EOF
+
+        origin <<EOF
+            git checkout release/2
+            git merge fix
+            git branch -D fix
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/fix")),
+        },
+    );
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            bases: vec!["develop", "master", "release/*"],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/fix")),
+        },
+    );
+    Ok(())
+}
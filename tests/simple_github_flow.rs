@@ -1,13 +1,35 @@
 mod fixture;
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::iter::FromIterator;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use git2::Repository;
 
-use git_trim::{get_trim_plan, ClassifiedBranch, Git, LocalBranch, RemoteTrackingBranch};
+use git_trim::args::{DeleteFilter, DeleteRange, ScanTarget, Scope, SortKey};
+use git_trim::config;
+use git_trim::report::{
+    Classification, ExplanationReport, OutdatedReport, TimingReport, TrimReport,
+};
+use git_trim::trim_log::{self, TrimStats};
+use git_trim::{
+    check_no_operation_in_progress, delete_local_branches, delete_local_branches_detaching_to,
+    delete_orphan_namespaces, delete_remote_branches, delete_remote_branches_paced,
+    detect_remote_heads, fast_forward_bases, fast_forward_to_upstream, gc_after_deletion,
+    get_branch_tips, get_delete_commands, get_orphan_namespace_delete_commands, get_trim_plan,
+    get_trim_plan_of_branches, get_trim_plan_with_progress, keep_pairs_together,
+    record_reclaimable_bytes, restore_local_branch, restore_remote_branch,
+    retarget_renamed_upstreams, retarget_stacked_upstreams, set_remote_head, skip_moved_branches,
+    skip_pairs_of_failed_remotes, unset_upstreams, BranchPair, BranchRole, BrokenUpstream,
+    ClassifiedBranch, DeletePacing, Error, Git, LocalBranch, MergeStrategy, MovedBranch,
+    OrphanNamespace, PlanParam, PlannedBranch, Progress, RejectionKind, RemoteBranch,
+    RemoteOnlyBranch, RemoteTrackingBranch, RenamedUpstream,
+};
 
-use fixture::{rc, test_default_param, Fixture};
+use fixture::{git_trim, rc, test_default_param, Fixture};
 
 fn fixture() -> Fixture {
     rc().append_fixture_trace(
@@ -63,6 +85,364 @@ fn test_accepted() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_offline() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            scan: vec![ScanTarget::RemoteOnly("origin".to_owned())],
+            offline: true,
+            ..test_default_param()
+        },
+    )?;
+    let merged_local = ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature"));
+    let merged_remote = ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+        "refs/remotes/origin/feature",
+    ));
+    assert_eq!(
+        plan.to_delete,
+        set! { merged_local.clone(), merged_remote.clone() },
+    );
+    assert!(!plan.possibly_stale(&merged_local));
+    assert!(plan.possibly_stale(&merged_remote));
+    assert_eq!(plan.unreachable_remotes, vec!["origin".to_owned()]);
+
+    let report = TrimReport::new(&git.repo, &plan)?;
+    assert!(report.offline);
+    assert_eq!(
+        report
+            .to_delete
+            .iter()
+            .map(|branch| (branch.classification, branch.possibly_stale))
+            .collect::<Vec<_>>(),
+        vec![
+            (Classification::MergedRemoteTracking, true),
+            (Classification::MergedLocal, false),
+        ],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_include_slash_prefixes() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b users/me/patch master
+            touch my-patch
+            git add my-patch
+            git commit -m "My patch"
+            git push -u origin users/me/patch
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git merge users/me/patch
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            include_slash_prefixes: vec!["users/me"],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/users/me/patch")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/users/me/patch")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_scan_remote_only() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config --replace-all remote.origin.fetch "+refs/heads/master:refs/remotes/origin/master"
+        EOF
+        origin <<EOF
+            git checkout -b merged master
+            touch merged-patch
+            git add merged-patch
+            git commit -m "Merged patch"
+            git checkout master
+            git merge --no-ff merged
+
+            git checkout -b unmerged master
+            touch unmerged-patch
+            git add unmerged-patch
+            git commit -m "Unmerged patch"
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    let merged = origin.refname_to_id("refs/heads/merged")?;
+    let unmerged = origin.refname_to_id("refs/heads/unmerged")?;
+    assert!(git.repo.find_commit(unmerged).is_err());
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            scan: vec![ScanTarget::RemoteOnly("origin".to_owned())],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedRemoteOnly(RemoteOnlyBranch {
+                remote: RemoteBranch {
+                    remote: "origin".to_owned(),
+                    refname: "refs/heads/merged".to_owned(),
+                },
+                commit: merged.to_string(),
+            }),
+        },
+    );
+    // Only objects are fetched.
+    assert!(git.repo.find_commit(unmerged).is_ok());
+    assert!(git
+        .repo
+        .find_reference("refs/remotes/origin/unmerged")
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_scan_remote_only_with_default_refspec() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    // It is pushed after the last `git remote update`, so it is remote-only.
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    let master = origin.head()?.peel_to_commit()?;
+    let signature = git2::Signature::now("Origin Test", "origin@test")?;
+    let unmerged = origin.commit(
+        Some("refs/heads/unmerged"),
+        &signature,
+        &signature,
+        "Unmerged patch",
+        &master.tree()?,
+        &[&master],
+    )?;
+    assert!(git.repo.find_commit(unmerged).is_err());
+    let refnames = |repo: &Repository| -> Result<Vec<String>> {
+        let mut result = Vec::new();
+        for reference in repo.references()? {
+            result.extend(reference?.name().map(str::to_owned));
+        }
+        Ok(result)
+    };
+    let before = refnames(&git.repo)?;
+
+    get_trim_plan(
+        &git,
+        &PlanParam {
+            scan: vec![ScanTarget::RemoteOnly("origin".to_owned())],
+            ..test_default_param()
+        },
+    )?;
+    // Only objects are fetched, even though the fetch refspec covers the branch.
+    assert!(git.repo.find_commit(unmerged).is_ok());
+    assert_eq!(refnames(&git.repo)?, before);
+    Ok(())
+}
+
+#[test]
+fn test_unresponsive_remote_times_out() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    // Added after the fixture, since it updates all remotes.
+    let repo = Repository::open(guard.working_directory())?;
+    repo.config()?.set_str("protocol.ext.allow", "always")?;
+    repo.remote("hang", "ext::sleep 30")?;
+    let git = Git::try_from(repo)?;
+    let started = std::time::Instant::now();
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            scan: vec![ScanTarget::RemoteOnly("hang".to_owned())],
+            remote_timeout: 1,
+            ..test_default_param()
+        },
+    )?;
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert_eq!(plan.unreachable_remotes, vec!["hang".to_owned()]);
+    assert_eq!(plan.to_delete, set! {});
+    Ok(())
+}
+
+#[test]
+fn test_detect_missing_remote_head() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git remote set-head origin --delete
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let heads = detect_remote_heads(&git, Duration::from_secs(0))?;
+    assert_eq!(
+        heads,
+        vec![(
+            RemoteBranch {
+                remote: "origin".to_owned(),
+                refname: "refs/heads/master".to_owned(),
+            },
+            RemoteTrackingBranch::new("refs/remotes/origin/master"),
+        )]
+    );
+    let bases = config::get_bases_tracking(
+        &git.repo,
+        &git.config,
+        &["refs/remotes/origin/master".to_owned()],
+    )?;
+    assert_eq!(bases, vec!["master".to_owned()]);
+
+    set_remote_head(&git.repo, "origin", &heads[0].1, false)?;
+    let head = git.repo.find_reference("refs/remotes/origin/HEAD")?;
+    assert_eq!(head.symbolic_target(), Some("refs/remotes/origin/master"));
+    assert!(detect_remote_heads(&git, Duration::from_secs(0))?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_pure_local_base() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b integration master
+            git merge feature --no-ff
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            bases: vec!["master", "integration"],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/feature"
+            )),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_plan_of_given_branches() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b tmp/other master
+            touch other-patch
+            git add other-patch
+            git commit -m "Other patch"
+            git push -u origin tmp/other
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git merge tmp/other
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan_of_branches(&git, &test_default_param(), &["refs/heads/tmp/other"])?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/tmp/other")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/tmp/other"
+            )),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_iter_classified() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let mut to_delete: Vec<_> = plan
+        .iter_classified()
+        .filter(|planned| planned.preserved.is_none())
+        .collect();
+    to_delete.sort_by(|a, b| a.refname.cmp(&b.refname));
+    assert_eq!(
+        to_delete,
+        vec![
+            PlannedBranch {
+                refname: "refs/heads/feature".to_owned(),
+                remote: None,
+                role: BranchRole::Local,
+                classification: Classification::MergedLocal,
+                preserved: None,
+            },
+            PlannedBranch {
+                refname: "refs/remotes/origin/feature".to_owned(),
+                remote: None,
+                role: BranchRole::Remote,
+                classification: Classification::MergedRemoteTracking,
+                preserved: None,
+            },
+        ]
+    );
+    assert!(plan.iter_classified().any(|planned| {
+        planned.refname == "refs/heads/master" && planned.preserved.as_deref() == Some("base")
+    }));
+    Ok(())
+}
+
 #[test]
 fn test_accepted_but_edited() -> Result<()> {
     let guard = fixture().prepare(
@@ -88,83 +468,114 @@ fn test_accepted_but_edited() -> Result<()> {
             ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
         },
     );
+    assert!(plan.gone_upstreams.is_empty());
     Ok(())
 }
 
 #[test]
-fn test_accepted_but_forgot_to_delete() -> Result<()> {
+fn test_accepted_but_edited_recently() -> Result<()> {
     let guard = fixture().prepare(
         "local",
         r#"
         origin <<EOF
             git checkout master
             git merge feature
+            git branch -d feature
+        EOF
+        local <<EOF
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
         EOF
         "#,
     )?;
     let git = Git::try_from(Repository::open(guard.working_directory())?)?;
-    let plan = get_trim_plan(&git, &test_default_param())?;
-    assert_eq!(
-        plan.to_delete,
-        set! {
-            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
-            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            stray_grace_days: 7,
+            ..test_default_param()
         },
+    )?;
+    assert_eq!(plan.to_delete, set! {});
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch == ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature"))
+            && preserved.reason == "stray but recent"
+    }));
+    assert_eq!(
+        plan.gone_upstreams,
+        vec![LocalBranch::new("refs/heads/feature")]
     );
     Ok(())
 }
 
 #[test]
-fn test_accepted_but_forgot_to_delete_and_edited() -> Result<()> {
+fn test_accepted_but_edited_long_ago() -> Result<()> {
     let guard = fixture().prepare(
         "local",
         r#"
         origin <<EOF
             git checkout master
             git merge feature
+            git branch -d feature
         EOF
         local <<EOF
             touch another-patch
             git add another-patch
-            git commit -m "Another patch"
+            GIT_COMMITTER_DATE="2000-01-01T00:00:00" git commit -m "Another patch"
         EOF
         "#,
     )?;
     let git = Git::try_from(Repository::open(guard.working_directory())?)?;
-    let plan = get_trim_plan(&git, &test_default_param())?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            stray_grace_days: 7,
+            ..test_default_param()
+        },
+    )?;
     assert_eq!(
         plan.to_delete,
         set! {
             ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
-            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
         },
     );
     Ok(())
 }
 
 #[test]
-fn test_rejected() -> Result<()> {
+fn test_accepted_into_tag() -> Result<()> {
     let guard = fixture().prepare(
         "local",
         r#"
         origin <<EOF
+            git tag v1 feature
             git branch -D feature
         EOF
-    "#,
+        local <<EOF
+            git fetch --tags
+        EOF
+        "#,
     )?;
     let git = Git::try_from(Repository::open(guard.working_directory())?)?;
-    let plan = get_trim_plan(&git, &test_default_param())?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            bases: vec!["master", "v1"],
+            ..test_default_param()
+        },
+    )?;
     assert_eq!(
         plan.to_delete,
         set! {
-            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
         },
     );
     Ok(())
 }
 
 #[test]
-fn test_rejected_but_edited() -> Result<()> {
+fn test_accepted_into_base_commit() -> Result<()> {
     let guard = fixture().prepare(
         "local",
         r#"
@@ -172,46 +583,2048 @@ fn test_rejected_but_edited() -> Result<()> {
             git branch -D feature
         EOF
         local <<EOF
-            touch another-patch
-            git add another-patch
-            git commit -m "Another patch"
+            git checkout -b release master
+            git merge --no-ff feature -m "Merge feature"
+            git tag v1.0
+            git commit --allow-empty -m "Bump version"
+            git checkout master
+            git branch -D release
         EOF
-    "#,
+        "#,
     )?;
     let git = Git::try_from(Repository::open(guard.working_directory())?)?;
-    let plan = get_trim_plan(&git, &test_default_param())?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            base_commits: vec!["v1.0"],
+            ..test_default_param()
+        },
+    )?;
     assert_eq!(
         plan.to_delete,
         set! {
-            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
         },
     );
     Ok(())
 }
 
 #[test]
-fn test_rejected_but_forgot_to_delete() -> Result<()> {
-    let guard = fixture().prepare("local", r#""#)?;
+fn test_accepted_but_forgot_to_delete() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
     let git = Git::try_from(Repository::open(guard.working_directory())?)?;
     let plan = get_trim_plan(&git, &test_default_param())?;
-    assert_eq!(plan.to_delete, set! {});
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+        },
+    );
     Ok(())
 }
 
 #[test]
-fn test_rejected_but_forgot_to_delete_and_edited() -> Result<()> {
+fn test_accepted_but_forgot_to_delete_recently_checked_out() -> Result<()> {
     let guard = fixture().prepare(
         "local",
         r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
         local <<EOF
-            touch another-patch
-            git add another-patch
-            git commit -m "Another patch"
+            git checkout master
         EOF
-    "#,
+        "#,
     )?;
     let git = Git::try_from(Repository::open(guard.working_directory())?)?;
-    let plan = get_trim_plan(&git, &test_default_param())?;
-    assert_eq!(plan.to_delete, set! {});
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            recent_checkout_days: 1,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+        },
+    );
+    assert_eq!(
+        plan.preserved
+            .iter()
+            .filter(|preserved| !preserved.base)
+            .map(|preserved| &preserved.branch)
+            .collect::<Vec<_>>(),
+        vec![&ClassifiedBranch::MergedLocal(LocalBranch::new(
+            "refs/heads/feature"
+        ))],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_url_pattern_scope() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let param = |pattern: &str| PlanParam {
+        delete: DeleteFilter::from_iter(vec![
+            DeleteRange::MergedLocal,
+            DeleteRange::MergedRemote(Scope::UrlPattern(pattern.to_owned())),
+        ]),
+        ..test_default_param()
+    };
+
+    let plan = get_trim_plan(&git, &param("*/origin"))?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+        },
+    );
+
+    let plan = get_trim_plan(&git, &param("github.com/*"))?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_report() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let report = TrimReport::new(&git.repo, &plan)?;
+    let feature = git.repo.refname_to_id("refs/heads/feature")?.to_string();
+    assert_eq!(
+        report
+            .to_delete
+            .iter()
+            .map(|branch| branch.classification)
+            .collect::<Vec<_>>(),
+        vec![
+            Classification::MergedRemoteTracking,
+            Classification::MergedLocal
+        ],
+    );
+    let remote = report.to_delete[0].remote.as_ref().unwrap();
+    assert_eq!(remote.remote, "origin");
+    assert_eq!(remote.refname, "refs/heads/feature");
+    assert_eq!(remote.oid.as_ref(), Some(&feature));
+    let local = report.to_delete[1].local.as_ref().unwrap();
+    assert_eq!(local.refname, "refs/heads/feature");
+    assert_eq!(local.oid.as_ref(), Some(&feature));
+    assert_eq!(
+        report.to_delete[1].explanations,
+        vec![ExplanationReport {
+            base: "refs/remotes/origin/master".to_owned(),
+            message: "local & upstream are merged".to_owned(),
+        }],
+    );
+
+    let json = serde_json::to_string(&report)?;
+    assert_eq!(serde_json::from_str::<TrimReport>(&json)?, report);
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_github_actions() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let report = TrimReport::new(&git.repo, &plan)?;
+    assert_eq!(
+        report.github_actions_annotations(),
+        vec![
+            "::notice title=git-trim::2 branches can be trimmed",
+            "::warning title=Deletable branch::origin/feature is merged",
+            "::warning title=Deletable branch::feature is merged",
+        ],
+    );
+
+    let last_commit = report.to_delete[1].last_commit.as_ref().unwrap();
+    assert_eq!(last_commit.author, "Local Test");
+    let three_days_later = last_commit.time + 3 * 24 * 60 * 60;
+    assert_eq!(
+        report.markdown_table(three_days_later),
+        "### 2 branches can be trimmed\n\n\
+         | Branch | Classification | Merged into | Last commit | Author | Why |\n\
+         | --- | --- | --- | --- | --- | --- |\n\
+         | `origin/feature` | merged-remote-tracking | origin/master | 3 days ago | Local Test | merged |\n\
+         | `feature` | merged-local | origin/master | 3 days ago | Local Test | merged |\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn test_plan_cache() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let param = PlanParam {
+        cache: true,
+        ..test_default_param()
+    };
+    let plan = get_trim_plan(&git, &param)?;
+    assert!(!plan.cached);
+    let cached = get_trim_plan(&git, &param)?;
+    assert!(cached.cached);
+    assert_eq!(cached.to_delete, plan.to_delete);
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            cache: false,
+            ..test_default_param()
+        },
+    )?;
+    assert!(!plan.cached);
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            cache: true,
+            merge_strategies: vec![MergeStrategy::Ancestor],
+            ..test_default_param()
+        },
+    )?;
+    assert!(!plan.cached);
+
+    // Any change of refs misses the cache.
+    let head = git.repo.head()?.peel_to_commit()?;
+    git.repo.branch("another", &head, false)?;
+    let plan = get_trim_plan(&git, &param)?;
+    assert!(!plan.cached);
+    assert!(get_trim_plan(&git, &param)?.cached);
+    Ok(())
+}
+
+#[test]
+fn test_timings() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.timings
+            .phases
+            .iter()
+            .map(|(phase, _)| *phase)
+            .collect::<Vec<_>>(),
+        vec![
+            "collection",
+            "remote heads",
+            "merge tracker",
+            "classification",
+            "filter passes"
+        ],
+    );
+
+    let mut report = TrimReport::new(&git.repo, &plan)?;
+    assert!(!serde_json::to_string(&report)?.contains("timings"));
+    report.timings = plan.timings.phases.iter().map(TimingReport::from).collect();
+    let json = serde_json::to_string(&report)?;
+    assert_eq!(serde_json::from_str::<TrimReport>(&json)?, report);
+    Ok(())
+}
+
+#[test]
+fn test_report_branches_to_delete() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let report = TrimReport::new(&git.repo, &plan)?;
+    let (locals, remotes) = report.branches_to_delete(&git.repo)?;
+    assert_eq!(locals, vec![LocalBranch::new("refs/heads/feature")]);
+    assert_eq!(
+        remotes,
+        vec![RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        }],
+    );
+
+    let parent = git.repo.head()?.peel_to_commit()?.parent_id(0)?;
+    git.repo
+        .reference("refs/heads/feature", parent, true, "reset")?;
+    let err = report.branches_to_delete(&git.repo).unwrap_err();
+    let outdated = err.downcast_ref::<OutdatedReport>().unwrap();
+    assert_eq!(outdated.0, vec!["refs/heads/feature".to_owned()]);
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_progress() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let classified = Mutex::new(Vec::new());
+    get_trim_plan_with_progress(&git, &test_default_param(), &|progress| {
+        if let Progress::Classify { done, total } = progress {
+            classified.lock().unwrap().push((done, total));
+        }
+    })?;
+    let mut classified = classified.into_inner().unwrap();
+    classified.sort();
+    assert_eq!(classified, vec![(1, 2), (2, 2)]);
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_commands() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let commands = get_delete_commands(
+        &git.repo,
+        &plan.locals_to_delete(),
+        &plan.remotes_to_delete(&git.repo)?,
+        None,
+        0,
+    )?;
+    assert_eq!(
+        commands,
+        vec![
+            vec!["push", "--delete", "origin", "refs/heads/feature"],
+            vec!["checkout", "refs/heads/feature"],
+            vec!["branch", "--delete", "--force", "feature"],
+        ],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_detach_to() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -d feature
+        EOF
+        local <<EOF
+            git fetch --prune
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let feature = LocalBranch::new("refs/heads/feature");
+    let commands = get_delete_commands(&git.repo, &[&feature], &[], Some("master"), 0)?;
+    assert_eq!(
+        commands,
+        vec![
+            vec!["checkout", "master"],
+            vec!["branch", "--delete", "--force", "feature"],
+        ],
+    );
+    let commands = get_delete_commands(&git.repo, &[&feature], &[], Some("origin/master"), 0)?;
+    assert_eq!(commands[0], vec!["checkout", "--detach", "origin/master"]);
+
+    let failures =
+        delete_local_branches_detaching_to(&git.repo, &[&feature], false, Some("master"), false)?;
+    assert!(failures.is_empty());
+    assert_eq!(git.repo.head()?.name(), Some("refs/heads/master"));
+    Ok(())
+}
+
+#[test]
+fn test_fast_forward_to_upstream() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git checkout master
+            git fetch
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    fast_forward_to_upstream(&git.repo, false)?;
+    assert_eq!(
+        git.repo.refname_to_id("refs/heads/master")?,
+        git.repo.refname_to_id("refs/remotes/origin/master")?,
+    );
+    Ok(())
+}
+
+#[test]
+fn test_fast_forward_bases() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git fetch
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let before = git.repo.refname_to_id("refs/heads/master")?;
+    fast_forward_bases(&git.repo, &git.config, &["master"], true)?;
+    assert_eq!(git.repo.refname_to_id("refs/heads/master")?, before);
+
+    fast_forward_bases(&git.repo, &git.config, &["master"], false)?;
+    assert_eq!(
+        git.repo.refname_to_id("refs/heads/master")?,
+        git.repo.refname_to_id("refs/remotes/origin/master")?,
+    );
+    Ok(())
+}
+
+#[test]
+fn test_delete_local_branches_in_chunks() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git branch --no-track feature-2 feature
+            git branch --no-track feature-3 feature
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let param = PlanParam {
+        delete: DeleteFilter::from_iter(vec![
+            DeleteRange::MergedLocal,
+            DeleteRange::MergedRemote(Scope::All),
+            DeleteRange::Local,
+        ]),
+        ..test_default_param()
+    };
+    let plan = get_trim_plan(&git, &param)?;
+    let locals = plan.locals_to_delete();
+    assert_eq!(locals.len(), 3);
+
+    let commands = get_delete_commands(&git.repo, &locals, &[], None, 2)?;
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].len() - 3 + commands[1].len() - 3, 3);
+
+    let failures = delete_local_branches(&git.repo, &locals, false, false)?;
+    assert!(failures.is_empty());
+    for local in locals {
+        assert!(git.repo.find_reference(&local.refname).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_delete_local_branches_removes_config() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout master
+            git config branch.feature.description "Awesome patch"
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let feature = LocalBranch::new("refs/heads/feature");
+    let failures = delete_local_branches(&git.repo, &[&feature], false, false)?;
+    assert!(failures.is_empty());
+    assert!(git.repo.find_reference(&feature.refname).is_err());
+
+    let config = git.repo.config()?.snapshot()?;
+    for key in ["remote", "merge", "description"] {
+        let key = format!("branch.feature.{}", key);
+        assert!(config.get_string(&key).is_err(), "{} is not removed", key);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_restore_deleted_local_branches() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout master
+            git config branch.feature.description "Awesome patch"
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let feature = LocalBranch::new("refs/heads/feature");
+    let oid = git.repo.refname_to_id(&feature.refname)?;
+    let failures = delete_local_branches(&git.repo, &[&feature], false, false)?;
+    assert!(failures.is_empty());
+
+    let records = trim_log::read(&git.repo)?;
+    assert_eq!(records.len(), 1);
+    let deleted = &records[0].locals;
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].refname, feature.refname);
+    assert_eq!(deleted[0].oid, oid.to_string());
+
+    restore_local_branch(&git.repo, &deleted[0], false)?;
+    assert_eq!(git.repo.refname_to_id(&feature.refname)?, oid);
+    let config = git.repo.config()?.snapshot()?;
+    assert_eq!(config.get_string("branch.feature.remote")?, "origin");
+    assert_eq!(
+        config.get_string("branch.feature.description")?,
+        "Awesome patch"
+    );
+
+    // It is fine to restore it again.
+    restore_local_branch(&git.repo, &deleted[0], false)?;
+    assert_eq!(trim_log::pop(&git.repo)?.as_ref(), records.last());
+    assert!(trim_log::read(&git.repo)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_restore_deleted_remote_branches() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let feature = RemoteBranch {
+        remote: "origin".to_owned(),
+        refname: "refs/heads/feature".to_owned(),
+    };
+    let oid = git.repo.refname_to_id("refs/remotes/origin/feature")?;
+    let failures = delete_remote_branches(
+        &git.repo,
+        std::slice::from_ref(&feature),
+        &HashMap::new(),
+        false,
+    );
+    assert!(failures.is_empty());
+
+    let origin = Repository::open(guard.working_directory().parent().unwrap().join("origin"))?;
+    assert!(origin.refname_to_id(&feature.refname).is_err());
+
+    let records = trim_log::read(&git.repo)?;
+    assert_eq!(records.len(), 1);
+    let deleted = &records[0].remotes;
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].remote, feature.remote);
+    assert_eq!(deleted[0].refname, feature.refname);
+    assert_eq!(deleted[0].oid, oid.to_string());
+
+    restore_remote_branch(&git.repo, &deleted[0], false)?;
+    assert_eq!(origin.refname_to_id(&feature.refname)?, oid);
+    Ok(())
+}
+
+#[test]
+fn test_protect_head_of_remotes() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout -b next master
+            git merge feature
+        EOF
+        local <<EOF
+            git fetch origin
+            git checkout next
+            git checkout --detach
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let param = PlanParam {
+        bases: vec!["next"],
+        delete: DeleteFilter::from_iter(vec![
+            DeleteRange::MergedLocal,
+            DeleteRange::MergedRemote(Scope::All),
+        ]),
+        ..test_default_param()
+    };
+
+    let plan = get_trim_plan(&git, &param)?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/feature"
+            )),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    let heads: Vec<_> = plan
+        .preserved
+        .iter()
+        .filter(|preserved| preserved.reason == "HEAD of remote (`refs/remotes/origin/master`)")
+        .map(|preserved| preserved.branch.clone())
+        .collect();
+    assert_eq!(heads.len(), 2);
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            protect_head_of_remotes: false,
+            ..param
+        },
+    )?;
+    assert!(plan
+        .to_delete
+        .contains(&ClassifiedBranch::MergedRemoteTracking(
+            RemoteTrackingBranch::new("refs/remotes/origin/master")
+        )));
+    Ok(())
+}
+
+#[test]
+fn test_renamed_upstream() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git branch -m feature renamed-feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    assert_eq!(
+        plan.renamed_upstreams,
+        vec![RenamedUpstream {
+            local: LocalBranch::new("refs/heads/feature"),
+            upstream: RemoteTrackingBranch::new("refs/remotes/origin/renamed-feature"),
+            remote_branch: RemoteBranch {
+                remote: "origin".to_owned(),
+                refname: "refs/heads/renamed-feature".to_owned(),
+            },
+        }],
+    );
+
+    retarget_renamed_upstreams(&git.repo, &plan.renamed_upstreams, false)?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    assert!(plan.renamed_upstreams.is_empty());
+    assert_eq!(
+        git.config.get_string("branch.feature.merge")?,
+        "refs/heads/renamed-feature"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_trim_history() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b local-only
+            echo "Local only patch" > local-only-patch
+            git add local-only-patch
+            git commit -m "Local only patch"
+            git checkout master
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let local_only = LocalBranch::new("refs/heads/local-only");
+    let tips = get_branch_tips(&git.repo, &[&local_only], &[])?;
+    assert_eq!(tips.len(), 1);
+    let failures = delete_local_branches(&git.repo, &[&local_only], false, false)?;
+    assert!(failures.is_empty());
+    record_reclaimable_bytes(&git.repo, &tips)?;
+
+    let records = trim_log::read(&git.repo)?;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].locals.len(), 1);
+    assert_eq!(records[0].locals[0].refname, local_only.refname);
+    assert!(records[0].remotes.is_empty());
+    assert!(records[0].reclaimable_bytes.unwrap() > 0);
+
+    let stats = TrimStats::aggregate(&records, 0);
+    assert_eq!(stats.runs, 1);
+    assert_eq!(stats.locals, 1);
+    assert_eq!(stats.remotes, 0);
+    assert_eq!(
+        stats.reclaimable_bytes,
+        records[0].reclaimable_bytes.unwrap()
+    );
+    assert_eq!(
+        TrimStats::aggregate(&records, records[0].time + 1),
+        TrimStats::default()
+    );
+
+    // Undone runs are not counted.
+    trim_log::pop(&git.repo)?;
+    assert_eq!(
+        TrimStats::aggregate(&trim_log::read(&git.repo)?, 0),
+        TrimStats::default()
+    );
+    Ok(())
+}
+
+/// Days that overflow seconds count every run.
+#[test]
+fn test_stats_of_many_days() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b local-only
+            echo "Local only patch" > local-only-patch
+            git add local-only-patch
+            git commit -m "Local only patch"
+            git checkout master
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let local_only = LocalBranch::new("refs/heads/local-only");
+    let tips = get_branch_tips(&git.repo, &[&local_only], &[])?;
+    delete_local_branches(&git.repo, &[&local_only], false, false)?;
+    record_reclaimable_bytes(&git.repo, &tips)?;
+
+    let days = u64::MAX.to_string();
+    let output = git_trim(&guard.working_directory(), &["stats", "--days", &days])?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .starts_with("Deleted 1 branches (1 local, 0 remote) in 1 runs"));
+    Ok(())
+}
+
+#[test]
+fn test_gc_after_deletion() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    assert_eq!(gc_after_deletion(&git.repo, 10, 0, false)?, None);
+    assert_eq!(gc_after_deletion(&git.repo, 1, 1, false)?, None);
+
+    let (before, after) = gc_after_deletion(&git.repo, 2, 1, false)?.unwrap();
+    assert!(before > 0);
+    assert!(after > 0);
+    Ok(())
+}
+
+#[test]
+fn test_delete_local_branches_keeps_config() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout master
+            git config branch.feature.description "Awesome patch"
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let feature = LocalBranch::new("refs/heads/feature");
+    let failures = delete_local_branches(&git.repo, &[&feature], true, false)?;
+    assert!(failures.is_empty());
+    assert!(git.repo.find_reference(&feature.refname).is_err());
+
+    let config = git.repo.config()?.snapshot()?;
+    assert_eq!(config.get_string("branch.feature.remote")?, "origin");
+    assert_eq!(
+        config.get_string("branch.feature.merge")?,
+        "refs/heads/feature"
+    );
+    assert_eq!(
+        config.get_string("branch.feature.description")?,
+        "Awesome patch"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rejected_remote_deletion_does_not_block_others() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    // `origin` refuses to delete its current branch
+    let failures = delete_remote_branches(
+        &git.repo,
+        &[
+            RemoteBranch {
+                remote: "origin".to_owned(),
+                refname: "refs/heads/master".to_owned(),
+            },
+            RemoteBranch {
+                remote: "origin".to_owned(),
+                refname: "refs/heads/feature".to_owned(),
+            },
+        ],
+        &HashMap::new(),
+        false,
+    );
+    assert_eq!(failures.len(), 1);
+    assert_eq!(
+        failures[0].branches,
+        vec![RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/master".to_owned(),
+        }],
+    );
+    let rejection = match &failures[0].error {
+        Error::PushRejected(rejection) => rejection,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(rejection.refname, "refs/heads/master");
+    assert_eq!(rejection.kind, RejectionKind::CurrentBranch);
+
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    assert!(origin.find_reference("refs/heads/master").is_ok());
+    assert!(origin.find_reference("refs/heads/feature").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_keep_local_of_failed_remote_deletion() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+            cat > .git/hooks/pre-receive <<'HOOK'
+        #!/bin/sh
+        echo "feature is protected"
+        exit 1
+        HOOK
+            chmod +x .git/hooks/pre-receive
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let pair = BranchPair {
+        local: LocalBranch::new("refs/heads/feature"),
+        remote: RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        },
+    };
+    let pairs = plan.pairs_to_delete(&git.repo, &git.config)?;
+    assert_eq!(pairs, vec![pair.clone()]);
+
+    // Declining the remote branch keeps the local branch too.
+    let locals = plan.locals_to_delete();
+    let (kept_locals, kept_remotes, split) = keep_pairs_together(&pairs, &locals, &[]);
+    assert!(kept_locals.is_empty());
+    assert!(kept_remotes.is_empty());
+    assert_eq!(split, vec![pair.clone()]);
+
+    let remotes = plan.remotes_to_delete(&git.repo)?;
+    let remote_failures = delete_remote_branches(&git.repo, &remotes, &plan.tips, false);
+    assert_eq!(remote_failures.len(), 1);
+    let (locals, local_failures) = skip_pairs_of_failed_remotes(&pairs, &locals, &remote_failures);
+    assert!(locals.is_empty());
+    assert_eq!(local_failures.len(), 1);
+    assert_eq!(local_failures[0].branch, pair.local);
+    match &local_failures[0].error {
+        Error::PartiallyFailed { remote } => assert_eq!(remote, &pair.remote),
+        error => panic!("unexpected error: {}", error),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_skip_branches_moved_after_planned() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let planned = git.repo.refname_to_id("refs/heads/feature")?;
+    let initial = git.repo.refname_to_id("refs/heads/master")?;
+    assert_eq!(
+        plan.tips.get("refs/heads/feature"),
+        Some(&planned.to_string())
+    );
+
+    // New commits arrive while it is waiting for the confirmation.
+    git.repo
+        .reference("refs/heads/feature", initial, true, "moved")?;
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    origin.reference("refs/heads/feature", initial, true, "moved")?;
+
+    let locals = plan.locals_to_delete();
+    let remotes = plan.remotes_to_delete(&git.repo)?;
+    let (locals, remotes, moved) = skip_moved_branches(&git.repo, &plan.tips, &locals, &remotes)?;
+    assert!(locals.is_empty());
+    assert_eq!(
+        moved,
+        vec![MovedBranch {
+            refname: "refs/heads/feature".to_owned(),
+            planned: planned.to_string(),
+            current: Some(initial.to_string()),
+        }],
+    );
+
+    // The remote tracking branch is not updated yet, but the remote refuses to delete.
+    assert_eq!(
+        remotes,
+        vec![RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        }],
+    );
+    let failures = delete_remote_branches(&git.repo, &remotes, &plan.tips, false);
+    assert_eq!(failures.len(), 1);
+    assert!(matches!(failures[0].error, Error::PushRejected(_)));
+    assert_eq!(origin.refname_to_id("refs/heads/feature")?, initial);
+    Ok(())
+}
+
+#[test]
+fn test_base_resolution_error() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let param = PlanParam {
+        bases: vec!["nonexistent"],
+        ..test_default_param()
+    };
+    match get_trim_plan(&git, &param) {
+        Err(Error::BaseResolution { bases, tried }) => {
+            assert_eq!(bases, vec!["nonexistent"]);
+            assert!(tried.contains(&"refs/heads/nonexistent".to_owned()));
+            assert!(tried.contains(&"refs/remotes/nonexistent".to_owned()));
+        }
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_dry_run_previews_remote_rejections() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    let initial = git.repo.refname_to_id("refs/heads/master")?;
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    origin.reference("refs/heads/feature", initial, true, "moved")?;
+
+    let remotes = plan.remotes_to_delete(&git.repo)?;
+    let failures = delete_remote_branches(&git.repo, &remotes, &plan.tips, true);
+    assert_eq!(failures.len(), 1);
+    let rejection = match &failures[0].error {
+        Error::PushRejected(rejection) => rejection,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(rejection.refname, "refs/heads/feature");
+    assert_eq!(rejection.kind, RejectionKind::Stale);
+    assert_eq!(origin.refname_to_id("refs/heads/feature")?, initial);
+    Ok(())
+}
+
+#[test]
+fn test_deny_deletes() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git config receive.denyDeletes true
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let failures = delete_remote_branches(
+        &git.repo,
+        &[RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        }],
+        &HashMap::new(),
+        false,
+    );
+    assert_eq!(failures.len(), 1);
+    let rejection = match &failures[0].error {
+        Error::PushRejected(rejection) => rejection,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(rejection.kind, RejectionKind::DenyDeletes);
+    Ok(())
+}
+
+#[test]
+fn test_paced_remote_deletion() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git branch another feature
+            git branch third feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let remotes: Vec<_> = [
+        "refs/heads/another",
+        "refs/heads/feature",
+        "refs/heads/third",
+    ]
+    .iter()
+    .map(|refname| RemoteBranch {
+        remote: "origin".to_owned(),
+        refname: (*refname).to_owned(),
+    })
+    .collect();
+    let started = Instant::now();
+    let failures = delete_remote_branches_paced(
+        &git.repo,
+        &remotes,
+        &HashMap::new(),
+        false,
+        &DeletePacing {
+            chunk_size: 1,
+            // A branch per 0.2 seconds
+            rate_limit: 300,
+        },
+    );
+    assert!(failures.is_empty());
+    assert!(started.elapsed() >= Duration::from_millis(400));
+
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    for remote in &remotes {
+        assert!(origin.find_reference(&remote.refname).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_permission_denied() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    repo.config()?.set_str("protocol.ext.allow", "always")?;
+    repo.remote_set_pushurl(
+        "origin",
+        Some("ext::sh -c echo% ERROR:% Permission% to% myorg/myrepo.git% denied% to% someone.>&2;% exit% 128"),
+    )?;
+    let git = Git::try_from(repo)?;
+
+    let failures = delete_remote_branches(
+        &git.repo,
+        &[RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        }],
+        &HashMap::new(),
+        false,
+    );
+    assert_eq!(failures.len(), 1);
+    let rejection = match &failures[0].error {
+        Error::PushRejected(rejection) => rejection,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(rejection.refname, "refs/heads/feature");
+    assert_eq!(rejection.kind, RejectionKind::PermissionDenied);
+    assert_eq!(
+        rejection.reason,
+        "ERROR: Permission to myorg/myrepo.git denied to someone."
+    );
+    Ok(())
+}
+
+#[test]
+fn test_authentication_failure() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let repo = Repository::open(guard.working_directory())?;
+    repo.config()?.set_str("protocol.ext.allow", "always")?;
+    repo.remote_set_pushurl(
+        "origin",
+        Some("ext::sh -c echo% fatal:% Authentication% failed% for% origin>&2;% exit% 128"),
+    )?;
+    let git = Git::try_from(repo)?;
+
+    let failures = delete_remote_branches(
+        &git.repo,
+        &[RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        }],
+        &HashMap::new(),
+        false,
+    );
+    assert_eq!(failures.len(), 1);
+    let failure = match &failures[0].error {
+        Error::Authentication(failure) => failure,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(failure.remote, "origin");
+    assert_eq!(failure.message, "fatal: Authentication failed for origin");
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_and_pruned() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git fetch origin
+            git update-ref -d refs/remotes/origin/feature
+            git config remote.origin.skipFetchAll true
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            scan_push_remote: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedPushRemote {
+                local: LocalBranch::new("refs/heads/feature"),
+                remote: RemoteBranch {
+                    remote: "origin".to_owned(),
+                    refname: "refs/heads/feature".to_owned(),
+                },
+            },
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_and_pruned_push_default_nothing() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git fetch origin
+            git update-ref -d refs/remotes/origin/feature
+            git config remote.origin.skipFetchAll true
+            git config push.default nothing
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            scan_push_remote: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_forgot_to_delete_and_edited() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rejected() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git branch -D feature
+        EOF
+    "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rejected_but_edited() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git branch -D feature
+        EOF
+        local <<EOF
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+        EOF
+    "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::Stray(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rejected_but_forgot_to_delete() -> Result<()> {
+    let guard = fixture().prepare("local", r#""#)?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    Ok(())
+}
+
+#[test]
+fn test_rejected_but_forgot_to_delete_and_edited() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+        EOF
+    "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    Ok(())
+}
+
+#[test]
+fn test_broken_upstream() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git config branch.feature.remote removed
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    assert_eq!(
+        plan.broken_upstreams,
+        vec![BrokenUpstream {
+            local: LocalBranch::new("refs/heads/feature"),
+            remote: "removed".to_owned(),
+        }],
+    );
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            fix_upstreams: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    assert_eq!(plan.broken_upstreams, vec![]);
+    Ok(())
+}
+
+#[test]
+fn test_broken_upstream_not_merged() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config branch.feature.remote removed
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            fix_upstreams: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(plan.to_delete, set! {});
+    let feature = LocalBranch::new("refs/heads/feature");
+    assert_eq!(
+        plan.broken_upstreams,
+        vec![BrokenUpstream {
+            local: feature.clone(),
+            remote: "removed".to_owned(),
+        }],
+    );
+
+    unset_upstreams(&git.repo, &[&feature], false)?;
+    let config = git.repo.config()?.snapshot()?;
+    assert!(config.get_string("branch.feature.remote").is_err());
+    assert!(config.get_string("branch.feature.merge").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_accepted_signed_by_others() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            ssh-keygen -q -t ed25519 -N "" -C mine -f ../mine
+            ssh-keygen -q -t ed25519 -N "" -C other -f ../other
+            git config gpg.format ssh
+            git config user.signingKey ../other.pub
+            git commit --allow-empty -S -m "Signed patch"
+            git push origin feature
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let read_key = |name: &str| -> Result<String> {
+        let path = guard.working_directory().join("..").join(name);
+        Ok(std::fs::read_to_string(path)?.trim().to_owned())
+    };
+    let mine = read_key("mine.pub")?;
+    let other = read_key("other.pub")?;
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            preserve_signed_by_others: true,
+            signing_keys: vec![&mine],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch
+            == ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/feature",
+            ))
+            && preserved.reason.contains("signed")
+    }));
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            preserve_signed_by_others: true,
+            signing_keys: vec![&mine, &other],
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/feature"
+            )),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_pruned_signed_by_others() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            ssh-keygen -q -t ed25519 -N "" -C mine -f ../mine
+            ssh-keygen -q -t ed25519 -N "" -C other -f ../other
+            git config gpg.format ssh
+            git config user.signingKey ../other.pub
+            git commit --allow-empty -S -m "Signed patch"
+            git push origin feature
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git fetch origin
+            git update-ref -d refs/remotes/origin/feature
+            git config remote.origin.skipFetchAll true
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let mine = std::fs::read_to_string(guard.working_directory().join("../mine.pub"))?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            scan_push_remote: true,
+            preserve_signed_by_others: true,
+            signing_keys: vec![mine.trim()],
+            ..test_default_param()
+        },
+    )?;
+    let pushed = ClassifiedBranch::MergedPushRemote {
+        local: LocalBranch::new("refs/heads/feature"),
+        remote: RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        },
+    };
+    assert!(!plan.to_delete.contains(&pushed));
+    assert!(plan
+        .preserved
+        .iter()
+        .any(|preserved| { preserved.branch == pushed && preserved.reason.contains("signed") }));
+    Ok(())
+}
+
+#[test]
+fn test_accepted_only_mine() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            only_mine: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/feature"
+            )),
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_accepted_only_mine_authored_by_others() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout feature
+            git commit --allow-empty -m "Teammate's patch"
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git pull
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            only_mine: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch
+            == ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new(
+                "refs/remotes/origin/feature",
+            ))
+            && preserved.reason.contains("origin@test")
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_accepted_but_pruned_only_mine_authored_by_others() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git -c user.email=teammate@test commit --allow-empty -m "Teammate's patch"
+            git push origin feature
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        local <<EOF
+            git fetch origin
+            git update-ref -d refs/remotes/origin/feature
+            git config remote.origin.skipFetchAll true
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            scan_push_remote: true,
+            only_mine: true,
+            ..test_default_param()
+        },
+    )?;
+    let pushed = ClassifiedBranch::MergedPushRemote {
+        local: LocalBranch::new("refs/heads/feature"),
+        remote: RemoteBranch {
+            remote: "origin".to_owned(),
+            refname: "refs/heads/feature".to_owned(),
+        },
+    };
+    assert!(!plan.to_delete.contains(&pushed));
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch == pushed && preserved.reason.contains("teammate@test")
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_shallow_clone_keeps_unknowns() -> Result<()> {
+    let guard = fixture().prepare(
+        "shallow",
+        r#"
+        origin <<EOF
+            git checkout master
+            git commit --allow-empty -m "Another commit"
+        EOF
+        git clone --depth 1 "file://$PWD/origin" shallow
+        within shallow <<EOF
+            git config user.name "Shallow Test"
+            git config user.email "shallow@test"
+            git config remote.origin.fetch "+refs/heads/*:refs/remotes/origin/*"
+            git checkout -b topic
+            git commit --allow-empty -m "Topic patch"
+            git push -u origin topic
+        EOF
+        origin <<EOF
+            git branch -D topic
+        EOF
+        within shallow <<EOF
+            git fetch --prune
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch == ClassifiedBranch::Stray(LocalBranch::new("refs/heads/topic"))
+            && preserved.reason == "unknown (shallow history)"
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_orphan_namespaces() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git update-ref refs/remotes/old-origin/feature HEAD
+            git update-ref refs/remotes/old-origin/master master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    assert_eq!(
+        plan.orphan_namespaces,
+        vec![OrphanNamespace {
+            remote: "old-origin".to_owned(),
+            refnames: vec![
+                "refs/remotes/old-origin/feature".to_owned(),
+                "refs/remotes/old-origin/master".to_owned(),
+            ],
+        }],
+    );
+
+    delete_orphan_namespaces(&git.repo, &plan.orphan_namespaces, false)?;
+    assert!(git
+        .repo
+        .references_glob("refs/remotes/old-origin/*")?
+        .next()
+        .is_none());
+    assert!(git
+        .repo
+        .find_reference("refs/remotes/origin/master")
+        .is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_orphan_namespaces_of_prefix_remote() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config remote.foo/bar.url ../origin
+            git config remote.foo/bar.fetch '+refs/heads/*:refs/remotes/foo/bar/*'
+            git fetch foo/bar
+            git config branch.master.remote foo
+            git update-ref refs/remotes/foo/abandoned HEAD
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.orphan_namespaces,
+        vec![OrphanNamespace {
+            remote: "foo".to_owned(),
+            refnames: vec!["refs/remotes/foo/abandoned".to_owned()],
+        }],
+    );
+    assert_eq!(
+        get_orphan_namespace_delete_commands(&plan.orphan_namespaces, 0),
+        vec![vec![
+            "branch".to_owned(),
+            "--delete".to_owned(),
+            "--remotes".to_owned(),
+            "foo/abandoned".to_owned(),
+        ]],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_skip_non_utf8_refnames() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git update-ref $'refs/heads/invalid-\xff' feature
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+    assert_eq!(
+        plan.non_utf8_refnames,
+        vec!["refs/heads/invalid-\u{FFFD}".to_owned()],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_limit() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b another feature
+            git push -u origin another
+            git checkout -b zzz feature
+            git push -u origin zzz
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            sort: SortKey::Name,
+            limit: 2,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/another")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/another")),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedRemoteTracking(RemoteTrackingBranch::new("refs/remotes/origin/feature")),
+        },
+    );
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch == ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/zzz"))
+            && preserved.reason == "over the limit"
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_operation_in_progress() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git bisect start
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let err = check_no_operation_in_progress(&git.repo).unwrap_err();
+    assert_eq!(err.operation, "A bisect");
+    Ok(())
+}
+
+#[test]
+fn test_preserve_base_of_unmerged_stack() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b child feature
+            touch child-patch
+            git add child-patch
+            git commit -m "Child patch"
+            git branch --set-upstream-to=feature
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch == ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature"))
+            && preserved.reason == "base of unmerged stack"
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_delete_merged_stack() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b child feature
+            touch child-patch
+            git add child-patch
+            git commit -m "Child patch"
+            git branch --set-upstream-to=feature
+            git push origin child:refs/heads/stacked
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge stacked
+            git branch -D feature stacked
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/child")),
+        },
+    );
+
+    let locals = plan.locals_to_delete();
+    let failures = delete_local_branches(&git.repo, &locals, false, false)?;
+    assert!(failures.is_empty());
+    assert!(git.repo.find_reference("refs/heads/feature").is_err());
+    assert!(git.repo.find_reference("refs/heads/child").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_retarget_stacks() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b child feature
+            touch child-patch
+            git add child-patch
+            git commit -m "Child patch"
+            git branch --set-upstream-to=feature
+            git checkout master
+        EOF
+        origin <<EOF
+            git checkout master
+            git merge feature
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            retarget_stacks: true,
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature")),
+        },
+    );
+
+    let locals = plan.locals_to_delete();
+    retarget_stacked_upstreams(&git.repo, &plan.local_bases(), &locals, false)?;
+    delete_local_branches(&git.repo, &locals, false, false)?;
+    let config = git.repo.config()?.snapshot()?;
+    assert_eq!(config.get_str("branch.child.remote")?, "origin");
+    assert_eq!(config.get_str("branch.child.merge")?, "refs/heads/master");
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_of_base() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git branch --track master-backup origin/master
+        EOF
+        "#,
+    )?;
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+    assert_eq!(plan.to_delete, set! {});
+    assert!(plan.preserved.iter().any(|preserved| {
+        preserved.branch.local() == Some(&LocalBranch::new("refs/heads/master-backup"))
+            && preserved.reason.contains("duplicate-base")
+    }));
+
+    let plan = get_trim_plan(
+        &git,
+        &PlanParam {
+            delete: DeleteFilter::from_iter(vec![DeleteRange::DuplicateBase]),
+            ..test_default_param()
+        },
+    )?;
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::DuplicateOfBase {
+                local: LocalBranch::new("refs/heads/master-backup"),
+                base: RemoteTrackingBranch::new("refs/remotes/origin/master"),
+            },
+        },
+    );
     Ok(())
 }
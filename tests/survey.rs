@@ -0,0 +1,101 @@
+//! The survey is shown only to interactive runs, so these run `git-trim` on a pseudo terminal.
+#![cfg(unix)]
+
+mod fixture;
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+use anyhow::Result;
+
+use git_trim::common_dir;
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config remote.origin.push "refs/heads/*:refs/heads/*"
+        EOF
+        "#,
+    )
+}
+
+/// Run `git-trim` with stdin and stderr on a pseudo terminal. Stdout is piped.
+fn git_trim_on_terminal(working_directory: &Path, args: &[&str]) -> Result<Output> {
+    let mut master = 0;
+    let mut slave = 0;
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut master = unsafe { File::from_raw_fd(master) };
+    let slave = unsafe { File::from_raw_fd(slave) };
+
+    let child = Command::new(env!("CARGO_BIN_EXE_git-trim"))
+        .args(args)
+        .current_dir(working_directory)
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::from(slave))
+        .spawn()?;
+    // Drain the terminal so that the child doesn't block on writing to it.
+    // It ends with an error once the child closes its end.
+    let drain = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = master.read_to_end(&mut buf);
+    });
+    let output = child.wait_with_output()?;
+    drain.join().unwrap();
+    Ok(output)
+}
+
+#[test]
+fn test_survey_is_shown_once() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let args = ["--bases", "master", "--yes"];
+
+    let first = git_trim_on_terminal(&guard.working_directory(), &args)?;
+    assert!(first.status.success());
+    assert!(String::from_utf8_lossy(&first.stdout).contains("Help wanted!"));
+    let repo = git2::Repository::open(guard.working_directory())?;
+    assert!(common_dir(&repo).join("trim-survey-shown").exists());
+
+    let second = git_trim_on_terminal(&guard.working_directory(), &args)?;
+    assert!(second.status.success());
+    assert!(!String::from_utf8_lossy(&second.stdout).contains("Help wanted!"));
+    Ok(())
+}
+
+#[test]
+fn test_survey_is_not_shown_without_terminal() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let output = fixture::git_trim(&guard.working_directory(), &["--bases", "master", "--yes"])?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Help wanted!"));
+    let repo = git2::Repository::open(guard.working_directory())?;
+    assert!(!common_dir(&repo).join("trim-survey-shown").exists());
+    Ok(())
+}
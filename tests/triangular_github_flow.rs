@@ -1,11 +1,15 @@
 mod fixture;
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use anyhow::Result;
 use git2::Repository;
 
-use git_trim::{get_trim_plan, ClassifiedBranch, Git, LocalBranch, RemoteTrackingBranch};
+use git_trim::{
+    delete_remote_branches, get_trim_plan, ClassifiedBranch, Git, LocalBranch, RemoteBranch,
+    RemoteTrackingBranch,
+};
 
 use fixture::{rc, test_default_param, Fixture};
 
@@ -257,3 +261,46 @@ fn test_rejected_but_forgot_to_delete_and_edited() -> Result<()> {
     assert_eq!(plan.to_delete, set! {});
     Ok(())
 }
+
+#[test]
+fn test_remote_deletion_failure_does_not_block_other_remotes() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git push upstream feature
+            git remote add broken ../non-existent
+            git config remote.broken.skipFetchAll true
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let failures = delete_remote_branches(
+        &git.repo,
+        &[
+            RemoteBranch {
+                remote: "origin".to_owned(),
+                refname: "refs/heads/feature".to_owned(),
+            },
+            RemoteBranch {
+                remote: "broken".to_owned(),
+                refname: "refs/heads/feature".to_owned(),
+            },
+            RemoteBranch {
+                remote: "upstream".to_owned(),
+                refname: "refs/heads/feature".to_owned(),
+            },
+        ],
+        &HashMap::new(),
+        false,
+    );
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].remote, "broken");
+
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    assert!(origin.find_reference("refs/heads/feature").is_err());
+    let upstream = Repository::open(guard.working_directory().join("../upstream"))?;
+    assert!(upstream.find_reference("refs/heads/feature").is_err());
+    Ok(())
+}
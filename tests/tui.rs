@@ -0,0 +1,121 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::SortKey;
+use git_trim::{get_trim_plan, ClassifiedBranch, Git, LocalBranch};
+
+use fixture::{rc, test_default_param, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+            git checkout -b feature-a master
+            touch feature-a
+            git add feature-a
+            git commit -m "Add feature-a"
+            git push -u origin feature-a
+            git checkout -b feature-b master
+            touch feature-b
+            git add feature-b
+            git commit -m "Add feature-b"
+            git push -u origin feature-b
+            git checkout -b feature-c master
+            touch feature-c
+            git add feature-c
+            git commit -m "Add feature-c"
+            git push -u origin feature-c
+            git checkout master
+        EOF
+        origin <<EOF
+            git merge feature-a --no-ff
+            git branch -D feature-a
+            git merge feature-b --no-ff
+            git branch -D feature-b
+            git merge feature-c --no-ff
+            git branch -D feature-c
+        EOF
+        "#,
+    )
+}
+
+fn kept_interactively(plan: &git_trim::TrimPlan) -> Vec<&ClassifiedBranch> {
+    plan.preserved
+        .iter()
+        .filter(|preserved| preserved.reason == "kept interactively")
+        .map(|preserved| &preserved.branch)
+        .collect()
+}
+
+#[test]
+fn test_preserve_unselected() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let mut plan = get_trim_plan(&git, &test_default_param())?;
+    let branches: Vec<_> = plan
+        .sorted_to_delete(&git.repo, SortKey::Name)?
+        .into_iter()
+        .cloned()
+        .collect();
+    assert_eq!(
+        branches,
+        vec![
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature-a")),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature-b")),
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature-c")),
+        ]
+    );
+
+    plan.preserve_unselected(&branches, &[1]);
+    assert_eq!(
+        plan.to_delete,
+        set! {
+            ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/feature-b")),
+        },
+    );
+    assert_eq!(kept_interactively(&plan), vec![&branches[0], &branches[2]]);
+    Ok(())
+}
+
+#[test]
+fn test_preserve_unselected_all_selected() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let mut plan = get_trim_plan(&git, &test_default_param())?;
+    let to_delete = plan.to_delete.clone();
+    let branches: Vec<_> = to_delete.iter().cloned().collect();
+
+    plan.preserve_unselected(&branches, &[0, 1, 2]);
+    assert_eq!(plan.to_delete, to_delete);
+    assert!(kept_interactively(&plan).is_empty());
+
+    // Branches that are not going to be deleted are not preserved again.
+    plan.preserve_unselected(
+        &[ClassifiedBranch::MergedLocal(LocalBranch::new(
+            "refs/heads/master",
+        ))],
+        &[],
+    );
+    assert_eq!(plan.to_delete, to_delete);
+    assert!(kept_interactively(&plan).is_empty());
+    Ok(())
+}
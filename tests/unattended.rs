@@ -0,0 +1,62 @@
+mod fixture;
+
+use anyhow::Result;
+use git2::Repository;
+
+use fixture::{git_trim, rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+        EOF
+        origin <<EOF
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )
+}
+
+#[test]
+fn test_refuse_to_delete_without_terminal() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let output = git_trim(&guard.working_directory(), &["--bases", "master"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Pass `--yes` to delete them"));
+
+    let repo = Repository::open(guard.working_directory())?;
+    assert!(repo.find_reference("refs/heads/feature").is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_delete_without_terminal_with_yes() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let output = git_trim(&guard.working_directory(), &["--bases", "master", "--yes"])?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let repo = Repository::open(guard.working_directory())?;
+    assert!(repo.find_reference("refs/heads/feature").is_err());
+    Ok(())
+}
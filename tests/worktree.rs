@@ -5,7 +5,7 @@ use std::convert::TryFrom;
 use anyhow::Result;
 use git2::Repository;
 
-use git_trim::{get_trim_plan, ClassifiedBranch, Git, LocalBranch};
+use git_trim::{delete_local_branches, get_trim_plan, ClassifiedBranch, Git, LocalBranch};
 
 use fixture::{rc, test_default_param, Fixture};
 
@@ -57,3 +57,48 @@ fn test_bases_implicit_value() -> Result<()> {
     }));
     Ok(())
 }
+
+#[test]
+fn test_worktree_path_with_spaces() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b "spaced"
+            git push -u origin spaced
+            git checkout master
+            git worktree add "../work tree" spaced
+        EOF
+
+        origin <<EOF
+            git merge spaced
+            git branch -d spaced
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let plan = get_trim_plan(&git, &test_default_param())?;
+
+    assert!(plan.preserved.iter().any(|w| {
+        w.branch == ClassifiedBranch::MergedLocal(LocalBranch::new("refs/heads/spaced"))
+            && w.reason.contains("work tree")
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_delete_checked_out_in_worktree_fails() -> Result<()> {
+    let guard = fixture().prepare("local", r#""#)?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let worktree = LocalBranch::new("refs/heads/worktree");
+    let failures = delete_local_branches(&git.repo, &[&worktree], false, false)?;
+
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].branch, worktree);
+    assert!(git.repo.find_reference(&worktree.refname).is_ok());
+    let config = git.repo.config()?.snapshot()?;
+    assert_eq!(config.get_string("branch.worktree.remote")?, "origin");
+    Ok(())
+}